@@ -6,8 +6,9 @@ fn main() -> std::io::Result<()> {
         "-s" => check_string(&args[2])?,
         "-b" => check_bytes(&args[2])?,
         "-u" => check_dbint(&args[2])?,
+        "-v" => verify(&args[2])?,
         _ => {
-            eprintln!("[usage] {} {{-s|-b|-u}} path", args[0]);
+            eprintln!("[usage] {} {{-s|-b|-u|-v}} path", args[0]);
         }
     }
     Ok(())
@@ -20,6 +21,16 @@ struct CheckC {
     f_graph: bool,
 }
 
+fn verify(db_name: &str) -> std::io::Result<()> {
+    let db = siamesedb::open_file(db_name)?;
+    let report = db.verify()?;
+    println!("{}", report);
+    if !report.is_ok() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
 fn check_string(db_name: &str) -> std::io::Result<()> {
     let db = siamesedb::open_file(db_name).unwrap();
     let db_map = db
@@ -0,0 +1,121 @@
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+/// The result type of siamesedb.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The error of siamesedb.
+///
+/// This is converted from and into `std::io::Error`, so the code with
+/// `std::io::Result` can use `?` on the results of siamesedb, and
+/// the variant is kept through the conversions.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// the io error of the file.
+    Io(std::io::Error),
+    /// the data in the file is broken.
+    Corruption {
+        /// the path of the broken file.
+        file: PathBuf,
+        /// the offset of the broken data in the file.
+        offset: u64,
+        /// what is broken.
+        reason: String,
+    },
+    /// the db map is opened as the other key type.
+    KeyTypeMismatch {
+        /// the path of the key file.
+        file: PathBuf,
+        /// the type signature of the opening.
+        expected: String,
+        /// the type signature in the file header.
+        found: String,
+    },
+    /// the database is locked by the other.
+    Locked(PathBuf),
+    /// the database is opened as read only.
+    ReadOnly(PathBuf),
+    /// the key or the value is too large.
+    ValueTooLarge {
+        /// the length of the key or the value.
+        len: usize,
+        /// the max length.
+        max: usize,
+    },
+}
+
+impl Error {
+    /// the `std::io::ErrorKind` of this error.
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::Io(err) => err.kind(),
+            Error::Corruption { .. } => ErrorKind::InvalidData,
+            Error::KeyTypeMismatch { .. } => ErrorKind::InvalidData,
+            Error::Locked(_) => ErrorKind::WouldBlock,
+            Error::ReadOnly(_) => ErrorKind::PermissionDenied,
+            Error::ValueTooLarge { .. } => ErrorKind::InvalidInput,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(err) => err.fmt(f),
+            Error::Corruption {
+                file,
+                offset,
+                reason,
+            } => write!(f, "{}: {}: offset {}", reason, file.display(), offset),
+            Error::KeyTypeMismatch {
+                file,
+                expected,
+                found,
+            } => write!(
+                f,
+                "invalid header signature2, type signature: {}: expected {}, found {}",
+                file.display(),
+                expected,
+                found
+            ),
+            Error::Locked(path) => write!(f, "the database is locked: {}", path.display()),
+            Error::ReadOnly(path) => {
+                write!(f, "the database is opened as read only: {}", path.display())
+            }
+            Error::ValueTooLarge { len, max } => {
+                write!(f, "too large: {} bytes, the max is {} bytes", len, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    /// the error that is converted from `Error` into `std::io::Error` gets back its variant.
+    fn from(err: std::io::Error) -> Self {
+        if err.get_ref().map(|a| a.is::<Error>()).unwrap_or(false) {
+            let inner = err.into_inner().unwrap();
+            return *inner.downcast::<Error>().unwrap();
+        }
+        Error::Io(err)
+    }
+}
+
+impl From<Error> for std::io::Error {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::Io(err) => err,
+            _ => std::io::Error::new(err.kind(), err),
+        }
+    }
+}
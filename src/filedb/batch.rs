@@ -0,0 +1,75 @@
+use super::super::DbMapKeyType;
+
+/// A batch of the puts and the deletes for a db map.
+///
+/// The batch is applied all-or-nothing by `FileDbMap::write_batch()`.
+/// It is written to the write-ahead log in the database directory before
+/// touching the db map files, and the log is replayed at the next open
+/// if the applying is interrupted. The log is checked by CRC-32C.
+///
+/// The changed pages of the files are kept in memory until the commit,
+/// so a batch can change up to 256 MiB of each file of the db map.
+/// Over it, the error kind is `InvalidInput`, and nothing is written.
+#[derive(Debug, Clone)]
+pub struct WriteBatch<KT: DbMapKeyType> {
+    ops: Vec<(KT, Option<Vec<u8>>)>,
+}
+
+impl<KT: DbMapKeyType> WriteBatch<KT> {
+    /// creates an empty batch.
+    pub fn new() -> Self {
+        Self { ops: Vec::new() }
+    }
+    /// inserts a key-value pair into the batch.
+    pub fn put<'a, Q>(&mut self, key: &'a Q, value: &[u8])
+    where
+        KT: From<&'a Q>,
+        Q: Ord + ?Sized,
+    {
+        self.ops.push((From::from(key), Some(value.to_vec())));
+    }
+    /// inserts a key-value pair into the batch. the value is `&str` and it is converted to `&[u8]`
+    pub fn put_string<'a, Q>(&mut self, key: &'a Q, value: &str)
+    where
+        KT: From<&'a Q>,
+        Q: Ord + ?Sized,
+    {
+        self.put(key, value.as_bytes())
+    }
+    /// removes a key in the batch.
+    pub fn delete<'a, Q>(&mut self, key: &'a Q)
+    where
+        KT: From<&'a Q>,
+        Q: Ord + ?Sized,
+    {
+        self.ops.push((From::from(key), None));
+    }
+    /// the number of the puts and the deletes.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+    /// removes all of the puts and the deletes.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.ops.clear();
+    }
+    #[inline]
+    pub(crate) fn put_kt(&mut self, key: KT, value: Vec<u8>) {
+        self.ops.push((key, Some(value)));
+    }
+    #[inline]
+    pub(crate) fn ops(&self) -> &[(KT, Option<Vec<u8>>)] {
+        &self.ops
+    }
+}
+
+impl<KT: DbMapKeyType> Default for WriteBatch<KT> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,50 @@
+/*!
+the shared ownership and the interior mutability of the file database.
+
+these are `Rc` and `RefCell` by default. with the feature `sync`, these are
+`Arc` and `RwLock`, and then `FileDb` and `FileDbMap` are `Send` and `Sync`.
+*/
+
+#[cfg(not(feature = "sync"))]
+pub(crate) use std::cell::{Ref, RefCell, RefMut};
+#[cfg(not(feature = "sync"))]
+pub(crate) use std::rc::Rc;
+
+#[cfg(feature = "sync")]
+pub(crate) use std::sync::Arc as Rc;
+
+#[cfg(feature = "sync")]
+pub(crate) type Ref<'a, T> = std::sync::RwLockReadGuard<'a, T>;
+
+#[cfg(feature = "sync")]
+pub(crate) type RefMut<'a, T> = std::sync::RwLockWriteGuard<'a, T>;
+
+/// `RefCell` on `RwLock`.
+/// the `borrow()` is shared between threads, and the `borrow_mut()` is exclusive.
+/// the reading of a db map takes the `borrow()`, and the files in it are locked
+/// by their own `borrow_mut()` while the file buffer is moved.
+#[cfg(feature = "sync")]
+#[derive(Debug, Default)]
+pub struct RefCell<T>(std::sync::RwLock<T>);
+
+#[cfg(feature = "sync")]
+const POISONED_MESSAGE: &str = "the lock is poisoned by the panic of the other thread";
+
+#[cfg(feature = "sync")]
+impl<T> RefCell<T> {
+    #[inline]
+    pub fn new(value: T) -> Self {
+        Self(std::sync::RwLock::new(value))
+    }
+    /// the panic of the other thread while it is locked is propagated, because
+    /// the value may be left broken by the panic, such as the db map in the middle
+    /// of the writing.
+    #[inline]
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.0.read().expect(POISONED_MESSAGE)
+    }
+    #[inline]
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.0.write().expect(POISONED_MESSAGE)
+    }
+}
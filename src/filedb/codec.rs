@@ -0,0 +1,84 @@
+use std::io::Result;
+
+/// The codec of the values in a db map.
+///
+/// It is set by `FileDbParams::value_codec`. The value is encoded on the writing,
+/// and it is kept raw if the encoded one is not smaller. Each value piece has
+/// the flag of the encoding, so the raw values that were written before the codec
+/// is set are still readable.
+pub trait ValueCodec: std::fmt::Debug + Send + Sync {
+    /// the id of the codec, it is stored in the header of the value file.
+    /// it must not be zero, and it must not be changed.
+    fn id(&self) -> u64;
+    /// encodes the value.
+    fn encode(&self, value: &[u8]) -> Vec<u8>;
+    /// decodes the encoded value.
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>>;
+}
+
+/// The LZ4 block codec, with the feature `lz4`. the id is 1.
+#[cfg(feature = "lz4")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Lz4Codec;
+
+#[cfg(feature = "lz4")]
+impl Lz4Codec {
+    pub const ID: u64 = 1;
+}
+
+#[cfg(feature = "lz4")]
+impl ValueCodec for Lz4Codec {
+    fn id(&self) -> u64 {
+        Self::ID
+    }
+    fn encode(&self, value: &[u8]) -> Vec<u8> {
+        lz4_flex::compress_prepend_size(value)
+    }
+    fn decode(&self, data: &[u8]) -> Result<Vec<u8>> {
+        lz4_flex::decompress_size_prepended(data)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))
+    }
+}
+
+/// the built-in codec of the id, that is used when the db map is opened
+/// without the codec.
+pub(crate) fn builtin_value_codec(_id: u64) -> Option<std::sync::Arc<dyn ValueCodec>> {
+    #[cfg(feature = "lz4")]
+    if _id == Lz4Codec::ID {
+        return Some(std::sync::Arc::new(Lz4Codec));
+    }
+    None
+}
+
+/// The key of the encryption of the values, 32 bytes.
+///
+/// It is set by `FileDbParams::encryption_key`, with the feature `encryption`.
+/// The bytes are not shown by `Debug`, and are cleared on the dropping.
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+    #[cfg(feature = "encryption")]
+    #[inline]
+    pub(crate) fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("EncryptionKey(..)")
+    }
+}
+
+impl Drop for EncryptionKey {
+    fn drop(&mut self) {
+        for b in self.0.iter_mut() {
+            // the volatile writing is not optimized out.
+            unsafe { std::ptr::write_volatile(b, 0) };
+        }
+    }
+}
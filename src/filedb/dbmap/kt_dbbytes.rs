@@ -1,11 +1,24 @@
 use super::super::super::{DbMapKeyType, HashValue};
-use super::FileDbMap;
+use super::{DbXxxIter, FileDbMap};
 use std::fmt::{Display, Error, Formatter};
 use std::ops::Deref;
 
 /// DbBytes Map in a file databse.
 pub type FileDbMapDbBytes = FileDbMap<DbBytes>;
 
+impl FileDbMap<DbBytes> {
+    /// gets an iterator over key-value pairs whose key starts with the prefix, in key order.
+    #[inline]
+    pub fn prefix_iter<'a, Q>(&self, prefix: &'a Q) -> DbXxxIter<DbBytes>
+    where
+        DbBytes: From<&'a Q>,
+        Q: Ord + ?Sized,
+    {
+        let prefix_kt: DbBytes = From::from(prefix);
+        DbXxxIter::with_prefix(self.0.clone(), &prefix_kt).unwrap()
+    }
+}
+
 /// db-key type. `&[u8]` can be used as keys.
 #[derive(Debug, Default, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct DbBytes(Vec<u8>);
@@ -1,11 +1,24 @@
 use super::super::super::{DbMapKeyType, HashValue};
-use super::FileDbMap;
+use super::{DbXxxIter, FileDbMap};
 use std::fmt::{Display, Error, Formatter};
 use std::ops::Deref;
 
 /// DbBytes Map in a file databse.
 pub type FileDbMapDbString = FileDbMap<DbString>;
 
+impl FileDbMap<DbString> {
+    /// gets an iterator over key-value pairs whose key starts with the prefix, in key order.
+    #[inline]
+    pub fn prefix_iter<'a, Q>(&self, prefix: &'a Q) -> DbXxxIter<DbString>
+    where
+        DbString: From<&'a Q>,
+        Q: Ord + ?Sized,
+    {
+        let prefix_kt: DbString = From::from(prefix);
+        DbXxxIter::with_prefix(self.0.clone(), &prefix_kt).unwrap()
+    }
+}
+
 /// db-key type. `String` can be used as key.
 #[derive(Debug, Default, Clone, PartialEq, PartialOrd, Eq, Ord, Hash)]
 pub struct DbString(Vec<u8>);
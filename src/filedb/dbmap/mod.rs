@@ -1,12 +1,13 @@
-use super::super::{DbMap, DbMapKeyType, DbXxx, DbXxxBase, DbXxxObjectSafe};
+use super::super::{DbMap, DbMapKeyType, DbXxx, DbXxxBase, DbXxxObjectSafe, Result};
+use super::cell::{Rc, RefCell, RefMut};
+use super::inner::wal::{WalLock, WalRecord};
 use super::{
-    CheckFileDbMap, CountOfPerSize, DbXxxIntoIter, DbXxxIter, DbXxxIterMut, FileDbParams,
-    FileDbXxxInner, Key, KeysCountStats, LengthStats, RecordSizeStats, Value,
+    CheckFileDbMap, CompactStats, CountOfPerSize, DbXxxIntoIter, DbXxxIter, DbXxxIterMut,
+    DbXxxTryIter, FileDbParams, FileDbXxxInner, Key, KeysCountStats, LengthStats, RecordSizeStats,
+    Value, VerifyProblem, WriteBatch,
 };
-use std::cell::RefCell;
-use std::io::Result;
+use std::ops::RangeBounds;
 use std::path::Path;
-use std::rc::Rc;
 
 pub mod kt_dbbytes;
 pub mod kt_dbint;
@@ -17,6 +18,12 @@ pub use kt_dbint::{DbInt, FileDbMapDbInt};
 pub use kt_dbstring::{DbString, FileDbMapDbString};
 
 /// DbMap in a file database.
+///
+/// With the feature `sync`, this is `Send` and `Sync`. The reading of the same
+/// db map, such as `get()` and the iteration, runs in parallel under the shared lock,
+/// and each of its files is locked only while the file buffer is read. The writing
+/// on it is serialized by the exclusive lock, and the calls on the different db maps
+/// run in parallel.
 #[derive(Debug, Clone)]
 pub struct FileDbMap<KT: DbMapKeyType>(Rc<RefCell<FileDbXxxInner<KT>>>);
 
@@ -25,15 +32,100 @@ impl<KT: DbMapKeyType> FileDbMap<KT> {
         path: P,
         ks_name: &str,
         params: FileDbParams,
+        read_only: bool,
+        wal_lock: WalLock,
     ) -> Result<FileDbMap<KT>> {
         Ok(Self(Rc::new(RefCell::new(
-            FileDbXxxInner::<KT>::open_with_params(path, ks_name, params)?,
+            FileDbXxxInner::<KT>::open_with_params(path, ks_name, params, read_only, wal_lock)?,
         ))))
     }
     #[inline]
     pub fn is_dirty(&self) -> bool {
         RefCell::borrow(&self.0).is_dirty()
     }
+    #[inline]
+    pub fn is_read_only(&self) -> bool {
+        RefCell::borrow(&self.0).is_read_only()
+    }
+    /// applies the puts and the deletes in the batch all-or-nothing.
+    /// the batch is durable when this returns `Ok`.
+    pub fn write_batch(&mut self, batch: WriteBatch<KT>) -> Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let wal_lock = RefCell::borrow(&self.0).wal_lock();
+        let _wal_locked = RefCell::borrow_mut(&wal_lock);
+        Ok(RefCell::borrow_mut(&self.0).write_batch(batch.ops())?)
+    }
+    /// the fallible iterator. the error of the reading is returned as `Err` of the item,
+    /// instead of the panic of `iter()`.
+    #[inline]
+    pub fn try_iter(&self) -> DbXxxTryIter<KT> {
+        DbXxxTryIter::new(self.0.clone())
+    }
+    /// compacts the files of the db map. the live pieces are moved to the front
+    /// of the files, and the files are truncated. returns the bytes reclaimed.
+    ///
+    /// the compacted copy is written to the temporary files, and then they are
+    /// renamed into place, so the free disk space of the size of the files is needed.
+    /// an interrupted compaction is finished or discarded on the next open.
+    pub fn compact(&mut self) -> Result<CompactStats> {
+        let wal_lock = RefCell::borrow(&self.0).wal_lock();
+        let _wal_locked = RefCell::borrow_mut(&wal_lock);
+        Ok(RefCell::borrow_mut(&self.0).compact()?)
+    }
+    /// verifies the files of the db map, and returns the problems that are found.
+    /// see `FileDb::verify()`.
+    pub fn verify(&self) -> Result<Vec<VerifyProblem>> {
+        Ok(RefCell::borrow(&self.0).verify()?)
+    }
+    /// locks the db map, the other calls on it wait until this is dropped.
+    #[inline]
+    pub(crate) fn lock(&self) -> RefMut<'_, FileDbXxxInner<KT>> {
+        RefCell::borrow_mut(&self.0)
+    }
+    /// count of the handles of the db map, including the one of `FileDb`
+    /// and the ones of the iterators.
+    #[inline]
+    pub(crate) fn handle_count(&self) -> usize {
+        Rc::strong_count(&self.0)
+    }
+    #[inline]
+    pub(crate) fn set_name(&self, name: &str) {
+        RefCell::borrow_mut(&self.0).set_name(name)
+    }
+    /// opens the view of a transaction on this db map.
+    pub(crate) fn begin_tx(&self) -> Result<FileDbMap<KT>> {
+        let view = RefCell::borrow_mut(&self.0).begin_tx()?;
+        Ok(Self(Rc::new(RefCell::new(view))))
+    }
+    /// the view that the records are applied takes the place of this db map.
+    pub(crate) fn commit_tx(&self, view: &FileDbMap<KT>) {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.commit_tx(&mut RefCell::borrow_mut(&view.0))
+    }
+    /// the view takes the snapshots of the backups, before the records are applied.
+    pub(crate) fn copy_snapshots_to(&self, view: &FileDbMap<KT>) {
+        let locked = RefCell::borrow(&self.0);
+        locked.copy_snapshots_to(&mut RefCell::borrow_mut(&view.0))
+    }
+    #[inline]
+    pub(crate) fn after_commit(&self) -> Result<()> {
+        Ok(RefCell::borrow_mut(&self.0).after_commit()?)
+    }
+    /// discards the view of a transaction, and unlocks this db map.
+    pub(crate) fn rollback_tx(&self, view: &FileDbMap<KT>) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        Ok(locked.rollback_tx(&mut RefCell::borrow_mut(&view.0))?)
+    }
+    #[inline]
+    pub(crate) fn take_batch_records(&self) -> Result<Vec<WalRecord>> {
+        Ok(RefCell::borrow_mut(&self.0).take_batch_records()?)
+    }
+    #[inline]
+    pub(crate) fn apply_batch_records(&self, records: &[WalRecord]) -> Result<()> {
+        Ok(RefCell::borrow_mut(&self.0).apply_batch_records(records)?)
+    }
 }
 
 /// for debug
@@ -135,7 +227,7 @@ impl<KT: DbMapKeyType> DbXxxBase for FileDbMap<KT> {
 impl<KT: DbMapKeyType> DbXxxObjectSafe<KT> for FileDbMap<KT> {
     #[inline]
     fn get_kt(&mut self, key: &KT) -> Result<Option<Vec<u8>>> {
-        RefCell::borrow_mut(&self.0).get_kt(key)
+        Ok(RefCell::borrow(&self.0).find_kt(key)?)
     }
     #[inline]
     fn put_kt(&mut self, key: &KT, value: &[u8]) -> Result<()> {
@@ -158,6 +250,10 @@ impl<KT: DbMapKeyType> DbMap<KT> for FileDbMap<KT> {
     fn iter_mut(&mut self) -> DbXxxIterMut<KT> {
         DbXxxIterMut::new(self.0.clone()).unwrap()
     }
+    #[inline]
+    fn range<R: RangeBounds<KT>>(&self, range: R) -> DbXxxIter<KT> {
+        DbXxxIter::with_range(self.0.clone(), range).unwrap()
+    }
 }
 
 // impl trait: IntoIterator
@@ -0,0 +1,608 @@
+use super::super::{DbMapKeyType, DbXxxBase, Result};
+use super::{DbMapKeyKind, FileDb, FileDbMap, WriteBatch};
+use std::io::{BufRead, Error, ErrorKind, Write};
+
+/// Encoding of the bytes in the export.
+///
+/// it is used for the values and the keys of the bytes key db map. the keys of
+/// the string key db map are the strings, and the keys of the integer key db map
+/// are the numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BytesEncoding {
+    /// the utf-8 string as it is. the bytes that are not utf-8 are the error.
+    Utf8,
+    /// the lowercase hex string.
+    Hex,
+    /// the base64 string of the standard alphabet with the padding.
+    Base64,
+}
+
+/// Text format of the export and the import of a db map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// JSON Lines, a `{"key":...,"value":...}` object per line.
+    JsonLines(BytesEncoding),
+    /// CSV of RFC 4180, the header line `key,value` and a record per line.
+    Csv(BytesEncoding),
+}
+
+impl ExportFormat {
+    fn bytes_encoding(&self) -> BytesEncoding {
+        match self {
+            ExportFormat::JsonLines(enc) => *enc,
+            ExportFormat::Csv(enc) => *enc,
+        }
+    }
+}
+
+/// the field of a record in the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Num(u64),
+    Str(String),
+}
+
+pub(crate) fn export_map<W: Write>(
+    db: &FileDb,
+    name: &str,
+    writer: W,
+    format: ExportFormat,
+) -> Result<u64> {
+    match db_map_kind(db, name)? {
+        DbMapKeyKind::DbString => export_kt(&db.db_map_string(name)?, KeyKind::Str, writer, format),
+        DbMapKeyKind::DbInt => export_kt(&db.db_map_int(name)?, KeyKind::Num, writer, format),
+        DbMapKeyKind::DbBytes => export_kt(&db.db_map_bytes(name)?, KeyKind::Bytes, writer, format),
+    }
+}
+
+/// the bytes of the keys and the values in a write batch of the import.
+/// it bounds the memory and the pending pages of the write batch.
+const IMPORT_BATCH_BYTES: usize = 4 * 1024 * 1024;
+
+/// the records are put by the write batches of `IMPORT_BATCH_BYTES`.
+/// the db map is opened or created after the first batch is parsed.
+pub(crate) fn import_map<R: BufRead>(
+    db: &FileDb,
+    name: &str,
+    kind: DbMapKeyKind,
+    reader: R,
+    format: ExportFormat,
+) -> Result<u64> {
+    match kind {
+        DbMapKeyKind::DbString => import_kt(
+            || db.db_map_string(name),
+            RecordReader::new(reader, KeyKind::Str, format)?,
+        ),
+        DbMapKeyKind::DbInt => import_kt(
+            || db.db_map_int(name),
+            RecordReader::new(reader, KeyKind::Num, format)?,
+        ),
+        DbMapKeyKind::DbBytes => import_kt(
+            || db.db_map_bytes(name),
+            RecordReader::new(reader, KeyKind::Bytes, format)?,
+        ),
+    }
+}
+
+fn db_map_kind(db: &FileDb, name: &str) -> Result<DbMapKeyKind> {
+    match db.list_maps()?.into_iter().find(|(a, _)| a == name) {
+        Some((_, kind)) => Ok(kind),
+        None => Err(Error::new(ErrorKind::NotFound, format!("not found db map: {}", name)).into()),
+    }
+}
+
+/// the text of the key of the db map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyKind {
+    /// the string
+    Str,
+    /// the number of the big endian `u64`
+    Num,
+    /// the bytes of the bytes encoding
+    Bytes,
+}
+
+fn export_kt<KT: DbMapKeyType, W: Write>(
+    db_map: &FileDbMap<KT>,
+    key_kind: KeyKind,
+    mut writer: W,
+    format: ExportFormat,
+) -> Result<u64> {
+    let enc = format.bytes_encoding();
+    if let ExportFormat::Csv(_) = format {
+        writer.write_all(b"key,value\r\n")?;
+    }
+    let mut count = 0;
+    for r in db_map.try_iter() {
+        let (key, value) = r?;
+        let key = match key_kind {
+            KeyKind::Str => Field::Str(encode_bytes(key.as_bytes(), BytesEncoding::Utf8)?),
+            KeyKind::Num => match <[u8; 8]>::try_from(key.as_bytes()) {
+                Ok(ary) => Field::Num(u64::from_be_bytes(ary)),
+                Err(_) => {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "the key of the integer key db map is not 8 bytes",
+                    )
+                    .into())
+                }
+            },
+            KeyKind::Bytes => Field::Str(encode_bytes(key.as_bytes(), enc)?),
+        };
+        let value = Field::Str(encode_bytes(&value, enc)?);
+        let line = match format {
+            ExportFormat::JsonLines(_) => json_line(&key, &value),
+            ExportFormat::Csv(_) => csv_line(&key, &value),
+        };
+        writer.write_all(line.as_bytes())?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+fn import_kt<KT, R, F>(open: F, mut records: RecordReader<R>) -> Result<u64>
+where
+    KT: DbMapKeyType,
+    R: BufRead,
+    F: Fn() -> Result<FileDbMap<KT>>,
+{
+    let mut db_map = None;
+    let mut count = 0;
+    let mut is_end = false;
+    while !is_end {
+        let mut batch = WriteBatch::new();
+        let mut bytes = 0;
+        while bytes < IMPORT_BATCH_BYTES {
+            match records.next_record()? {
+                Some((key, value)) => {
+                    bytes += key.len() + value.len();
+                    batch.put_kt(KT::from_bytes(&key), value);
+                }
+                None => {
+                    is_end = true;
+                    break;
+                }
+            }
+        }
+        let db_map = match &mut db_map {
+            Some(db_map) => db_map,
+            None => db_map.insert(open()?),
+        };
+        count += batch.len() as u64;
+        db_map.write_batch(batch)?;
+        if is_end {
+            db_map.sync_all()?;
+        }
+    }
+    Ok(count)
+}
+
+/// the reader of the records of the text, the keys are the bytes of the db map key.
+struct RecordReader<R: BufRead> {
+    reader: R,
+    key_kind: KeyKind,
+    format: ExportFormat,
+    line_num: u64,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    /// the header line of the csv is checked.
+    fn new(mut reader: R, key_kind: KeyKind, format: ExportFormat) -> Result<Self> {
+        let mut line_num = 0;
+        if let ExportFormat::Csv(_) = format {
+            match read_line(&mut reader, &mut line_num)? {
+                Some(line) if trim_line_break(&line) == "key,value" => (),
+                _ => return Err(invalid_data(line_num, "the header is not `key,value`").into()),
+            }
+        }
+        Ok(Self {
+            reader,
+            key_kind,
+            format,
+            line_num,
+        })
+    }
+    fn next_record(&mut self) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+        let key_kind = self.key_kind;
+        let enc = self.format.bytes_encoding();
+        let (start_num, key, value) = loop {
+            let mut line = match read_line(&mut self.reader, &mut self.line_num)? {
+                Some(line) => line,
+                None => return Ok(None),
+            };
+            let start_num = self.line_num;
+            let (key, value) = match self.format {
+                ExportFormat::JsonLines(_) => {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    parse_json_line(&line).map_err(|msg| invalid_data(start_num, &msg))?
+                }
+                ExportFormat::Csv(_) => {
+                    if trim_line_break(&line).is_empty() {
+                        continue;
+                    }
+                    // the quoted field may have the line breaks.
+                    while line.matches('"').count() % 2 != 0 {
+                        match read_line(&mut self.reader, &mut self.line_num)? {
+                            Some(next) => line.push_str(&next),
+                            None => {
+                                return Err(
+                                    invalid_data(start_num, "the quote is not closed").into()
+                                )
+                            }
+                        }
+                    }
+                    parse_csv_line(trim_line_break(&line), key_kind)
+                        .map_err(|msg| invalid_data(start_num, &msg))?
+                }
+            };
+            break (start_num, key, value);
+        };
+        let key = match (key_kind, key) {
+            (KeyKind::Num, Field::Num(n)) => n.to_be_bytes().to_vec(),
+            (KeyKind::Str, Field::Str(s)) => s.into_bytes(),
+            (KeyKind::Bytes, Field::Str(s)) => {
+                decode_bytes(&s, enc).map_err(|msg| invalid_data(start_num, &msg))?
+            }
+            _ => return Err(invalid_data(start_num, "the key is not of the db map").into()),
+        };
+        let value = match value {
+            Field::Str(s) => decode_bytes(&s, enc).map_err(|msg| invalid_data(start_num, &msg))?,
+            Field::Num(_) => {
+                return Err(invalid_data(start_num, "the value is not a string").into())
+            }
+        };
+        Ok(Some((key, value)))
+    }
+}
+
+/// reads a line with the line break, that may be in the quoted field.
+fn read_line<R: BufRead>(reader: &mut R, line_num: &mut u64) -> std::io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    *line_num += 1;
+    Ok(Some(line))
+}
+
+fn trim_line_break(line: &str) -> &str {
+    match line.strip_suffix('\n') {
+        Some(a) => a.strip_suffix('\r').unwrap_or(a),
+        None => line,
+    }
+}
+
+fn invalid_data(line_num: u64, msg: &str) -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        format!("line {}: {}", line_num, msg),
+    )
+}
+
+// for bytes encoding
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn encode_bytes(bytes: &[u8], enc: BytesEncoding) -> std::io::Result<String> {
+    match enc {
+        BytesEncoding::Utf8 => match std::str::from_utf8(bytes) {
+            Ok(s) => Ok(s.to_string()),
+            Err(_) => Err(Error::new(
+                ErrorKind::InvalidData,
+                "the bytes are not utf-8, use the hex or the base64 encoding",
+            )),
+        },
+        BytesEncoding::Hex => Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect()),
+        BytesEncoding::Base64 => {
+            let mut s = String::with_capacity((bytes.len() + 2) / 3 * 4);
+            for chunk in bytes.chunks(3) {
+                let n = chunk
+                    .iter()
+                    .enumerate()
+                    .fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+                for i in 0..4 {
+                    if i <= chunk.len() {
+                        s.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+                    } else {
+                        s.push('=');
+                    }
+                }
+            }
+            Ok(s)
+        }
+    }
+}
+
+fn decode_bytes(s: &str, enc: BytesEncoding) -> std::result::Result<Vec<u8>, String> {
+    match enc {
+        BytesEncoding::Utf8 => Ok(s.as_bytes().to_vec()),
+        BytesEncoding::Hex => {
+            if s.len() % 2 != 0 {
+                return Err("the hex string is of the odd length".to_string());
+            }
+            (0..s.len())
+                .step_by(2)
+                .map(|i| {
+                    // `from_str_radix()` accepts the sign, such as `+f`.
+                    s.get(i..i + 2)
+                        .filter(|a| a.bytes().all(|c| c.is_ascii_hexdigit()))
+                        .and_then(|a| u8::from_str_radix(a, 16).ok())
+                        .ok_or_else(|| format!("invalid hex string: {}", s))
+                })
+                .collect()
+        }
+        BytesEncoding::Base64 => {
+            let b_sl = s.as_bytes();
+            if b_sl.len() % 4 != 0 {
+                return Err(format!("invalid base64 string: {}", s));
+            }
+            let mut vec = Vec::with_capacity(b_sl.len() / 4 * 3);
+            for (ci, chunk) in b_sl.chunks(4).enumerate() {
+                let is_last = ci == b_sl.len() / 4 - 1;
+                let pad = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+                if pad > 2 || (pad > 0 && !is_last) {
+                    return Err(format!("invalid base64 string: {}", s));
+                }
+                let mut n = 0u32;
+                for &c in &chunk[..4 - pad] {
+                    let v = match BASE64_CHARS.iter().position(|&a| a == c) {
+                        Some(v) => v as u32,
+                        None => return Err(format!("invalid base64 string: {}", s)),
+                    };
+                    n = n << 6 | v;
+                }
+                n <<= 6 * pad as u32;
+                let bytes = n.to_be_bytes();
+                vec.extend_from_slice(&bytes[1..4 - pad]);
+            }
+            Ok(vec)
+        }
+    }
+}
+
+// for JSON Lines
+
+fn json_line(key: &Field, value: &Field) -> String {
+    let mut s = String::from("{\"key\":");
+    json_field(&mut s, key);
+    s.push_str(",\"value\":");
+    json_field(&mut s, value);
+    s.push_str("}\n");
+    s
+}
+
+fn json_field(s: &mut String, field: &Field) {
+    match field {
+        Field::Num(n) => s.push_str(&n.to_string()),
+        Field::Str(a) => {
+            s.push('"');
+            for c in a.chars() {
+                match c {
+                    '"' => s.push_str("\\\""),
+                    '\\' => s.push_str("\\\\"),
+                    '\n' => s.push_str("\\n"),
+                    '\r' => s.push_str("\\r"),
+                    '\t' => s.push_str("\\t"),
+                    c if (c as u32) < 0x20 => s.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => s.push(c),
+                }
+            }
+            s.push('"');
+        }
+    }
+}
+
+/// parses the json object that has the fields of "key" and "value".
+fn parse_json_line(line: &str) -> std::result::Result<(Field, Field), String> {
+    let mut parser = JsonParser {
+        chars: line.chars().peekable(),
+    };
+    let mut key = None;
+    let mut value = None;
+    parser.expect('{')?;
+    loop {
+        let name = match parser.parse_field()? {
+            Field::Str(name) => name,
+            Field::Num(_) => return Err("the name of the field is not a string".to_string()),
+        };
+        parser.expect(':')?;
+        let field = parser.parse_field()?;
+        match name.as_str() {
+            "key" => key = Some(field),
+            "value" => value = Some(field),
+            _ => return Err(format!("unknown field: {}", name)),
+        }
+        match parser.next_token() {
+            Some(',') => (),
+            Some('}') => break,
+            _ => return Err("`,` or `}` is expected".to_string()),
+        }
+    }
+    if parser.next_token().is_some() {
+        return Err("the trailing characters".to_string());
+    }
+    match (key, value) {
+        (Some(key), Some(value)) => Ok((key, value)),
+        _ => Err("the field of `key` or `value` is not found".to_string()),
+    }
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> JsonParser<'a> {
+    fn skip_ws(&mut self) {
+        while let Some(c) = self.chars.peek() {
+            if !c.is_ascii_whitespace() {
+                break;
+            }
+            let _ = self.chars.next();
+        }
+    }
+    fn next_token(&mut self) -> Option<char> {
+        self.skip_ws();
+        self.chars.next()
+    }
+    fn expect(&mut self, c: char) -> std::result::Result<(), String> {
+        match self.next_token() {
+            Some(a) if a == c => Ok(()),
+            _ => Err(format!("`{}` is expected", c)),
+        }
+    }
+    /// parses the string or the unsigned integer.
+    fn parse_field(&mut self) -> std::result::Result<Field, String> {
+        self.skip_ws();
+        match self.chars.peek() {
+            Some('"') => {
+                let _ = self.chars.next();
+                self.parse_string().map(Field::Str)
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let mut s = String::new();
+                while let Some(&c) = self.chars.peek() {
+                    if !c.is_ascii_digit() {
+                        break;
+                    }
+                    s.push(c);
+                    let _ = self.chars.next();
+                }
+                s.parse::<u64>()
+                    .map(Field::Num)
+                    .map_err(|_| format!("invalid number: {}", s))
+            }
+            _ => Err("a string or a number is expected".to_string()),
+        }
+    }
+    fn parse_string(&mut self) -> std::result::Result<String, String> {
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                None => return Err("the string is not closed".to_string()),
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => {
+                        let hi = self.parse_hex4()?;
+                        let code = if (0xD800..0xDC00).contains(&hi) {
+                            if self.chars.next() != Some('\\') || self.chars.next() != Some('u') {
+                                return Err("the low surrogate is expected".to_string());
+                            }
+                            let lo = self.parse_hex4()?;
+                            if !(0xDC00..0xE000).contains(&lo) {
+                                return Err("the low surrogate is expected".to_string());
+                            }
+                            0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00)
+                        } else {
+                            hi
+                        };
+                        match char::from_u32(code) {
+                            Some(c) => s.push(c),
+                            None => return Err(format!("invalid unicode escape: {:x}", code)),
+                        }
+                    }
+                    _ => return Err("invalid escape".to_string()),
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+    fn parse_hex4(&mut self) -> std::result::Result<u32, String> {
+        let s: String = self.chars.by_ref().take(4).collect();
+        match u32::from_str_radix(&s, 16) {
+            Ok(n) if s.len() == 4 => Ok(n),
+            _ => Err(format!("invalid unicode escape: {}", s)),
+        }
+    }
+}
+
+// for CSV
+
+fn csv_line(key: &Field, value: &Field) -> String {
+    let mut s = String::new();
+    csv_field(&mut s, key);
+    s.push(',');
+    csv_field(&mut s, value);
+    s.push_str("\r\n");
+    s
+}
+
+fn csv_field(s: &mut String, field: &Field) {
+    match field {
+        Field::Num(n) => s.push_str(&n.to_string()),
+        Field::Str(a) => {
+            if a.contains([',', '"', '\r', '\n']) {
+                s.push('"');
+                s.push_str(&a.replace('"', "\"\""));
+                s.push('"');
+            } else {
+                s.push_str(a);
+            }
+        }
+    }
+}
+
+/// parses the csv record of the key and the value.
+/// the key of the integer key db map is the number.
+fn parse_csv_line(line: &str, key_kind: KeyKind) -> std::result::Result<(Field, Field), String> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut s = String::new();
+        if chars.peek() == Some(&'"') {
+            let _ = chars.next();
+            loop {
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        let _ = chars.next();
+                        s.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => s.push(c),
+                    None => return Err("the quote is not closed".to_string()),
+                }
+            }
+            if !matches!(chars.peek(), None | Some(',')) {
+                return Err("`,` is expected after the quoted field".to_string());
+            }
+        } else {
+            while let Some(&c) = chars.peek() {
+                if c == ',' {
+                    break;
+                }
+                if c == '"' {
+                    return Err("the quote in the field that is not quoted".to_string());
+                }
+                s.push(c);
+                let _ = chars.next();
+            }
+        }
+        fields.push(s);
+        if chars.next().is_none() {
+            break;
+        }
+    }
+    if fields.len() != 2 {
+        return Err(format!("2 fields are expected: {}", fields.len()));
+    }
+    let value = Field::Str(fields.pop().unwrap());
+    let key = fields.pop().unwrap();
+    let key = match key_kind {
+        KeyKind::Num => match key.parse::<u64>() {
+            Ok(n) => Field::Num(n),
+            Err(_) => return Err(format!("invalid number: {}", key)),
+        },
+        _ => Field::Str(key),
+    };
+    Ok((key, value))
+}
@@ -0,0 +1,123 @@
+use super::super::codec::EncryptionKey;
+use std::io::{Error, ErrorKind, Result};
+
+#[cfg(feature = "encryption")]
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+#[cfg(feature = "encryption")]
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+/// the bytes of the nonce at the front of the encrypted value.
+#[cfg(feature = "encryption")]
+const NONCE_LEN: usize = 24;
+
+/// the bytes of the key check in the header of the value file.
+pub(crate) const KEY_CHECK_LEN: usize = 16;
+
+/// the cipher of the values, XChaCha20-Poly1305.
+/// the encrypted value is the random nonce and the sealed value.
+/// the value is bound to its key and the flag of the encoding by the associated data.
+#[cfg(feature = "encryption")]
+pub(crate) struct ValueCipher(XChaCha20Poly1305);
+
+#[cfg(feature = "encryption")]
+impl ValueCipher {
+    pub fn new(key: &EncryptionKey) -> Result<Self> {
+        Ok(Self(XChaCha20Poly1305::new(key.as_bytes().into())))
+    }
+    /// the tag of the empty value with the zero nonce, to check the key on the opening.
+    pub fn key_check(&self) -> Result<[u8; KEY_CHECK_LEN]> {
+        let tag = self
+            .0
+            .encrypt(&XNonce::default(), [0u8; 0].as_ref())
+            .map_err(|_| Error::new(ErrorKind::Other, "the encryption failed"))?;
+        let mut buf = [0u8; KEY_CHECK_LEN];
+        buf.copy_from_slice(&tag);
+        Ok(buf)
+    }
+    pub fn encrypt(&self, value: &[u8], key: &[u8], is_encoded: bool) -> Result<Vec<u8>> {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce)
+            .map_err(|err| Error::new(ErrorKind::Other, err.to_string()))?;
+        let aad = associated_data(key, is_encoded);
+        let sealed = self
+            .0
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: value,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| Error::new(ErrorKind::Other, "the encryption failed"))?;
+        let mut data = Vec::with_capacity(NONCE_LEN + sealed.len());
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&sealed);
+        Ok(data)
+    }
+    pub fn decrypt(&self, data: &[u8], key: &[u8], is_encoded: bool) -> Result<Vec<u8>> {
+        if data.len() < NONCE_LEN {
+            return Err(decrypt_error());
+        }
+        let (nonce, sealed) = data.split_at(NONCE_LEN);
+        let aad = associated_data(key, is_encoded);
+        self.0
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| decrypt_error())
+    }
+}
+
+/// the associated data, the flag of the encoding and the key of the value.
+/// the key bytes are used, not the offset of the key piece, because the compaction moves the pieces.
+#[cfg(feature = "encryption")]
+fn associated_data(key: &[u8], is_encoded: bool) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(1 + key.len());
+    aad.push(is_encoded as u8);
+    aad.extend_from_slice(key);
+    aad
+}
+
+#[cfg(feature = "encryption")]
+fn decrypt_error() -> Error {
+    Error::new(ErrorKind::InvalidData, "the value can not be decrypted")
+}
+
+/// without the feature `encryption`, this can not be made.
+#[cfg(not(feature = "encryption"))]
+pub(crate) enum ValueCipher {}
+
+#[cfg(not(feature = "encryption"))]
+impl ValueCipher {
+    pub fn new(_key: &EncryptionKey) -> Result<Self> {
+        Err(unsupported_error())
+    }
+    pub fn key_check(&self) -> Result<[u8; KEY_CHECK_LEN]> {
+        match *self {}
+    }
+    pub fn encrypt(&self, _value: &[u8], _key: &[u8], _is_encoded: bool) -> Result<Vec<u8>> {
+        match *self {}
+    }
+    pub fn decrypt(&self, _data: &[u8], _key: &[u8], _is_encoded: bool) -> Result<Vec<u8>> {
+        match *self {}
+    }
+}
+
+/// the error of the encryption key without the feature `encryption`.
+#[cfg(not(feature = "encryption"))]
+pub(crate) fn unsupported_error() -> Error {
+    Error::new(
+        ErrorKind::Unsupported,
+        "the encryption of the values needs the feature `encryption`",
+    )
+}
+
+impl std::fmt::Debug for ValueCipher {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("ValueCipher")
+    }
+}
@@ -1,36 +1,60 @@
-use super::super::super::{DbMapKeyType, DbXxxBase, DbXxxObjectSafe};
+use super::super::super::{
+    DbMapKeyType, DbXxxBase, DbXxxObjectSafe, Error as DbError, Result as DbResult,
+};
+use super::super::cell::{Rc, RefCell};
 use super::super::{
-    CheckFileDbMap, CountOfPerSize, FileDbParams, KeysCountStats, LengthStats, RecordSizeStats,
+    CheckFileDbMap, CompactStats, CountOfPerSize, FileDbParams, KeysCountStats, LengthStats,
+    RecordSizeStats, RepairStats, VerifyProblem, VerifyProblemKind,
 };
+#[cfg(not(feature = "encryption"))]
+use super::crypt;
+use super::piece::FreeListError;
 use super::semtype::*;
 use super::tr::IdxNode;
+use super::vfile::SnapshotRef;
+use super::wal::{self, WalLock, WalRecord};
 use super::{idx, key, val};
 use std::borrow::Borrow;
-use std::cell::RefCell;
 use std::cmp::Ordering;
-use std::convert::TryInto;
-use std::io::Result;
-use std::path::Path;
-use std::rc::Rc;
+use std::collections::HashSet;
+use std::io::{Error, ErrorKind, Result};
+use std::ops::{Bound, RangeBounds};
+use std::path::{Path, PathBuf};
 
-#[cfg(all(
-    feature = "idx_find_uu",
-    any(feature = "vf_node_u32", feature = "vf_node_u64")
-))]
+#[cfg(feature = "idx_find_uu")]
 use rabuf::SmallRead;
 
 #[cfg(feature = "htx")]
 use super::htx;
 
+/// the max length of the key or the value.
+/// the piece size is `u32`, and it includes the header of the piece.
+const DAT_LEN_MAX: usize = u32::MAX as usize - 32;
+
 #[derive(Debug)]
 pub struct FileDbXxxInner<KT: DbMapKeyType> {
     dirty: bool,
+    /// the dirty flag in the file headers is set
+    dirty_flag: bool,
+    read_only: bool,
+    batching: bool,
+    /// the writing is rejected, because the db map is in a transaction,
+    /// or this is the view of the transaction that is finished
+    tx_locked: bool,
+    //
+    path: PathBuf,
+    name: String,
+    /// the params of the opening, for the view of the transaction
+    params: FileDbParams,
+    wal_lock: WalLock,
     //
     key_file: key::KeyFile<KT>,
     val_file: val::ValueFile,
     idx_file: idx::IdxFile,
     #[cfg(feature = "htx")]
-    htx_file: htx::HtxFile,
+    htx_file: Option<htx::HtxFile>,
+    /// the max slots of the node, that is in the idx file
+    node_slots: u16,
     //
     _phantom: std::marker::PhantomData<KT>,
 }
@@ -40,27 +64,1071 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
         path: P,
         ks_name: &str,
         params: FileDbParams,
+        read_only: bool,
+        wal_lock: WalLock,
     ) -> Result<FileDbXxxInner<KT>> {
-        let key_file = key::KeyFile::open_with_params(&path, ks_name, KT::signature(), &params)?;
-        let val_file = val::ValueFile::open_with_params(&path, ks_name, KT::signature(), &params)?;
-        let idx_file = idx::IdxFile::open_with_params(&path, ks_name, KT::signature(), &params)?;
+        finish_compaction(path.as_ref(), ks_name, read_only)?;
+        let (mut r, htx_is_new) = Self::open_files(path, ks_name, params, read_only, wal_lock)?;
+        if r.read_dirty_flag()? {
+            // the last writing was not synced.
+            if read_only {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "the db map needs to be recovered, open it as writable: {}",
+                        ks_name
+                    ),
+                ));
+            }
+            r.recover()?;
+        }
+        #[cfg(feature = "htx")]
+        if htx_is_new {
+            // the htx is turned on for the existing db map.
+            let (_node_vec, key_vec) = r.idx_file.used_offsets()?;
+            if !key_vec.is_empty() {
+                r.rebuild_htx(&key_vec)?;
+                r.sync_files(false)?;
+            }
+        }
+        #[cfg(not(feature = "htx"))]
+        let _ = htx_is_new;
+        Ok(r)
+    }
+    /// opens the files of the db map, without the recovery.
+    /// returns the db map, and whether the htx file is created.
+    fn open_files<P: AsRef<Path>>(
+        path: P,
+        ks_name: &str,
+        params: FileDbParams,
+        read_only: bool,
+        wal_lock: WalLock,
+    ) -> Result<(FileDbXxxInner<KT>, bool)> {
+        // no file is created with the key that can not be used.
+        #[cfg(not(feature = "encryption"))]
+        if params.encryption_key.is_some() {
+            return Err(crypt::unsupported_error());
+        }
+        idx::node_slots_of_params(&params)?;
+        let sig2 = KT::signature();
+        let key_file = key::KeyFile::open_with_params(&path, ks_name, sig2, &params, read_only)?;
+        let val_file = val::ValueFile::open_with_params(&path, ks_name, sig2, &params, read_only)?;
+        let idx_file = idx::IdxFile::open_with_params(&path, ks_name, sig2, &params, read_only)?;
         #[cfg(feature = "htx")]
-        let htx_file = htx::HtxFile::open_with_params(&path, ks_name, KT::signature(), &params)?;
+        let (htx_file, htx_is_new) = open_htx_file(&path, ks_name, sig2, &params, read_only)?;
+        #[cfg(not(feature = "htx"))]
+        let htx_is_new = false;
         //
-        Ok(Self {
+        let node_slots = idx_file.node_slots();
+        let r = Self {
             key_file,
             val_file,
             idx_file,
             #[cfg(feature = "htx")]
             htx_file,
+            node_slots,
             dirty: false,
+            dirty_flag: false,
+            read_only,
+            batching: false,
+            tx_locked: false,
+            path: path.as_ref().to_path_buf(),
+            name: ks_name.to_string(),
+            params,
+            wal_lock,
             _phantom: std::marker::PhantomData,
-        })
+        };
+        Ok((r, htx_is_new))
     }
     #[inline]
     pub fn is_dirty(&self) -> bool {
         self.dirty
     }
+    #[inline]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    #[inline]
+    pub(crate) fn wal_lock(&self) -> WalLock {
+        self.wal_lock.clone()
+    }
+    #[inline]
+    pub(crate) fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+    #[inline]
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            Err(DbError::ReadOnly(self.path.clone()).into())
+        } else if self.tx_locked {
+            Err(Error::new(
+                ErrorKind::WouldBlock,
+                format!("the db map is locked by a transaction: {}", self.name),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// for transaction
+impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
+    /// opens the view of a transaction, that is the db map on the same files
+    /// with its own pending pages. this is locked against the writing until
+    /// the end of the transaction, and the reading of this sees the files,
+    /// that are not touched by the view.
+    pub(crate) fn begin_tx(&mut self) -> Result<Self> {
+        self.check_writable()?;
+        if self.batching {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "the database map is already in a batch",
+            ));
+        }
+        // the view reads the files.
+        self.sync_files(false)?;
+        self.clear_dirty_flag(false)?;
+        self.dirty = false;
+        let mut view = Self::open_with_params(
+            &self.path,
+            &self.name,
+            self.params.clone(),
+            false,
+            self.wal_lock.clone(),
+        )?;
+        view.begin_batch()?;
+        self.tx_locked = true;
+        Ok(view)
+    }
+    /// the view that the committed records are applied takes the place of this.
+    /// this goes into the view, that is left locked for the stale handles of it.
+    pub(crate) fn commit_tx(&mut self, view: &mut Self) {
+        debug_assert!(self.tx_locked && !view.batching);
+        std::mem::swap(self, view);
+    }
+    /// the work that is deferred until the write-ahead log is removed.
+    /// the htx grows here, outside of the pending pages, with the dirty flag.
+    pub(crate) fn after_commit(&mut self) -> Result<()> {
+        #[cfg(feature = "htx")]
+        if let Some(htx_file) = &self.htx_file {
+            if htx_file.grow_size()?.is_some() {
+                self.set_dirty_flag()?;
+                self.grow_htx()?;
+            }
+        }
+        Ok(())
+    }
+    /// discards the view of the transaction, and unlocks this.
+    pub(crate) fn rollback_tx(&mut self, view: &mut Self) -> Result<()> {
+        view.tx_locked = true;
+        self.tx_locked = false;
+        view.rollback_batch()
+    }
+}
+
+// for write batch
+impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
+    /// applies the puts and the deletes all-or-nothing.
+    /// in a transaction, these are a part of the transaction.
+    /// the caller holds the lock of the write-ahead log.
+    pub(crate) fn write_batch(&mut self, ops: &[(KT, Option<Vec<u8>>)]) -> Result<()> {
+        if self.batching {
+            return self.put_and_del_batch(ops);
+        }
+        self.begin_batch()?;
+        if let Err(err) = self.put_and_del_batch(ops) {
+            self.rollback_batch()?;
+            return Err(err);
+        }
+        self.commit_batch()
+    }
+    /// writes the batch to the write-ahead log, and then applies it to the files.
+    /// the batch is rolled back if the log is not written.
+    fn commit_batch(&mut self) -> Result<()> {
+        let records = match self.take_batch_records() {
+            Ok(records) => records,
+            Err(err) => {
+                self.rollback_batch()?;
+                return Err(err);
+            }
+        };
+        if let Err(err) = wal::write_wal_file(&self.path, &records) {
+            self.rollback_batch()?;
+            return Err(err);
+        }
+        self.apply_batch_records(&records)?;
+        wal::remove_wal_file(&self.path)?;
+        self.after_commit()
+    }
+    fn put_and_del_batch(&mut self, ops: &[(KT, Option<Vec<u8>>)]) -> Result<()> {
+        for (key_kt, opt_value) in ops {
+            match opt_value {
+                Some(value) => self.put_kt(key_kt, value)?,
+                None => {
+                    let _ = self.del_kt(key_kt)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// starts the batch. the files are synced and then the writing is kept in memory.
+    pub(crate) fn begin_batch(&mut self) -> Result<()> {
+        self.check_writable()?;
+        if self.batching {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "the database map is already in a batch",
+            ));
+        }
+        // the base of the write-ahead log must be on the disk.
+        self.sync_files(false)?;
+        self.clear_dirty_flag(false)?;
+        self.dirty = false;
+        //
+        self.val_file.begin_pending()?;
+        self.key_file.begin_pending()?;
+        self.idx_file.begin_pending()?;
+        #[cfg(feature = "htx")]
+        if let Some(htx_file) = &self.htx_file {
+            htx_file.begin_pending()?;
+        }
+        self.batching = true;
+        Ok(())
+    }
+    /// discards the writing in the batch.
+    pub(crate) fn rollback_batch(&mut self) -> Result<()> {
+        let _ = self.val_file.take_pending()?;
+        let _ = self.key_file.take_pending()?;
+        self.idx_file.rollback_pending()?;
+        #[cfg(feature = "htx")]
+        if let Some(htx_file) = &self.htx_file {
+            htx_file.rollback_pending()?;
+        }
+        self.batching = false;
+        self.dirty = false;
+        Ok(())
+    }
+    /// takes the writing in the batch as the records of the write-ahead log.
+    pub(crate) fn take_batch_records(&mut self) -> Result<Vec<WalRecord>> {
+        self.batching = false;
+        let mut records = Vec::new();
+        let mut push = |ext: &str, opt: Option<wal::PendingPages>| {
+            if let Some(pages) = opt {
+                if !pages.is_empty() {
+                    let file_name = format!("{}.{}", self.name, ext);
+                    records.push(WalRecord::new(file_name, pages));
+                }
+            }
+        };
+        push("val", self.val_file.take_pending()?);
+        push("key", self.key_file.take_pending()?);
+        push("idx", self.idx_file.take_pending()?);
+        #[cfg(feature = "htx")]
+        if let Some(htx_file) = &self.htx_file {
+            push("htx", htx_file.take_pending()?);
+        }
+        Ok(records)
+    }
+    /// writes the buffers and the node cache to the files, and starts the snapshots
+    /// of the files for the backup. returns the snapshots with the extensions.
+    pub(crate) fn begin_snapshot(&mut self) -> Result<Vec<(&'static str, SnapshotRef)>> {
+        if self.batching {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "the database map is in a batch",
+            ));
+        }
+        // the header of the new db map is in the buffer, even if it is not dirty.
+        self.sync_files(false)?;
+        self.clear_dirty_flag(false)?;
+        self.dirty = false;
+        let mut vec = vec![
+            ("val", self.val_file.begin_snapshot()?),
+            ("key", self.key_file.begin_snapshot()?),
+            ("idx", self.idx_file.begin_snapshot()?),
+        ];
+        #[cfg(feature = "htx")]
+        if let Some(htx_file) = &self.htx_file {
+            vec.push(("htx", htx_file.begin_snapshot()?));
+        }
+        Ok(vec)
+    }
+    /// the view of the transaction takes the snapshots of the backups from this,
+    /// before the committed records are applied to the files by it.
+    pub(crate) fn copy_snapshots_to(&self, view: &mut Self) {
+        view.val_file.set_snapshots(self.val_file.snapshots());
+        view.key_file.set_snapshots(self.key_file.snapshots());
+        view.idx_file.set_snapshots(self.idx_file.snapshots());
+        #[cfg(feature = "htx")]
+        if let (Some(htx_file), Some(view_htx_file)) = (&self.htx_file, &view.htx_file) {
+            view_htx_file.set_snapshots(htx_file.snapshots());
+        }
+    }
+    /// writes the records of this db map to the files, and syncs them.
+    pub(crate) fn apply_batch_records(&mut self, records: &[WalRecord]) -> Result<()> {
+        for rec in records {
+            let ext = match rec.file_name.strip_prefix(self.name.as_str()) {
+                Some(ext) => ext,
+                None => continue,
+            };
+            match ext {
+                ".val" => self.val_file.apply_pending(&rec.pages)?,
+                ".key" => self.key_file.apply_pending(&rec.pages)?,
+                ".idx" => self.idx_file.apply_pending(&rec.pages)?,
+                #[cfg(feature = "htx")]
+                ".htx" => {
+                    if let Some(htx_file) = &self.htx_file {
+                        htx_file.apply_pending(&rec.pages)?;
+                    }
+                }
+                _ => (),
+            }
+        }
+        self.sync_files(false)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+// for crash recovery
+impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
+    /// reads the dirty flag in the file headers.
+    /// if it is set, the last writing was not synced.
+    fn read_dirty_flag(&self) -> Result<bool> {
+        #[cfg(feature = "htx")]
+        let htx_flag = match &self.htx_file {
+            Some(htx_file) => htx_file.read_dirty_flag()?,
+            None => false,
+        };
+        #[cfg(not(feature = "htx"))]
+        let htx_flag = false;
+        Ok(self.val_file.read_dirty_flag()?
+            || self.key_file.read_dirty_flag()?
+            || self.idx_file.read_dirty_flag()?
+            || htx_flag)
+    }
+    /// sets the dirty flag on the first writing.
+    /// the batch does not set it, because it is written through the write-ahead log.
+    fn set_dirty_flag(&mut self) -> Result<()> {
+        if self.dirty_flag || self.batching {
+            return Ok(());
+        }
+        self.val_file.write_dirty_flag(true)?;
+        self.key_file.write_dirty_flag(true)?;
+        self.idx_file.write_dirty_flag(true)?;
+        #[cfg(feature = "htx")]
+        if let Some(htx_file) = &self.htx_file {
+            htx_file.write_dirty_flag(true)?;
+        }
+        self.dirty_flag = true;
+        Ok(())
+    }
+    /// clears the dirty flag, after the files are synced.
+    fn clear_dirty_flag(&mut self, data_only: bool) -> Result<()> {
+        if !self.dirty_flag {
+            return Ok(());
+        }
+        self.val_file.write_dirty_flag(false)?;
+        self.key_file.write_dirty_flag(false)?;
+        self.idx_file.write_dirty_flag(false)?;
+        #[cfg(feature = "htx")]
+        if let Some(htx_file) = &self.htx_file {
+            htx_file.write_dirty_flag(false)?;
+        }
+        self.sync_files(data_only)?;
+        self.dirty_flag = false;
+        Ok(())
+    }
+    fn sync_files(&self, data_only: bool) -> Result<()> {
+        if data_only {
+            self.val_file.sync_data()?;
+            self.key_file.sync_data()?;
+            self.idx_file.sync_data()?;
+            #[cfg(feature = "htx")]
+            if let Some(htx_file) = &self.htx_file {
+                htx_file.sync_data()?;
+            }
+        } else {
+            self.val_file.sync_all()?;
+            self.key_file.sync_all()?;
+            self.idx_file.sync_all()?;
+            #[cfg(feature = "htx")]
+            if let Some(htx_file) = &self.htx_file {
+                htx_file.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+    /// recovers the db map that the last writing was not synced.
+    ///
+    /// the index tree is validated, and then the free lists are rebuilt from
+    /// the nodes and the pieces that are reachable from the tree.
+    /// the orphaned pieces become free. the htx is rebuilt too.
+    fn recover(&mut self) -> Result<()> {
+        let top_node = self.idx_file.read_top_node()?;
+        if !self.idx_file.is_balanced(&top_node)? || !self.idx_file.is_mst_valid(&top_node, self)? {
+            return Err(DbError::Corruption {
+                file: self.path.join(format!("{}.idx", self.name)),
+                offset: top_node.get_ref().offset().as_value(),
+                reason: "the index of the db map is broken".to_string(),
+            }
+            .into());
+        }
+        let (node_vec, key_vec) = self.idx_file.used_offsets()?;
+        let mut value_set = HashSet::with_capacity(key_vec.len());
+        for &key_offset in key_vec.iter() {
+            let value_offset = self.key_file.read_piece_only_value_offset(key_offset)?;
+            let _ = value_set.insert(value_offset.as_value());
+        }
+        let key_set: HashSet<u64> = key_vec.iter().map(|a| a.as_value()).collect();
+        let node_set: HashSet<u64> = node_vec.iter().map(|a| a.as_value()).collect();
+        self.val_file.rebuild_free_piece_list(&value_set)?;
+        self.key_file.rebuild_free_piece_list(&key_set)?;
+        self.idx_file.rebuild_free_piece_list(&node_set)?;
+        #[cfg(feature = "htx")]
+        self.rebuild_htx(&key_vec)?;
+        self.sync_files(false)?;
+        self.dirty_flag = true;
+        self.clear_dirty_flag(false)
+    }
+}
+
+// for compaction
+impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
+    /// moves the live pieces and nodes to the front of the files, and
+    /// truncates the files. the offsets in the key pieces and the nodes are
+    /// updated, and the htx is rebuilt.
+    ///
+    /// the files are copied to the temporary names, and the copy is compacted,
+    /// and then it is renamed into place. the compaction is not written through
+    /// the write-ahead log, so the db map of any size can be compacted.
+    /// the caller holds the lock of the write-ahead log.
+    pub(crate) fn compact(&mut self) -> Result<CompactStats> {
+        self.check_writable()?;
+        if self.batching {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "the database map is in a batch",
+            ));
+        }
+        let free_count: u64 = [
+            self.key_file.count_of_free_key_piece()?,
+            self.val_file.count_of_free_value_piece()?,
+            self.idx_file.count_of_free_node()?,
+        ]
+        .iter()
+        .flatten()
+        .map(|&(_, cnt)| cnt)
+        .sum();
+        if free_count == 0 {
+            // nothing to reclaim, the free pieces are all on the free lists.
+            return Ok(CompactStats::default());
+        }
+        // the copy is made from the files that are synced.
+        self.sync_files(false)?;
+        self.clear_dirty_flag(false)?;
+        self.dirty = false;
+        let name = self.name.clone();
+        // the db map name can not have `..`, so this is not the name of the other db map.
+        let tmp_name = format!("{}..compact", name);
+        // the files of the interrupted compaction.
+        remove_compact_files(&self.path, &tmp_name)?;
+        let (mut r, (key_bytes, value_bytes, node_bytes)) =
+            match self.build_compacted_files(&tmp_name) {
+                Ok(r) => r,
+                Err(err) => {
+                    let _ = remove_compact_files(&self.path, &tmp_name);
+                    return Err(err);
+                }
+            };
+        // the old files are closed, and the compacted files are used.
+        r.wal_lock = self.wal_lock.clone();
+        std::mem::swap(self, &mut r);
+        drop(r);
+        self.set_name(&name);
+        // the mark that the compacted files are complete. after it, the interrupted
+        // compaction is finished on the next open.
+        let done_path = self.path.join(format!("{}.done", tmp_name));
+        std::fs::File::create(&done_path)?.sync_all()?;
+        finish_compaction(&self.path, &name, false)?;
+        Ok(CompactStats {
+            free_count,
+            key_bytes,
+            value_bytes,
+            node_bytes,
+        })
+    }
+    /// copies the files to `tmp_name`, and compacts the copy.
+    /// returns the db map of the copy, and the bytes reclaimed.
+    #[allow(clippy::type_complexity)]
+    fn build_compacted_files(&self, tmp_name: &str) -> Result<(Self, (u64, u64, u64))> {
+        for ext in super::DB_MAP_FILE_EXTS {
+            let file_path = self.path.join(format!("{}.{}", self.name, ext));
+            if file_path.exists() {
+                let _ = std::fs::copy(&file_path, self.path.join(format!("{}.{}", tmp_name, ext)))?;
+            }
+        }
+        let mut r = Self::open_with_params(
+            &self.path,
+            tmp_name,
+            self.params.clone(),
+            false,
+            // the copy does not write the log.
+            WalLock::default(),
+        )?;
+        let bytes = r.compact_pieces()?;
+        r.sync_files(false)?;
+        Ok((r, bytes))
+    }
+    /// returns the bytes reclaimed of the key file, the value file and the idx file.
+    fn compact_pieces(&mut self) -> Result<(u64, u64, u64)> {
+        let (node_vec, key_vec) = self.idx_file.used_offsets()?;
+        // the values
+        let mut value_vec = Vec::with_capacity(key_vec.len());
+        for &key_offset in key_vec.iter() {
+            let value_offset = self.key_file.read_piece_only_value_offset(key_offset)?;
+            value_vec.push(value_offset);
+        }
+        let value_set: HashSet<u64> = value_vec.iter().map(|a| a.as_value()).collect();
+        let (value_moved_map, value_bytes) = self.val_file.compact(&value_set)?;
+        for (&key_offset, &value_offset) in key_vec.iter().zip(value_vec.iter()) {
+            if let Some(&new_offset) = value_moved_map.get(&value_offset.as_value()) {
+                let mut piece = self.key_file.read_piece(key_offset)?;
+                piece.value_offset = ValuePieceOffset::new(new_offset);
+                // the size of the piece does not grow, it is over written.
+                let _piece = self.key_file.write_piece(piece)?;
+                debug_assert!(_piece.offset == key_offset);
+            }
+        }
+        // the keys
+        let key_set: HashSet<u64> = key_vec.iter().map(|a| a.as_value()).collect();
+        let (key_moved_map, key_bytes) = self.key_file.compact(&key_set)?;
+        // the nodes
+        let node_set: HashSet<u64> = node_vec.iter().map(|a| a.as_value()).collect();
+        let (_node_moved_map, node_bytes) = self.idx_file.compact(&node_set, &key_moved_map)?;
+        //
+        #[cfg(feature = "htx")]
+        if !key_moved_map.is_empty() {
+            let key_vec: Vec<KeyPieceOffset> = key_vec
+                .iter()
+                .map(|a| {
+                    let offset = a.as_value();
+                    KeyPieceOffset::new(*key_moved_map.get(&offset).unwrap_or(&offset))
+                })
+                .collect();
+            self.rebuild_htx(&key_vec)?;
+        }
+        Ok((key_bytes, value_bytes, node_bytes))
+    }
+}
+
+// for verify
+impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
+    /// opens the db map as read only, and verifies it. the db map that was not
+    /// synced is not recovered, it is reported as the problem.
+    pub(crate) fn open_and_verify<P: AsRef<Path>>(
+        path: P,
+        ks_name: &str,
+        params: FileDbParams,
+    ) -> Result<Vec<VerifyProblem>> {
+        let (r, _htx_is_new) = Self::open_files(path, ks_name, params, true, WalLock::default())?;
+        let mut problems = Vec::new();
+        if r.read_dirty_flag()? {
+            problems.push(VerifyProblem {
+                map: r.name.clone(),
+                file: r.path.join(format!("{}.key", r.name)),
+                offset: 0,
+                kind: VerifyProblemKind::NotSynced,
+                message: "the last writing was not synced, the db map is recovered on the opening"
+                    .to_string(),
+            });
+        }
+        problems.extend(r.verify()?);
+        Ok(problems)
+    }
+    /// verifies the files of the db map. the nodes and the key pieces that are
+    /// referenced in the tree, the value offsets of the key pieces, the free
+    /// piece lists and the htx entries are checked.
+    pub(crate) fn verify(&self) -> Result<Vec<VerifyProblem>> {
+        let file_path = |ext: &str| self.path.join(format!("{}.{}", self.name, ext));
+        let (idx_path, key_path, val_path) = (file_path("idx"), file_path("key"), file_path("val"));
+        let mut problems = Vec::new();
+        let mut push = |file: &PathBuf, offset: u64, kind, message: String| {
+            problems.push(VerifyProblem {
+                map: self.name.clone(),
+                file: file.clone(),
+                offset,
+                kind,
+                message,
+            })
+        };
+        let (node_sizes, node_free_set, node_free_errors) = self.idx_file.scan_for_verify()?;
+        let (key_sizes, key_free_set, key_free_errors) = self.key_file.scan_for_verify()?;
+        let (value_sizes, value_free_set, value_free_errors) = self.val_file.scan_for_verify()?;
+        // the tree
+        let mut node_set = HashSet::new();
+        let mut key_set = HashSet::new();
+        let mut tree_is_read = true;
+        let top_node_offset = self.idx_file.read_top_node()?.get_ref().offset();
+        let mut stack = vec![top_node_offset.as_value()];
+        while let Some(node_offset) = stack.pop() {
+            if !node_sizes.contains_key(&node_offset) {
+                push(
+                    &idx_path,
+                    node_offset,
+                    VerifyProblemKind::MissingNode,
+                    "the node is not found on the node boundary".to_string(),
+                );
+                tree_is_read = false;
+                continue;
+            }
+            if !node_set.insert(node_offset) {
+                push(
+                    &idx_path,
+                    node_offset,
+                    VerifyProblemKind::BrokenTree,
+                    "the node is referenced twice".to_string(),
+                );
+                tree_is_read = false;
+                continue;
+            }
+            let node_ = match self.idx_file.read_node(NodePieceOffset::new(node_offset)) {
+                Ok(node_) => node_,
+                Err(err) => {
+                    push(
+                        &idx_path,
+                        node_offset,
+                        VerifyProblemKind::Unreadable,
+                        err.to_string(),
+                    );
+                    tree_is_read = false;
+                    continue;
+                }
+            };
+            let node = node_.get_ref();
+            for i in 0..node.keys_len() {
+                #[cfg(not(feature = "tr_has_short_key"))]
+                let key_offset = node.keys_get(i);
+                #[cfg(feature = "tr_has_short_key")]
+                let (key_offset, _short_key) = node.keys_get(i);
+                //
+                if key_offset.is_zero() {
+                    continue;
+                }
+                if key_sizes.contains_key(&key_offset.as_value()) {
+                    let _ = key_set.insert(key_offset.as_value());
+                } else {
+                    push(
+                        &idx_path,
+                        node_offset,
+                        VerifyProblemKind::MissingKeyPiece,
+                        format!("the key piece is not found: {}", key_offset),
+                    );
+                    tree_is_read = false;
+                }
+            }
+            for i in 0..node.downs_len() {
+                let down_offset = node.downs_get(i);
+                if !down_offset.is_zero() {
+                    stack.push(down_offset.as_value());
+                }
+            }
+        }
+        // the order of the keys is checked on the tree that is read all.
+        if tree_is_read {
+            let top_node = self.idx_file.read_top_node()?;
+            if !self.idx_file.is_balanced(&top_node)? {
+                push(
+                    &idx_path,
+                    top_node_offset.as_value(),
+                    VerifyProblemKind::BrokenTree,
+                    "the index tree is not balanced".to_string(),
+                );
+            } else {
+                match self.idx_file.is_mst_valid(&top_node, self) {
+                    Ok(true) => (),
+                    Ok(false) => push(
+                        &idx_path,
+                        top_node_offset.as_value(),
+                        VerifyProblemKind::BrokenTree,
+                        "the keys of the index tree are not in order".to_string(),
+                    ),
+                    Err(err) => push(
+                        &idx_path,
+                        top_node_offset.as_value(),
+                        VerifyProblemKind::Unreadable,
+                        format!("the keys of the index tree can not be read: {}", err),
+                    ),
+                }
+            }
+        }
+        // the values
+        let mut value_set = HashSet::new();
+        let mut key_vec: Vec<u64> = key_set.iter().copied().collect();
+        key_vec.sort_unstable();
+        for key_offset in key_vec {
+            let value_offset = match self
+                .key_file
+                .read_piece_only_value_offset(KeyPieceOffset::new(key_offset))
+            {
+                Ok(value_offset) => value_offset,
+                Err(err) => {
+                    push(
+                        &key_path,
+                        key_offset,
+                        VerifyProblemKind::Unreadable,
+                        err.to_string(),
+                    );
+                    continue;
+                }
+            };
+            if value_sizes.contains_key(&value_offset.as_value()) {
+                let _ = value_set.insert(value_offset.as_value());
+            } else {
+                push(
+                    &key_path,
+                    key_offset,
+                    VerifyProblemKind::BadValueOffset,
+                    format!("the value piece is not found: {}", value_offset),
+                );
+            }
+        }
+        // the free piece lists
+        let free_lists = [
+            (&idx_path, &node_free_set, &node_free_errors, &node_set),
+            (&key_path, &key_free_set, &key_free_errors, &key_set),
+            (&val_path, &value_free_set, &value_free_errors, &value_set),
+        ];
+        for (path, free_set, free_errors, used_set) in free_lists {
+            for &(offset, err) in free_errors.iter() {
+                let (kind, message) = match err {
+                    FreeListError::Cycle => (
+                        VerifyProblemKind::FreeListCycle,
+                        "the free list goes back to the free piece".to_string(),
+                    ),
+                    FreeListError::NotOnBoundary => (
+                        VerifyProblemKind::BadFreePiece,
+                        "the free piece is not on the piece boundary".to_string(),
+                    ),
+                    FreeListError::SizeMismatch(list_size, piece_size) => (
+                        VerifyProblemKind::BadFreePiece,
+                        format!(
+                            "the free piece of {} bytes is on the list of {} bytes",
+                            piece_size, list_size
+                        ),
+                    ),
+                };
+                push(path, offset, kind, message);
+            }
+            let mut live_vec: Vec<u64> = free_set.intersection(used_set).copied().collect();
+            live_vec.sort_unstable();
+            for offset in live_vec {
+                push(
+                    path,
+                    offset,
+                    VerifyProblemKind::LiveFreePiece,
+                    "the used piece is on the free list".to_string(),
+                );
+            }
+        }
+        // the htx
+        #[cfg(feature = "htx")]
+        if let Some(htx_file) = &self.htx_file {
+            let htx_path = file_path("htx");
+            for (slot_offset, key_offset) in htx_file.used_slots()? {
+                if !key_set.contains(&key_offset.as_value()) {
+                    push(
+                        &htx_path,
+                        slot_offset,
+                        VerifyProblemKind::BadHtxEntry,
+                        format!("the key piece is not in the db map: {}", key_offset),
+                    );
+                }
+            }
+        }
+        Ok(problems)
+    }
+}
+
+// for repair
+impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
+    /// rebuilds the index tree and the htx of the db map from the key file.
+    ///
+    /// the new idx file and htx file are built under the temporary names, with
+    /// the key file and the value file opened as read only. they are renamed
+    /// into place only after the all key pieces are read, and then the free
+    /// piece lists of the key file and the value file are rebuilt.
+    ///
+    /// the used key pieces are inserted into the new tree and the htx in the
+    /// order of the offset. the key piece that its value piece is not found, or that
+    /// its key is already inserted, is not recovered and becomes free.
+    /// only the encryption key and the node slots of the params are used.
+    pub(crate) fn repair<P: AsRef<Path>>(
+        path: P,
+        ks_name: &str,
+        params: FileDbParams,
+    ) -> Result<RepairStats> {
+        let path = path.as_ref();
+        // the db map name can not have `..`, so this is not the name of the other db map.
+        let tmp_name = format!("{}..repair", ks_name);
+        let remove_tmp_files = || -> Result<()> {
+            for ext in ["htx", "idx"] {
+                match std::fs::remove_file(path.join(format!("{}.{}", tmp_name, ext))) {
+                    Ok(()) => (),
+                    Err(err) if err.kind() == ErrorKind::NotFound => (),
+                    Err(err) => return Err(err),
+                }
+            }
+            Ok(())
+        };
+        // the files of the interrupted repair.
+        remove_tmp_files()?;
+        let mut params = FileDbParams {
+            encryption_key: params.encryption_key,
+            node_slots: params.node_slots,
+            ..Default::default()
+        };
+        let r = Self::build_repaired_files(path, ks_name, &tmp_name, &mut params);
+        let (stats, key_set, value_set) = match r {
+            Ok(r) => r,
+            Err(err) => {
+                let _ = remove_tmp_files();
+                return Err(err);
+            }
+        };
+        for ext in ["htx", "idx"] {
+            let tmp_path = path.join(format!("{}.{}", tmp_name, ext));
+            let file_path = path.join(format!("{}.{}", ks_name, ext));
+            if tmp_path.exists() {
+                std::fs::rename(&tmp_path, &file_path)?;
+            } else {
+                match std::fs::remove_file(&file_path) {
+                    Ok(()) => (),
+                    Err(err) if err.kind() == ErrorKind::NotFound => (),
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        // the free piece lists
+        let mut r = Self::open_with_params(path, ks_name, params, false, WalLock::default())?;
+        r.set_dirty_flag()?;
+        r.val_file.rebuild_free_piece_list(&value_set)?;
+        r.key_file.rebuild_free_piece_list(&key_set)?;
+        r.sync_files(false)?;
+        r.clear_dirty_flag(false)?;
+        Ok(stats)
+    }
+    /// builds the idx file and the htx file of `tmp_name` from the key file.
+    /// returns the stats, and the offsets of the used key pieces and value pieces.
+    #[allow(clippy::type_complexity)]
+    fn build_repaired_files(
+        path: &Path,
+        ks_name: &str,
+        tmp_name: &str,
+        params: &mut FileDbParams,
+    ) -> Result<(RepairStats, HashSet<u64>, HashSet<u64>)> {
+        let sig2 = KT::signature();
+        let key_file = key::KeyFile::open_with_params(path, ks_name, sig2, params, true)?;
+        // the new idx file has the same format as the key file.
+        params.checksum = key_file.has_checksum();
+        let val_file = val::ValueFile::open_with_params(path, ks_name, sig2, params, true)?;
+        let idx_file = idx::IdxFile::open_with_params(path, tmp_name, sig2, params, false)?;
+        #[cfg(feature = "htx")]
+        let (htx_file, _htx_is_new) = open_htx_file(path, tmp_name, sig2, params, false)?;
+        //
+        let node_slots = idx_file.node_slots();
+        let mut r = Self {
+            key_file,
+            val_file,
+            idx_file,
+            #[cfg(feature = "htx")]
+            htx_file,
+            node_slots,
+            dirty: false,
+            dirty_flag: false,
+            read_only: false,
+            batching: false,
+            tx_locked: false,
+            path: path.to_path_buf(),
+            name: ks_name.to_string(),
+            params: params.clone(),
+            // the repair does not write the log.
+            wal_lock: WalLock::default(),
+            _phantom: std::marker::PhantomData,
+        };
+        //
+        let (value_sizes, _value_free_set, _value_free_errors) = r.val_file.scan_for_verify()?;
+        let mut stats = RepairStats::default();
+        let mut key_set = HashSet::new();
+        let mut value_set = HashSet::new();
+        for key_offset in r.key_file.used_pieces()? {
+            let value_offset = r.key_file.read_piece_only_value_offset(key_offset)?;
+            if !value_sizes.contains_key(&value_offset.as_value()) {
+                stats.lost_count += 1;
+                continue;
+            }
+            let key_kt = r.load_key_data(key_offset)?;
+            let top_node = r.idx_file.read_top_node()?;
+            match r.insert_key_piece_into_node_tree_kt(top_node, &key_kt, key_offset)? {
+                Some(active_node) => {
+                    let new_top_node = active_node.deactivate();
+                    r.idx_file.write_top_node(new_top_node)?;
+                }
+                None => {
+                    stats.lost_count += 1;
+                    continue;
+                }
+            }
+            #[cfg(feature = "htx")]
+            {
+                r.htx_write_key_piece_offset(key_kt.hash_value(), key_offset)?;
+                r.grow_htx()?;
+            }
+            let _ = key_set.insert(key_offset.as_value());
+            let _ = value_set.insert(value_offset.as_value());
+            stats.key_count += 1;
+        }
+        r.idx_file.sync_all()?;
+        #[cfg(feature = "htx")]
+        if let Some(htx_file) = &r.htx_file {
+            htx_file.sync_all()?;
+        }
+        Ok((stats, key_set, value_set))
+    }
+    /// inserts the key piece that is already in the key file. returns `None`
+    /// if the key is already in the tree, then the tree is not changed.
+    fn insert_key_piece_into_node_tree_kt(
+        &mut self,
+        mut node_: IdxNode,
+        key_kt: &KT,
+        key_offset: KeyPieceOffset,
+    ) -> Result<Option<IdxNode>> {
+        let r = {
+            let node = node_.get_ref();
+            if node.keys_is_empty() {
+                #[cfg(not(feature = "tr_has_short_key"))]
+                let new_active_node = IdxNode::new_active(
+                    key_offset,
+                    NodePieceOffset::new(0),
+                    NodePieceOffset::new(0),
+                );
+                #[cfg(feature = "tr_has_short_key")]
+                let new_active_node = IdxNode::new_active(
+                    key_offset,
+                    NodePieceOffset::new(0),
+                    NodePieceOffset::new(0),
+                    key_kt.as_short_bytes().map(|o| o.to_vec()),
+                );
+                return Ok(Some(new_active_node));
+            }
+            #[cfg(not(feature = "tr_has_short_key"))]
+            let r = self.keys_binary_search_kt(node.keys(), key_kt)?;
+            #[cfg(feature = "tr_has_short_key")]
+            let r = self.keys_binary_search_kt(node.keys(), node.short_keys(), key_kt)?;
+            //
+            r
+        };
+        let k = match r {
+            Ok(_k) => return Ok(None),
+            Err(k) => k,
+        };
+        let node_offset1 = node_.get_ref().downs_get(k);
+        let node2_ = if !node_offset1.is_zero() {
+            let node1_ = self.idx_file.read_node(node_offset1)?;
+            match self.insert_key_piece_into_node_tree_kt(node1_, key_kt, key_offset)? {
+                Some(node2_) => node2_,
+                None => return Ok(None),
+            }
+        } else {
+            #[cfg(not(feature = "tr_has_short_key"))]
+            let new_active_node =
+                IdxNode::new_active(key_offset, NodePieceOffset::new(0), NodePieceOffset::new(0));
+            #[cfg(feature = "tr_has_short_key")]
+            let new_active_node = IdxNode::new_active(
+                key_offset,
+                NodePieceOffset::new(0),
+                NodePieceOffset::new(0),
+                key_kt.as_short_bytes().map(|o| o.to_vec()),
+            );
+            //
+            new_active_node
+        };
+        if !node2_.is_active_on_insert() {
+            debug_assert!(!node2_.get_ref().offset().is_zero());
+            let node2_ = self.write_node(node2_)?;
+            node_.get_mut().downs_set(k, node2_.get_ref().offset());
+            Ok(Some(self.write_node(node_)?))
+        } else {
+            Ok(Some(self.balance_on_insert(node_, k, &node2_)?))
+        }
+    }
+}
+
+// for htx
+#[cfg(feature = "htx")]
+impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
+    /// doubles the htx when its filling rate crosses the threshold,
+    /// and rebuilds it from the tree walk.
+    /// in the batch, it is deferred until the commit, because the writing is
+    /// kept in memory. the batch may add many keys, so the size is doubled
+    /// until all the keys are under the threshold.
+    fn grow_htx(&mut self) -> Result<()> {
+        if self.batching {
+            return Ok(());
+        }
+        let need_grow = match &self.htx_file {
+            Some(htx_file) => htx_file.grow_size()?.is_some(),
+            None => false,
+        };
+        if !need_grow {
+            return Ok(());
+        }
+        let (_node_vec, key_vec) = self.idx_file.used_offsets()?;
+        if let Some(htx_file) = &self.htx_file {
+            let ht_size = htx_file.size_for_count(key_vec.len() as u64);
+            htx_file.set_hash_table_size(ht_size)?;
+        }
+        self.rebuild_htx(&key_vec)
+    }
+    /// rebuilds the htx from the key pieces.
+    fn rebuild_htx(&self, key_vec: &[KeyPieceOffset]) -> Result<()> {
+        let htx_file = match &self.htx_file {
+            Some(htx_file) => htx_file,
+            None => return Ok(()),
+        };
+        htx_file.clear_hash_table()?;
+        for &key_offset in key_vec.iter() {
+            let hash = self.load_key_data(key_offset)?.hash_value();
+            htx_file.write_key_piece_offset(hash, key_offset)?;
+        }
+        Ok(())
+    }
+    /// the key piece offset in the htx. it is zero if the htx is not used.
+    #[inline]
+    fn htx_read_key_piece_offset(&self, hash: u64) -> Result<KeyPieceOffset> {
+        match &self.htx_file {
+            Some(htx_file) => htx_file.read_key_piece_offset(hash),
+            None => Ok(KeyPieceOffset::new(0)),
+        }
+    }
+    #[inline]
+    fn htx_write_key_piece_offset(&self, hash: u64, offset: KeyPieceOffset) -> Result<()> {
+        match &self.htx_file {
+            Some(htx_file) => htx_file.write_key_piece_offset(hash, offset),
+            None => Ok(()),
+        }
+    }
+    #[cfg(feature = "htx_print_hits")]
+    #[inline]
+    fn htx_set_hits(&self, is_hit: bool) {
+        if let Some(htx_file) = &self.htx_file {
+            if is_hit {
+                htx_file.set_hits();
+            } else {
+                htx_file.set_miss();
+            }
+        }
+    }
 }
 
 // for utils
@@ -80,10 +1148,11 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
     }
     //
     #[inline]
-    fn load_value(&self, piece_offset: KeyPieceOffset) -> Result<Vec<u8>> {
+    fn load_value(&self, piece_offset: KeyPieceOffset, key_kt: &KT) -> Result<Vec<u8>> {
         debug_assert!(!piece_offset.is_zero());
         let value_offset = self.key_file.read_piece_only_value_offset(piece_offset)?;
-        self.val_file.read_piece_only_value(value_offset)
+        self.val_file
+            .read_piece_only_value(value_offset, key_kt.as_bytes())
     }
     #[inline]
     fn load_value_piece_size(&self, piece_offset: KeyPieceOffset) -> Result<ValuePieceSize> {
@@ -96,25 +1165,25 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
         self.val_file.read_piece_only_value_length(value_offset)
     }
 
-    #[cfg(all(
-        feature = "idx_find_uu",
-        any(feature = "vf_node_u32", feature = "vf_node_u64")
-    ))]
+    /// the binary search on the node in the file, the offsets in the node are
+    /// the fixed width integer of `offset_len` bytes.
+    #[cfg(feature = "idx_find_uu")]
     fn keys_binary_search_uu_kt(
-        &mut self,
+        &self,
         node_offset: NodePieceOffset,
         key_kt: &KT,
+        offset_len: u32,
     ) -> Result<std::result::Result<KeyPieceOffset, NodePieceOffset>> {
-        #[cfg(feature = "vf_node_u32")]
-        const OFFSET_BYTE_SIZE: u32 = 4;
-        #[cfg(feature = "vf_node_u64")]
-        const OFFSET_BYTE_SIZE: u32 = 8;
-        //
         let mut locked_key = self.key_file.0.borrow_mut();
         let mut locked_idx = self.idx_file.0.borrow_mut();
         //
         let _ = locked_idx.0.seek_from_start(node_offset)?;
-        let _ = locked_idx.0.read_node_size()?;
+        let node_size = locked_idx.0.read_node_size()?;
+        if locked_idx.0.has_checksum() {
+            locked_idx.0.check_piece_checksum(node_offset, node_size)?;
+            let _ = locked_idx.0.seek_from_start(node_offset)?;
+            let _ = locked_idx.0.read_node_size()?;
+        }
         let is_leaf = locked_idx.0.read_u16_le()?;
         let keys_count = locked_idx.0.read_keys_count()?;
         if keys_count.is_zero() {
@@ -132,11 +1201,8 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
             //let key_offset = node.keys[mid];
             let _ = locked_idx
                 .0
-                .seek_from_start(keys_start + NodePieceSize::new(OFFSET_BYTE_SIZE * mid))?;
-            #[cfg(feature = "vf_node_u32")]
-            let key_offset: KeyPieceOffset = locked_idx.0.read_piece_offset_u32()?;
-            #[cfg(feature = "vf_node_u64")]
-            let key_offset: KeyPieceOffset = locked_idx.0.read_piece_offset_u64()?;
+                .seek_from_start(keys_start + NodePieceSize::new(offset_len * mid))?;
+            let key_offset: KeyPieceOffset = locked_idx.0.read_node_piece_offset()?;
             //
             debug_assert!(!key_offset.is_zero());
             let key_string = locked_key.read_piece_only_key_maybeslice(key_offset)?;
@@ -150,12 +1216,9 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
         }
         if is_leaf == 0 {
             let _ = locked_idx.0.seek_from_start(
-                keys_start + NodePieceSize::new(OFFSET_BYTE_SIZE * (keys_count + left)),
+                keys_start + NodePieceSize::new(offset_len * (keys_count + left)),
             )?;
-            #[cfg(feature = "vf_node_u32")]
-            let node_offset = locked_idx.0.read_node_offset_u32()?;
-            #[cfg(feature = "vf_node_u64")]
-            let node_offset = locked_idx.0.read_node_offset_u64()?;
+            let node_offset = locked_idx.0.read_node_offset()?;
             Ok(Err(node_offset))
         } else {
             Ok(Err(NodePieceOffset::new(0)))
@@ -164,7 +1227,7 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
     //
     #[cfg(not(feature = "tr_has_short_key"))]
     fn keys_binary_search_kt(
-        &mut self,
+        &self,
         keys: &[KeyPieceOffset],
         key_kt: &KT,
     ) -> Result<std::result::Result<usize, usize>> {
@@ -195,7 +1258,7 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
     }
     #[cfg(feature = "tr_has_short_key")]
     fn keys_binary_search_kt(
-        &mut self,
+        &self,
         keys: &[KeyPieceOffset],
         short_keys: &[Option<Vec<u8>>],
         key_kt: &KT,
@@ -258,56 +1321,59 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
 // for debug
 impl<KT: DbMapKeyType + std::fmt::Display> CheckFileDbMap for FileDbXxxInner<KT> {
     #[cfg(feature = "htx")]
-    fn ht_size_and_count(&self) -> Result<(u64, u64)> {
-        self.htx_file.ht_size_and_count()
+    fn ht_size_and_count(&self) -> DbResult<(u64, u64)> {
+        match &self.htx_file {
+            Some(htx_file) => Ok(htx_file.ht_size_and_count()?),
+            None => Ok((0, 0)),
+        }
     }
     /// convert the index node tree to graph string for debug.
-    fn graph_string(&self) -> Result<String> {
-        self.idx_file.graph_string()
+    fn graph_string(&self) -> DbResult<String> {
+        Ok(self.idx_file.graph_string()?)
     }
     /// convert the index node tree to graph string for debug.
-    fn graph_string_with_key_string(&self) -> Result<String> {
-        self.idx_file.graph_string_with_key_string(self)
+    fn graph_string_with_key_string(&self) -> DbResult<String> {
+        Ok(self.idx_file.graph_string_with_key_string(self)?)
     }
     /// check the index node tree is balanced
-    fn is_balanced(&self) -> Result<bool> {
+    fn is_balanced(&self) -> DbResult<bool> {
         let top_node = self.idx_file.read_top_node()?;
-        self.idx_file.is_balanced(&top_node)
+        Ok(self.idx_file.is_balanced(&top_node)?)
     }
     /// check the index node tree is multi search tree
-    fn is_mst_valid(&self) -> Result<bool> {
+    fn is_mst_valid(&self) -> DbResult<bool> {
         let top_node = self.idx_file.read_top_node()?;
-        self.idx_file.is_mst_valid(&top_node, self)
+        Ok(self.idx_file.is_mst_valid(&top_node, self)?)
     }
     /// check the index node except the root and leaves of the tree has branches of hm or more.
-    fn is_dense(&self) -> Result<bool> {
+    fn is_dense(&self) -> DbResult<bool> {
         let top_node = self.idx_file.read_top_node()?;
-        self.idx_file.is_dense(&top_node)
+        Ok(self.idx_file.is_dense(&top_node)?)
     }
     /// get the depth of the index node
-    fn depth_of_node_tree(&self) -> Result<u64> {
+    fn depth_of_node_tree(&self) -> DbResult<u64> {
         let top_node = self.idx_file.read_top_node()?;
-        self.idx_file.depth_of_node_tree(&top_node)
+        Ok(self.idx_file.depth_of_node_tree(&top_node)?)
     }
     /// count of the free node
-    fn count_of_free_node(&self) -> Result<CountOfPerSize> {
-        self.idx_file.count_of_free_node()
+    fn count_of_free_node(&self) -> DbResult<CountOfPerSize> {
+        Ok(self.idx_file.count_of_free_node()?)
     }
     /// count of the free key piece
-    fn count_of_free_key_piece(&self) -> Result<CountOfPerSize> {
-        self.key_file.count_of_free_key_piece()
+    fn count_of_free_key_piece(&self) -> DbResult<CountOfPerSize> {
+        Ok(self.key_file.count_of_free_key_piece()?)
     }
     /// count of the free value piece
-    fn count_of_free_value_piece(&self) -> Result<CountOfPerSize> {
-        self.val_file.count_of_free_value_piece()
+    fn count_of_free_value_piece(&self) -> DbResult<CountOfPerSize> {
+        Ok(self.val_file.count_of_free_value_piece()?)
     }
     /// count of the used piece and the used node
-    fn count_of_used_node(&self) -> Result<(CountOfPerSize, CountOfPerSize, CountOfPerSize)> {
-        self.idx_file.count_of_used_node(|off| {
+    fn count_of_used_node(&self) -> DbResult<(CountOfPerSize, CountOfPerSize, CountOfPerSize)> {
+        Ok(self.idx_file.count_of_used_node(|off| {
             let ks = self.load_key_piece_size(off)?;
             let vs = self.load_value_piece_size(off)?;
             Ok((ks, vs))
-        })
+        })?)
     }
     /// buffer statistics
     #[cfg(feature = "buf_stats")]
@@ -318,31 +1384,38 @@ impl<KT: DbMapKeyType + std::fmt::Display> CheckFileDbMap for FileDbXxxInner<KT>
         vec
     }
     /// piece size statistics
-    fn key_piece_size_stats(&self) -> Result<RecordSizeStats<Key>> {
-        self.idx_file
-            .piece_size_stats(|off| self.load_key_piece_size(off))
+    fn key_piece_size_stats(&self) -> DbResult<RecordSizeStats<Key>> {
+        Ok(self
+            .idx_file
+            .piece_size_stats(|off| self.load_key_piece_size(off))?)
     }
-    fn value_piece_size_stats(&self) -> Result<RecordSizeStats<Value>> {
-        self.idx_file
-            .piece_size_stats(|off| self.load_value_piece_size(off))
+    fn value_piece_size_stats(&self) -> DbResult<RecordSizeStats<Value>> {
+        Ok(self
+            .idx_file
+            .piece_size_stats(|off| self.load_value_piece_size(off))?)
     }
     /// keys count statistics
-    fn keys_count_stats(&self) -> Result<KeysCountStats> {
-        self.idx_file.keys_count_stats()
+    fn keys_count_stats(&self) -> DbResult<KeysCountStats> {
+        Ok(self.idx_file.keys_count_stats()?)
     }
     /// key length statistics
-    fn key_length_stats(&self) -> Result<LengthStats<Key>> {
-        self.idx_file
-            .length_stats::<Key, _>(|off| self.load_key_length(off))
+    fn key_length_stats(&self) -> DbResult<LengthStats<Key>> {
+        Ok(self
+            .idx_file
+            .length_stats::<Key, _>(|off| self.load_key_length(off))?)
     }
     /// value length statistics
-    fn value_length_stats(&self) -> Result<LengthStats<Value>> {
-        self.idx_file
-            .length_stats::<Value, _>(|off| self.load_value_length(off))
+    fn value_length_stats(&self) -> DbResult<LengthStats<Value>> {
+        Ok(self
+            .idx_file
+            .length_stats::<Value, _>(|off| self.load_value_length(off))?)
     }
     #[cfg(feature = "htx")]
-    fn htx_filling_rate_per_mill(&self) -> Result<(u64, u32)> {
-        self.htx_file.htx_filling_rate_per_mill()
+    fn htx_filling_rate_per_mill(&self) -> DbResult<(u64, u32)> {
+        match &self.htx_file {
+            Some(htx_file) => Ok(htx_file.htx_filling_rate_per_mill()?),
+            None => Ok((0, 0)),
+        }
     }
 }
 
@@ -390,7 +1463,7 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
                     #[cfg(feature = "htx")]
                     {
                         let hash = key_kt.hash_value();
-                        self.htx_file.write_key_piece_offset(hash, new_key_offset)?;
+                        self.htx_write_key_piece_offset(hash, new_key_offset)?;
                     }
                     #[cfg(not(feature = "tr_has_short_key"))]
                     node_.get_mut().keys_set(k, new_key_offset);
@@ -412,14 +1485,13 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
                     let node1_ = self.idx_file.read_node(node_offset1)?;
                     self.insert_into_node_tree_kt(node1_, key_kt, value)?
                 } else {
-                    let new_val_piece = self.val_file.add_value_piece(value)?;
+                    let new_val_piece = self.val_file.add_value_piece(value, key_kt.as_bytes())?;
                     let new_key_piece =
                         self.key_file.add_key_piece(key_kt, new_val_piece.offset)?;
                     #[cfg(feature = "htx")]
                     {
                         let hash = key_kt.hash_value();
-                        self.htx_file
-                            .write_key_piece_offset(hash, new_key_piece.offset)?;
+                        self.htx_write_key_piece_offset(hash, new_key_piece.offset)?;
                     }
                     //
                     #[cfg(not(feature = "tr_has_short_key"))]
@@ -450,13 +1522,13 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
         }
     }
     fn keys_is_empty_on_insert_(&mut self, key_kt: &KT, value: &[u8]) -> Result<IdxNode> {
-        let new_val_piece = self.val_file.add_value_piece(value)?;
+        let new_val_piece = self.val_file.add_value_piece(value, key_kt.as_bytes())?;
         let new_key_piece = self.key_file.add_key_piece(key_kt, new_val_piece.offset)?;
         #[cfg(feature = "htx")]
         {
             let off = new_key_piece.offset;
             let hash = new_key_piece.hash_value();
-            self.htx_file.write_key_piece_offset(hash, off)?;
+            self.htx_write_key_piece_offset(hash, off)?;
         }
         //
         #[cfg(not(feature = "tr_has_short_key"))]
@@ -482,9 +1554,10 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
         value: &[u8],
     ) -> Result<KeyPieceOffset> {
         let mut key_piece = self.key_file.read_piece(piece_offset)?;
-        let mut val_piece = self.val_file.read_piece(key_piece.value_offset)?;
+        let key = key_piece.key.as_bytes();
+        let mut val_piece = self.val_file.read_piece(key_piece.value_offset, key)?;
         val_piece.value = value.to_vec();
-        let new_value_piece = self.val_file.write_piece(val_piece)?;
+        let new_value_piece = self.val_file.write_piece(val_piece, key)?;
         let new_key_piece = if key_piece.value_offset == new_value_piece.offset {
             key_piece
         } else {
@@ -538,7 +1611,7 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
             node.keys_insert(i, key_0, short_key_0.map(|o| o.to_vec()));
         }
         //
-        if !node_.borrow().is_over_len() {
+        if !node_.borrow().is_over_len(self.node_slots) {
             let node = self.write_node(node_)?;
             Ok(node)
         } else {
@@ -547,24 +1620,21 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
     }
     #[inline]
     fn split_on_insert(&mut self, mut node_: IdxNode) -> Result<IdxNode> {
-        debug_assert!(node_.get_ref().keys_len() == idx::NODE_SLOTS_MAX as usize);
-        debug_assert!(node_.get_ref().downs_len() == idx::NODE_SLOTS_MAX as usize + 1);
-        debug_assert!(node_.get_ref().keys_len() >= idx::NODE_SLOTS_MAX_HALF as usize);
-        debug_assert!(node_.get_ref().downs_len() >= idx::NODE_SLOTS_MAX_HALF as usize);
+        let node_slots_half = (self.node_slots / 2) as usize;
+        debug_assert!(node_.get_ref().keys_len() == self.node_slots as usize);
+        debug_assert!(node_.get_ref().downs_len() == self.node_slots as usize + 1);
+        debug_assert!(node_.get_ref().keys_len() >= node_slots_half);
+        debug_assert!(node_.get_ref().downs_len() >= node_slots_half);
         let mut node1_ = IdxNode::new_empty();
         {
             let mut node1 = node1_.get_mut();
             let node = node_.get_ref();
-            node1.keys_downs_extend_from_node(&node, idx::NODE_SLOTS_MAX_HALF as usize);
+            node1.keys_downs_extend_from_node(&node, node_slots_half);
         }
         #[cfg(not(feature = "tr_has_short_key"))]
-        let key_offset1 = node_
-            .get_mut()
-            .keys_downs_resize(idx::NODE_SLOTS_MAX_HALF as usize);
+        let key_offset1 = node_.get_mut().keys_downs_resize(node_slots_half);
         #[cfg(feature = "tr_has_short_key")]
-        let (key_offset1, short_key1) = node_
-            .get_mut()
-            .keys_downs_resize(idx::NODE_SLOTS_MAX_HALF as usize);
+        let (key_offset1, short_key1) = node_.get_mut().keys_downs_resize(node_slots_half);
         //
         let node1_ = self.write_new_node(node1_)?;
         let node_ = self.write_node(node_)?;
@@ -640,12 +1710,11 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
             #[cfg(feature = "htx")]
             {
                 let hash = key_piece.key.hash_value();
-                self.htx_file
-                    .write_key_piece_offset(hash, KeyPieceOffset::new(0))?;
+                self.htx_write_key_piece_offset(hash, KeyPieceOffset::new(0))?;
             }
             let value = self
                 .val_file
-                .read_piece_only_value(key_piece.value_offset)?;
+                .read_piece_only_value(key_piece.value_offset, key_piece.key.as_bytes())?;
             self.val_file.delete_piece(key_piece.value_offset)?;
             self.key_file.delete_piece(key_offset)?;
             Some(value)
@@ -724,7 +1793,7 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
             return Ok(node_);
         }
         let mut node1_ = self.idx_file.read_node(node_offset1)?;
-        if !node1_.is_active_on_delete() {
+        if !node1_.is_active_on_delete(self.node_slots) {
             return Ok(node_);
         }
         let j = i + 1;
@@ -739,7 +1808,7 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
         debug_assert!(!node_offset2.is_zero());
         if !node_offset2.is_zero() {
             let mut node2_ = self.idx_file.read_node(node_offset2)?;
-            if node2_.get_ref().downs_len() == idx::NODE_SLOTS_MAX_HALF as usize {
+            if node2_.get_ref().downs_len() == (self.node_slots / 2) as usize {
                 // unification
                 #[cfg(not(feature = "tr_has_short_key"))]
                 node1_.get_mut().keys_push(key_offset2);
@@ -792,7 +1861,7 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
             return Ok(node_);
         }
         let mut node1_ = self.idx_file.read_node(node_offset1)?;
-        if !node1_.is_active_on_delete() {
+        if !node1_.is_active_on_delete(self.node_slots) {
             return Ok(node_);
         }
         let i = j - 1;
@@ -806,7 +1875,7 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
         debug_assert!(!node_offset2.is_zero());
         if !node_offset2.is_zero() {
             let mut node2_ = self.idx_file.read_node(node_offset2)?;
-            if node2_.get_ref().downs_len() == idx::NODE_SLOTS_MAX_HALF as usize {
+            if node2_.get_ref().downs_len() == (self.node_slots / 2) as usize {
                 // unification
                 #[cfg(not(feature = "tr_has_short_key"))]
                 node2_.get_mut().keys_push(key_offset2);
@@ -929,35 +1998,29 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
 
 // find: NEW
 impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
-    #[cfg(all(
-        feature = "idx_find_uu",
-        any(feature = "vf_node_u32", feature = "vf_node_u64")
-    ))]
+    #[cfg(feature = "idx_find_uu")]
     fn find_in_node_tree_uu_kt(
-        &mut self,
+        &self,
         node_offset: NodePieceOffset,
         key_kt: &KT,
+        offset_len: u32,
     ) -> Result<Option<Vec<u8>>> {
-        let r = self.keys_binary_search_uu_kt(node_offset, key_kt)?;
+        let r = self.keys_binary_search_uu_kt(node_offset, key_kt, offset_len)?;
         match r {
             Ok(key_offset) => {
                 debug_assert!(!key_offset.is_zero());
-                self.load_value(key_offset).map(Some)
+                self.load_value(key_offset, key_kt).map(Some)
             }
             Err(node_offset) => {
                 if !node_offset.is_zero() {
-                    self.find_in_node_tree_uu_kt(node_offset, key_kt)
+                    self.find_in_node_tree_uu_kt(node_offset, key_kt, offset_len)
                 } else {
                     Ok(None)
                 }
             }
         }
     }
-    #[cfg(not(all(
-        feature = "idx_find_uu",
-        any(feature = "vf_node_u32", feature = "vf_node_u64")
-    )))]
-    fn find_in_node_tree_kt(&mut self, node_: IdxNode, key_kt: &KT) -> Result<Option<Vec<u8>>> {
+    fn find_in_node_tree_kt(&self, node_: IdxNode, key_kt: &KT) -> Result<Option<Vec<u8>>> {
         let r = {
             let node = node_.get_ref();
             #[cfg(not(feature = "tr_has_short_key"))]
@@ -984,7 +2047,7 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
                 let (key_offset, _short_key) = unsafe { node_.get_ref().keys_get_unchecked(k) };
                 //
                 debug_assert!(!key_offset.is_zero());
-                self.load_value(key_offset).map(Some)
+                self.load_value(key_offset, key_kt).map(Some)
             }
             Err(k) => {
                 #[cfg(feature = "siamese_debug")]
@@ -1001,54 +2064,96 @@ impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
             }
         }
     }
+    /// finds the value of the key. this reads the files under their own locks,
+    /// so the readers of the db map can call this at the same time.
+    pub(crate) fn find_kt(&self, key_kt: &KT) -> Result<Option<Vec<u8>>> {
+        #[cfg(feature = "htx")]
+        {
+            let hash = key_kt.hash_value();
+            let key_offset = self.htx_read_key_piece_offset(hash)?;
+            if !key_offset.is_zero() {
+                let flg = {
+                    let mut locked_key = self.key_file.0.borrow_mut();
+                    let key_string = locked_key.read_piece_only_key_maybeslice(key_offset)?;
+                    match key_kt.cmp_u8(&key_string) {
+                        Ordering::Equal => true,
+                        Ordering::Greater => false,
+                        Ordering::Less => false,
+                    }
+                };
+                if flg {
+                    #[cfg(feature = "htx_print_hits")]
+                    self.htx_set_hits(true);
+                    return Ok(Some(self.load_value(key_offset, key_kt)?));
+                } else {
+                    #[cfg(feature = "htx_print_hits")]
+                    self.htx_set_hits(false);
+                }
+            }
+        }
+        // the nodes in the file are searched, if the offsets in them are fixed width.
+        #[cfg(feature = "idx_find_uu")]
+        if let Some(offset_len) = self.idx_file.node_offset_len() {
+            #[cfg(feature = "node_cache")]
+            {
+                let mut locked_idx = RefCell::borrow_mut(&self.idx_file.0);
+                locked_idx.flush_node_cache_clear()?
+            }
+            let node_offset = {
+                let mut locked_idx = self.idx_file.0.borrow_mut();
+                locked_idx.0.read_top_node_offset()?
+            };
+            return self.find_in_node_tree_uu_kt(node_offset, key_kt, offset_len);
+        }
+        let top_node = self.idx_file.read_top_node()?;
+        self.find_in_node_tree_kt(top_node, key_kt)
+    }
 }
 
 // impl trait: DbXxxBase
 impl<KT: DbMapKeyType> DbXxxBase for FileDbXxxInner<KT> {
     #[inline]
-    fn read_fill_buffer(&mut self) -> Result<()> {
+    fn read_fill_buffer(&mut self) -> DbResult<()> {
         self.val_file.read_fill_buffer()?;
         self.key_file.read_fill_buffer()?;
         self.idx_file.read_fill_buffer()?;
         #[cfg(feature = "htx")]
-        self.htx_file.read_fill_buffer()?;
+        if let Some(htx_file) = &self.htx_file {
+            htx_file.read_fill_buffer()?;
+        }
         Ok(())
     }
     #[inline]
-    fn flush(&mut self) -> Result<()> {
+    fn flush(&mut self) -> DbResult<()> {
         if self.is_dirty() {
             // save all data
             self.val_file.flush()?;
             self.key_file.flush()?;
             self.idx_file.flush()?;
             #[cfg(feature = "htx")]
-            self.htx_file.flush()?;
+            if let Some(htx_file) = &self.htx_file {
+                htx_file.flush()?;
+            }
             self.dirty = false;
         }
         Ok(())
     }
     #[inline]
-    fn sync_all(&mut self) -> Result<()> {
-        if self.is_dirty() {
+    fn sync_all(&mut self) -> DbResult<()> {
+        if self.is_dirty() || self.dirty_flag {
             // save all data and meta
-            self.val_file.sync_all()?;
-            self.key_file.sync_all()?;
-            self.idx_file.sync_all()?;
-            #[cfg(feature = "htx")]
-            self.htx_file.sync_all()?;
+            self.sync_files(false)?;
+            self.clear_dirty_flag(false)?;
             self.dirty = false;
         }
         Ok(())
     }
     #[inline]
-    fn sync_data(&mut self) -> Result<()> {
-        if self.is_dirty() {
+    fn sync_data(&mut self) -> DbResult<()> {
+        if self.is_dirty() || self.dirty_flag {
             // save all data
-            self.val_file.sync_data()?;
-            self.key_file.sync_data()?;
-            self.idx_file.sync_data()?;
-            #[cfg(feature = "htx")]
-            self.htx_file.sync_data()?;
+            self.sync_files(true)?;
+            self.clear_dirty_flag(true)?;
             self.dirty = false;
         }
         Ok(())
@@ -1057,86 +2162,20 @@ impl<KT: DbMapKeyType> DbXxxBase for FileDbXxxInner<KT> {
 
 // impl trait: DbXxxObjectSafe<KT>
 impl<KT: DbMapKeyType> DbXxxObjectSafe<KT> for FileDbXxxInner<KT> {
-    #[cfg(all(
-        feature = "idx_find_uu",
-        any(feature = "vf_node_u32", feature = "vf_node_u64")
-    ))]
-    #[inline]
-    fn get_kt(&mut self, key_kt: &KT) -> Result<Option<Vec<u8>>> {
-        #[cfg(feature = "htx")]
-        {
-            let hash = key_kt.hash_value();
-            let key_offset = self.htx_file.read_key_piece_offset(hash)?;
-            if !key_offset.is_zero() {
-                let flg = {
-                    let mut locked_key = self.key_file.0.borrow_mut();
-                    let key_string = locked_key.read_piece_only_key_maybeslice(key_offset)?;
-                    match key_kt.cmp_u8(&key_string) {
-                        Ordering::Equal => true,
-                        Ordering::Greater => false,
-                        Ordering::Less => false,
-                    }
-                };
-                if flg {
-                    #[cfg(feature = "htx_print_hits")]
-                    self.htx_file.set_hits();
-                    return self.load_value(key_offset).map(Some);
-                } else {
-                    #[cfg(feature = "htx_print_hits")]
-                    self.htx_file.set_miss();
-                }
-            }
-        }
-        #[cfg(feature = "node_cache")]
-        {
-            let mut locked_idx = RefCell::borrow_mut(&self.idx_file.0);
-            locked_idx.flush_node_cache_clear()?
-        }
-        let node_offset = {
-            let mut locked_idx = self.idx_file.0.borrow_mut();
-            locked_idx.0.read_top_node_offset()?
-        };
-        self.find_in_node_tree_uu_kt(node_offset, key_kt)
-    }
-    #[cfg(not(all(
-        feature = "idx_find_uu",
-        any(feature = "vf_node_u32", feature = "vf_node_u64")
-    )))]
     #[inline]
-    fn get_kt(&mut self, key_kt: &KT) -> Result<Option<Vec<u8>>> {
-        #[cfg(feature = "htx")]
-        {
-            let hash = key_kt.hash_value();
-            let key_offset = self.htx_file.read_key_piece_offset(hash)?;
-            if !key_offset.is_zero() {
-                let flg = {
-                    let mut locked_key = self.key_file.0.borrow_mut();
-                    let key_string = locked_key.read_piece_only_key_maybeslice(key_offset)?;
-                    match key_kt.cmp_u8(&key_string) {
-                        Ordering::Equal => true,
-                        Ordering::Greater => false,
-                        Ordering::Less => false,
-                    }
-                };
-                if flg {
-                    #[cfg(feature = "htx_print_hits")]
-                    self.htx_file.set_hits();
-                    return self.load_value(key_offset).map(Some);
-                } else {
-                    #[cfg(feature = "htx_print_hits")]
-                    self.htx_file.set_miss();
-                }
-            }
-        }
-        let top_node = self.idx_file.read_top_node()?;
-        self.find_in_node_tree_kt(top_node, key_kt)
+    fn get_kt(&mut self, key_kt: &KT) -> DbResult<Option<Vec<u8>>> {
+        Ok(self.find_kt(key_kt)?)
     }
     #[inline]
-    fn put_kt(&mut self, key_kt: &KT, value: &[u8]) -> Result<()> {
+    fn put_kt(&mut self, key_kt: &KT, value: &[u8]) -> DbResult<()> {
+        self.check_writable()?;
+        check_dat_len(key_kt.as_bytes().len())?;
+        check_dat_len(value.len())?;
+        self.set_dirty_flag()?;
         #[cfg(feature = "htx")]
         {
             let hash = key_kt.hash_value();
-            let key_offset = self.htx_file.read_key_piece_offset(hash)?;
+            let key_offset = self.htx_read_key_piece_offset(hash)?;
             if !key_offset.is_zero() {
                 let flg = {
                     let mut locked_key = self.key_file.0.borrow_mut();
@@ -1149,14 +2188,14 @@ impl<KT: DbMapKeyType> DbXxxObjectSafe<KT> for FileDbXxxInner<KT> {
                 };
                 if flg {
                     #[cfg(feature = "htx_print_hits")]
-                    self.htx_file.set_hits();
+                    self.htx_set_hits(true);
                     //
                     let new_piece_offset = self.store_value_on_insert(key_offset, value)?;
                     assert!(key_offset == new_piece_offset);
                     return Ok(());
                 } else {
                     #[cfg(feature = "htx_print_hits")]
-                    self.htx_file.set_miss();
+                    self.htx_set_hits(false);
                 }
             }
         }
@@ -1164,10 +2203,14 @@ impl<KT: DbMapKeyType> DbXxxObjectSafe<KT> for FileDbXxxInner<KT> {
         let active_node = self.insert_into_node_tree_kt(top_node, key_kt, value)?;
         let new_top_node = active_node.deactivate();
         self.idx_file.write_top_node(new_top_node)?;
+        #[cfg(feature = "htx")]
+        self.grow_htx()?;
         Ok(())
     }
     #[inline]
-    fn del_kt(&mut self, key_kt: &KT) -> Result<Option<Vec<u8>>> {
+    fn del_kt(&mut self, key_kt: &KT) -> DbResult<Option<Vec<u8>>> {
+        self.check_writable()?;
+        self.set_dirty_flag()?;
         let top_node = self.idx_file.read_top_node()?;
         let top_node_offset = top_node.get_ref().offset();
         let (top_node, opt_val) = self.delete_from_node_tree_kt(top_node, key_kt)?;
@@ -1179,161 +2222,418 @@ impl<KT: DbMapKeyType> DbXxxObjectSafe<KT> for FileDbXxxInner<KT> {
     }
 }
 
+/// opens the htx file, if the htx is used. if it is not used, the existing htx file
+/// is removed, because it becomes stale by the writing.
+/// returns the htx file, and the flag that it is created now.
+#[cfg(feature = "htx")]
+/// finishes the compaction that is interrupted. the compacted files are renamed
+/// into place if they are complete, or else they are removed.
+fn finish_compaction(path: &Path, ks_name: &str, read_only: bool) -> Result<()> {
+    let tmp_name = format!("{}..compact", ks_name);
+    let done_path = path.join(format!("{}.done", tmp_name));
+    if !done_path.exists() {
+        if !read_only {
+            remove_compact_files(path, &tmp_name)?;
+        }
+        return Ok(());
+    }
+    if read_only {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "the compaction of the db map needs to be finished, open it as writable: {}",
+                ks_name
+            ),
+        ));
+    }
+    for ext in super::DB_MAP_FILE_EXTS {
+        let tmp_path = path.join(format!("{}.{}", tmp_name, ext));
+        if tmp_path.exists() {
+            std::fs::rename(&tmp_path, path.join(format!("{}.{}", ks_name, ext)))?;
+        }
+    }
+    std::fs::remove_file(&done_path)?;
+    Ok(())
+}
+
+/// removes the files of the compaction that are not complete.
+fn remove_compact_files(path: &Path, tmp_name: &str) -> Result<()> {
+    for ext in super::DB_MAP_FILE_EXTS {
+        match std::fs::remove_file(path.join(format!("{}.{}", tmp_name, ext))) {
+            Ok(()) => (),
+            Err(err) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+fn open_htx_file<P: AsRef<Path>>(
+    path: P,
+    ks_name: &str,
+    sig2: [u8; 8],
+    params: &FileDbParams,
+    read_only: bool,
+) -> Result<(Option<htx::HtxFile>, bool)> {
+    let htx_path = path.as_ref().join(format!("{}.htx", ks_name));
+    let is_exists = htx_path.exists();
+    if !params.use_htx {
+        if is_exists && !read_only {
+            std::fs::remove_file(&htx_path)?;
+        }
+        return Ok((None, false));
+    }
+    if read_only && !is_exists {
+        return Ok((None, false));
+    }
+    let htx_file = htx::HtxFile::open_with_params(path, ks_name, sig2, params, read_only)?;
+    Ok((Some(htx_file), !is_exists))
+}
+
+#[inline]
+fn check_dat_len(len: usize) -> Result<()> {
+    if len > DAT_LEN_MAX {
+        Err(DbError::ValueTooLarge {
+            len,
+            max: DAT_LEN_MAX,
+        }
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
 // for Iterator
+impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
+    /// seek the node stack to the first key of the lower bound.
+    fn seek_depth_nodes(&self, start_bound: Bound<&KT>) -> Result<Vec<(IdxNode, usize)>> {
+        let mut start_bound = start_bound;
+        let mut depth_nodes = Vec::new();
+        let mut node_ = self.idx_file.read_top_node()?;
+        loop {
+            let k = match start_bound {
+                Bound::Unbounded => 0,
+                Bound::Included(key_kt) | Bound::Excluded(key_kt) => {
+                    let r = {
+                        let node = node_.get_ref();
+                        #[cfg(not(feature = "tr_has_short_key"))]
+                        let r = self.keys_binary_search_kt(node.keys(), key_kt)?;
+                        #[cfg(feature = "tr_has_short_key")]
+                        let r =
+                            self.keys_binary_search_kt(node.keys(), node.short_keys(), key_kt)?;
+                        //
+                        r
+                    };
+                    match r {
+                        Ok(k) => {
+                            if let Bound::Included(_) = start_bound {
+                                depth_nodes.push((node_, k));
+                                return Ok(depth_nodes);
+                            }
+                            // the rest of the right side is the leftmost.
+                            start_bound = Bound::Unbounded;
+                            k + 1
+                        }
+                        Err(k) => k,
+                    }
+                }
+            };
+            let node_offset = node_.get_ref().downs_get(k);
+            depth_nodes.push((node_, k));
+            if node_offset.is_zero() {
+                break;
+            }
+            node_ = self.idx_file.read_node(node_offset)?;
+        }
+        Ok(depth_nodes)
+    }
+}
+
+impl<KT: DbMapKeyType> FileDbXxxInner<KT> {
+    /// seek the node stack to the last key of the upper bound.
+    fn seek_depth_nodes_rev(&self, end_bound: Bound<&KT>) -> Result<Vec<(IdxNode, usize)>> {
+        let mut end_bound = end_bound;
+        let mut depth_nodes = Vec::new();
+        let mut node_ = self.idx_file.read_top_node()?;
+        loop {
+            let k = match end_bound {
+                Bound::Unbounded => node_.get_ref().keys_len(),
+                Bound::Included(key_kt) | Bound::Excluded(key_kt) => {
+                    let r = {
+                        let node = node_.get_ref();
+                        #[cfg(not(feature = "tr_has_short_key"))]
+                        let r = self.keys_binary_search_kt(node.keys(), key_kt)?;
+                        #[cfg(feature = "tr_has_short_key")]
+                        let r =
+                            self.keys_binary_search_kt(node.keys(), node.short_keys(), key_kt)?;
+                        //
+                        r
+                    };
+                    match r {
+                        Ok(k) => {
+                            if let Bound::Included(_) = end_bound {
+                                depth_nodes.push((node_, k + 1));
+                                return Ok(depth_nodes);
+                            }
+                            // the rest of the left side is the rightmost.
+                            end_bound = Bound::Unbounded;
+                            k
+                        }
+                        Err(k) => k,
+                    }
+                }
+            };
+            let node_offset = node_.get_ref().downs_get(k);
+            depth_nodes.push((node_, k));
+            if node_offset.is_zero() {
+                break;
+            }
+            node_ = self.idx_file.read_node(node_offset)?;
+        }
+        Ok(depth_nodes)
+    }
+}
+
+/// the smallest bytes that is greater than all bytes starting with the prefix.
+fn prefix_successor(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut end = prefix.to_vec();
+    while let Some(last) = end.pop() {
+        if last < u8::MAX {
+            end.push(last + 1);
+            return Some(end);
+        }
+    }
+    None
+}
+
+#[inline]
+fn is_in_start_bound<KT: Ord>(key: &KT, start_bound: &Bound<KT>) -> bool {
+    match start_bound {
+        Bound::Included(start) => key >= start,
+        Bound::Excluded(start) => key > start,
+        Bound::Unbounded => true,
+    }
+}
+
+#[inline]
+fn is_in_end_bound<KT: Ord>(key: &KT, end_bound: &Bound<KT>) -> bool {
+    match end_bound {
+        Bound::Included(end) => key <= end,
+        Bound::Excluded(end) => key < end,
+        Bound::Unbounded => true,
+    }
+}
+
 //
 #[derive(Debug)]
 pub struct DbXxxIterMut<KT: DbMapKeyType> {
     db_map: Rc<RefCell<FileDbXxxInner<KT>>>,
     /// node depth of top node to leaf node.
-    depth_nodes: Vec<(IdxNode, i32, i32)>,
+    /// the index is the next key slot in the node.
+    depth_nodes: Vec<(IdxNode, usize)>,
+    /// node depth for the reverse direction, it is seeked at the first `next_back()`.
+    /// the index is the count of the remaining key slots in the node.
+    rev_depth_nodes: Option<Vec<(IdxNode, usize)>>,
+    /// the lower bound of the range.
+    start_bound: Bound<KT>,
+    /// the upper bound of the range.
+    end_bound: Bound<KT>,
+    /// the last key piece of `next()`.
+    front_key_offset: KeyPieceOffset,
+    /// the last key piece of `next_back()`.
+    back_key_offset: KeyPieceOffset,
 }
 
 impl<KT: DbMapKeyType> DbXxxIterMut<KT> {
+    #[inline]
     pub fn new(db_map: Rc<RefCell<FileDbXxxInner<KT>>>) -> Result<Self> {
+        Self::with_range(db_map, ..)
+    }
+    pub fn with_range<R: RangeBounds<KT>>(
+        db_map: Rc<RefCell<FileDbXxxInner<KT>>>,
+        range: R,
+    ) -> Result<Self> {
         let depth_nodes = {
             let db_map_inner = RefCell::borrow(&db_map);
-            let top_node = db_map_inner.idx_file.read_top_node()?;
-            let mut depth_nodes = vec![(top_node.clone(), 0, 0)];
-            let mut node = top_node;
-            //
-            loop {
-                let node_offset = node.get_ref().downs_get(0);
-                if node_offset.is_zero() {
-                    break;
-                }
-                let down_node = db_map_inner.idx_file.read_node(node_offset).unwrap();
-                depth_nodes.push((down_node.clone(), 0, 0));
-                node = down_node;
-            }
-            depth_nodes
+            db_map_inner.seek_depth_nodes(range.start_bound())?
         };
         //
         Ok(Self {
             db_map,
             depth_nodes,
+            rev_depth_nodes: None,
+            start_bound: range.start_bound().cloned(),
+            end_bound: range.end_bound().cloned(),
+            front_key_offset: KeyPieceOffset::new(0),
+            back_key_offset: KeyPieceOffset::new(0),
         })
     }
-    fn next_piece_offset(&mut self) -> Option<KeyPieceOffset> {
-        if self.depth_nodes.is_empty() {
-            return None;
-        }
-        /*
-        {
-            let depth = self.depth_nodes.len();
-            let (_, keys_idx, downs_idx) = self.depth_nodes.last_mut().unwrap();
-            eprintln!("CHECK 001: {}, {}, {}", depth, *keys_idx, *downs_idx);
-        }
-        */
-        //
-        let (key_offset, sw) = {
-            let (idx_node, keys_idx, downs_idx) = self.depth_nodes.last_mut().unwrap();
-            #[cfg(not(feature = "tr_has_short_key"))]
-            let key_offset = if *keys_idx < *downs_idx {
-                if *keys_idx < idx_node.get_ref().keys_len().try_into().unwrap() {
-                    idx_node.get_ref().keys_get((*keys_idx).try_into().unwrap())
-                } else {
-                    return None;
-                }
-            } else {
-                let node_offset = idx_node
-                    .get_ref()
-                    .downs_get((*downs_idx).try_into().unwrap());
-                if node_offset.is_zero() {
-                    idx_node.get_ref().keys_get((*keys_idx).try_into().unwrap())
+    pub fn with_prefix(db_map: Rc<RefCell<FileDbXxxInner<KT>>>, prefix: &[u8]) -> Result<Self> {
+        let start_bound = Bound::Included(KT::from_bytes(prefix));
+        let end_bound = match prefix_successor(prefix) {
+            Some(end) => Bound::Excluded(KT::from_bytes(&end)),
+            None => Bound::Unbounded,
+        };
+        Self::with_range(db_map, (start_bound, end_bound))
+    }
+    /// both of the directions are finished.
+    fn finish(&mut self) {
+        self.depth_nodes.clear();
+        self.rev_depth_nodes = Some(Vec::new());
+    }
+    fn next_piece_offset(&mut self) -> Result<Option<KeyPieceOffset>> {
+        loop {
+            let (key_offset, mut node_offset) = {
+                let (idx_node, keys_idx) = match self.depth_nodes.last_mut() {
+                    Some(a) => a,
+                    None => return Ok(None),
+                };
+                let node = idx_node.get_ref();
+                if *keys_idx >= node.keys_len() {
+                    (KeyPieceOffset::new(0), NodePieceOffset::new(0))
                 } else {
-                    {
-                        let db_map_inner = RefCell::borrow(&self.db_map);
-                        let down_node = db_map_inner.idx_file.read_node(node_offset).unwrap();
-                        self.depth_nodes.push((down_node, 0, 0));
-                    }
-                    return self.next_piece_offset();
+                    #[cfg(not(feature = "tr_has_short_key"))]
+                    let key_offset = node.keys_get(*keys_idx);
+                    #[cfg(feature = "tr_has_short_key")]
+                    let (key_offset, _short_key) = node.keys_get(*keys_idx);
+                    *keys_idx += 1;
+                    (key_offset, node.downs_get(*keys_idx))
                 }
             };
-            #[cfg(feature = "tr_has_short_key")]
-            let (key_offset, _short_key) = if *keys_idx < *downs_idx {
-                if *keys_idx < idx_node.get_ref().keys_len().try_into().unwrap() {
-                    idx_node.get_ref().keys_get((*keys_idx).try_into().unwrap())
-                } else {
-                    return None;
-                }
-            } else {
-                let node_offset = idx_node
-                    .get_ref()
-                    .downs_get((*downs_idx).try_into().unwrap());
-                if node_offset.is_zero() {
-                    idx_node.get_ref().keys_get((*keys_idx).try_into().unwrap())
-                } else {
-                    {
-                        let db_map_inner = RefCell::borrow(&self.db_map);
-                        let down_node = db_map_inner.idx_file.read_node(node_offset).unwrap();
-                        self.depth_nodes.push((down_node, 0, 0));
-                    }
-                    return self.next_piece_offset();
-                }
+            if key_offset.is_zero() {
+                let _ = self.depth_nodes.pop();
+                continue;
+            }
+            // the leftmost of the right side
+            let db_map_inner = RefCell::borrow(&self.db_map);
+            while !node_offset.is_zero() {
+                let down_node = db_map_inner.idx_file.read_node(node_offset)?;
+                node_offset = down_node.get_ref().downs_get(0);
+                self.depth_nodes.push((down_node, 0));
+            }
+            return Ok(Some(key_offset));
+        }
+    }
+    fn next_back_piece_offset(&mut self) -> Result<Option<KeyPieceOffset>> {
+        if self.rev_depth_nodes.is_none() {
+            let end_bound = match &self.end_bound {
+                Bound::Included(key_kt) => Bound::Included(key_kt),
+                Bound::Excluded(key_kt) => Bound::Excluded(key_kt),
+                Bound::Unbounded => Bound::Unbounded,
             };
-            debug_assert!(!key_offset.is_zero());
-            //
-            *keys_idx += 1;
-            if *keys_idx >= idx_node.get_ref().keys_len().try_into().unwrap() {
-                //eprintln!("CHECK 002");
-                if *downs_idx < idx_node.get_ref().downs_len().try_into().unwrap() {
-                    let node_offset = idx_node
-                        .get_ref()
-                        .downs_get((*downs_idx).try_into().unwrap());
-                    if !node_offset.is_zero() {
-                        //eprintln!("CHECK 002.1");
-                        let db_map_inner = RefCell::borrow(&self.db_map);
-                        let down_node = db_map_inner.idx_file.read_node(node_offset).unwrap();
-                        self.depth_nodes.push((down_node, 0, 0));
-                        (key_offset, 1)
-                    } else {
-                        //eprintln!("CHECK 002.2");
-                        (key_offset, 2)
-                    }
+            let db_map_inner = RefCell::borrow(&self.db_map);
+            self.rev_depth_nodes = Some(db_map_inner.seek_depth_nodes_rev(end_bound)?);
+        }
+        let rev_depth_nodes = self.rev_depth_nodes.as_mut().unwrap();
+        loop {
+            let (key_offset, mut node_offset) = {
+                let (idx_node, keys_idx) = match rev_depth_nodes.last_mut() {
+                    Some(a) => a,
+                    None => return Ok(None),
+                };
+                let node = idx_node.get_ref();
+                if *keys_idx == 0 {
+                    (KeyPieceOffset::new(0), NodePieceOffset::new(0))
                 } else {
-                    //eprintln!("CHECK 002.3");
-                    let (_, _, _) = self.depth_nodes.pop().unwrap();
-                    let (_, _keys_idx, downs_idx) = self.depth_nodes.last_mut().unwrap();
-                    *downs_idx += 1;
-                    (key_offset, 3)
+                    *keys_idx -= 1;
+                    #[cfg(not(feature = "tr_has_short_key"))]
+                    let key_offset = node.keys_get(*keys_idx);
+                    #[cfg(feature = "tr_has_short_key")]
+                    let (key_offset, _short_key) = node.keys_get(*keys_idx);
+                    (key_offset, node.downs_get(*keys_idx))
                 }
+            };
+            if key_offset.is_zero() {
+                let _ = rev_depth_nodes.pop();
+                continue;
+            }
+            // the rightmost of the left side
+            let db_map_inner = RefCell::borrow(&self.db_map);
+            while !node_offset.is_zero() {
+                let down_node = db_map_inner.idx_file.read_node(node_offset)?;
+                let keys_len = down_node.get_ref().keys_len();
+                node_offset = down_node.get_ref().downs_get(keys_len);
+                rev_depth_nodes.push((down_node, keys_len));
+            }
+            return Ok(Some(key_offset));
+        }
+    }
+    fn try_next(&mut self) -> Result<Option<(KT, Vec<u8>)>> {
+        let key_offset = match self.next_piece_offset()? {
+            Some(key_offset) => key_offset,
+            None => return Ok(None),
+        };
+        if key_offset == self.back_key_offset {
+            self.finish();
+            return Ok(None);
+        }
+        let (key, value_vec) = {
+            let db_map_inner = RefCell::borrow(&self.db_map);
+            let key = db_map_inner.load_key_data(key_offset)?;
+            if !is_in_end_bound(&key, &self.end_bound) {
+                (None, Vec::new())
             } else {
-                (key_offset, 0)
+                let value_vec = db_map_inner.load_value(key_offset, &key)?;
+                (Some(key), value_vec)
             }
         };
-        if sw == 2 {
-            loop {
-                if self.depth_nodes.is_empty() {
-                    break;
-                }
-                let (_, _, _) = self.depth_nodes.pop().unwrap();
-                if self.depth_nodes.is_empty() {
-                    break;
-                }
-                let (idx_node, _keys_idx, downs_idx) = self.depth_nodes.last_mut().unwrap();
-                *downs_idx += 1;
-                if *downs_idx < idx_node.get_ref().downs_len().try_into().unwrap() {
-                    break;
-                }
+        match key {
+            Some(key) => {
+                self.front_key_offset = key_offset;
+                Ok(Some((key, value_vec)))
+            }
+            None => {
+                self.finish();
+                Ok(None)
+            }
+        }
+    }
+    fn try_next_back(&mut self) -> Result<Option<(KT, Vec<u8>)>> {
+        let key_offset = match self.next_back_piece_offset()? {
+            Some(key_offset) => key_offset,
+            None => return Ok(None),
+        };
+        if key_offset == self.front_key_offset {
+            self.finish();
+            return Ok(None);
+        }
+        let (key, value_vec) = {
+            let db_map_inner = RefCell::borrow(&self.db_map);
+            let key = db_map_inner.load_key_data(key_offset)?;
+            if !is_in_start_bound(&key, &self.start_bound) {
+                (None, Vec::new())
+            } else {
+                let value_vec = db_map_inner.load_value(key_offset, &key)?;
+                (Some(key), value_vec)
+            }
+        };
+        match key {
+            Some(key) => {
+                self.back_key_offset = key_offset;
+                Ok(Some((key, value_vec)))
+            }
+            None => {
+                self.finish();
+                Ok(None)
             }
         }
-        //
-        Some(key_offset)
     }
 }
 
 // impl trait: Iterator
 impl<KT: DbMapKeyType> Iterator for DbXxxIterMut<KT> {
     type Item = (KT, Vec<u8>);
+    #[inline]
     fn next(&mut self) -> Option<(KT, Vec<u8>)> {
-        if let Some(key_offset) = self.next_piece_offset() {
-            let db_map_inner = RefCell::borrow_mut(&self.db_map);
-            let key = db_map_inner.load_key_data(key_offset).unwrap();
-            let value_vec = db_map_inner.load_value(key_offset).unwrap();
-            Some((key, value_vec))
-        } else {
-            None
-        }
+        self.try_next().unwrap()
+    }
+}
+
+// impl trait: DoubleEndedIterator
+impl<KT: DbMapKeyType> DoubleEndedIterator for DbXxxIterMut<KT> {
+    #[inline]
+    fn next_back(&mut self) -> Option<(KT, Vec<u8>)> {
+        self.try_next_back().unwrap()
     }
 }
 
@@ -1350,6 +2650,21 @@ impl<KT: DbMapKeyType> DbXxxIter<KT> {
             iter: DbXxxIterMut::new(db_map)?,
         })
     }
+    #[inline]
+    pub fn with_range<R: RangeBounds<KT>>(
+        db_map: Rc<RefCell<FileDbXxxInner<KT>>>,
+        range: R,
+    ) -> Result<Self> {
+        Ok(Self {
+            iter: DbXxxIterMut::with_range(db_map, range)?,
+        })
+    }
+    #[inline]
+    pub fn with_prefix(db_map: Rc<RefCell<FileDbXxxInner<KT>>>, prefix: &[u8]) -> Result<Self> {
+        Ok(Self {
+            iter: DbXxxIterMut::with_prefix(db_map, prefix)?,
+        })
+    }
 }
 
 // impl trait: Iterator
@@ -1361,6 +2676,14 @@ impl<KT: DbMapKeyType> Iterator for DbXxxIter<KT> {
     }
 }
 
+// impl trait: DoubleEndedIterator
+impl<KT: DbMapKeyType> DoubleEndedIterator for DbXxxIter<KT> {
+    #[inline]
+    fn next_back(&mut self) -> Option<(KT, Vec<u8>)> {
+        self.iter.next_back()
+    }
+}
+
 //
 #[derive(Debug)]
 pub struct DbXxxIntoIter<KT: DbMapKeyType> {
@@ -1374,6 +2697,21 @@ impl<KT: DbMapKeyType> DbXxxIntoIter<KT> {
             iter: DbXxxIterMut::new(db_map)?,
         })
     }
+    #[inline]
+    pub fn with_range<R: RangeBounds<KT>>(
+        db_map: Rc<RefCell<FileDbXxxInner<KT>>>,
+        range: R,
+    ) -> Result<Self> {
+        Ok(Self {
+            iter: DbXxxIterMut::with_range(db_map, range)?,
+        })
+    }
+    #[inline]
+    pub fn with_prefix(db_map: Rc<RefCell<FileDbXxxInner<KT>>>, prefix: &[u8]) -> Result<Self> {
+        Ok(Self {
+            iter: DbXxxIterMut::with_prefix(db_map, prefix)?,
+        })
+    }
 }
 
 // impl trait: Iterator
@@ -1384,3 +2722,70 @@ impl<KT: DbMapKeyType> Iterator for DbXxxIntoIter<KT> {
         self.iter.next()
     }
 }
+
+// impl trait: DoubleEndedIterator
+impl<KT: DbMapKeyType> DoubleEndedIterator for DbXxxIntoIter<KT> {
+    #[inline]
+    fn next_back(&mut self) -> Option<(KT, Vec<u8>)> {
+        self.iter.next_back()
+    }
+}
+
+/// The fallible iterator of the db map.
+///
+/// The error of the reading is returned as `Err` of the item, instead of
+/// the panic of `DbXxxIter`. The iteration is finished after the error.
+#[derive(Debug)]
+pub struct DbXxxTryIter<KT: DbMapKeyType> {
+    iter: Option<DbXxxIterMut<KT>>,
+    /// the error of the seeking at the start.
+    err: Option<Error>,
+}
+
+impl<KT: DbMapKeyType> DbXxxTryIter<KT> {
+    #[inline]
+    pub fn new(db_map: Rc<RefCell<FileDbXxxInner<KT>>>) -> Self {
+        match DbXxxIterMut::new(db_map) {
+            Ok(iter) => Self {
+                iter: Some(iter),
+                err: None,
+            },
+            Err(err) => Self {
+                iter: None,
+                err: Some(err),
+            },
+        }
+    }
+    fn finish_with(&mut self, r: Result<Option<(KT, Vec<u8>)>>) -> Option<DbResult<(KT, Vec<u8>)>> {
+        match r {
+            Ok(opt) => opt.map(Ok),
+            Err(err) => {
+                self.iter = None;
+                Some(Err(err.into()))
+            }
+        }
+    }
+}
+
+// impl trait: Iterator
+impl<KT: DbMapKeyType> Iterator for DbXxxTryIter<KT> {
+    type Item = DbResult<(KT, Vec<u8>)>;
+    fn next(&mut self) -> Option<DbResult<(KT, Vec<u8>)>> {
+        if let Some(err) = self.err.take() {
+            return Some(Err(err.into()));
+        }
+        let r = self.iter.as_mut()?.try_next();
+        self.finish_with(r)
+    }
+}
+
+// impl trait: DoubleEndedIterator
+impl<KT: DbMapKeyType> DoubleEndedIterator for DbXxxTryIter<KT> {
+    fn next_back(&mut self) -> Option<DbResult<(KT, Vec<u8>)>> {
+        if let Some(err) = self.err.take() {
+            return Some(Err(err.into()));
+        }
+        let r = self.iter.as_mut()?.try_next_back();
+        self.finish_with(r)
+    }
+}
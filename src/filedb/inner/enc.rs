@@ -0,0 +1,227 @@
+use super::super::{FileDbParams, IntEncoding};
+use super::invalid_header_error;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// the version of the file format, it is in the header of each file.
+/// 0 is the file before the version, that is read with the legacy encoding.
+pub(crate) const FORMAT_VERSION: u32 = 1;
+
+/// the encoding of the key offsets and the down offsets in the node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NodeOffsetEncoding {
+    /// the same as the piece offset.
+    Piece,
+    /// `u32`
+    U32,
+    /// `u64`
+    U64,
+}
+
+/// the encoding of the integers in a file, it is in the header of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Encoding {
+    /// the offsets, the sizes and the lengths of the pieces.
+    pub piece: IntEncoding,
+    /// the offsets in the node.
+    pub node: NodeOffsetEncoding,
+    /// the value offset in the key piece is the fixed width integer,
+    /// not the piece offset.
+    pub value_offset_fixed: bool,
+    /// the node has the short keys.
+    pub short_key: bool,
+    /// the id of the tuned node sizes, 0 is the node sizes from the node slots.
+    pub node_sizes: u8,
+}
+
+#[cfg(feature = "vf_u32u32")]
+const BUILD_INT_ENCODING: IntEncoding = IntEncoding::U32U32;
+#[cfg(feature = "vf_vu64")]
+const BUILD_INT_ENCODING: IntEncoding = IntEncoding::Vu64;
+#[cfg(not(any(feature = "vf_u32u32", feature = "vf_vu64")))]
+const BUILD_INT_ENCODING: IntEncoding = IntEncoding::U64U64;
+
+#[cfg(feature = "vf_node_u32")]
+const BUILD_NODE_OFFSET_ENCODING: NodeOffsetEncoding = NodeOffsetEncoding::U32;
+#[cfg(feature = "vf_node_u64")]
+const BUILD_NODE_OFFSET_ENCODING: NodeOffsetEncoding = NodeOffsetEncoding::U64;
+#[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
+const BUILD_NODE_OFFSET_ENCODING: NodeOffsetEncoding = NodeOffsetEncoding::Piece;
+
+/// the encoding of the files before the format version. they were written by
+/// the build of the default features, and the encoding is not recorded in them.
+const LEGACY_ENCODING: Encoding = Encoding {
+    piece: IntEncoding::U64U64,
+    node: NodeOffsetEncoding::U64,
+    value_offset_fixed: true,
+    short_key: false,
+    node_sizes: 0,
+};
+
+/*
+```text
+the encoding word in the header:
++-------+-----------------------------------------------------+
+| bits  | comment                                             |
++-------+-----------------------------------------------------+
+| 0..4  | piece: 1 is u32u32, 2 is u64u64, 3 is vu64          |
+| 4..8  | node offset: 0 is the piece offset, 1 is u32, 2 u64 |
+| 8     | the value offset is the fixed width integer         |
+| 9     | the node has the short keys                         |
+| 16..24| the id of the tuned node sizes                      |
++-------+-----------------------------------------------------+
+```
+*/
+
+impl Encoding {
+    /// the encoding of the build, that is selected by the features.
+    pub const fn build() -> Self {
+        Self {
+            piece: BUILD_INT_ENCODING,
+            node: BUILD_NODE_OFFSET_ENCODING,
+            value_offset_fixed: cfg!(any(feature = "htx", feature = "idx_straight")),
+            short_key: cfg!(feature = "tr_has_short_key"),
+            node_sizes: 0,
+        }
+    }
+    /// the encoding of the new file.
+    pub fn of_params(params: &FileDbParams) -> Self {
+        let mut enc = Self::build();
+        if let Some(piece) = params.int_encoding {
+            enc.piece = piece;
+        }
+        enc
+    }
+    /// the byte size of the offset in the node, if it is the fixed width integer.
+    pub fn node_offset_len(&self) -> Option<u32> {
+        match (self.node, self.piece) {
+            (NodeOffsetEncoding::U32, _) => Some(4),
+            (NodeOffsetEncoding::U64, _) => Some(8),
+            (NodeOffsetEncoding::Piece, IntEncoding::U32U32) => Some(4),
+            (NodeOffsetEncoding::Piece, IntEncoding::U64U64) => Some(8),
+            (NodeOffsetEncoding::Piece, IntEncoding::Vu64) => None,
+        }
+    }
+    pub fn to_u32(self) -> u32 {
+        let piece = match self.piece {
+            IntEncoding::U32U32 => 1,
+            IntEncoding::U64U64 => 2,
+            IntEncoding::Vu64 => 3,
+        };
+        let node = match self.node {
+            NodeOffsetEncoding::Piece => 0,
+            NodeOffsetEncoding::U32 => 1,
+            NodeOffsetEncoding::U64 => 2,
+        };
+        piece
+            | node << 4
+            | u32::from(self.value_offset_fixed) << 8
+            | u32::from(self.short_key) << 9
+            | u32::from(self.node_sizes) << 16
+    }
+    pub fn from_u32(val: u32) -> Option<Self> {
+        if val & !0x00FF_03FF != 0 {
+            return None;
+        }
+        let piece = match val & 0x0F {
+            1 => IntEncoding::U32U32,
+            2 => IntEncoding::U64U64,
+            3 => IntEncoding::Vu64,
+            _ => return None,
+        };
+        let node = match (val >> 4) & 0x0F {
+            0 => NodeOffsetEncoding::Piece,
+            1 => NodeOffsetEncoding::U32,
+            2 => NodeOffsetEncoding::U64,
+            _ => return None,
+        };
+        Some(Self {
+            piece,
+            node,
+            value_offset_fixed: val & (1 << 8) != 0,
+            short_key: val & (1 << 9) != 0,
+            node_sizes: (val >> 16) as u8,
+        })
+    }
+}
+
+/// checks the format version and the encoding in the header.
+/// it returns none for the file before the version.
+pub(crate) fn check_format_version(
+    path: &Path,
+    offset: u64,
+    version: u32,
+    encoding: u32,
+) -> Result<Option<Encoding>> {
+    if version == 0 && encoding == 0 {
+        return Ok(None);
+    }
+    if version > FORMAT_VERSION {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "the format version {} is newer than {}, that this build supports: {}",
+                version,
+                FORMAT_VERSION,
+                path.display()
+            ),
+        ));
+    }
+    match Encoding::from_u32(encoding) {
+        Some(enc) if version != 0 => Ok(Some(enc)),
+        _ => Err(invalid_header_error(
+            path,
+            offset + 4,
+            "encoding",
+            "the known encoding".to_string(),
+            format!("{:#x}", encoding),
+        )),
+    }
+}
+
+/// the encoding of the file before the format version. the file has no record
+/// of the encoding, so it is read only by the build of the legacy encoding,
+/// the build of the other encoding would misread it.
+pub(crate) fn legacy_encoding(path: &Path, build: Encoding) -> Result<Encoding> {
+    if build != LEGACY_ENCODING {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "the file before the format version is read only by the build of the default features: {}",
+                path.display()
+            ),
+        ));
+    }
+    Ok(LEGACY_ENCODING)
+}
+
+//--
+#[cfg(test)]
+mod debug {
+    use super::{check_format_version, legacy_encoding, Encoding, IntEncoding, LEGACY_ENCODING};
+    use std::path::Path;
+    //
+    #[test]
+    fn test_legacy_encoding() {
+        let path = Path::new("some_string_1.key");
+        assert_eq!(check_format_version(path, 160, 0, 0).unwrap(), None);
+        assert_eq!(
+            legacy_encoding(path, LEGACY_ENCODING).unwrap(),
+            LEGACY_ENCODING
+        );
+        // the build of the other encoding
+        for piece in [IntEncoding::U32U32, IntEncoding::Vu64] {
+            let build = Encoding {
+                piece,
+                ..LEGACY_ENCODING
+            };
+            let err = legacy_encoding(path, build).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        }
+        let build = Encoding {
+            short_key: true,
+            ..LEGACY_ENCODING
+        };
+        assert!(legacy_encoding(path, build).is_err());
+    }
+}
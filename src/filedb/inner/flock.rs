@@ -0,0 +1,81 @@
+use super::super::super::Error as DbError;
+use super::super::FileDbLockMode;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+const LOCK_FILE_NAME: &str = "siamesedb.lock";
+
+/// advisory lock of the database directory.
+/// the lock is released when this is dropped.
+#[derive(Debug)]
+pub struct FileLock {
+    _file: Option<File>,
+}
+
+impl FileLock {
+    pub fn lock<P: AsRef<Path>>(path: P, mode: FileDbLockMode) -> Result<Self> {
+        let mut pb = path.as_ref().to_path_buf();
+        pb.push(LOCK_FILE_NAME);
+        let file = match mode {
+            FileDbLockMode::Exclusive => open_lock_file(&pb)?,
+            // the reader may be on the read only mount or the unwritable directory.
+            // the existing lock file is opened as read only, and if it is not found
+            // and can not be created, the reader continues without the lock.
+            FileDbLockMode::Shared => match File::open(&pb) {
+                Ok(file) => file,
+                Err(err) if err.kind() == ErrorKind::NotFound => match open_lock_file(&pb) {
+                    Ok(file) => file,
+                    Err(_) => return Ok(Self { _file: None }),
+                },
+                Err(err) => return Err(err),
+            },
+        };
+        match try_lock(&file, mode) {
+            Ok(()) => Ok(Self { _file: Some(file) }),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                Err(DbError::Locked(path.as_ref().to_path_buf()).into())
+            }
+            Err(err) => Err(err),
+        }
+    }
+}
+
+fn open_lock_file(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+}
+
+#[cfg(unix)]
+fn try_lock(file: &File, mode: FileDbLockMode) -> Result<()> {
+    use std::os::raw::c_int;
+    use std::os::unix::io::AsRawFd;
+    //
+    const LOCK_SH: c_int = 1;
+    const LOCK_EX: c_int = 2;
+    const LOCK_NB: c_int = 4;
+    extern "C" {
+        fn flock(fd: c_int, operation: c_int) -> c_int;
+    }
+    //
+    let operation = match mode {
+        FileDbLockMode::Exclusive => LOCK_EX | LOCK_NB,
+        FileDbLockMode::Shared => LOCK_SH | LOCK_NB,
+    };
+    let r = unsafe { flock(file.as_raw_fd(), operation) };
+    if r == 0 {
+        Ok(())
+    } else {
+        Err(Error::last_os_error())
+    }
+}
+
+#[cfg(not(unix))]
+fn try_lock(_file: &File, _mode: FileDbLockMode) -> Result<()> {
+    // not supported yet, the lock file is only created.
+    Ok(())
+}
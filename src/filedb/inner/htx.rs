@@ -1,14 +1,16 @@
+use super::super::cell::{Rc, RefCell};
 use super::super::{FileBufSizeParam, FileDbParams};
+use super::enc::{check_format_version, Encoding, FORMAT_VERSION};
 use super::piece::PieceMgr;
 use super::semtype::*;
-use super::vfile::VarFile;
+use super::vfile::{SnapshotRef, VarFile};
+use super::wal::PendingPages;
+use super::{invalid_header_error, key_type_mismatch_error, signature_string};
 use rabuf::{SmallRead, SmallWrite};
-use std::cell::RefCell;
 use std::convert::TryInto;
 use std::fs::OpenOptions;
-use std::io::{Read, Result, Write};
+use std::io::{Error, ErrorKind, Read, Result, Write};
 use std::path::Path;
-use std::rc::Rc;
 
 type HeaderSignature = [u8; 8];
 
@@ -17,15 +19,19 @@ const CHUNK_SIZE: u32 = 128 * 1024;
 //const CHUNK_SIZE: u32 = 1024 * 1024;
 const HTX_HEADER_SZ: u64 = 128;
 const HTX_HEADER_SIGNATURE: HeaderSignature = [b's', b'i', b'a', b'm', b'd', b'b', b'H', 0u8];
-const DEFAULT_HT_SIZE: u64 = 16 * 1024 * 1024;
+/// the hash table is doubled when its filling rate crosses this.
+const HT_GROW_PER_MILL: u64 = 500;
 
+/// (file, ht size, the hash is mixed, count of used slots, the count is changed)
+///
+/// the count is kept in memory, and is written to the header on the flush and the sync.
 #[cfg(not(feature = "htx_print_hits"))]
 #[derive(Debug)]
-pub struct VarFileHtxCache(pub VarFile, u64);
+pub struct VarFileHtxCache(pub VarFile, u64, bool, u64, bool);
 
 #[cfg(feature = "htx_print_hits")]
 #[derive(Debug)]
-pub struct VarFileHtxCache(pub VarFile, u64, u64, u64);
+pub struct VarFileHtxCache(pub VarFile, u64, bool, u64, bool, u64, u64);
 
 #[derive(Debug, Clone)]
 pub struct HtxFile(pub Rc<RefCell<VarFileHtxCache>>);
@@ -39,16 +45,17 @@ impl HtxFile {
         ks_name: &str,
         sig2: HeaderSignature,
         params: &FileDbParams,
+        read_only: bool,
     ) -> Result<Self> {
         let piece_mgr = PieceMgr::new(&HTX_SIZE_FREE_OFFSET, &HTX_SIZE_ARY);
         let mut pb = path.as_ref().to_path_buf();
         pb.push(format!("{}.htx", ks_name));
         let std_file = OpenOptions::new()
             .read(true)
-            .write(true)
-            .create(true)
+            .write(!read_only)
+            .create(!read_only)
             .truncate(false)
-            .open(pb)?;
+            .open(&pb)?;
         let mut file = match params.htx_buf_size {
             FileBufSizeParam::Size(val) => {
                 let idx_buf_chunk_size = CHUNK_SIZE;
@@ -67,24 +74,38 @@ impl HtxFile {
             FileBufSizeParam::Auto => VarFile::new(piece_mgr, "htx", std_file)?,
         };
         let file_length: NodePieceOffset = file.seek_to_end()?;
+        if read_only && file_length.is_zero() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("empty file: {}", pb.display()),
+            ));
+        }
         //
         #[cfg(not(feature = "htx_print_hits"))]
-        let mut file_nc = VarFileHtxCache(file, 0);
+        let mut file_nc = VarFileHtxCache(file, 0, true, 0, false);
         #[cfg(feature = "htx_print_hits")]
-        let mut file_nc = VarFileHtxCache(file, 0, 0, 0);
+        let mut file_nc = VarFileHtxCache(file, 0, true, 0, false, 0, 0);
         //
         if file_length.is_zero() {
-            write_htxf_init_header(&mut file_nc.0, sig2)?;
-            let off = NodePieceOffset::new(HTX_HEADER_SZ + 8 * DEFAULT_HT_SIZE);
+            let ht_size = params.htx_init_size.max(1);
+            let enc = Encoding::of_params(params);
+            write_htxf_init_header(&mut file_nc.0, sig2, ht_size, enc)?;
+            let off = NodePieceOffset::new(HTX_HEADER_SZ + 8 * ht_size);
             file_nc.0.set_file_length(off)?;
-            let off = NodePieceOffset::new(HTX_HEADER_SZ + 8 * DEFAULT_HT_SIZE - 8);
+            let off = NodePieceOffset::new(HTX_HEADER_SZ + 8 * ht_size - 8);
             file_nc.0.seek_from_start(off)?;
             file_nc.0.write_u64_le(0)?;
-            file_nc.1 = DEFAULT_HT_SIZE;
-        } else {
-            check_htxf_header(&mut file_nc.0, sig2)?;
-            let ht_size = file_nc.0.read_hash_table_size()?;
             file_nc.1 = ht_size;
+        } else {
+            let version = check_htxf_header(&mut file_nc.0, sig2, &pb)?;
+            file_nc.1 = file_nc.0.read_hash_table_size()?;
+            file_nc.2 = file_nc.0.read_hash_mix()?;
+            file_nc.3 = file_nc.0.read_item_count()?;
+            if file_nc.3 == 0 && version == 0 {
+                // the file before the version does not have the count.
+                file_nc.3 = file_nc.recount_items()?;
+                file_nc.4 = !read_only && file_nc.3 > 0;
+            }
         }
         Ok(Self(Rc::new(RefCell::new(file_nc))))
     }
@@ -96,18 +117,128 @@ impl HtxFile {
     #[inline]
     pub fn flush(&self) -> Result<()> {
         let mut locked = RefCell::borrow_mut(&self.0);
+        locked.write_changed_count()?;
         locked.0.flush()
     }
     #[inline]
     pub fn sync_all(&self) -> Result<()> {
         let mut locked = RefCell::borrow_mut(&self.0);
+        locked.write_changed_count()?;
         locked.0.sync_all()
     }
     #[inline]
     pub fn sync_data(&self) -> Result<()> {
         let mut locked = RefCell::borrow_mut(&self.0);
+        locked.write_changed_count()?;
         locked.0.sync_data()
     }
+    //
+    #[inline]
+    pub fn begin_pending(&self) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.begin_pending()
+    }
+    /// the count is written into the pending pages, and then they are taken.
+    #[inline]
+    pub fn take_pending(&self) -> Result<Option<PendingPages>> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.write_changed_count()?;
+        Ok(locked.0.take_pending())
+    }
+    /// discards the pending pages, and the count is read from the file again.
+    #[inline]
+    pub fn rollback_pending(&self) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        let _ = locked.0.take_pending();
+        locked.3 = locked.0.read_item_count()?;
+        locked.4 = false;
+        Ok(())
+    }
+    #[inline]
+    pub fn apply_pending(&self, pages: &PendingPages) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.apply_pending(pages)
+    }
+    //
+    #[inline]
+    pub fn begin_snapshot(&self) -> Result<SnapshotRef> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.begin_snapshot()
+    }
+    #[inline]
+    pub fn snapshots(&self) -> Vec<SnapshotRef> {
+        let locked = RefCell::borrow(&self.0);
+        locked.0.snapshots()
+    }
+    #[inline]
+    pub fn set_snapshots(&self, snapshots: Vec<SnapshotRef>) {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.set_snapshots(snapshots)
+    }
+    //
+    #[inline]
+    pub fn read_dirty_flag(&self) -> Result<bool> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked
+            .0
+            .read_dirty_flag(NodePieceOffset::new(HTX_DIRTY_FLAG_OFFSET))
+    }
+    #[inline]
+    pub fn write_dirty_flag(&self, flag: bool) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked
+            .0
+            .write_dirty_flag(NodePieceOffset::new(HTX_DIRTY_FLAG_OFFSET), flag)
+    }
+    /// clears the all items of the hash table.
+    pub fn clear_hash_table(&self) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        let ht_size = locked.1;
+        locked
+            .0
+            .set_file_length(NodePieceOffset::new(HTX_HEADER_SZ))?;
+        let off = NodePieceOffset::new(HTX_HEADER_SZ + 8 * ht_size);
+        locked.0.set_file_length(off)?;
+        let off = NodePieceOffset::new(HTX_HEADER_SZ + 8 * ht_size - 8);
+        locked.0.seek_from_start(off)?;
+        locked.0.write_u64_le(0)?;
+        locked.3 = 0;
+        locked.4 = true;
+        // the old file becomes the mixed hash, because it is empty now.
+        if !locked.2 {
+            locked.0.write_hash_mix(true)?;
+            locked.2 = true;
+        }
+        Ok(())
+    }
+    /// the doubled size of the hash table, if its filling rate crosses the threshold.
+    pub fn grow_size(&self) -> Result<Option<u64>> {
+        let locked = RefCell::borrow(&self.0);
+        let ht_size = locked.1;
+        let count = locked.3;
+        if count * 1000 >= ht_size * HT_GROW_PER_MILL {
+            Ok(Some(ht_size * 2))
+        } else {
+            Ok(None)
+        }
+    }
+    /// the size of the hash table, that is doubled until the count of the items
+    /// is under the threshold.
+    pub fn size_for_count(&self, count: u64) -> u64 {
+        let mut ht_size = RefCell::borrow(&self.0).1;
+        while count * 1000 >= ht_size * HT_GROW_PER_MILL {
+            ht_size *= 2;
+        }
+        ht_size
+    }
+    /// changes the size of the hash table. the items must be rebuilt after this.
+    pub fn set_hash_table_size(&self, ht_size: u64) -> Result<()> {
+        debug_assert!(ht_size > 0);
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.write_hash_table_size(ht_size)?;
+        locked.1 = ht_size;
+        Ok(())
+    }
     #[cfg(feature = "buf_stats")]
     #[inline]
     pub fn buf_stats(&self) -> Vec<(String, i64)> {
@@ -119,28 +250,52 @@ impl HtxFile {
     pub fn read_key_piece_offset(&self, hash: u64) -> Result<KeyPieceOffset> {
         let mut locked = RefCell::borrow_mut(&self.0);
         //let ht_size = locked.0.read_hash_table_size()?;
-        let ht_size = locked.1;
-        let idx = hash % ht_size;
+        let idx = ht_index(hash, locked.1, locked.2);
         locked.0.read_key_piece_offset(idx)
     }
     #[inline]
     pub fn write_key_piece_offset(&self, hash: u64, offset: KeyPieceOffset) -> Result<()> {
         let mut locked = RefCell::borrow_mut(&self.0);
         //let ht_size = locked.0.read_hash_table_size()?;
-        let ht_size = locked.1;
-        let idx = hash % ht_size;
+        let idx = ht_index(hash, locked.1, locked.2);
+        // the count of the used slots
+        let old_offset = locked.0.read_key_piece_offset(idx)?;
+        if old_offset.is_zero() != offset.is_zero() {
+            if offset.is_zero() {
+                locked.3 = locked.3.saturating_sub(1);
+            } else {
+                locked.3 += 1;
+            }
+            locked.4 = true;
+        }
         locked.0.write_key_piece_offset(idx, offset)
     }
     #[cfg(feature = "htx_print_hits")]
-    pub fn set_hits(&mut self) {
+    pub fn set_hits(&self) {
         let mut locked = RefCell::borrow_mut(&self.0);
-        locked.2 += 1;
+        locked.5 += 1;
     }
     #[cfg(feature = "htx_print_hits")]
-    pub fn set_miss(&mut self) {
+    pub fn set_miss(&self) {
         let mut locked = RefCell::borrow_mut(&self.0);
-        locked.3 += 1;
+        locked.6 += 1;
+    }
+}
+
+/// the index of the hash table. the hash is mixed, because the low bits of
+/// the hash are not uniform, and the hash table may be small.
+#[inline]
+fn ht_index(hash: u64, ht_size: u64, is_mixed: bool) -> u64 {
+    if !is_mixed {
+        return hash % ht_size;
     }
+    let mut x = hash;
+    x ^= x >> 30;
+    x = x.wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x ^= x >> 27;
+    x = x.wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+    x % ht_size
 }
 
 #[cfg(feature = "htx_print_hits")]
@@ -148,7 +303,7 @@ impl Drop for HtxFile {
     fn drop(&mut self) {
         let (hits, miss) = {
             let locked = RefCell::borrow_mut(&self.0);
-            (locked.2, locked.3)
+            (locked.5, locked.6)
         };
         let total = hits + miss;
         let ratio = hits as f64 / total as f64;
@@ -161,8 +316,7 @@ impl HtxFile {
     pub fn ht_size_and_count(&self) -> Result<(u64, u64)> {
         let mut locked = RefCell::borrow_mut(&self.0);
         let ht_size = locked.0.read_hash_table_size()?;
-        let count = locked.0.read_item_count()?;
-        Ok((ht_size, count))
+        Ok((ht_size, locked.3))
     }
     pub fn htx_filling_rate_per_mill(&self) -> Result<(u64, u32)> {
         let mut locked = RefCell::borrow_mut(&self.0);
@@ -176,6 +330,19 @@ impl HtxFile {
         }
         Ok((count, (count * 1000 / ht_size) as u32))
     }
+    /// the key piece offsets of the used slots, with the file offset of the slot.
+    pub fn used_slots(&self) -> Result<Vec<(u64, KeyPieceOffset)>> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        let ht_size = locked.1;
+        let mut vec = Vec::new();
+        for idx in 0..ht_size {
+            let offset = locked.0.read_key_piece_offset(idx)?;
+            if !offset.is_zero() {
+                vec.push((HTX_HEADER_SZ + 8 * idx, offset));
+            }
+        }
+        Ok(vec)
+    }
 }
 
 /**
@@ -192,51 +359,137 @@ The htx header size is 128 bytes.
 | 0      | 8     | signature1  | b"siamdbH\0"              |
 | 8      | 8     | signature2  | 8 bytes type signature    |
 | 16     | 8     | ht size     | hash table size           |
-| 24     | 8     | count       | count of items            |
-| 32     | 96    | reserve1    |                           |
+| 24     | 8     | count       | count of used slots       |
+| 32     | 8     | dirty flag  | not zero while writing    |
+| 40     | 8     | hash mix    | the hash is mixed         |
+| 48     | 4     | version     | version of the format     |
+| 52     | 4     | encoding    | encoding of the db map    |
+| 56     | 72    | reserve1    |                           |
 +--------+-------+-------------+---------------------------+
 ```
 
 - signature1: always fixed 8 bytes
 - signature2: 8 bytes type signature
+- ht size: it is `FileDbParams::htx_init_size` at first, and is doubled when
+  the count of used slots crosses the half of it.
+- count: it is kept in memory, and is written on the flush and the sync. it is
+  zero on the file before the version, and is counted on opening it.
+- dirty flag: it is set on the first writing, and is cleared by the sync.
+- hash mix: if it is zero, the index of the hash table is `hash % ht size`, as
+  the old files. it becomes not zero when the hash table is rebuilt.
+- version and encoding: 0 is the file before the version. the slots are
+  always `u64`, the encoding is the one of the other files on creating.
 
 */
 const HTX_HT_SIZE_OFFSET: u64 = 16;
 const HTX_ITEM_COUNT_OFFSET: u64 = 24;
+const HTX_DIRTY_FLAG_OFFSET: u64 = 32;
+const HTX_HASH_MIX_OFFSET: u64 = 40;
+const HTX_VERSION_OFFSET: u64 = 48;
 
-fn write_htxf_init_header(file: &mut VarFile, signature2: HeaderSignature) -> Result<()> {
+fn write_htxf_init_header(
+    file: &mut VarFile,
+    signature2: HeaderSignature,
+    ht_size: u64,
+    enc: Encoding,
+) -> Result<()> {
     file.seek_from_start(NodePieceOffset::new(0))?;
     // signature1
     file.write_all(&HTX_HEADER_SIGNATURE)?;
     // signature2
     file.write_all(&signature2)?;
     // ht size
-    file.write_u64_le(DEFAULT_HT_SIZE)?;
-    // count .. rserve1
-    file.write_all(&[0u8; 104])?;
+    file.write_u64_le(ht_size)?;
+    // count .. dirty flag
+    file.write_all(&[0u8; 16])?;
+    // hash mix
+    file.write_u64_le(1)?;
+    // version and encoding
+    file.write_u32_le(FORMAT_VERSION)?;
+    file.write_u32_le(enc.to_u32())?;
+    // reserve1
+    file.write_all(&[0u8; 72])?;
     //
     Ok(())
 }
 
-fn check_htxf_header(file: &mut VarFile, signature2: HeaderSignature) -> Result<()> {
+/// returns the version of the format.
+fn check_htxf_header(file: &mut VarFile, signature2: HeaderSignature, path: &Path) -> Result<u32> {
+    // file length
+    let file_length: NodePieceOffset = file.seek_to_end()?;
+    if file_length.as_value() < HTX_HEADER_SZ {
+        return Err(invalid_header_error(
+            path,
+            file_length.as_value(),
+            "file length",
+            format!("at least {} bytes", HTX_HEADER_SZ),
+            format!("{} bytes", file_length.as_value()),
+        ));
+    }
     file.seek_from_start(NodePieceOffset::new(0))?;
     // signature1
     let mut sig1 = [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8];
     file.read_exact(&mut sig1)?;
-    assert!(sig1 == HTX_HEADER_SIGNATURE, "invalid header signature1");
+    if sig1 != HTX_HEADER_SIGNATURE {
+        return Err(invalid_header_error(
+            path,
+            0,
+            "signature1",
+            signature_string(&HTX_HEADER_SIGNATURE),
+            signature_string(&sig1),
+        ));
+    }
     // signature2
     let mut sig2 = [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8];
     file.read_exact(&mut sig2)?;
-    assert!(
-        sig2 == signature2,
-        "invalid header signature2, type signature: {:?}",
-        sig2
-    );
-    // top node offset
-    let _top_node_offset = file.read_u64_le()?;
-    assert!(_top_node_offset != 0, "invalid root offset");
+    if sig2 != signature2 {
+        return Err(key_type_mismatch_error(path, &signature2, &sig2));
+    }
+    // ht size
+    let ht_size = file.read_u64_le()?;
+    let ht_size_max = (file_length.as_value() - HTX_HEADER_SZ) / 8;
+    if ht_size == 0 || ht_size > ht_size_max {
+        return Err(invalid_header_error(
+            path,
+            16,
+            "ht size",
+            format!("in 1..={}", ht_size_max),
+            ht_size.to_string(),
+        ));
+    }
+    // version and encoding
+    file.seek_from_start(NodePieceOffset::new(HTX_VERSION_OFFSET))?;
+    let version = file.read_u32_le()?;
+    let encoding = file.read_u32_le()?;
+    let _ = check_format_version(path, HTX_VERSION_OFFSET, version, encoding)?;
     //
-    Ok(())
+    Ok(version)
+}
+
+impl VarFileHtxCache {
+    fn write_changed_count(&mut self) -> Result<()> {
+        if self.4 {
+            self.0.write_item_count(self.3)?;
+            self.4 = false;
+        }
+        Ok(())
+    }
+    fn recount_items(&mut self) -> Result<u64> {
+        let mut count = 0;
+        for idx in 0..self.1 {
+            if !self.0.read_key_piece_offset(idx)?.is_zero() {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
+
+impl Drop for VarFileHtxCache {
+    /// the count that is changed is written, same as the dirty nodes of the idx.
+    fn drop(&mut self) {
+        let _ = self.write_changed_count();
+    }
 }
 
 impl VarFile {
@@ -244,7 +497,7 @@ impl VarFile {
         self.seek_from_start(NodePieceOffset::new(HTX_HT_SIZE_OFFSET))?;
         self.read_u64_le()
     }
-    fn _write_hash_table_size(&mut self, val: u64) -> Result<()> {
+    fn write_hash_table_size(&mut self, val: u64) -> Result<()> {
         self.seek_from_start(NodePieceOffset::new(HTX_HT_SIZE_OFFSET))?;
         self.write_u64_le(val)
     }
@@ -252,25 +505,23 @@ impl VarFile {
         self.seek_from_start(NodePieceOffset::new(HTX_ITEM_COUNT_OFFSET))?;
         self.read_u64_le()
     }
-    fn _write_item_count(&mut self, val: u64) -> Result<()> {
+    fn write_item_count(&mut self, val: u64) -> Result<()> {
         self.seek_from_start(NodePieceOffset::new(HTX_ITEM_COUNT_OFFSET))?;
         self.write_u64_le(val)
     }
+    fn read_hash_mix(&mut self) -> Result<bool> {
+        self.seek_from_start(NodePieceOffset::new(HTX_HASH_MIX_OFFSET))?;
+        Ok(self.read_u64_le()? != 0)
+    }
+    fn write_hash_mix(&mut self, is_mixed: bool) -> Result<()> {
+        self.seek_from_start(NodePieceOffset::new(HTX_HASH_MIX_OFFSET))?;
+        self.write_u64_le(u64::from(is_mixed))
+    }
     fn read_key_piece_offset(&mut self, idx: u64) -> Result<KeyPieceOffset> {
         self.seek_from_start(NodePieceOffset::new(HTX_HEADER_SZ + 8 * idx))?;
         self.read_u64_le().map(KeyPieceOffset::new)
     }
     fn write_key_piece_offset(&mut self, idx: u64, offset: KeyPieceOffset) -> Result<()> {
-        /*<CHECK>
-        let count = self.read_item_count()?;
-        if offset.is_zero() {
-            if count > 0 {
-                self.write_item_count(count - 1)?;
-            }
-        } else {
-            self.write_item_count(count + 1)?;
-        }
-        */
         self.seek_from_start(NodePieceOffset::new(HTX_HEADER_SZ + 8 * idx))?;
         self.write_u64_le(offset.into())?;
         Ok(())
@@ -1,19 +1,22 @@
 use super::super::super::DbMapKeyType;
+use super::super::cell::{Rc, RefCell};
 use super::super::{
     CountOfPerSize, FileBufSizeParam, FileDbParams, KeysCountStats, LengthStats, RecordSizeStats,
 };
 use super::dbxxx::FileDbXxxInner;
-use super::piece::PieceMgr;
+use super::enc::{check_format_version, legacy_encoding, Encoding, FORMAT_VERSION};
+use super::piece::{FreeListError, PieceMgr};
 use super::semtype::*;
 use super::tr::IdxNode;
-use super::vfile::VarFile;
+use super::vfile::{SnapshotRef, VarFile};
+use super::wal::PendingPages;
+use super::{invalid_header_error, key_type_mismatch_error, signature_string, FORMAT_CHECKSUM};
 use rabuf::{SmallRead, SmallWrite};
-use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::fs::OpenOptions;
-use std::io::{Read, Result, Write};
+use std::io::{Error, ErrorKind, Read, Result, Write};
 use std::path::Path;
-use std::rc::Rc;
 
 type HeaderSignature = [u8; 8];
 
@@ -24,6 +27,10 @@ const CHUNK_SIZE: u32 = 128 * 1024;
 const IDX_HEADER_SZ: u64 = 128;
 const IDX_HEADER_SIGNATURE: HeaderSignature = [b's', b'i', b'a', b'm', b'd', b'b', b'T', 0u8];
 const IDX_HEADER_TOP_NODE_OFFSET: u64 = 16;
+const IDX_HEADER_DIRTY_FLAG_OFFSET: u64 = 88;
+const IDX_HEADER_FORMAT_OFFSET: u64 = 96;
+const IDX_HEADER_NODE_SLOTS_OFFSET: u64 = 104;
+const IDX_HEADER_VERSION_OFFSET: u64 = 112;
 
 #[cfg(not(feature = "node_cache"))]
 use std::marker::PhantomData;
@@ -33,11 +40,11 @@ use super::nc::NodeCache;
 
 #[cfg(not(feature = "node_cache"))]
 #[derive(Debug)]
-pub struct VarFileNodeCache(pub VarFile, PhantomData<i32>, NodePieceOffset);
+pub struct VarFileNodeCache(pub VarFile, PhantomData<i32>, NodePieceOffset, u16);
 
 #[cfg(feature = "node_cache")]
 #[derive(Debug)]
-pub struct VarFileNodeCache(pub VarFile, NodeCache, NodePieceOffset);
+pub struct VarFileNodeCache(pub VarFile, NodeCache, NodePieceOffset, u16);
 
 #[derive(Debug, Clone)]
 pub struct IdxFile(pub Rc<RefCell<VarFileNodeCache>>);
@@ -48,16 +55,24 @@ impl IdxFile {
         ks_name: &str,
         sig2: HeaderSignature,
         params: &FileDbParams,
+        read_only: bool,
     ) -> Result<Self> {
-        let piece_mgr = PieceMgr::new(&NODE_SIZE_FREE_OFFSET, &NODE_SIZE_ARY);
+        // the piece manager is replaced after the header is read.
+        let piece_mgr = node_piece_mgr(
+            Encoding {
+                node_sizes: NODE_SIZES_ID,
+                ..Encoding::build()
+            },
+            NODE_SLOTS_MAX,
+        );
         let mut pb = path.as_ref().to_path_buf();
         pb.push(format!("{}.idx", ks_name));
         let std_file = OpenOptions::new()
             .read(true)
-            .write(true)
-            .create(true)
+            .write(!read_only)
+            .create(!read_only)
             .truncate(false)
-            .open(pb)?;
+            .open(&pb)?;
         let mut file = match params.idx_buf_size {
             FileBufSizeParam::Size(val) => {
                 let idx_buf_chunk_size = CHUNK_SIZE;
@@ -76,27 +91,60 @@ impl IdxFile {
             FileBufSizeParam::Auto => VarFile::new(piece_mgr, "idx", std_file)?,
         };
         let file_length: NodePieceOffset = file.seek_to_end()?;
+        if read_only && file_length.is_zero() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("empty file: {}", pb.display()),
+            ));
+        }
         //
         #[cfg(not(feature = "node_cache"))]
-        let mut file_nc = VarFileNodeCache(file, PhantomData, NodePieceOffset::new(0));
+        let mut file_nc =
+            VarFileNodeCache(file, PhantomData, NodePieceOffset::new(0), NODE_SLOTS_MAX);
         #[cfg(feature = "node_cache")]
-        let mut file_nc = VarFileNodeCache(file, NodeCache::new(), NodePieceOffset::new(0));
+        let mut file_nc = VarFileNodeCache(
+            file,
+            NodeCache::new(),
+            NodePieceOffset::new(0),
+            NODE_SLOTS_MAX,
+        );
         //
         if file_length.is_zero() {
-            write_idxf_init_header(&mut file_nc.0, sig2)?;
+            let format = if params.checksum { FORMAT_CHECKSUM } else { 0 };
+            if format & FORMAT_CHECKSUM != 0 {
+                file_nc.0.set_checksum(&pb);
+            }
+            let node_slots = node_slots_of_params(params)?;
+            let enc = node_encoding_of_params(params, node_slots);
+            file_nc.set_node_layout(enc, node_slots);
+            write_idxf_init_header(&mut file_nc.0, sig2, format, node_slots, enc)?;
             // writing top node
             let top_node = IdxNode::new(NodePieceOffset::new(IDX_HEADER_SZ));
             let new_top_node_ = file_nc.write_node(top_node, true)?;
             debug_assert!(new_top_node_.get_ref().offset() == NodePieceOffset::new(IDX_HEADER_SZ));
             file_nc.2 = new_top_node_.get_ref().offset();
         } else {
-            check_idxf_header(&mut file_nc.0, sig2)?;
+            let (format, node_slots, enc) = check_idxf_header(&mut file_nc.0, sig2, &pb)?;
+            if format & FORMAT_CHECKSUM != 0 {
+                file_nc.0.set_checksum(&pb);
+            }
+            file_nc.set_node_layout(enc, node_slots);
             let top_node_offset = file_nc.0.read_top_node_offset()?;
             file_nc.2 = top_node_offset;
         }
         //
         Ok(Self(Rc::new(RefCell::new(file_nc))))
     }
+    /// the max slots of the node, that is in the header.
+    #[inline]
+    pub fn node_slots(&self) -> u16 {
+        RefCell::borrow(&self.0).3
+    }
+    /// the byte size of the offset in the node, if it is the fixed width integer.
+    #[inline]
+    pub fn node_offset_len(&self) -> Option<u32> {
+        RefCell::borrow(&self.0).0.encoding().node_offset_len()
+    }
     #[inline]
     pub fn read_fill_buffer(&self) -> Result<()> {
         let mut locked = RefCell::borrow_mut(&self.0);
@@ -123,6 +171,173 @@ impl IdxFile {
         locked.flush_node_cache_clear()?;
         locked.0.sync_data()
     }
+    //
+    #[inline]
+    pub fn begin_pending(&self) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.begin_pending()
+    }
+    #[inline]
+    pub fn take_pending(&self) -> Result<Option<PendingPages>> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        #[cfg(feature = "node_cache")]
+        locked.flush_node_cache_clear()?;
+        Ok(locked.0.take_pending())
+    }
+    #[inline]
+    pub fn apply_pending(&self, pages: &PendingPages) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.apply_pending(pages)
+    }
+    //
+    #[inline]
+    pub fn begin_snapshot(&self) -> Result<SnapshotRef> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.begin_snapshot()
+    }
+    #[inline]
+    pub fn snapshots(&self) -> Vec<SnapshotRef> {
+        let locked = RefCell::borrow(&self.0);
+        locked.0.snapshots()
+    }
+    #[inline]
+    pub fn set_snapshots(&self, snapshots: Vec<SnapshotRef>) {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.set_snapshots(snapshots)
+    }
+    //
+    #[inline]
+    pub fn read_dirty_flag(&self) -> Result<bool> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked
+            .0
+            .read_dirty_flag(NodePieceOffset::new(IDX_HEADER_DIRTY_FLAG_OFFSET))
+    }
+    #[inline]
+    pub fn write_dirty_flag(&self, flag: bool) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked
+            .0
+            .write_dirty_flag(NodePieceOffset::new(IDX_HEADER_DIRTY_FLAG_OFFSET), flag)
+    }
+    /// rebuilds the free node lists, the nodes that are not in `used` become free.
+    pub fn rebuild_free_piece_list(&self, used: &HashSet<u64>) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.rebuild_free_piece_list(
+            NodePieceOffset::new(IDX_HEADER_SZ),
+            used,
+            |file: &mut VarFile| file.read_node_size(),
+        )
+    }
+    /// moves the used nodes to the front and truncates the file.
+    /// the key offsets in the nodes are replaced by `key_moved_map`.
+    pub fn compact(
+        &self,
+        used: &HashSet<u64>,
+        key_moved_map: &HashMap<u64, u64>,
+    ) -> Result<(HashMap<u64, u64>, u64)> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        #[cfg(feature = "node_cache")]
+        locked.flush_node_cache_clear()?;
+        let (node_moved_map, reclaimed) = locked.0.compact_pieces(
+            NodePieceOffset::new(IDX_HEADER_SZ),
+            used,
+            |file: &mut VarFile| file.read_node_size(),
+        )?;
+        if node_moved_map.is_empty() && key_moved_map.is_empty() {
+            return Ok((node_moved_map, reclaimed));
+        }
+        for &old_offset in used.iter() {
+            let offset = *node_moved_map.get(&old_offset).unwrap_or(&old_offset);
+            let (mut node_, node_size) = locked.read_node_no_cache(NodePieceOffset::new(offset))?;
+            {
+                let mut node = node_.get_mut();
+                node.set_size(node_size);
+                for i in 0..node.keys_len() {
+                    #[cfg(not(feature = "tr_has_short_key"))]
+                    let key_offset = node.keys_get(i);
+                    #[cfg(feature = "tr_has_short_key")]
+                    let (key_offset, short_key) = node.keys_get(i);
+                    //
+                    if let Some(&new_offset) = key_moved_map.get(&key_offset.as_value()) {
+                        #[cfg(not(feature = "tr_has_short_key"))]
+                        node.keys_set(i, KeyPieceOffset::new(new_offset));
+                        #[cfg(feature = "tr_has_short_key")]
+                        node.keys_set(i, KeyPieceOffset::new(new_offset), short_key);
+                    }
+                }
+                for i in 0..node.downs_len() {
+                    let down_offset = node.downs_get(i);
+                    if let Some(&new_offset) = node_moved_map.get(&down_offset.as_value()) {
+                        node.downs_set(i, NodePieceOffset::new(new_offset));
+                    }
+                }
+            }
+            node_.idx_write_node_one(&mut locked.0)?;
+        }
+        let top_node_offset = locked.2;
+        if let Some(&new_offset) = node_moved_map.get(&top_node_offset.as_value()) {
+            let new_offset = NodePieceOffset::new(new_offset);
+            locked.0.write_top_node_offset(new_offset)?;
+            locked.2 = new_offset;
+        }
+        Ok((node_moved_map, reclaimed))
+    }
+    /// the sizes of the pieces by the offset, the free pieces and the problems
+    /// of the free piece lists.
+    #[allow(clippy::type_complexity)]
+    pub fn scan_for_verify(
+        &self,
+    ) -> Result<(HashMap<u64, u32>, HashSet<u64>, Vec<(u64, FreeListError)>)> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        #[cfg(feature = "node_cache")]
+        locked.flush_node_cache()?;
+        let piece_sizes = locked
+            .0
+            .piece_sizes(NodePieceOffset::new(IDX_HEADER_SZ), |file: &mut VarFile| {
+                file.read_node_size()
+            })?;
+        let (free_set, errors) = locked.0.walk_free_piece_lists(&piece_sizes)?;
+        Ok((piece_sizes, free_set, errors))
+    }
+    /// the offsets of the all nodes and the all key pieces in the tree.
+    pub fn used_offsets(&self) -> Result<(Vec<NodePieceOffset>, Vec<KeyPieceOffset>)> {
+        let mut node_vec = Vec::new();
+        let mut key_vec = Vec::new();
+        let top_node = self.read_top_node()?;
+        let mut stack = vec![top_node.get_ref().offset()];
+        while let Some(node_offset) = stack.pop() {
+            let node_ = self.read_node(node_offset)?;
+            let node = node_.get_ref();
+            node_vec.push(node_offset);
+            for i in 0..node.keys_len() {
+                #[cfg(not(feature = "tr_has_short_key"))]
+                let key_offset = node.keys_get(i);
+                #[cfg(feature = "tr_has_short_key")]
+                let (key_offset, _short_key) = node.keys_get(i);
+                //
+                if !key_offset.is_zero() {
+                    key_vec.push(key_offset);
+                }
+            }
+            for i in 0..node.downs_len() {
+                let down_offset = node.downs_get(i);
+                if !down_offset.is_zero() {
+                    stack.push(down_offset);
+                }
+            }
+        }
+        Ok((node_vec, key_vec))
+    }
+    /// discards the pending pages and the node cache.
+    pub fn rollback_pending(&self) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        #[cfg(feature = "node_cache")]
+        locked.flush_node_cache_clear()?;
+        let _ = locked.0.take_pending();
+        locked.2 = locked.0.read_top_node_offset()?;
+        Ok(())
+    }
     #[cfg(feature = "buf_stats")]
     #[inline]
     pub fn buf_stats(&self) -> Vec<(String, i64)> {
@@ -243,7 +458,8 @@ impl IdxFile {
         } else {
             0
         };
-        for i in 1..node.get_ref().downs_len() {
+        let downs_len = node.get_ref().downs_len();
+        for i in 1..downs_len {
             let node_offset = node.get_ref().downs_get(i);
             let hh = if !node_offset.is_zero() {
                 let node1 = self.read_node(node_offset)?;
@@ -269,7 +485,8 @@ impl IdxFile {
         } else {
             0
         };
-        for i in 1..node.get_ref().downs_len() {
+        let downs_len = node.get_ref().downs_len();
+        for i in 1..downs_len {
             let node_offset = node.get_ref().downs_get(i);
             let h = if !node_offset.is_zero() {
                 let node1 = self.read_node(node_offset)?;
@@ -286,7 +503,7 @@ impl IdxFile {
     //
     pub fn is_mst_valid<KT>(&self, node: &IdxNode, dbxxx: &FileDbXxxInner<KT>) -> Result<bool>
     where
-        KT: DbMapKeyType + std::fmt::Display + std::default::Default + std::cmp::PartialOrd,
+        KT: DbMapKeyType,
     {
         if node.get_ref().keys_is_empty() {
             return Ok(true);
@@ -312,7 +529,8 @@ impl IdxFile {
             }
         }
         //
-        for i in 1..node.get_ref().keys_len() {
+        let keys_len = node.get_ref().keys_len();
+        for i in 1..keys_len {
             #[cfg(not(feature = "tr_has_short_key"))]
             let key_offset1 = node.get_ref().keys_get(i - 1);
             #[cfg(feature = "tr_has_short_key")]
@@ -373,9 +591,10 @@ impl IdxFile {
     //
     fn is_small<KT>(&self, key: &KT, node: &IdxNode, dbxxx: &FileDbXxxInner<KT>) -> Result<bool>
     where
-        KT: DbMapKeyType + std::fmt::Display + std::default::Default + std::cmp::PartialOrd,
+        KT: DbMapKeyType,
     {
-        for i in 0..node.get_ref().keys_len() {
+        let keys_len = node.get_ref().keys_len();
+        for i in 0..keys_len {
             let node_offset = node.get_ref().downs_get(i);
             if !node_offset.is_zero() {
                 let node1 = self.read_node(node_offset)?;
@@ -414,9 +633,10 @@ impl IdxFile {
         dbxxx: &FileDbXxxInner<KT>,
     ) -> Result<bool>
     where
-        KT: DbMapKeyType + std::fmt::Display + std::default::Default + std::cmp::PartialOrd,
+        KT: DbMapKeyType,
     {
-        for i in 0..node.get_ref().keys_len() {
+        let keys_len = node.get_ref().keys_len();
+        for i in 0..keys_len {
             let node_offset = node.get_ref().downs_get(i);
             if !node_offset.is_zero() {
                 let node1 = self.read_node(node_offset)?;
@@ -452,9 +672,10 @@ impl IdxFile {
     }
     fn is_large<KT>(&self, key: &KT, node: &IdxNode, dbxxx: &FileDbXxxInner<KT>) -> Result<bool>
     where
-        KT: DbMapKeyType + std::fmt::Display + std::default::Default + std::cmp::PartialOrd,
+        KT: DbMapKeyType,
     {
-        for i in 0..node.get_ref().keys_len() {
+        let keys_len = node.get_ref().keys_len();
+        for i in 0..keys_len {
             let node_offset = node.get_ref().downs_get(i);
             if !node_offset.is_zero() {
                 let node1 = self.read_node(node_offset)?;
@@ -491,7 +712,7 @@ impl IdxFile {
             return Ok(true);
         }
         let n = top_node.get_ref().downs_len();
-        if n > NODE_SLOTS_MAX as usize {
+        if n > self.node_slots() as usize {
             return Ok(false);
         }
         if n == 1 && !top_node.get_ref().downs_get(0).is_zero() {
@@ -511,7 +732,8 @@ impl IdxFile {
     }
     fn is_dense_half(&self, node: &IdxNode) -> Result<bool> {
         let n = node.get_ref().downs_len();
-        if n < NODE_SLOTS_MAX_HALF as usize || n > NODE_SLOTS_MAX as usize {
+        let node_slots = self.node_slots() as usize;
+        if n < node_slots / 2 || n > node_slots {
             return Ok(false);
         }
         for i in 0..n {
@@ -539,10 +761,9 @@ impl IdxFile {
         Ok(cnt)
     }
     pub fn count_of_free_node(&self) -> Result<Vec<(u32, u64)>> {
-        let sz_ary = NODE_SIZE_ARY;
-        //
         let mut vec = Vec::new();
         let mut locked = RefCell::borrow_mut(&self.0);
+        let sz_ary = locked.0.piece_mgr.size_ary().to_vec();
         for node_size in sz_ary {
             let cnt = locked
                 .0
@@ -570,7 +791,7 @@ impl IdxFile {
         F: Fn(KeyPieceOffset) -> Result<(KeyPieceSize, ValuePieceSize)> + std::marker::Copy,
     {
         let mut node_vec = Vec::new();
-        for node_size in NODE_SIZE_ARY {
+        for &node_size in RefCell::borrow(&self.0).0.piece_mgr.size_ary() {
             let cnt = 0;
             node_vec.push((node_size, cnt));
         }
@@ -652,15 +873,31 @@ The db index header size is 128 bytes.
 | 64     | 8     | free6 off   | offset of free 6th list   |
 | 72     | 8     | free7 off   | offset of free 7th list   |
 | 80     | 8     | free8 off   | offset of free 8th list   |
-| 88     | 40    | reserve1    |                           |
+| 88     | 8     | dirty flag  | not zero while writing    |
+| 96     | 8     | format      | format flags              |
+| 104    | 8     | node slots  | max slots of the node     |
+| 112    | 4     | version     | version of the format     |
+| 116    | 4     | encoding    | encoding of the integers  |
+| 120    | 8     | reserve1    |                           |
 +--------+-------+-------------+---------------------------+
 ```
 
 - signature1: always fixed 8 bytes
 - signature2: 8 bytes type signature
+- dirty flag: it is set on the first writing, and is cleared by the sync.
+- format: 1 is the checksum at the end of the node.
+- node slots: it is decided on creating the file. 0 is the default of the build.
+- version and encoding: 0 is the file before the version, it has the encoding of
+  the default features, and only the build of it reads the file.
 
 */
-fn write_idxf_init_header(file: &mut VarFile, signature2: HeaderSignature) -> Result<()> {
+fn write_idxf_init_header(
+    file: &mut VarFile,
+    signature2: HeaderSignature,
+    format: u64,
+    node_slots: u16,
+    enc: Encoding,
+) -> Result<()> {
     file.seek_from_start(NodePieceOffset::new(0))?;
     // signature1
     file.write_all(&IDX_HEADER_SIGNATURE)?;
@@ -668,31 +905,117 @@ fn write_idxf_init_header(file: &mut VarFile, signature2: HeaderSignature) -> Re
     file.write_all(&signature2)?;
     // root offset
     file.write_u64_le(IDX_HEADER_SZ)?;
-    // free1 .. rserve1
-    file.write_all(&[0u8; 104])?;
+    // free1 .. dirty flag
+    file.write_all(&[0u8; 72])?;
+    // format
+    file.write_u64_le(format)?;
+    // node slots
+    file.write_u64_le(node_slots.into())?;
+    // version and encoding
+    file.write_u32_le(FORMAT_VERSION)?;
+    file.write_u32_le(enc.to_u32())?;
+    // reserve1
+    file.write_all(&[0u8; 8])?;
     //
     Ok(())
 }
 
-fn check_idxf_header(file: &mut VarFile, signature2: HeaderSignature) -> Result<()> {
+fn check_idxf_header(
+    file: &mut VarFile,
+    signature2: HeaderSignature,
+    path: &Path,
+) -> Result<(u64, u16, Encoding)> {
+    // file length
+    let file_length: NodePieceOffset = file.seek_to_end()?;
+    if file_length.as_value() < IDX_HEADER_SZ {
+        return Err(invalid_header_error(
+            path,
+            file_length.as_value(),
+            "file length",
+            format!("at least {} bytes", IDX_HEADER_SZ),
+            format!("{} bytes", file_length.as_value()),
+        ));
+    }
     file.seek_from_start(NodePieceOffset::new(0))?;
     // signature1
     let mut sig1 = [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8];
     file.read_exact(&mut sig1)?;
-    assert!(sig1 == IDX_HEADER_SIGNATURE, "invalid header signature1");
+    if sig1 != IDX_HEADER_SIGNATURE {
+        return Err(invalid_header_error(
+            path,
+            0,
+            "signature1",
+            signature_string(&IDX_HEADER_SIGNATURE),
+            signature_string(&sig1),
+        ));
+    }
     // signature2
     let mut sig2 = [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8];
     file.read_exact(&mut sig2)?;
-    assert!(
-        sig2 == signature2,
-        "invalid header signature2, type signature: {:?}",
-        sig2
-    );
+    if sig2 != signature2 {
+        return Err(key_type_mismatch_error(path, &signature2, &sig2));
+    }
     // top node offset
-    let _top_node_offset = file.read_u64_le()?;
-    assert!(_top_node_offset != 0, "invalid root offset");
+    let top_node_offset = file.read_u64_le()?;
+    if top_node_offset < IDX_HEADER_SZ || top_node_offset >= file_length.as_value() {
+        return Err(invalid_header_error(
+            path,
+            16,
+            "root offset",
+            format!("in {}..{}", IDX_HEADER_SZ, file_length.as_value()),
+            top_node_offset.to_string(),
+        ));
+    }
+    // format
+    file.seek_from_start(NodePieceOffset::new(IDX_HEADER_FORMAT_OFFSET))?;
+    let format = file.read_u64_le()?;
+    if format & !FORMAT_CHECKSUM != 0 {
+        return Err(invalid_header_error(
+            path,
+            IDX_HEADER_FORMAT_OFFSET,
+            "format",
+            format!("0 or {}", FORMAT_CHECKSUM),
+            format.to_string(),
+        ));
+    }
+    // node slots
+    let node_slots = match file.read_u64_le()? {
+        0 => NODE_SLOTS_MAX,
+        n if is_valid_node_slots(n) => n as u16,
+        n => {
+            return Err(invalid_header_error(
+                path,
+                IDX_HEADER_NODE_SLOTS_OFFSET,
+                "node slots",
+                format!(
+                    "0 or the even number in {}..={}",
+                    NODE_SLOTS_MIN, NODE_SLOTS_LIMIT
+                ),
+                n.to_string(),
+            ))
+        }
+    };
+    // version and encoding
+    let version = file.read_u32_le()?;
+    let encoding = file.read_u32_le()?;
+    let enc = match check_format_version(path, IDX_HEADER_VERSION_OFFSET, version, encoding)? {
+        Some(enc) => enc,
+        None => node_encoding_of_legacy(path, node_slots)?,
+    };
+    if enc.short_key != Encoding::build().short_key
+        || enc.node_sizes as usize > NODE_SIZE_ARYS.len()
+    {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "the nodes of the encoding {:#x} are not supported by this build: {}",
+                encoding,
+                path.display()
+            ),
+        ));
+    }
     //
-    Ok(())
+    Ok((format, node_slots, enc))
 }
 
 impl VarFile {
@@ -724,7 +1047,9 @@ const NODE_SIZE_FREE_OFFSET: [u64; 8] = [
 pub const NODE_SLOTS_MAX: u16 = 6;
 
 #[cfg(feature = "small_node_slots")]
-const NODE_SIZE_ARY: [u32; 8] = [
+const NODE_SIZES_ID: u8 = 1;
+
+const NODE_SIZE_ARY_SMALL: [u32; 8] = [
     16,
     16 * 2,
     16 * 2 * 2,
@@ -748,7 +1073,9 @@ pub const NODE_SLOTS_MAX: u16 = 12;
 
 #[cfg(not(feature = "small_node_slots"))]
 #[cfg(feature = "vf_u32u32")]
-const NODE_SIZE_ARY: [u32; 8] = [
+const NODE_SIZES_ID: u8 = 2;
+
+const NODE_SIZE_ARY_U32U32: [u32; 8] = [
     16 * 2 * 2,
     16 * 2 * 3,
     16 * 2 * 4,
@@ -822,7 +1149,9 @@ pub const NODE_SLOTS_MAX: u16 = 64;
 #[cfg(not(feature = "small_node_slots"))]
 #[cfg(not(feature = "oi_hash_turbo"))]
 #[cfg(feature = "vf_u64u64")]
-const NODE_SIZE_ARY: [u32; 8] = [
+const NODE_SIZES_ID: u8 = 3;
+
+const NODE_SIZE_ARY_U64U64: [u32; 8] = [
     16 * 4 * 2,
     16 * 4 * 2 * 2, //
     16 * 4 * 2 * 4, //
@@ -841,7 +1170,9 @@ pub const NODE_SLOTS_MAX: u16 = 64;
 #[cfg(not(feature = "small_node_slots"))]
 #[cfg(feature = "oi_hash_turbo")]
 #[cfg(feature = "vf_u64u64")]
-const NODE_SIZE_ARY: [u32; 8] = [
+const NODE_SIZES_ID: u8 = 4;
+
+const NODE_SIZE_ARY_U64U64_TURBO: [u32; 8] = [
     16 * 4 * 2 * 2,  //
     16 * 4 * 2 * 4,  //
     16 * 4 * 2 * 8,  //
@@ -969,7 +1300,9 @@ pub const NODE_SLOTS_MAX: u16 = 64;
 #[cfg(not(feature = "small_node_slots"))]
 #[cfg(not(feature = "tr_has_short_key"))]
 #[cfg(feature = "vf_vu64")]
-const NODE_SIZE_ARY: [u32; 8] = [
+const NODE_SIZES_ID: u8 = 5;
+
+const NODE_SIZE_ARY_VU64: [u32; 8] = [
     16 * 4 * 2,
     16 * 4 * 4,  //
     16 * 4 * 8,  //
@@ -995,7 +1328,9 @@ pub const NODE_SLOTS_MAX: u16 = 32;
 #[cfg(not(feature = "small_node_slots"))]
 #[cfg(feature = "tr_has_short_key")]
 #[cfg(feature = "vf_vu64")]
-const NODE_SIZE_ARY: [u32; 8] = [
+const NODE_SIZES_ID: u8 = 6;
+
+const NODE_SIZE_ARY_VU64_SHORT_KEY: [u32; 8] = [
     16 * 4 * 3 * 3,
     16 * 4 * 3 * 4,
     16 * 4 * 3 * 5,
@@ -1113,7 +1448,82 @@ const NODE_SIZE_ARY: [u32; 8] = [
 ];
 */
 
-pub const NODE_SLOTS_MAX_HALF: u16 = NODE_SLOTS_MAX / 2;
+/// the tuned node sizes above, the index is the id in the header.
+/// they are all here, to read the file of the other build.
+const NODE_SIZE_ARYS: [&[u32; 8]; 6] = [
+    &NODE_SIZE_ARY_SMALL,
+    &NODE_SIZE_ARY_U32U32,
+    &NODE_SIZE_ARY_U64U64,
+    &NODE_SIZE_ARY_U64U64_TURBO,
+    &NODE_SIZE_ARY_VU64,
+    &NODE_SIZE_ARY_VU64_SHORT_KEY,
+];
+
+/// the range of the node slots of the db map.
+pub const NODE_SLOTS_MIN: u16 = 4;
+pub const NODE_SLOTS_LIMIT: u16 = 512;
+
+#[inline]
+fn is_valid_node_slots(node_slots: u64) -> bool {
+    node_slots % 2 == 0
+        && node_slots >= NODE_SLOTS_MIN.into()
+        && node_slots <= NODE_SLOTS_LIMIT.into()
+}
+
+/// the node slots of the new idx file. 0 is `NODE_SLOTS_MAX`, the default of the build.
+pub(crate) fn node_slots_of_params(params: &FileDbParams) -> Result<u16> {
+    match params.node_slots {
+        0 => Ok(NODE_SLOTS_MAX),
+        n if is_valid_node_slots(n.into()) => Ok(n),
+        n => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "the node slots must be 0 or the even number in {}..={}: {}",
+                NODE_SLOTS_MIN, NODE_SLOTS_LIMIT, n
+            ),
+        )),
+    }
+}
+
+/// the encoding of the new idx file. the default node slots of the build
+/// use the tuned node sizes of the build.
+fn node_encoding_of_params(params: &FileDbParams, node_slots: u16) -> Encoding {
+    let mut enc = Encoding::of_params(params);
+    if node_slots == NODE_SLOTS_MAX && enc.piece == Encoding::build().piece {
+        enc.node_sizes = NODE_SIZES_ID;
+    }
+    enc
+}
+
+/// the encoding of the idx file before the format version.
+fn node_encoding_of_legacy(path: &Path, node_slots: u16) -> Result<Encoding> {
+    let mut enc = legacy_encoding(path, Encoding::build())?;
+    if node_slots == NODE_SLOTS_MAX {
+        enc.node_sizes = NODE_SIZES_ID;
+    }
+    Ok(enc)
+}
+
+/// the piece manager of the node. the tuned node sizes are used if the encoding
+/// has the id of them, and the others use the node sizes from the max size of the node.
+fn node_piece_mgr(enc: Encoding, node_slots: u16) -> PieceMgr {
+    if enc.node_sizes != 0 {
+        let size_ary = NODE_SIZE_ARYS[enc.node_sizes as usize - 1];
+        return PieceMgr::new(&NODE_SIZE_FREE_OFFSET, size_ary);
+    }
+    // node size, is leaf, keys count, the keys and the downs of 9 bytes at most, checksum
+    let node_slots = node_slots as u32;
+    let mut max_size = 4 + 2 + 2 + (2 * node_slots + 1) * 9 + 4;
+    // and the short keys of 32 bytes at most
+    if enc.short_key {
+        max_size += node_slots * 33;
+    }
+    let unit = ((max_size + 127) / 128) * 16;
+    let size_ary = (1..=NODE_SIZE_FREE_OFFSET.len() as u32)
+        .map(|i| unit * i)
+        .collect();
+    PieceMgr::with_size_vec(&NODE_SIZE_FREE_OFFSET, size_ary)
+}
 
 /*
  * node_size = keys_count.len + (2 * NODE_SLOTS_MAX - 1) * vu64.len
@@ -1126,7 +1536,21 @@ pub const NODE_SLOTS_MAX_HALF: u16 = NODE_SLOTS_MAX / 2;
  * node_size = 2 + (2 * 512 -1) * 9 = 9209 --> vu64 encoded len: 2
 */
 
+#[cfg(feature = "node_cache")]
+impl Drop for VarFileNodeCache {
+    /// the dirty nodes in the cache are written, or the tree is broken without the sync.
+    fn drop(&mut self) {
+        let _ = self.flush_node_cache();
+    }
+}
+
 impl VarFileNodeCache {
+    fn set_node_layout(&mut self, enc: Encoding, node_slots: u16) {
+        self.0.set_encoding(enc);
+        self.0.piece_mgr = node_piece_mgr(enc, node_slots);
+        self.3 = node_slots;
+    }
+
     #[cfg(feature = "node_cache")]
     #[inline]
     fn flush_node_cache(&mut self) -> Result<()> {
@@ -1170,24 +1594,20 @@ impl VarFileNodeCache {
         //
         let new_node_size = {
             #[cfg(feature = "siamese_debug")]
-            let buf_len: u32 = node_.get_ref().encoded_node_size().try_into().unwrap();
+            let buf_len: u32 = node_
+                .get_ref()
+                .encoded_node_size(self.0.encoding())
+                .try_into()
+                .unwrap();
             #[cfg(not(feature = "siamese_debug"))]
-            let buf_len: u32 = node_.get_ref().encoded_node_size() as u32;
+            let buf_len: u32 = node_.get_ref().encoded_node_size(self.0.encoding()) as u32;
             //
-            #[cfg(any(feature = "vf_u32u32", feature = "vf_u64u64"))]
-            let encoded_len: u32 = 2;
-            #[cfg(feature = "vf_vu64")]
-            let encoded_len: u32 = {
-                let encoded_len = if buf_len < 128 { 1 } else { 2 };
-                //let encoded_len = vu64::encoded_len(buf_len as u64);
-                debug_assert!(encoded_len == vu64::encoded_len(buf_len as u64));
-                encoded_len.into()
-            };
+            let encoded_len = self.0.node_size_encoded_len(buf_len);
             //
             // buggy: size operation for node size.
-            self.0
-                .piece_mgr
-                .roundup(NodePieceSize::new(buf_len + encoded_len))
+            self.0.piece_mgr.roundup(NodePieceSize::new(
+                buf_len + encoded_len + self.0.checksum_len(),
+            ))
         };
         //
         if !is_new {
@@ -1238,11 +1658,12 @@ impl VarFileNodeCache {
                 let node_size = self.0.read_node_size()?;
                 #[cfg(debug_assertions)]
                 if node_size != new_node_size {
+                    let sz_ary = self.0.piece_mgr.size_ary();
                     debug_assert!(
-                        new_node_size.as_value() > NODE_SIZE_ARY[NODE_SIZE_ARY.len() - 2],
-                        "new_node_size: {} == NODE_SIZE_ARY[NODE_SIZE_ARY.len() - 2]: {}",
+                        new_node_size.as_value() > sz_ary[sz_ary.len() - 2],
+                        "new_node_size: {} == sz_ary[sz_ary.len() - 2]: {}",
                         new_node_size.as_value(),
-                        NODE_SIZE_ARY[NODE_SIZE_ARY.len() - 2]
+                        sz_ary[sz_ary.len() - 2]
                     );
                 }
                 //self.0.write_node_clear(free_node_offset, node_size)?;
@@ -1293,6 +1714,11 @@ impl VarFileNodeCache {
     }
 
     fn read_node_no_cache(&mut self, offset: NodePieceOffset) -> Result<(IdxNode, NodePieceSize)> {
+        if self.0.has_checksum() {
+            self.0.seek_from_start(offset)?;
+            let node_size = self.0.read_node_size()?;
+            self.0.check_piece_checksum(offset, node_size)?;
+        }
         IdxNode::idx_read_node_one(&mut self.0, offset)
     }
     #[cfg(not(feature = "node_cache"))]
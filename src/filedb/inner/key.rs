@@ -1,15 +1,18 @@
 use super::super::super::DbMapKeyType;
-use super::super::{FileBufSizeParam, FileDbParams};
-use super::piece::PieceMgr;
+use super::super::cell::{Rc, RefCell};
+use super::super::{FileBufSizeParam, FileDbParams, IntEncoding};
+use super::enc::{check_format_version, legacy_encoding, Encoding, FORMAT_VERSION};
+use super::piece::{FreeListError, PieceMgr};
 use super::semtype::*;
-use super::vfile::VarFile;
+use super::vfile::{SnapshotRef, VarFile};
+use super::wal::PendingPages;
+use super::{invalid_header_error, key_type_mismatch_error, signature_string, FORMAT_CHECKSUM};
 use rabuf::{SmallRead, SmallWrite};
-use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::fs::OpenOptions;
-use std::io::{Read, Result, Write};
+use std::io::{Error, ErrorKind, Read, Result, Write};
 use std::path::Path;
-use std::rc::Rc;
 
 type HeaderSignature = [u8; 8];
 
@@ -17,7 +20,9 @@ type HeaderSignature = [u8; 8];
 //const CHUNK_SIZE: u32 = 4 * 4 * 1024;
 //const CHUNK_SIZE: u32 = 4 * 4 * 4 * 1024;
 const CHUNK_SIZE: u32 = 128 * 1024;
-const _DAT_HEADER_SZ: u64 = 192;
+const DAT_HEADER_SZ: u64 = 192;
+const DAT_HEADER_DIRTY_FLAG_OFFSET: u64 = 24;
+const DAT_HEADER_VERSION_OFFSET: u64 = 160;
 const DAT_HEADER_SIGNATURE: HeaderSignature = [b's', b'i', b'a', b'm', b'd', b'b', b'K', 0u8];
 
 use std::marker::PhantomData;
@@ -34,16 +39,17 @@ impl<KT: DbMapKeyType> KeyFile<KT> {
         ks_name: &str,
         sig2: HeaderSignature,
         params: &FileDbParams,
+        read_only: bool,
     ) -> Result<Self> {
         let piece_mgr = PieceMgr::new(&REC_SIZE_FREE_OFFSET, &REC_SIZE_ARY);
         let mut pb = path.as_ref().to_path_buf();
         pb.push(format!("{}.key", ks_name));
         let std_file = OpenOptions::new()
             .read(true)
-            .write(true)
-            .create(true)
+            .write(!read_only)
+            .create(!read_only)
             .truncate(false)
-            .open(pb)?;
+            .open(&pb)?;
         let mut file = match params.key_buf_size {
             FileBufSizeParam::Size(val) => {
                 let dat_buf_chunk_size = CHUNK_SIZE;
@@ -62,10 +68,21 @@ impl<KT: DbMapKeyType> KeyFile<KT> {
             FileBufSizeParam::Auto => VarFile::new(piece_mgr, "key", std_file)?,
         };
         let file_length: KeyPieceOffset = file.seek_to_end()?;
-        if file_length.is_zero() {
-            write_keyrecf_init_header(&mut file, sig2)?;
+        if read_only && file_length.is_zero() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("empty file: {}", pb.display()),
+            ));
+        }
+        let format = if file_length.is_zero() {
+            let format = if params.checksum { FORMAT_CHECKSUM } else { 0 };
+            write_keyrecf_init_header(&mut file, sig2, format, Encoding::of_params(params))?;
+            format
         } else {
-            check_keyrecf_header(&mut file, sig2)?;
+            check_keyrecf_header(&mut file, sig2, &pb)?
+        };
+        if format & FORMAT_CHECKSUM != 0 {
+            file.set_checksum(&pb);
         }
         //
         let file_rc = VarFileKeyCache(file, PhantomData);
@@ -92,6 +109,112 @@ impl<KT: DbMapKeyType> KeyFile<KT> {
         let mut locked = self.0.borrow_mut();
         locked.0.sync_data()
     }
+    //
+    #[inline]
+    pub fn begin_pending(&self) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.begin_pending()
+    }
+    #[inline]
+    pub fn take_pending(&self) -> Result<Option<PendingPages>> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        Ok(locked.0.take_pending())
+    }
+    #[inline]
+    pub fn apply_pending(&self, pages: &PendingPages) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.apply_pending(pages)
+    }
+    //
+    #[inline]
+    pub fn begin_snapshot(&self) -> Result<SnapshotRef> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.begin_snapshot()
+    }
+    #[inline]
+    pub fn snapshots(&self) -> Vec<SnapshotRef> {
+        let locked = RefCell::borrow(&self.0);
+        locked.0.snapshots()
+    }
+    #[inline]
+    pub fn set_snapshots(&self, snapshots: Vec<SnapshotRef>) {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.set_snapshots(snapshots)
+    }
+    //
+    #[inline]
+    pub fn read_dirty_flag(&self) -> Result<bool> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked
+            .0
+            .read_dirty_flag(KeyPieceOffset::new(DAT_HEADER_DIRTY_FLAG_OFFSET))
+    }
+    #[inline]
+    pub fn write_dirty_flag(&self, flag: bool) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked
+            .0
+            .write_dirty_flag(KeyPieceOffset::new(DAT_HEADER_DIRTY_FLAG_OFFSET), flag)
+    }
+    /// rebuilds the free piece lists, the pieces that are not in `used` become free.
+    pub fn rebuild_free_piece_list(&self, used: &HashSet<u64>) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.rebuild_free_piece_list(
+            KeyPieceOffset::new(DAT_HEADER_SZ),
+            used,
+            |file: &mut VarFile| file.read_piece_size(),
+        )
+    }
+    /// moves the used pieces to the front and truncates the file.
+    pub fn compact(&self, used: &HashSet<u64>) -> Result<(HashMap<u64, u64>, u64)> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.compact_pieces(
+            KeyPieceOffset::new(DAT_HEADER_SZ),
+            used,
+            |file: &mut VarFile| file.read_piece_size(),
+        )
+    }
+    /// the sizes of the pieces by the offset, the free pieces and the problems
+    /// of the free piece lists.
+    #[allow(clippy::type_complexity)]
+    pub fn scan_for_verify(
+        &self,
+    ) -> Result<(HashMap<u64, u32>, HashSet<u64>, Vec<(u64, FreeListError)>)> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        let piece_sizes = locked
+            .0
+            .piece_sizes(KeyPieceOffset::new(DAT_HEADER_SZ), |file: &mut VarFile| {
+                file.read_piece_size()
+            })?;
+        let (free_set, errors) = locked.0.walk_free_piece_lists(&piece_sizes)?;
+        Ok((piece_sizes, free_set, errors))
+    }
+    /// the used pieces in the order of the offset, that are scanned from the
+    /// front of the file. the free piece has the zero key length.
+    pub fn used_pieces(&self) -> Result<Vec<KeyPieceOffset>> {
+        let mut offsets: Vec<u64> = {
+            let mut locked = RefCell::borrow_mut(&self.0);
+            let piece_sizes = locked
+                .0
+                .piece_sizes(KeyPieceOffset::new(DAT_HEADER_SZ), |file: &mut VarFile| {
+                    file.read_piece_size()
+                })?;
+            piece_sizes.into_keys().collect()
+        };
+        offsets.sort_unstable();
+        let mut vec = Vec::with_capacity(offsets.len());
+        for offset in offsets {
+            let offset = KeyPieceOffset::new(offset);
+            if !self.read_piece_only_key_length(offset)?.is_zero() {
+                vec.push(offset);
+            }
+        }
+        Ok(vec)
+    }
+    #[inline]
+    pub fn has_checksum(&self) -> bool {
+        RefCell::borrow(&self.0).0.has_checksum()
+    }
     #[cfg(feature = "buf_stats")]
     #[inline]
     pub fn buf_stats(&self) -> Vec<(String, i64)> {
@@ -171,54 +294,126 @@ The db data header size is 192 bytes.
 +--------+-------+-------------+---------------------------+
 | 0      | 8     | signature1  | b"siamdbK\0"              |
 | 8      | 8     | signature2  | 8 bytes type signature    |
-| 16     | 8     | reserve0    |                           |
-| 24     | 8     | reserve1    |                           |
+| 16     | 8     | format      | format flags              |
+| 24     | 8     | dirty flag  | not zero while writing    |
 | 32     | 8     | free1 off   | offset of free 1st list   |
 | ...    | ...   | ...         | ...                       |
 | 152    | 8     | free16 off  | offset of free 16th list  |
-| 160    | 32    | reserve2    |                           |
+| 160    | 4     | version     | version of the format     |
+| 164    | 4     | encoding    | encoding of the integers  |
+| 168    | 24    | reserve2    |                           |
 +--------+-------+-------------+---------------------------+
 ```
 
 - signature1: always fixed 8 bytes
 - signature2: 8 bytes type signature
+- format: 1 is the checksum at the end of the piece.
+- dirty flag: it is set on the first writing, and is cleared by the sync.
+- version and encoding: 0 is the file before the version, it has the encoding of
+  the default features, and only the build of it reads the file.
 
 */
-fn write_keyrecf_init_header(file: &mut VarFile, signature2: HeaderSignature) -> Result<()> {
+fn write_keyrecf_init_header(
+    file: &mut VarFile,
+    signature2: HeaderSignature,
+    format: u64,
+    enc: Encoding,
+) -> Result<()> {
+    file.set_encoding(enc);
     file.seek_from_start(KeyPieceOffset::new(0))?;
     // signature1
     file.write_all(&DAT_HEADER_SIGNATURE)?;
     // signature2
     file.write_all(&signature2)?;
-    // reserve0
-    file.write_u64_le(0)?;
-    // reserve1
+    // format
+    file.write_u64_le(format)?;
+    // dirty flag
     file.write_u64_le(0)?;
-    // free1 .. reserve2
-    file.write_all(&[0u8; 160])?;
+    // free1 .. free16
+    file.write_all(&[0u8; 128])?;
+    // version and encoding
+    file.write_u32_le(FORMAT_VERSION)?;
+    file.write_u32_le(enc.to_u32())?;
+    // reserve2
+    file.write_all(&[0u8; 24])?;
     //
     Ok(())
 }
 
-fn check_keyrecf_header(file: &mut VarFile, signature2: HeaderSignature) -> Result<()> {
+/// read the signature2 of the key file header, without opening as the db map.
+pub(crate) fn read_keyrecf_signature2<P: AsRef<Path>>(path: P) -> Result<HeaderSignature> {
+    let mut file = std::fs::File::open(path.as_ref())?;
+    let mut sig = [0u8; 16];
+    file.read_exact(&mut sig)?;
+    if sig[..8] != DAT_HEADER_SIGNATURE {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid header signature1: {}", path.as_ref().display()),
+        ));
+    }
+    let mut sig2 = [0u8; 8];
+    sig2.copy_from_slice(&sig[8..]);
+    Ok(sig2)
+}
+
+fn check_keyrecf_header(
+    file: &mut VarFile,
+    signature2: HeaderSignature,
+    path: &Path,
+) -> Result<u64> {
+    // file length
+    let file_length: KeyPieceOffset = file.seek_to_end()?;
+    if file_length.as_value() < DAT_HEADER_SZ {
+        return Err(invalid_header_error(
+            path,
+            file_length.as_value(),
+            "file length",
+            format!("at least {} bytes", DAT_HEADER_SZ),
+            format!("{} bytes", file_length.as_value()),
+        ));
+    }
     file.seek_from_start(KeyPieceOffset::new(0))?;
     // signature1
     let mut sig1 = [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8];
     file.read_exact(&mut sig1)?;
-    assert!(sig1 == DAT_HEADER_SIGNATURE, "invalid header signature1");
+    if sig1 != DAT_HEADER_SIGNATURE {
+        return Err(invalid_header_error(
+            path,
+            0,
+            "signature1",
+            signature_string(&DAT_HEADER_SIGNATURE),
+            signature_string(&sig1),
+        ));
+    }
     // signature2
     let mut sig2 = [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8];
     file.read_exact(&mut sig2)?;
-    assert!(
-        sig2 == signature2,
-        "invalid header signature2, type signature: {:?}",
-        sig2
-    );
-    // reserve0
-    let _reserve0 = file.read_u64_le()?;
-    assert!(_reserve0 == 0, "invalid reserve0");
+    if sig2 != signature2 {
+        return Err(key_type_mismatch_error(path, &signature2, &sig2));
+    }
+    // format
+    let format = file.read_u64_le()?;
+    if format & !FORMAT_CHECKSUM != 0 {
+        return Err(invalid_header_error(
+            path,
+            16,
+            "format",
+            format!("0 or {}", FORMAT_CHECKSUM),
+            format.to_string(),
+        ));
+    }
+    // version and encoding
+    file.seek_from_start(KeyPieceOffset::new(DAT_HEADER_VERSION_OFFSET))?;
+    let version = file.read_u32_le()?;
+    let encoding = file.read_u32_le()?;
+    let enc = check_format_version(path, DAT_HEADER_VERSION_OFFSET, version, encoding)?;
+    let enc = match enc {
+        Some(enc) => enc,
+        None => legacy_encoding(path, Encoding::build())?,
+    };
+    file.set_encoding(enc);
     //
-    Ok(())
+    Ok(format)
 }
 
 const REC_SIZE_FREE_OFFSET_1ST: u64 = 32;
@@ -321,39 +516,41 @@ impl<KT: DbMapKeyType> KeyPiece<KT> {
         self.key.hash_value()
     }
     //
-    fn encoded_piece_size(&self) -> (u32, u32, KeyLength) {
+    fn encoded_piece_size(&self, enc: Encoding) -> (u32, u32, KeyLength) {
         let key = self.key.as_bytes();
         #[cfg(feature = "siamese_debug")]
         let key_len = KeyLength::new(key.len().try_into().unwrap());
         #[cfg(not(feature = "siamese_debug"))]
         let key_len = KeyLength::new(key.len() as u32);
         //
-        #[cfg(any(feature = "vf_u32u32", feature = "vf_u64u64"))]
-        let (encorded_piece_len, piece_len) = {
-            let enc_key_len = 4;
-            #[cfg(feature = "vf_u32u32")]
-            let enc_val_off = 4;
-            #[cfg(feature = "vf_u64u64")]
-            let enc_val_off = 8;
-            //
-            let piece_len: u32 = enc_key_len + key_len.as_value() + enc_val_off;
-            //
-            let encorded_piece_len = 4;
-            (encorded_piece_len, piece_len)
-        };
-        #[cfg(feature = "vf_vu64")]
-        let (encorded_piece_len, piece_len) = {
-            let enc_key_len = vu64::encoded_len(key_len.as_value() as u64) as u32;
-            //
-            #[cfg(any(feature = "htx", feature = "idx_straight"))]
-            let enc_val_off = 8;
-            #[cfg(not(any(feature = "htx", feature = "idx_straight")))]
-            let enc_val_off = vu64::encoded_len(self.value_offset.as_value() as u64) as u32;
-            //
-            let piece_len: u32 = enc_key_len + key_len.as_value() + enc_val_off;
-            //
-            let encorded_piece_len = vu64::encoded_len((piece_len as u64 + 7) / 8) as u32;
-            (encorded_piece_len, piece_len)
+        let (encorded_piece_len, piece_len) = match enc.piece {
+            IntEncoding::U32U32 | IntEncoding::U64U64 => {
+                let enc_key_len = 4;
+                let enc_val_off = if enc.piece == IntEncoding::U32U32 {
+                    4
+                } else {
+                    8
+                };
+                //
+                let piece_len: u32 = enc_key_len + key_len.as_value() + enc_val_off;
+                //
+                let encorded_piece_len = 4;
+                (encorded_piece_len, piece_len)
+            }
+            IntEncoding::Vu64 => {
+                let enc_key_len = vu64::encoded_len(key_len.as_value() as u64) as u32;
+                //
+                let enc_val_off = if enc.value_offset_fixed {
+                    8
+                } else {
+                    vu64::encoded_len(self.value_offset.as_value() / 8) as u32
+                };
+                //
+                let piece_len: u32 = enc_key_len + key_len.as_value() + enc_val_off;
+                //
+                let encorded_piece_len = vu64::encoded_len((piece_len as u64 + 7) / 8) as u32;
+                (encorded_piece_len, piece_len)
+            }
         };
         //
         (encorded_piece_len, piece_len, key_len)
@@ -373,12 +570,10 @@ impl<KT: DbMapKeyType> KeyPiece<KT> {
         file.write_key_len(key_len)?;
         file.write_all_small(key)?;
         //
-        #[cfg(any(feature = "htx", feature = "idx_straight"))]
         file.write_value_piece_offset(self.value_offset)?;
-        #[cfg(not(any(feature = "htx", feature = "idx_straight")))]
-        file.write_piece_offset(self.value_offset)?;
         //
         file.write_zero_to_offset(self.offset + self.size)?;
+        file.write_piece_checksum(self.offset, self.size)?;
         //
         Ok(())
     }
@@ -403,11 +598,10 @@ impl<KT: DbMapKeyType> VarFileKeyCache<KT> {
     fn write_piece(&mut self, mut piece: KeyPiece<KT>, is_new: bool) -> Result<KeyPiece<KT>> {
         debug_assert!(is_new || !piece.offset.is_zero());
         //
-        let (encorded_piece_len, piece_len, _key_len) = piece.encoded_piece_size();
-        let new_piece_size = self
-            .0
-            .piece_mgr
-            .roundup(KeyPieceSize::new(encorded_piece_len + piece_len));
+        let (encorded_piece_len, piece_len, _key_len) = piece.encoded_piece_size(self.0.encoding());
+        let new_piece_size = self.0.piece_mgr.roundup(KeyPieceSize::new(
+            encorded_piece_len + piece_len + self.0.checksum_len(),
+        ));
         //
         if !is_new {
             let old_piece_size = {
@@ -453,10 +647,22 @@ impl<KT: DbMapKeyType> VarFileKeyCache<KT> {
         Ok(piece)
     }
 
+    /// checks the checksum of the piece, if the file has it.
+    #[inline]
+    fn check_piece_checksum(&mut self, offset: KeyPieceOffset) -> Result<()> {
+        if self.0.has_checksum() {
+            self.0.seek_from_start(offset)?;
+            let piece_size = self.0.read_piece_size()?;
+            self.0.check_piece_checksum(offset, piece_size)?;
+        }
+        Ok(())
+    }
+
     #[inline]
     fn read_piece(&mut self, offset: KeyPieceOffset) -> Result<KeyPiece<KT>> {
         debug_assert!(!offset.is_zero());
         //
+        self.check_piece_checksum(offset)?;
         self.0.seek_from_start(offset)?;
         let piece_size = self.0.read_piece_size()?;
         debug_assert!(piece_size.is_valid_key());
@@ -464,10 +670,7 @@ impl<KT: DbMapKeyType> VarFileKeyCache<KT> {
         let maybe_slice = self.0.read_exact_maybeslice(key_len.into())?;
         let key = KT::from_bytes(&maybe_slice);
         //
-        #[cfg(any(feature = "htx", feature = "idx_straight"))]
         let val_offset = self.0.read_value_piece_offset()?;
-        #[cfg(not(any(feature = "htx", feature = "idx_straight")))]
-        let val_offset = self.0.read_piece_offset()?;
         //
         let piece = KeyPiece::with(offset, piece_size, key, val_offset);
         //
@@ -487,6 +690,7 @@ impl<KT: DbMapKeyType> VarFileKeyCache<KT> {
     fn read_piece_only_key_length(&mut self, offset: KeyPieceOffset) -> Result<KeyLength> {
         debug_assert!(!offset.is_zero());
         //
+        self.check_piece_checksum(offset)?;
         self.0.seek_skip_to_piece_key(offset)?;
         let key_len = self.0.read_key_len()?;
         Ok(key_len)
@@ -496,9 +700,10 @@ impl<KT: DbMapKeyType> VarFileKeyCache<KT> {
     pub fn read_piece_only_key_maybeslice(
         &mut self,
         offset: KeyPieceOffset,
-    ) -> Result<rabuf::MaybeSlice> {
+    ) -> Result<rabuf::MaybeSlice<'_>> {
         debug_assert!(!offset.is_zero());
         //
+        self.check_piece_checksum(offset)?;
         self.0.seek_skip_to_piece_key(offset)?;
         let key_len = self.0.read_key_len()?;
         let maybe_slice = self.0.read_exact_maybeslice(key_len.into())?;
@@ -509,6 +714,7 @@ impl<KT: DbMapKeyType> VarFileKeyCache<KT> {
     fn read_piece_only_key(&mut self, offset: KeyPieceOffset) -> Result<KT> {
         debug_assert!(!offset.is_zero());
         //
+        self.check_piece_checksum(offset)?;
         self.0.seek_skip_to_piece_key(offset)?;
         let key_len = self.0.read_key_len()?;
         let maybe_slice = self.0.read_exact_maybeslice(key_len.into())?;
@@ -519,14 +725,12 @@ impl<KT: DbMapKeyType> VarFileKeyCache<KT> {
     fn read_piece_only_value_offset(&mut self, offset: KeyPieceOffset) -> Result<ValuePieceOffset> {
         debug_assert!(!offset.is_zero());
         //
+        self.check_piece_checksum(offset)?;
         self.0.seek_skip_to_piece_key(offset)?;
         let key_len = self.0.read_key_len()?;
         self.0.seek_skip_length(key_len)?;
         //
-        #[cfg(any(feature = "htx", feature = "idx_straight"))]
         let value_offset = self.0.read_value_piece_offset()?;
-        #[cfg(not(any(feature = "htx", feature = "idx_straight")))]
-        let value_offset = self.0.read_piece_offset()?;
         //
         Ok(value_offset)
     }
@@ -543,6 +747,7 @@ used piece:
 | --     | --    | key data    | raw key data                      |
 | --     | 8     | val offset  | value piece offset: u64           |
 | --     | --    | reserve     | reserved free space               |
+| --     | 4     | checksum    | CRC-32C of the above, if format 1 |
 +--------+-------+-------------+-----------------------------------+
 ```
 */
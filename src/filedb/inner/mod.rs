@@ -1,12 +1,23 @@
-use super::super::DbXxxBase;
-use super::{FileDbMapDbBytes, FileDbMapDbInt, FileDbMapDbString, FileDbParams};
+use super::super::{DbMapKeyType, DbXxxBase};
+use super::super::{Error as DbError, Result as DbResult};
+use super::cell::{RefCell, RefMut};
+use super::{
+    BackupStats, DbBytes, DbInt, DbMapKeyKind, DbString, FileDbLockMode, FileDbMapDbBytes,
+    FileDbMapDbInt, FileDbMapDbString, FileDbParams, FileDbXxxInner, RepairStats, VerifyProblem,
+};
 use std::collections::BTreeMap;
-use std::io::Result;
+use std::fs::File;
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use vfile::{SnapshotRef, SNAPSHOT_PAGE_SIZE};
 
 pub(crate) mod dbxxx;
 pub(crate) mod semtype;
 
+mod crc;
+mod crypt;
+mod enc;
+mod flock;
 mod piece;
 mod tr;
 
@@ -14,6 +25,7 @@ mod idx;
 mod key;
 mod val;
 mod vfile;
+pub(crate) mod wal;
 
 #[cfg(feature = "htx")]
 mod htx;
@@ -24,6 +36,10 @@ mod nc;
 #[cfg(feature = "node_cache")]
 mod offidx;
 
+/// the file extensions of a db map. the key file is the last,
+/// because it is the mark of the existence of the db map.
+const DB_MAP_FILE_EXTS: [&str; 4] = ["htx", "idx", "val", "key"];
+
 #[derive(Debug)]
 pub struct FileDbInner {
     db_bytes_map: BTreeMap<String, FileDbMapDbBytes>,
@@ -31,25 +47,73 @@ pub struct FileDbInner {
     db_string_map: BTreeMap<String, FileDbMapDbString>,
     //
     path: PathBuf,
+    read_only: bool,
+    wal_lock: wal::WalLock,
+    _lock: flock::FileLock,
 }
 
 impl FileDbInner {
-    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileDbInner> {
+    pub fn open<P: AsRef<Path>>(path: P, mode: FileDbLockMode) -> Result<FileDbInner> {
+        // the shared lock is for the readers, the writing needs the exclusive one.
+        if mode == FileDbLockMode::Shared {
+            return Self::open_read_only(path);
+        }
         let path = path.as_ref();
+        super::migrate::recover(path)?;
         if !path.is_dir() {
             std::fs::create_dir_all(path)?;
         }
+        let lock = flock::FileLock::lock(path, mode)?;
+        wal::recover(path)?;
         Ok(FileDbInner {
             db_bytes_map: BTreeMap::new(),
             db_int_map: BTreeMap::new(),
             db_string_map: BTreeMap::new(),
             path: path.to_path_buf(),
+            read_only: false,
+            wal_lock: wal::WalLock::default(),
+            _lock: lock,
+        })
+    }
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<FileDbInner> {
+        let path = path.as_ref();
+        if !path.is_dir() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("not found the database: {}", path.display()),
+            ));
+        }
+        let lock = flock::FileLock::lock(path, FileDbLockMode::Shared)?;
+        wal::check_no_committed_wal(path)?;
+        Ok(FileDbInner {
+            db_bytes_map: BTreeMap::new(),
+            db_int_map: BTreeMap::new(),
+            db_string_map: BTreeMap::new(),
+            path: path.to_path_buf(),
+            read_only: true,
+            wal_lock: wal::WalLock::default(),
+            _lock: lock,
         })
     }
     #[inline]
     pub fn path(&self) -> &Path {
         self.path.as_path()
     }
+    #[inline]
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    #[inline]
+    pub fn wal_lock(&self) -> wal::WalLock {
+        self.wal_lock.clone()
+    }
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            Err(DbError::ReadOnly(self.path.clone()).into())
+        } else {
+            Ok(())
+        }
+    }
     pub fn sync_all(&self) -> Result<()> {
         self.applay_all(|o| o.sync_all())
     }
@@ -58,7 +122,7 @@ impl FileDbInner {
     }
     fn applay_all<F>(&self, func: F) -> Result<()>
     where
-        F: Fn(&mut dyn DbXxxBase) -> Result<()>,
+        F: Fn(&mut dyn DbXxxBase) -> DbResult<()>,
     {
         {
             let keys: Vec<_> = self.db_bytes_map.keys().cloned().collect();
@@ -126,18 +190,537 @@ impl FileDbInner {
 
 impl FileDbInner {
     pub(super) fn create_db_map(&mut self, name: &str, params: FileDbParams) -> Result<()> {
-        let child: FileDbMapDbString = FileDbMapDbString::open(self.path(), name, params)?;
+        check_map_name(name)?;
+        self.check_opened_kind(name, DbMapKeyKind::DbString)?;
+        let child: FileDbMapDbString =
+            FileDbMapDbString::open(self.path(), name, params, self.read_only, self.wal_lock())?;
         let _ = self.db_map_insert(name, child);
         Ok(())
     }
     pub(super) fn create_db_map_bytes(&mut self, name: &str, params: FileDbParams) -> Result<()> {
-        let child: FileDbMapDbBytes = FileDbMapDbBytes::open(self.path(), name, params)?;
+        check_map_name(name)?;
+        self.check_opened_kind(name, DbMapKeyKind::DbBytes)?;
+        let child: FileDbMapDbBytes =
+            FileDbMapDbBytes::open(self.path(), name, params, self.read_only, self.wal_lock())?;
         let _ = self.db_map_bytes_insert(name, child);
         Ok(())
     }
     pub(super) fn create_db_map_dbint(&mut self, name: &str, params: FileDbParams) -> Result<()> {
-        let child: FileDbMapDbInt = FileDbMapDbInt::open(self.path(), name, params)?;
+        check_map_name(name)?;
+        self.check_opened_kind(name, DbMapKeyKind::DbInt)?;
+        let child: FileDbMapDbInt =
+            FileDbMapDbInt::open(self.path(), name, params, self.read_only, self.wal_lock())?;
         let _ = self.db_map_dbint_insert(name, child);
         Ok(())
     }
+    /// the header of the db map that is opened may be not yet written to the file,
+    /// so the key kind of it is checked here.
+    fn check_opened_kind(&self, name: &str, kind: DbMapKeyKind) -> Result<()> {
+        let opened = if self.db_string_map.contains_key(name) {
+            DbMapKeyKind::DbString
+        } else if self.db_int_map.contains_key(name) {
+            DbMapKeyKind::DbInt
+        } else if self.db_bytes_map.contains_key(name) {
+            DbMapKeyKind::DbBytes
+        } else {
+            return Ok(());
+        };
+        if opened == kind {
+            return Ok(());
+        }
+        Err(key_type_mismatch_error(
+            &self.db_map_file_path(name, "key"),
+            &kind.signature(),
+            &opened.signature(),
+        ))
+    }
+}
+
+impl FileDbInner {
+    fn db_map_file_path(&self, name: &str, ext: &str) -> PathBuf {
+        let mut pb = self.path.clone();
+        pb.push(format!("{}.{}", name, ext));
+        pb
+    }
+    fn db_map_kind(&self, name: &str) -> Result<Option<DbMapKeyKind>> {
+        if self.db_string_map.contains_key(name) {
+            return Ok(Some(DbMapKeyKind::DbString));
+        }
+        if self.db_int_map.contains_key(name) {
+            return Ok(Some(DbMapKeyKind::DbInt));
+        }
+        if self.db_bytes_map.contains_key(name) {
+            return Ok(Some(DbMapKeyKind::DbBytes));
+        }
+        let key_path = self.db_map_file_path(name, "key");
+        if !key_path.is_file() {
+            return Ok(None);
+        }
+        let sig2 = key::read_keyrecf_signature2(&key_path)?;
+        match DbMapKeyKind::from_signature(&sig2) {
+            Some(kind) => Ok(Some(kind)),
+            None => Err(invalid_header_error(
+                &key_path,
+                8,
+                "signature2, type signature",
+                "\"string\", \"uint64\" or \"bytes\"".to_string(),
+                signature_string(&sig2),
+            )),
+        }
+    }
+    pub(super) fn list_maps(&self) -> Result<Vec<(String, DbMapKeyKind)>> {
+        let mut names: Vec<String> = Vec::new();
+        for entry in std::fs::read_dir(self.path())? {
+            let file_name = entry?.file_name();
+            if let Some(name) = file_name.to_str().and_then(|a| a.strip_suffix(".key")) {
+                // the temporary files of the compaction are not the db map.
+                if check_map_name(name).is_ok() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        names.extend(self.db_string_map.keys().cloned());
+        names.extend(self.db_int_map.keys().cloned());
+        names.extend(self.db_bytes_map.keys().cloned());
+        names.sort_unstable();
+        names.dedup();
+        //
+        let mut vec = Vec::with_capacity(names.len());
+        for name in names {
+            if let Some(kind) = self.db_map_kind(&name)? {
+                vec.push((name, kind));
+            }
+        }
+        Ok(vec)
+    }
+    pub(super) fn drop_map(&mut self, name: &str) -> Result<()> {
+        self.check_writable()?;
+        check_map_name(name)?;
+        if self.db_map_kind(name)?.is_none() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("not found db map: {}", name),
+            ));
+        }
+        self.check_not_in_use(name)?;
+        let _ = self.db_string_map.remove(name);
+        let _ = self.db_int_map.remove(name);
+        let _ = self.db_bytes_map.remove(name);
+        for ext in DB_MAP_FILE_EXTS {
+            match std::fs::remove_file(self.db_map_file_path(name, ext)) {
+                Ok(()) => (),
+                Err(err) if err.kind() == ErrorKind::NotFound => (),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+    pub(super) fn repair_map(&mut self, name: &str, params: FileDbParams) -> Result<RepairStats> {
+        check_map_name(name)?;
+        self.check_writable()?;
+        let kind = match self.db_map_kind(name)? {
+            Some(kind) => kind,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::NotFound,
+                    format!("not found db map: {}", name),
+                ))
+            }
+        };
+        // the handle of the db map becomes invalid by the repair.
+        self.check_not_in_use(name)?;
+        // the buffers of the opened db map are written before it is closed.
+        if let Some(mut m) = self.db_string_map.remove(name) {
+            m.sync_all()?;
+        }
+        if let Some(mut m) = self.db_int_map.remove(name) {
+            m.sync_all()?;
+        }
+        if let Some(mut m) = self.db_bytes_map.remove(name) {
+            m.sync_all()?;
+        }
+        match kind {
+            DbMapKeyKind::DbString => FileDbXxxInner::<DbString>::repair(self.path(), name, params),
+            DbMapKeyKind::DbInt => FileDbXxxInner::<DbInt>::repair(self.path(), name, params),
+            DbMapKeyKind::DbBytes => FileDbXxxInner::<DbBytes>::repair(self.path(), name, params),
+        }
+    }
+    /// verifies the db map by the opened handle, or by the files opened as read only.
+    pub(super) fn verify_map(&self, name: &str, kind: DbMapKeyKind) -> Result<Vec<VerifyProblem>> {
+        let path = self.path();
+        match kind {
+            DbMapKeyKind::DbString => match self.db_map_string(name) {
+                Some(m) => m.verify().map_err(Error::from),
+                None => FileDbXxxInner::<DbString>::open_and_verify(path, name, Default::default()),
+            },
+            DbMapKeyKind::DbInt => match self.db_map_int(name) {
+                Some(m) => m.verify().map_err(Error::from),
+                None => FileDbXxxInner::<DbInt>::open_and_verify(path, name, Default::default()),
+            },
+            DbMapKeyKind::DbBytes => match self.db_map_bytes(name) {
+                Some(m) => m.verify().map_err(Error::from),
+                None => FileDbXxxInner::<DbBytes>::open_and_verify(path, name, Default::default()),
+            },
+        }
+    }
+    pub(super) fn rename_map(&mut self, old_name: &str, new_name: &str) -> Result<()> {
+        self.check_writable()?;
+        check_map_name(old_name)?;
+        check_map_name(new_name)?;
+        if self.db_map_kind(old_name)?.is_none() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("not found db map: {}", old_name),
+            ));
+        }
+        if self.db_map_kind(new_name)?.is_some() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("already exists db map: {}", new_name),
+            ));
+        }
+        self.check_not_in_use(old_name)?;
+        // the files that are already renamed are renamed back on the error.
+        let mut renamed: Vec<&str> = Vec::new();
+        for ext in DB_MAP_FILE_EXTS {
+            let old_path = self.db_map_file_path(old_name, ext);
+            if !old_path.exists() {
+                continue;
+            }
+            if let Err(err) = std::fs::rename(&old_path, self.db_map_file_path(new_name, ext)) {
+                for ext in renamed.into_iter().rev() {
+                    let _ = std::fs::rename(
+                        self.db_map_file_path(new_name, ext),
+                        self.db_map_file_path(old_name, ext),
+                    );
+                }
+                return Err(err);
+            }
+            renamed.push(ext);
+        }
+        // the opened files are still valid after the renaming.
+        if let Some(m) = self.db_string_map.remove(old_name) {
+            m.set_name(new_name);
+            let _ = self.db_map_insert(new_name, m);
+        }
+        if let Some(m) = self.db_int_map.remove(old_name) {
+            m.set_name(new_name);
+            let _ = self.db_map_dbint_insert(new_name, m);
+        }
+        if let Some(m) = self.db_bytes_map.remove(old_name) {
+            m.set_name(new_name);
+            let _ = self.db_map_bytes_insert(new_name, m);
+        }
+        Ok(())
+    }
+    /// the db map that has the handles other than the one of `FileDb` can not be
+    /// dropped or renamed, because the handles keep writing the files.
+    fn check_not_in_use(&self, name: &str) -> Result<()> {
+        let count = if let Some(m) = self.db_string_map.get(name) {
+            m.handle_count()
+        } else if let Some(m) = self.db_int_map.get(name) {
+            m.handle_count()
+        } else if let Some(m) = self.db_bytes_map.get(name) {
+            m.handle_count()
+        } else {
+            return Ok(());
+        };
+        if count > 1 {
+            return Err(Error::new(
+                ErrorKind::WouldBlock,
+                format!("the db map is in use: {}", name),
+            ));
+        }
+        Ok(())
+    }
+}
+
+// for backup
+impl FileDbInner {
+    /// copies the files of all db maps into the empty directory of `dst`.
+    ///
+    /// the opened db maps are locked and synced all together, and the snapshots
+    /// of their files are started, that is the point in time of the backup.
+    /// the locks are released soon after it, and then the files are copied.
+    /// the old contents of the pages that are changed while the copying are kept
+    /// in the snapshots, until the copy goes over them.
+    /// the committed write-ahead log of the transaction that is not applied yet
+    /// is copied too, and it is replayed on opening the backup.
+    pub(super) fn backup_to(&self, dst: &Path) -> Result<BackupStats> {
+        if dst.exists() && std::fs::read_dir(dst)?.next().is_some() {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("the backup directory is not empty: {}", dst.display()),
+            ));
+        }
+        std::fs::create_dir_all(dst)?;
+        let mut stats = BackupStats::default();
+        let mut snapshots: Vec<(String, &'static str, SnapshotRef)> = Vec::new();
+        let r = self
+            .begin_backup(dst, &mut stats, &mut snapshots)
+            .and_then(|files| {
+                for (file_name, mut file, snapshot) in files {
+                    let dst_path = dst.join(file_name);
+                    stats.byte_count +=
+                        copy_file_snapshot(&mut file, snapshot.as_ref(), &dst_path)?;
+                }
+                Ok(())
+            });
+        // the writing does not keep the pages any more, even on the error.
+        for (_name, _ext, snapshot) in snapshots.iter() {
+            RefCell::borrow_mut(snapshot).finish();
+        }
+        r.map(|()| stats)
+    }
+    /// starts the snapshots of the opened db maps under the locks, and opens
+    /// the files to be copied. returns the file names, the files and the snapshots.
+    #[allow(clippy::type_complexity)]
+    fn begin_backup(
+        &self,
+        dst: &Path,
+        stats: &mut BackupStats,
+        snapshots: &mut Vec<(String, &'static str, SnapshotRef)>,
+    ) -> Result<Vec<(String, File, Option<SnapshotRef>)>> {
+        // the log is not written while the snapshots are started.
+        let _wal_locked = RefCell::borrow_mut(&self.wal_lock);
+        let names = self.list_maps()?;
+        let mut locked: Vec<Box<dyn BackupLock + '_>> = Vec::new();
+        for (name, _kind) in names.iter() {
+            let mut lock: Option<Box<dyn BackupLock + '_>> = None;
+            if let Some(m) = self.db_string_map.get(name) {
+                lock = Some(Box::new(m.lock()));
+            }
+            if let Some(m) = self.db_int_map.get(name) {
+                lock = Some(Box::new(m.lock()));
+            }
+            if let Some(m) = self.db_bytes_map.get(name) {
+                lock = Some(Box::new(m.lock()));
+            }
+            if let Some(mut lock) = lock {
+                for (ext, snapshot) in lock.begin_snapshot()? {
+                    snapshots.push((name.clone(), ext, snapshot));
+                }
+                locked.push(lock);
+            }
+        }
+        stats.byte_count += wal::copy_wal_file(self.path(), dst)?;
+        let mut files = Vec::new();
+        for (name, _kind) in names.iter() {
+            for ext in DB_MAP_FILE_EXTS {
+                let src_path = self.db_map_file_path(name, ext);
+                if !src_path.exists() {
+                    continue;
+                }
+                let snapshot = snapshots
+                    .iter()
+                    .find(|a| &a.0 == name && a.1 == ext)
+                    .map(|a| a.2.clone());
+                let file_name = format!("{}.{}", name, ext);
+                files.push((file_name, File::open(&src_path)?, snapshot));
+            }
+        }
+        stats.map_count = names.len() as u64;
+        Ok(files)
+    }
+}
+
+/// the db map that is locked while the snapshots of its files are started.
+trait BackupLock {
+    fn begin_snapshot(&mut self) -> Result<Vec<(&'static str, SnapshotRef)>>;
+}
+
+impl<KT: DbMapKeyType> BackupLock for RefMut<'_, FileDbXxxInner<KT>> {
+    fn begin_snapshot(&mut self) -> Result<Vec<(&'static str, SnapshotRef)>> {
+        FileDbXxxInner::begin_snapshot(self)
+    }
+}
+
+/// copies the file at the point in time of the snapshot, and syncs the copy.
+/// the file that is not opened as the db map has no snapshot, it is not changed.
+fn copy_file_snapshot(src: &mut File, snapshot: Option<&SnapshotRef>, dst: &Path) -> Result<u64> {
+    let mut dst_file = File::create(dst)?;
+    let snapshot = match snapshot {
+        Some(snapshot) => snapshot,
+        None => {
+            let n = std::io::copy(src, &mut dst_file)?;
+            dst_file.sync_all()?;
+            return Ok(n);
+        }
+    };
+    let len = RefCell::borrow(snapshot).len();
+    let mut buf = vec![0u8; BACKUP_CHUNK_SIZE as usize];
+    let mut offset = 0;
+    while offset < len {
+        let end = len.min(offset + BACKUP_CHUNK_SIZE);
+        let chunk = &mut buf[..((end - offset) as usize)];
+        {
+            // the file is read under the lock of the snapshot, so the page that
+            // is not kept in it is not changed yet.
+            let mut locked = RefCell::borrow_mut(snapshot);
+            let _ = src.seek(SeekFrom::Start(offset))?;
+            read_fill_zero(src, chunk)?;
+            for (page_offset, page) in locked.take_pages(end) {
+                let st = (page_offset - offset) as usize;
+                chunk[st..(st + page.len())].copy_from_slice(&page);
+            }
+        }
+        dst_file.write_all(chunk)?;
+        offset = end;
+    }
+    dst_file.sync_all()?;
+    Ok(len)
+}
+
+/// reads the buffer, the part of it over the end of the file is zero.
+/// the file may be truncated after the snapshot, the pages are kept in it.
+fn read_fill_zero(src: &mut File, buf: &mut [u8]) -> Result<()> {
+    let mut done = 0;
+    while done < buf.len() {
+        match src.read(&mut buf[done..]) {
+            Ok(0) => break,
+            Ok(n) => done += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+    buf[done..].fill(0);
+    Ok(())
+}
+
+/// the chunk size of the copy of the backup, it is a multiple of the page size
+/// of the snapshot.
+const BACKUP_CHUNK_SIZE: u64 = 16 * SNAPSHOT_PAGE_SIZE;
+
+/// the name of the db map is a part of the file names, so it must not have
+/// the path separators or `..`.
+fn check_map_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains(['/', '\\', '\0']) || name.contains("..") {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("invalid db map name: {:?}", name),
+        ));
+    }
+    Ok(())
+}
+
+/// the format flag in the file header, the pieces have the checksum.
+pub(crate) const FORMAT_CHECKSUM: u64 = 1;
+
+/// the format flag in the header of the value file, the value may be encoded
+/// by the value codec.
+pub(crate) const FORMAT_VALUE_CODEC: u64 = 2;
+
+/// the format flag in the header of the value file, the values are encrypted.
+pub(crate) const FORMAT_ENCRYPTION: u64 = 4;
+
+/// the error of the file header that is not the expected one.
+pub(crate) fn invalid_header_error<D: std::fmt::Display>(
+    path: &Path,
+    offset: u64,
+    field: &str,
+    expected: D,
+    found: D,
+) -> Error {
+    DbError::Corruption {
+        file: path.to_path_buf(),
+        offset,
+        reason: format!(
+            "invalid header {}: expected {}, found {}",
+            field, expected, found
+        ),
+    }
+    .into()
+}
+
+/// the error of the piece that the checksum does not match.
+pub(crate) fn checksum_error(path: &Path, offset: u64) -> Error {
+    DbError::Corruption {
+        file: path.to_path_buf(),
+        offset,
+        reason: "checksum mismatch".to_string(),
+    }
+    .into()
+}
+
+/// the error of the type signature in the file header that is not the expected one.
+pub(crate) fn key_type_mismatch_error(path: &Path, expected: &[u8; 8], found: &[u8; 8]) -> Error {
+    DbError::KeyTypeMismatch {
+        file: path.to_path_buf(),
+        expected: signature_string(expected),
+        found: signature_string(found),
+    }
+    .into()
+}
+
+/// the header signature as the quoted string for the error message.
+pub(crate) fn signature_string(sig: &[u8; 8]) -> String {
+    format!("{:?}", String::from_utf8_lossy(sig).trim_end_matches('\0'))
+}
+
+//--
+#[cfg(test)]
+mod debug {
+    use super::super::super::{open_file, DbXxx, DbXxxBase};
+    use super::copy_file_snapshot;
+    use super::RefCell;
+    use std::fs::File;
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 1000;
+    //
+    #[test]
+    fn test_backup_snapshot() {
+        let db_name = "target/tmp/test_inner-s.siamesedb";
+        let backup_name = "target/tmp/test_inner-s.backup.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let _ = std::fs::remove_dir_all(backup_name);
+        let path = Path::new(db_name);
+        let db = open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        for i in 0..LOOP_MAX {
+            db_map.put_string(&i, &format!("value{}", i)).unwrap();
+        }
+        // the point in time of the backup
+        let mut files: Vec<_> = db_map
+            .lock()
+            .begin_snapshot()
+            .unwrap()
+            .into_iter()
+            .map(|(ext, snapshot)| {
+                let file = File::open(path.join(format!("some_u64_1.{}", ext))).unwrap();
+                (ext, file, snapshot)
+            })
+            .collect();
+        // the writing after it is not in the backup
+        for i in 0..LOOP_MAX {
+            if i % 2 == 0 {
+                db_map.delete(&i).unwrap();
+            } else {
+                db_map.put_string(&i, &"new".repeat(i as usize)).unwrap();
+            }
+        }
+        for i in LOOP_MAX..(LOOP_MAX * 2) {
+            db_map.put_string(&i, &format!("value{}", i)).unwrap();
+        }
+        db_map.sync_all().unwrap();
+        let _ = db_map.compact().unwrap();
+        //
+        std::fs::create_dir_all(backup_name).unwrap();
+        for (ext, file, snapshot) in files.iter_mut() {
+            let dst = Path::new(backup_name).join(format!("some_u64_1.{}", ext));
+            let _ = copy_file_snapshot(file, Some(&*snapshot), &dst).unwrap();
+            RefCell::borrow_mut(snapshot).finish();
+        }
+        let backup = open_file(backup_name).unwrap();
+        let mut backup_map = backup.db_map_int("some_u64_1").unwrap();
+        for i in 0..(LOOP_MAX * 2) {
+            let expected = if i < LOOP_MAX {
+                Some(format!("value{}", i))
+            } else {
+                None
+            };
+            assert_eq!(backup_map.get_string(&i).unwrap(), expected);
+        }
+        assert!(backup.verify().unwrap().is_ok());
+    }
 }
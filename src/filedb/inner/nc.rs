@@ -167,9 +167,9 @@ impl NodeCache {
                     ncb.node_offset.as_value(),
                     offset.as_value()
                 );
-                if ncb.node.is_some() {
-                    debug_assert!(ncb.node.as_ref().unwrap().get_ref().offset() == *offset);
-                    Some(ncb.node.as_ref().unwrap().clone())
+                if let Some(node) = &ncb.node {
+                    debug_assert!(node.get_ref().offset() == *offset);
+                    Some(node.clone())
                 } else {
                     None
                 }
@@ -217,8 +217,8 @@ impl NodeCache {
                 self.touch(idx);
                 let ncb = unsafe { self.vec.get_unchecked_mut(idx) };
                 debug_assert!(ncb.node_offset == node_offset);
-                if ncb.node.is_some() {
-                    debug_assert!(ncb.node.as_ref().unwrap().get_ref().offset() == node_offset);
+                if let Some(_node) = &ncb.node {
+                    debug_assert!(_node.get_ref().offset() == node_offset);
                 }
                 ncb.node = Some(node);
                 ncb.node_size = node_size;
@@ -344,10 +344,10 @@ impl NodeCache {
 #[inline]
 fn write_node(file: &mut VarFile, ncb: &mut NodeCacheBean) -> Result<()> {
     if ncb.dirty {
-        if ncb.node.is_some() {
-            debug_assert!(ncb.node_offset == ncb.node.as_ref().unwrap().get_ref().offset());
-            debug_assert!(ncb.node_size == ncb.node.as_ref().unwrap().get_ref().size());
-            ncb.node.as_mut().unwrap().idx_write_node_one(file)?;
+        if let Some(node) = &ncb.node {
+            debug_assert!(ncb.node_offset == node.get_ref().offset());
+            debug_assert!(ncb.node_size == node.get_ref().size());
+            node.idx_write_node_one(file)?;
         }
         ncb.dirty = false;
     }
@@ -1,22 +1,35 @@
 use super::semtype::*;
 use super::vfile::VarFile;
 use rabuf::{SmallRead, SmallWrite};
-use std::io::Result;
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Read, Result, Write};
 
 /// piece manager. managing free piece list.
 #[derive(Debug)]
 pub struct PieceMgr {
     free_list_offset: &'static [u64],
-    size_ary: &'static [u32],
+    size_ary: Box<[u32]>,
 }
 
 impl PieceMgr {
     pub fn new(free_list_offset: &'static [u64], size_ary: &'static [u32]) -> Self {
         Self {
             free_list_offset,
-            size_ary,
+            size_ary: size_ary.into(),
         }
     }
+    /// the piece manager with the piece sizes that are decided at runtime.
+    pub fn with_size_vec(free_list_offset: &'static [u64], size_ary: Vec<u32>) -> Self {
+        debug_assert!(size_ary.len() == free_list_offset.len());
+        Self {
+            free_list_offset,
+            size_ary: size_ary.into_boxed_slice(),
+        }
+    }
+    #[inline]
+    pub fn size_ary(&self) -> &[u32] {
+        &self.size_ary
+    }
 }
 
 impl PieceMgr {
@@ -216,3 +229,193 @@ impl VarFile {
         Ok((piece_size, next_offset))
     }
 }
+
+// for crash recovery
+impl VarFile {
+    /// rebuilds the free piece lists from scratch. the pieces from `start` to the end
+    /// of file are scanned, and the pieces that are not in `used` become free.
+    /// the broken tail that is not used is truncated.
+    pub fn rebuild_free_piece_list<T, F>(
+        &mut self,
+        start: PieceOffset<T>,
+        used: &HashSet<u64>,
+        read_piece_size_func: F,
+    ) -> Result<()>
+    where
+        T: Copy + PartialEq + PartialOrd,
+        F: Fn(&mut VarFile) -> Result<PieceSize<T>>,
+    {
+        self.clear_free_piece_list()?;
+        let (piece_vec, end) = self.scan_pieces(start, used, read_piece_size_func)?;
+        let file_end: PieceOffset<T> = self.seek_to_end()?;
+        if end < file_end {
+            self.set_file_length(end)?;
+        }
+        let free_iter = piece_vec.into_iter().filter(|a| !a.2);
+        for (piece_offset, piece_size, _) in free_iter.rev() {
+            self.push_free_piece_list(piece_offset, piece_size)?;
+        }
+        Ok(())
+    }
+    /// moves the pieces that are in `used` to the front, from `start`, and
+    /// truncates the file. the free piece lists become empty.
+    /// returns the map of the moved pieces, the old offset to the new offset,
+    /// and the bytes that are reclaimed.
+    pub fn compact_pieces<T, F>(
+        &mut self,
+        start: PieceOffset<T>,
+        used: &HashSet<u64>,
+        read_piece_size_func: F,
+    ) -> Result<(HashMap<u64, u64>, u64)>
+    where
+        T: Copy + PartialEq + PartialOrd,
+        F: Fn(&mut VarFile) -> Result<PieceSize<T>>,
+    {
+        self.clear_free_piece_list()?;
+        let (piece_vec, _end) = self.scan_pieces(start, used, read_piece_size_func)?;
+        let file_end: PieceOffset<T> = self.seek_to_end()?;
+        let mut moved_map = HashMap::new();
+        let mut buf = Vec::new();
+        let mut dst = start;
+        for (piece_offset, piece_size, is_used) in piece_vec {
+            if !is_used {
+                continue;
+            }
+            if piece_offset != dst {
+                buf.resize(piece_size.as_value() as usize, 0);
+                self.seek_from_start(piece_offset)?;
+                self.read_exact(&mut buf)?;
+                self.seek_from_start(dst)?;
+                self.write_all(&buf)?;
+                let _ = moved_map.insert(piece_offset.as_value(), dst.as_value());
+            }
+            dst = dst + piece_size;
+        }
+        if dst < file_end {
+            self.set_file_length(dst)?;
+        }
+        Ok((moved_map, file_end.as_value() - dst.as_value()))
+    }
+    fn clear_free_piece_list(&mut self) -> Result<()> {
+        for i in 0..self.piece_mgr.free_list_offset.len() {
+            let free_offset = self.piece_mgr.free_list_offset[i];
+            self.seek_from_start(Offset::<u64>::new(free_offset))?;
+            self.write_u64_le(0)?;
+        }
+        Ok(())
+    }
+    /// scans the pieces from `start` to the end of file. returns the pieces
+    /// with the used flag, and the end offset of the pieces.
+    #[allow(clippy::type_complexity)]
+    fn scan_pieces<T, F>(
+        &mut self,
+        start: PieceOffset<T>,
+        used: &HashSet<u64>,
+        read_piece_size_func: F,
+    ) -> Result<(Vec<(PieceOffset<T>, PieceSize<T>, bool)>, PieceOffset<T>)>
+    where
+        T: Copy + PartialEq + PartialOrd,
+        F: Fn(&mut VarFile) -> Result<PieceSize<T>>,
+    {
+        let file_end: PieceOffset<T> = self.seek_to_end()?;
+        let mut piece_vec = Vec::new();
+        let mut used_count = 0;
+        let mut offset = start;
+        while offset < file_end {
+            self.seek_from_start(offset)?;
+            let piece_size = read_piece_size_func(self)?;
+            if piece_size.is_zero() || offset + piece_size > file_end {
+                break;
+            }
+            let is_used = used.contains(&offset.as_value());
+            if is_used {
+                used_count += 1;
+            }
+            piece_vec.push((offset, piece_size, is_used));
+            offset = offset + piece_size;
+        }
+        if used_count != used.len() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "the used piece is not found on the piece boundary",
+            ));
+        }
+        Ok((piece_vec, offset))
+    }
+}
+
+/// the problem on the free piece list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FreeListError {
+    /// the free piece list goes back to the piece that is already on the lists.
+    Cycle,
+    /// the free piece is not on the piece boundary.
+    NotOnBoundary,
+    /// the size of the free piece is not of the list: (list size, piece size).
+    SizeMismatch(u32, u32),
+}
+
+// for verify
+impl VarFile {
+    /// the sizes of the pieces from `start` to the end of file, by the offset.
+    pub fn piece_sizes<T, F>(
+        &mut self,
+        start: PieceOffset<T>,
+        read_piece_size_func: F,
+    ) -> Result<HashMap<u64, u32>>
+    where
+        T: Copy + PartialEq + PartialOrd,
+        F: Fn(&mut VarFile) -> Result<PieceSize<T>>,
+    {
+        let (piece_vec, _end) = self.scan_pieces(start, &HashSet::new(), read_piece_size_func)?;
+        Ok(piece_vec
+            .iter()
+            .map(|a| (a.0.as_value(), a.1.as_value()))
+            .collect())
+    }
+    /// walks the all free piece lists. returns the offsets of the free pieces,
+    /// and the problems with the offset. the walking of a list is stopped at
+    /// the problem of the cycle or the piece that is not on the boundary.
+    #[allow(clippy::type_complexity)]
+    pub fn walk_free_piece_lists(
+        &mut self,
+        piece_sizes: &HashMap<u64, u32>,
+    ) -> Result<(HashSet<u64>, Vec<(u64, FreeListError)>)> {
+        let mut free_set = HashSet::new();
+        let mut errors = Vec::new();
+        let last_idx = self.piece_mgr.size_ary.len() - 1;
+        for i in 0..=last_idx {
+            let list_size = self.piece_mgr.size_ary[i];
+            self.seek_from_start(Offset::<u64>::new(self.piece_mgr.free_list_offset[i]))?;
+            let mut offset = self.read_u64_le()?;
+            while offset != 0 {
+                let piece_size = match piece_sizes.get(&offset) {
+                    Some(&piece_size) => piece_size,
+                    None => {
+                        errors.push((offset, FreeListError::NotOnBoundary));
+                        break;
+                    }
+                };
+                if !free_set.insert(offset) {
+                    errors.push((offset, FreeListError::Cycle));
+                    break;
+                }
+                let is_of_list = if i < last_idx {
+                    piece_size == list_size
+                } else {
+                    !self.piece_mgr.size_ary[..last_idx].contains(&piece_size)
+                };
+                if !is_of_list {
+                    errors.push((offset, FreeListError::SizeMismatch(list_size, piece_size)));
+                }
+                // the live piece may be here, so the key len is not checked.
+                self.seek_from_start(PieceOffset::<u64>::new(offset))?;
+                let _piece_size: PieceSize<u64> = self.read_piece_size()?;
+                let _key_len = self.read_key_len()?;
+                let next: PieceOffset<u64> = self.read_free_piece_offset()?;
+                offset = next.as_value();
+            }
+        }
+        Ok((free_set, errors))
+    }
+}
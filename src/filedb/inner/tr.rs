@@ -1,10 +1,11 @@
-use super::idx::{NODE_SLOTS_MAX, NODE_SLOTS_MAX_HALF};
+use super::super::cell::{Rc, Ref, RefCell, RefMut};
+use super::super::IntEncoding;
+use super::enc::Encoding;
+use super::idx::{NODE_SLOTS_LIMIT, NODE_SLOTS_MAX};
 use super::semtype::*;
 use super::vfile::VarFile;
 use rabuf::{SmallRead, SmallWrite};
-use std::cell::{Ref, RefCell, RefMut};
 use std::io::Result;
-use std::rc::Rc;
 
 #[cfg(feature = "siamese_debug")]
 use std::convert::TryInto;
@@ -81,18 +82,18 @@ impl IdxNode {
     }
     //
     #[inline]
-    pub fn get_mut(&mut self) -> RefMut<TreeNode> {
+    pub fn get_mut(&mut self) -> RefMut<'_, TreeNode> {
         RefCell::borrow_mut(&self.0)
     }
     #[inline]
-    pub fn get_ref(&self) -> Ref<TreeNode> {
+    pub fn get_ref(&self) -> Ref<'_, TreeNode> {
         RefCell::borrow(&self.0)
     }
     //
     #[inline]
-    pub fn is_over_len(&self) -> bool {
+    pub fn is_over_len(&self, node_slots: u16) -> bool {
         let locked = RefCell::borrow(&self.0);
-        locked.is_over_len()
+        locked.is_over_len(node_slots)
     }
     #[inline]
     pub fn deactivate(&self) -> Self {
@@ -104,9 +105,9 @@ impl IdxNode {
         locked.is_active_on_insert()
     }
     #[inline]
-    pub fn is_active_on_delete(&self) -> bool {
+    pub fn is_active_on_delete(&self, node_slots: u16) -> bool {
         let locked = RefCell::borrow(&self.0);
-        locked.is_active_on_delete()
+        locked.is_active_on_delete(node_slots)
     }
     #[inline]
     pub(crate) fn idx_write_node_one(&self, file: &mut VarFile) -> Result<()> {
@@ -660,9 +661,8 @@ impl TreeNode {
 
 impl TreeNode {
     #[inline]
-    pub fn is_over_len(&self) -> bool {
-        if self.keys.len() < NODE_SLOTS_MAX as usize && self.downs.len() <= NODE_SLOTS_MAX as usize
-        {
+    pub fn is_over_len(&self, node_slots: u16) -> bool {
+        if self.keys.len() < node_slots as usize && self.downs.len() <= node_slots as usize {
             return false;
         }
         true
@@ -672,8 +672,8 @@ impl TreeNode {
         self.is_active
     }
     #[inline]
-    pub fn is_active_on_delete(&self) -> bool {
-        self.downs.len() < NODE_SLOTS_MAX_HALF as usize
+    pub fn is_active_on_delete(&self, node_slots: u16) -> bool {
+        self.downs.len() < (node_slots / 2) as usize
     }
     #[inline]
     pub fn is_leaf(&self) -> bool {
@@ -699,7 +699,7 @@ impl TreeNode {
     }
     //
     #[inline(never)]
-    pub fn encoded_node_size(&self) -> usize {
+    pub fn encoded_node_size(&self, enc: Encoding) -> usize {
         let mut sum_size = 0usize;
         // node or leaf + padding
         sum_size += 2;
@@ -709,77 +709,51 @@ impl TreeNode {
         #[cfg(not(feature = "siamese_debug"))]
         let keys_count: u16 = self.keys.len() as u16;
         //
-        #[cfg(any(feature = "vf_u32u32", feature = "vf_u64u64"))]
-        {
-            sum_size += 2;
-        }
-        #[cfg(not(any(feature = "vf_u32u32", feature = "vf_u64u64")))]
-        #[cfg(feature = "vf_vu64")]
-        {
-            sum_size += vu64::encoded_len(keys_count as u64) as usize;
+        match enc.piece {
+            IntEncoding::Vu64 => sum_size += vu64::encoded_len(keys_count as u64) as usize,
+            _ => sum_size += 2,
         }
         //
-        #[cfg(any(feature = "vf_u32u32", feature = "vf_node_u32"))]
-        {
-            sum_size += 4 * keys_count as usize;
-        }
-        #[cfg(any(feature = "vf_u64u64", feature = "vf_node_u64"))]
-        {
-            sum_size += 8 * keys_count as usize;
-        }
-        #[cfg(not(any(
-            feature = "vf_u32u32",
-            feature = "vf_u64u64",
-            feature = "vf_node_u32",
-            feature = "vf_node_u64"
-        )))]
-        #[cfg(feature = "vf_vu64")]
-        for i in 0..(keys_count as usize) {
-            #[cfg(feature = "siamese_debug")]
-            let _offset = self.keys[i];
-            #[cfg(not(feature = "siamese_debug"))]
-            let _offset = unsafe { *self.keys.as_ptr().add(i) };
-            //let _offset = unsafe { *self.keys.get_unchecked(i) };
-            debug_assert!(!_offset.is_zero());
-            //
-            sum_size += vu64::encoded_len(_offset.as_value() / 8) as usize;
+        let offset_len = enc.node_offset_len();
+        if let Some(offset_len) = offset_len {
+            sum_size += offset_len as usize * keys_count as usize;
+        } else {
+            for i in 0..(keys_count as usize) {
+                #[cfg(feature = "siamese_debug")]
+                let _offset = self.keys[i];
+                #[cfg(not(feature = "siamese_debug"))]
+                let _offset = unsafe { *self.keys.as_ptr().add(i) };
+                //let _offset = unsafe { *self.keys.get_unchecked(i) };
+                debug_assert!(!_offset.is_zero());
+                //
+                sum_size += vu64::encoded_len(_offset.as_value() / 8) as usize;
+            }
         }
         let is_leaf = self.is_leaf();
         if !is_leaf {
-            #[cfg(any(feature = "vf_u32u32", feature = "vf_node_u32"))]
-            {
-                sum_size += 4 * (keys_count as usize + 1);
-            }
-            #[cfg(any(feature = "vf_u64u64", feature = "vf_node_u64"))]
-            {
-                sum_size += 8 * (keys_count as usize + 1);
-            }
-            #[cfg(not(any(
-                feature = "vf_u32u32",
-                feature = "vf_u64u64",
-                feature = "vf_node_u32",
-                feature = "vf_node_u64"
-            )))]
-            #[cfg(feature = "vf_vu64")]
-            for i in 0..((keys_count as usize) + 1) {
-                debug_assert!(
-                    keys_count == 0 || i < self.downs.len(),
-                    "i: {} < self.downs.len(): {}, keys_count: {}",
-                    i,
-                    self.downs.len(),
-                    keys_count
-                );
-                let _offset = if i < (keys_count as usize) + 1 {
-                    #[cfg(feature = "siamese_debug")]
-                    let _offset = self.downs[i];
-                    #[cfg(not(feature = "siamese_debug"))]
-                    let _offset = unsafe { *self.downs.as_ptr().add(i) };
-                    //let _offset = unsafe { *self.downs.get_unchecked(i) };
-                    _offset
-                } else {
-                    NodePieceOffset::new(0)
-                };
-                sum_size += vu64::encoded_len(_offset.as_value() / 8) as usize;
+            if let Some(offset_len) = offset_len {
+                sum_size += offset_len as usize * (keys_count as usize + 1);
+            } else {
+                for i in 0..((keys_count as usize) + 1) {
+                    debug_assert!(
+                        keys_count == 0 || i < self.downs.len(),
+                        "i: {} < self.downs.len(): {}, keys_count: {}",
+                        i,
+                        self.downs.len(),
+                        keys_count
+                    );
+                    let _offset = if i < (keys_count as usize) + 1 {
+                        #[cfg(feature = "siamese_debug")]
+                        let _offset = self.downs[i];
+                        #[cfg(not(feature = "siamese_debug"))]
+                        let _offset = unsafe { *self.downs.as_ptr().add(i) };
+                        //let _offset = unsafe { *self.downs.get_unchecked(i) };
+                        _offset
+                    } else {
+                        NodePieceOffset::new(0)
+                    };
+                    sum_size += vu64::encoded_len(_offset.as_value() / 8) as usize;
+                }
             }
         }
         //
@@ -817,8 +791,8 @@ impl TreeNode {
         let keys_count = self.keys.len();
         file.write_keys_count(KeysCount::new(keys_count as u16))?;
         debug_assert!(
-            keys_count < NODE_SLOTS_MAX as usize,
-            "keys_count: {} < NODE_SLOTS_MAX as usize - 1",
+            keys_count < NODE_SLOTS_LIMIT as usize,
+            "keys_count: {} < NODE_SLOTS_LIMIT as usize",
             keys_count
         );
         //debug_assert!(keys_count == 0 || self.downs.len() == keys_count + 1);
@@ -829,8 +803,13 @@ impl TreeNode {
             keys_count + 1
         );
         //
-        #[cfg(not(all(feature = "vf_node_u64", feature = "idx_straight")))]
-        {
+        // the offsets of u64 are written at once.
+        #[cfg(feature = "idx_straight")]
+        let is_straight = file.encoding().node_offset_len() == Some(8);
+        #[cfg(not(feature = "idx_straight"))]
+        let is_straight = false;
+        //
+        if !is_straight {
             for i in 0..keys_count {
                 #[cfg(feature = "siamese_debug")]
                 let offset = self.keys[i];
@@ -839,12 +818,7 @@ impl TreeNode {
                 //let offset = unsafe { *self.keys.get_unchecked(i) };
                 debug_assert!(!offset.is_zero());
                 //
-                #[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
-                file.write_piece_offset(offset)?;
-                #[cfg(feature = "vf_node_u32")]
-                file.write_piece_offset_u32(offset)?;
-                #[cfg(feature = "vf_node_u64")]
-                file.write_piece_offset_u64(offset)?;
+                file.write_node_piece_offset(offset)?;
             }
             //
             if is_leaf == 0u8 {
@@ -860,17 +834,12 @@ impl TreeNode {
                         NodePieceOffset::new(0)
                     };
                     debug_assert!((offset.as_value() & 0x0F) == 0);
-                    #[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
                     file.write_node_offset(offset)?;
-                    #[cfg(feature = "vf_node_u32")]
-                    file.write_node_offset_u32(offset)?;
-                    #[cfg(feature = "vf_node_u64")]
-                    file.write_node_offset_u64(offset)?;
                 }
             }
         }
-        #[cfg(all(feature = "vf_node_u64", feature = "idx_straight"))]
-        {
+        #[cfg(feature = "idx_straight")]
+        if is_straight {
             if keys_count == 0 {
                 if is_leaf == 0u8 {
                     debug_assert!(
@@ -923,6 +892,7 @@ impl TreeNode {
         );
         //
         file.write_zero_to_offset(self.offset + self.size)?;
+        file.write_piece_checksum(self.offset, self.size)?;
         //
         self.is_dirty = false;
         //
@@ -961,8 +931,8 @@ impl IdxNode {
         let is_leaf = file.read_u16_le()?;
         let keys_count = file.read_keys_count()?;
         debug_assert!(
-            keys_count.as_value() < NODE_SLOTS_MAX,
-            "keys_count: {} < NODE_SLOTS_MAX",
+            keys_count.as_value() < NODE_SLOTS_LIMIT,
+            "keys_count: {} < NODE_SLOTS_LIMIT",
             keys_count
         );
         let keys_count: usize = keys_count.into();
@@ -976,17 +946,8 @@ impl IdxNode {
         {
             keys.resize(keys_count, KeyPieceOffset::new(0));
             for _i in 0..keys_count {
-                #[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
-                let piece_offset = file
-                    .read_piece_offset()
-                    .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
-                #[cfg(feature = "vf_node_u32")]
                 let piece_offset = file
-                    .read_piece_offset_u32()
-                    .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
-                #[cfg(feature = "vf_node_u64")]
-                let piece_offset = file
-                    .read_piece_offset_u64()
+                    .read_node_piece_offset()
                     .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
                 debug_assert!(!piece_offset.is_zero());
                 #[cfg(feature = "siamese_debug")]
@@ -1002,18 +963,9 @@ impl IdxNode {
             downs.resize(keys_count + 1, NodePieceOffset::new(0));
             if is_leaf == 0 {
                 for _i in 0..(keys_count + 1) {
-                    #[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
                     let node_offset = file
                         .read_node_offset()
                         .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
-                    #[cfg(feature = "vf_node_u32")]
-                    let node_offset = file
-                        .read_node_offset_u32()
-                        .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
-                    #[cfg(feature = "vf_node_u64")]
-                    let node_offset = file
-                        .read_node_offset_u64()
-                        .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
                     debug_assert!(
                         (node_offset.as_value() & 0x0F) == 0,
                         "(node_offset.as_value(): {} & 0x0F) == 0, offset: {}",
@@ -1038,17 +990,8 @@ impl IdxNode {
             #[cfg(not(feature = "siamese_debug"))]
             let keys_ptr = keys.as_mut_ptr();
             for _i in 0..keys_count {
-                #[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
-                let piece_offset = file
-                    .read_piece_offset()
-                    .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
-                #[cfg(feature = "vf_node_u32")]
-                let piece_offset = file
-                    .read_piece_offset_u32()
-                    .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
-                #[cfg(feature = "vf_node_u64")]
                 let piece_offset = file
-                    .read_piece_offset_u64()
+                    .read_node_piece_offset()
                     .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
                 debug_assert!(!piece_offset.is_zero());
                 #[cfg(feature = "siamese_debug")]
@@ -1067,18 +1010,9 @@ impl IdxNode {
                 #[cfg(not(feature = "siamese_debug"))]
                 let downs_ptr = downs.as_mut_ptr();
                 for _i in 0..(keys_count + 1) {
-                    #[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
                     let node_offset = file
                         .read_node_offset()
                         .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
-                    #[cfg(feature = "vf_node_u32")]
-                    let node_offset = file
-                        .read_node_offset_u32()
-                        .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
-                    #[cfg(feature = "vf_node_u64")]
-                    let node_offset = file
-                        .read_node_offset_u64()
-                        .unwrap_or_else(|_| panic!("offset:{}, i:{}", offset, _i));
                     debug_assert!(
                         (node_offset.as_value() & 0x0F) == 0,
                         "(node_offset.as_value(): {} & 0x0F) == 0, offset: {}",
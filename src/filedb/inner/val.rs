@@ -1,14 +1,25 @@
-use super::super::{FileBufSizeParam, FileDbParams};
-use super::piece::PieceMgr;
+use super::super::super::Error as DbError;
+use super::super::cell::{Rc, RefCell};
+use super::super::codec::{builtin_value_codec, ValueCodec};
+use super::super::{FileBufSizeParam, FileDbParams, IntEncoding};
+use super::crypt::{ValueCipher, KEY_CHECK_LEN};
+use super::enc::{check_format_version, legacy_encoding, Encoding, FORMAT_VERSION};
+use super::piece::{FreeListError, PieceMgr};
 use super::semtype::*;
-use super::vfile::VarFile;
+use super::vfile::{SnapshotRef, VarFile};
+use super::wal::PendingPages;
+use super::{
+    invalid_header_error, key_type_mismatch_error, signature_string, FORMAT_CHECKSUM,
+    FORMAT_ENCRYPTION, FORMAT_VALUE_CODEC,
+};
 use rabuf::{SmallRead, SmallWrite};
-use std::cell::RefCell;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::fs::OpenOptions;
-use std::io::{Read, Result, Write};
+use std::io::{Error, ErrorKind, Read, Result, Write};
 use std::path::Path;
-use std::rc::Rc;
+use std::sync::Arc;
 
 type HeaderSignature = [u8; 8];
 
@@ -16,13 +27,27 @@ type HeaderSignature = [u8; 8];
 //const CHUNK_SIZE: u32 = 4 * 4 * 1024;
 //const CHUNK_SIZE: u32 = 4 * 4 * 4 * 1024;
 const CHUNK_SIZE: u32 = 128 * 1024;
-const _DAT_HEADER_SZ: u64 = 192;
+const DAT_HEADER_SZ: u64 = 192;
+const DAT_HEADER_DIRTY_FLAG_OFFSET: u64 = 24;
+const DAT_HEADER_CODEC_ID_OFFSET: u64 = 160;
+const DAT_HEADER_KEY_CHECK_OFFSET: u64 = 168;
+const DAT_HEADER_VERSION_OFFSET: u64 = 184;
 const DAT_HEADER_SIGNATURE: HeaderSignature = [b's', b'i', b'a', b'm', b'd', b'b', b'V', 0u8];
 
-use std::marker::PhantomData;
+/// the flag of the encoded value in the value length, if the format has the value codec.
+const VALUE_LEN_ENCODED: u32 = 0x8000_0000;
 
+/// the max length of the stored value, the same as the max length of the value.
+const DATA_LEN_MAX: usize = u32::MAX as usize - 32;
+
+/// the file, the format has the value codec, the value codec and the cipher.
 #[derive(Debug)]
-struct VarFileValueCache(VarFile, PhantomData<i32>);
+struct VarFileValueCache(
+    VarFile,
+    bool,
+    Option<Arc<dyn ValueCodec>>,
+    Option<ValueCipher>,
+);
 
 #[derive(Debug, Clone)]
 pub struct ValueFile(Rc<RefCell<VarFileValueCache>>);
@@ -33,16 +58,17 @@ impl ValueFile {
         ks_name: &str,
         sig2: HeaderSignature,
         params: &FileDbParams,
+        read_only: bool,
     ) -> Result<Self> {
         let piece_mgr = PieceMgr::new(&REC_SIZE_FREE_OFFSET, &REC_SIZE_ARY);
         let mut pb = path.as_ref().to_path_buf();
         pb.push(format!("{}.val", ks_name));
         let std_file = OpenOptions::new()
             .read(true)
-            .write(true)
-            .create(true)
+            .write(!read_only)
+            .create(!read_only)
             .truncate(false)
-            .open(pb)?;
+            .open(&pb)?;
         let mut file = match params.val_buf_size {
             FileBufSizeParam::Size(val) => {
                 let dat_buf_chunk_size = CHUNK_SIZE;
@@ -61,13 +87,34 @@ impl ValueFile {
             FileBufSizeParam::Auto => VarFile::new(piece_mgr, "val", std_file)?,
         };
         let file_length: ValuePieceOffset = file.seek_to_end()?;
-        if file_length.is_zero() {
-            write_valrecf_init_header(&mut file, sig2)?;
+        if read_only && file_length.is_zero() {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("empty file: {}", pb.display()),
+            ));
+        }
+        let format = if file_length.is_zero() {
+            let mut format = if params.checksum { FORMAT_CHECKSUM } else { 0 };
+            if params.encryption_key.is_some() {
+                format |= FORMAT_ENCRYPTION;
+            }
+            write_valrecf_init_header(&mut file, sig2, format, Encoding::of_params(params))?;
+            if let Some(key) = &params.encryption_key {
+                let key_check = ValueCipher::new(key)?.key_check()?;
+                file.seek_from_start(ValuePieceOffset::new(DAT_HEADER_KEY_CHECK_OFFSET))?;
+                file.write_all(&key_check)?;
+            }
+            format
         } else {
-            check_valrecf_header(&mut file, sig2)?;
+            check_valrecf_header(&mut file, sig2, &pb)?
+        };
+        if format & FORMAT_CHECKSUM != 0 {
+            file.set_checksum(&pb);
         }
+        let (has_codec, codec) = open_value_codec(&mut file, format, params, read_only, &pb)?;
+        let cipher = open_value_cipher(&mut file, format, params, &pb)?;
         //
-        let file_rc = VarFileValueCache(file, PhantomData);
+        let file_rc = VarFileValueCache(file, has_codec, codec, cipher);
         //
         Ok(Self(Rc::new(RefCell::new(file_rc))))
     }
@@ -91,6 +138,85 @@ impl ValueFile {
         let mut locked = self.0.borrow_mut();
         locked.0.sync_data()
     }
+    //
+    #[inline]
+    pub fn begin_pending(&self) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.begin_pending()
+    }
+    #[inline]
+    pub fn take_pending(&self) -> Result<Option<PendingPages>> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        Ok(locked.0.take_pending())
+    }
+    #[inline]
+    pub fn apply_pending(&self, pages: &PendingPages) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.apply_pending(pages)
+    }
+    //
+    #[inline]
+    pub fn begin_snapshot(&self) -> Result<SnapshotRef> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.begin_snapshot()
+    }
+    #[inline]
+    pub fn snapshots(&self) -> Vec<SnapshotRef> {
+        let locked = RefCell::borrow(&self.0);
+        locked.0.snapshots()
+    }
+    #[inline]
+    pub fn set_snapshots(&self, snapshots: Vec<SnapshotRef>) {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.set_snapshots(snapshots)
+    }
+    //
+    #[inline]
+    pub fn read_dirty_flag(&self) -> Result<bool> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked
+            .0
+            .read_dirty_flag(ValuePieceOffset::new(DAT_HEADER_DIRTY_FLAG_OFFSET))
+    }
+    #[inline]
+    pub fn write_dirty_flag(&self, flag: bool) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked
+            .0
+            .write_dirty_flag(ValuePieceOffset::new(DAT_HEADER_DIRTY_FLAG_OFFSET), flag)
+    }
+    /// rebuilds the free piece lists, the pieces that are not in `used` become free.
+    pub fn rebuild_free_piece_list(&self, used: &HashSet<u64>) -> Result<()> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.rebuild_free_piece_list(
+            ValuePieceOffset::new(DAT_HEADER_SZ),
+            used,
+            |file: &mut VarFile| file.read_piece_size(),
+        )
+    }
+    /// moves the used pieces to the front and truncates the file.
+    pub fn compact(&self, used: &HashSet<u64>) -> Result<(HashMap<u64, u64>, u64)> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        locked.0.compact_pieces(
+            ValuePieceOffset::new(DAT_HEADER_SZ),
+            used,
+            |file: &mut VarFile| file.read_piece_size(),
+        )
+    }
+    /// the sizes of the pieces by the offset, the free pieces and the problems
+    /// of the free piece lists.
+    #[allow(clippy::type_complexity)]
+    pub fn scan_for_verify(
+        &self,
+    ) -> Result<(HashMap<u64, u32>, HashSet<u64>, Vec<(u64, FreeListError)>)> {
+        let mut locked = RefCell::borrow_mut(&self.0);
+        let piece_sizes = locked.0.piece_sizes(
+            ValuePieceOffset::new(DAT_HEADER_SZ),
+            |file: &mut VarFile| file.read_piece_size(),
+        )?;
+        let (free_set, errors) = locked.0.walk_free_piece_lists(&piece_sizes)?;
+        Ok((piece_sizes, free_set, errors))
+    }
     #[cfg(feature = "buf_stats")]
     #[inline]
     pub fn buf_stats(&self) -> Vec<(String, i64)> {
@@ -103,25 +229,27 @@ impl ValueFile {
         let mut locked = self.0.borrow_mut();
         locked.read_piece_only_size(offset)
     }
+    /// the length of the stored value, it is the encoded one if the value is encoded.
     #[inline]
     pub fn read_piece_only_value_length(&self, offset: ValuePieceOffset) -> Result<ValueLength> {
         let mut locked = self.0.borrow_mut();
         locked.read_piece_only_value_length(offset)
     }
+    /// the key is the associated data of the encrypted value.
     #[inline]
-    pub fn read_piece_only_value(&self, offset: ValuePieceOffset) -> Result<Vec<u8>> {
+    pub fn read_piece_only_value(&self, offset: ValuePieceOffset, key: &[u8]) -> Result<Vec<u8>> {
         let mut locked = self.0.borrow_mut();
-        locked.read_piece_only_value(offset)
+        locked.read_piece_only_value(offset, key)
     }
     #[inline]
-    pub fn read_piece(&self, offset: ValuePieceOffset) -> Result<ValuePiece> {
+    pub fn read_piece(&self, offset: ValuePieceOffset, key: &[u8]) -> Result<ValuePiece> {
         let mut locked = self.0.borrow_mut();
-        locked.read_piece(offset)
+        locked.read_piece(offset, key)
     }
     #[inline]
-    pub fn write_piece(&self, piece: ValuePiece) -> Result<ValuePiece> {
+    pub fn write_piece(&self, piece: ValuePiece, key: &[u8]) -> Result<ValuePiece> {
         let mut locked = self.0.borrow_mut();
-        locked.write_piece(piece, false)
+        locked.write_piece(piece, key, false)
     }
     #[inline]
     pub fn delete_piece(&self, offset: ValuePieceOffset) -> Result<ValuePieceSize> {
@@ -129,9 +257,9 @@ impl ValueFile {
         locked.delete_piece(offset)
     }
     #[inline]
-    pub fn add_value_piece(&self, value: &[u8]) -> Result<ValuePiece> {
+    pub fn add_value_piece(&self, value: &[u8], key: &[u8]) -> Result<ValuePiece> {
         let mut locked = self.0.borrow_mut();
-        locked.add_value_piece(value)
+        locked.add_value_piece(value, key)
     }
 }
 
@@ -165,54 +293,186 @@ The db data header size is 192 bytes.
 +--------+-------+-------------+---------------------------+
 | 0      | 8     | signature1  | b"siamdbV\0"              |
 | 8      | 8     | signature2  | 8 bytes type signature    |
-| 16     | 8     | reserve0    |                           |
-| 24     | 8     | reserve1    |                           |
+| 16     | 8     | format      | format flags              |
+| 24     | 8     | dirty flag  | not zero while writing    |
 | 32     | 8     | free1 off   | offset of free 1st list   |
 | ...    | ...   | ...         | ...                       |
 | 152    | 8     | free16 off  | offset of free 16th list  |
-| 160    | 32    | reserve2    |                           |
+| 160    | 8     | codec id    | id of the value codec     |
+| 168    | 16    | key check   | check of the encryption   |
+| 184    | 4     | version     | version of the format     |
+| 188    | 4     | encoding    | encoding of the integers  |
 +--------+-------+-------------+---------------------------+
 ```
 
 - signature1: always fixed 8 bytes
 - signature2: 8 bytes type signature
+- format: 1 is the checksum at the end of the piece. 2 is the value codec,
+  the value may be encoded by the codec of the codec id. 4 is the encryption,
+  the values are encrypted, and the key is checked by the key check.
+- dirty flag: it is set on the first writing, and is cleared by the sync.
+- version and encoding: 0 is the file before the version, it has the encoding of
+  the default features, and only the build of it reads the file.
 
 */
-fn write_valrecf_init_header(file: &mut VarFile, signature2: HeaderSignature) -> Result<()> {
+fn write_valrecf_init_header(
+    file: &mut VarFile,
+    signature2: HeaderSignature,
+    format: u64,
+    enc: Encoding,
+) -> Result<()> {
+    file.set_encoding(enc);
     file.seek_from_start(ValuePieceOffset::new(0))?;
     // signature1
     file.write_all(&DAT_HEADER_SIGNATURE)?;
     // signature2
     file.write_all(&signature2)?;
-    // reserve0
-    file.write_u64_le(0)?;
-    // reserve1
+    // format
+    file.write_u64_le(format)?;
+    // dirty flag
     file.write_u64_le(0)?;
-    // free1 .. reserve2
-    file.write_all(&[0u8; 160])?;
+    // free1 .. key check
+    file.write_all(&[0u8; 152])?;
+    // version and encoding
+    file.write_u32_le(FORMAT_VERSION)?;
+    file.write_u32_le(enc.to_u32())?;
     //
     Ok(())
 }
 
-fn check_valrecf_header(file: &mut VarFile, signature2: HeaderSignature) -> Result<()> {
+fn check_valrecf_header(
+    file: &mut VarFile,
+    signature2: HeaderSignature,
+    path: &Path,
+) -> Result<u64> {
+    // file length
+    let file_length: ValuePieceOffset = file.seek_to_end()?;
+    if file_length.as_value() < DAT_HEADER_SZ {
+        return Err(invalid_header_error(
+            path,
+            file_length.as_value(),
+            "file length",
+            format!("at least {} bytes", DAT_HEADER_SZ),
+            format!("{} bytes", file_length.as_value()),
+        ));
+    }
     file.seek_from_start(ValuePieceOffset::new(0))?;
     // signature1
     let mut sig1 = [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8];
     file.read_exact(&mut sig1)?;
-    assert!(sig1 == DAT_HEADER_SIGNATURE, "invalid header signature1");
+    if sig1 != DAT_HEADER_SIGNATURE {
+        return Err(invalid_header_error(
+            path,
+            0,
+            "signature1",
+            signature_string(&DAT_HEADER_SIGNATURE),
+            signature_string(&sig1),
+        ));
+    }
     // signature2
     let mut sig2 = [0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8, 0u8];
     file.read_exact(&mut sig2)?;
-    assert!(
-        sig2 == signature2,
-        "invalid header signature2, type signature: {:?}",
-        sig2
-    );
-    // reserve0
-    let _reserve0 = file.read_u64_le()?;
-    assert!(_reserve0 == 0, "invalid reserve0");
+    if sig2 != signature2 {
+        return Err(key_type_mismatch_error(path, &signature2, &sig2));
+    }
+    // format
+    let format = file.read_u64_le()?;
+    let flags = FORMAT_CHECKSUM | FORMAT_VALUE_CODEC | FORMAT_ENCRYPTION;
+    if format & !flags != 0 {
+        return Err(invalid_header_error(
+            path,
+            16,
+            "format",
+            format!("the flags of {}", flags),
+            format.to_string(),
+        ));
+    }
+    // version and encoding
+    file.seek_from_start(ValuePieceOffset::new(DAT_HEADER_VERSION_OFFSET))?;
+    let version = file.read_u32_le()?;
+    let encoding = file.read_u32_le()?;
+    let enc = check_format_version(path, DAT_HEADER_VERSION_OFFSET, version, encoding)?;
+    let enc = match enc {
+        Some(enc) => enc,
+        None => legacy_encoding(path, Encoding::build())?,
+    };
+    file.set_encoding(enc);
     //
-    Ok(())
+    Ok(format)
+}
+
+/// the value codec of the file. the codec id in the header is checked with
+/// the codec of the params. the codec is set to the file that has no codec.
+/// if the params has no codec, the built-in codec of the codec id is used.
+fn open_value_codec(
+    file: &mut VarFile,
+    format: u64,
+    params: &FileDbParams,
+    read_only: bool,
+    path: &Path,
+) -> Result<(bool, Option<Arc<dyn ValueCodec>>)> {
+    if format & FORMAT_VALUE_CODEC == 0 {
+        return match &params.value_codec {
+            Some(codec) if !read_only => {
+                file.seek_from_start(ValuePieceOffset::new(16))?;
+                file.write_u64_le(format | FORMAT_VALUE_CODEC)?;
+                file.seek_from_start(ValuePieceOffset::new(DAT_HEADER_CODEC_ID_OFFSET))?;
+                file.write_u64_le(codec.id())?;
+                Ok((true, Some(codec.clone())))
+            }
+            _ => Ok((false, None)),
+        };
+    }
+    file.seek_from_start(ValuePieceOffset::new(DAT_HEADER_CODEC_ID_OFFSET))?;
+    let codec_id = file.read_u64_le()?;
+    match &params.value_codec {
+        Some(codec) if codec.id() != codec_id => Err(invalid_header_error(
+            path,
+            DAT_HEADER_CODEC_ID_OFFSET,
+            "codec id",
+            codec.id(),
+            codec_id,
+        )),
+        Some(codec) => Ok((true, Some(codec.clone()))),
+        None => Ok((true, builtin_value_codec(codec_id))),
+    }
+}
+
+/// the cipher of the file. the encryption is decided on creating the file,
+/// and the key is checked with the key check in the header.
+fn open_value_cipher(
+    file: &mut VarFile,
+    format: u64,
+    params: &FileDbParams,
+    path: &Path,
+) -> Result<Option<ValueCipher>> {
+    let key = match (&params.encryption_key, format & FORMAT_ENCRYPTION != 0) {
+        (None, false) => return Ok(None),
+        (Some(key), true) => key,
+        (Some(_), false) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the db map is not encrypted: {}", path.display()),
+            ))
+        }
+        (None, true) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("the encryption key is not given: {}", path.display()),
+            ))
+        }
+    };
+    let cipher = ValueCipher::new(key)?;
+    let mut key_check = [0u8; KEY_CHECK_LEN];
+    file.seek_from_start(ValuePieceOffset::new(DAT_HEADER_KEY_CHECK_OFFSET))?;
+    file.read_exact(&mut key_check)?;
+    if key_check != cipher.key_check()? {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("the encryption key does not match: {}", path.display()),
+        ));
+    }
+    Ok(Some(cipher))
 }
 
 const REC_SIZE_FREE_OFFSET_1ST: u64 = 32;
@@ -305,47 +565,62 @@ impl ValuePiece {
             ..Default::default()
         }
     }
+}
+
+/// the encoded length of the piece size, and the length of the value length
+/// and the stored value.
+fn encoded_piece_size(data: &[u8], value_len: ValueLength, enc: IntEncoding) -> (u32, u32) {
+    #[cfg(feature = "siamese_debug")]
+    let data_len: u32 = data.len().try_into().unwrap();
+    #[cfg(not(feature = "siamese_debug"))]
+    let data_len = data.len() as u32;
     //
-    fn encoded_piece_size(&self) -> (u32, u32, ValueLength) {
-        #[cfg(feature = "siamese_debug")]
-        let value_len = ValueLength::new(self.value.len().try_into().unwrap());
-        #[cfg(not(feature = "siamese_debug"))]
-        let value_len = ValueLength::new(self.value.len() as u32);
-        //
-        #[cfg(any(feature = "vf_u32u32", feature = "vf_u64u64"))]
-        let (encorded_piece_len, piece_len) = {
+    let (encorded_piece_len, piece_len) = match enc {
+        IntEncoding::U32U32 | IntEncoding::U64U64 => {
             let enc_val_len = 4;
-            let piece_len: u32 = enc_val_len + value_len.as_value();
+            let piece_len: u32 = enc_val_len + data_len;
             let encorded_piece_len = 4;
             (encorded_piece_len, piece_len)
-        };
-        #[cfg(feature = "vf_vu64")]
-        let (encorded_piece_len, piece_len) = {
+        }
+        IntEncoding::Vu64 => {
             let enc_val_len = vu64::encoded_len(value_len.as_value() as u64) as u32;
-            let piece_len: u32 = enc_val_len + value_len.as_value();
+            let piece_len: u32 = enc_val_len + data_len;
             let encorded_piece_len = vu64::encoded_len((piece_len as u64 + 7) / 8) as u32;
             (encorded_piece_len, piece_len)
-        };
-        //
-        (encorded_piece_len, piece_len, value_len)
-    }
+        }
+    };
     //
-    pub(crate) fn dat_write_piece_one(&self, file: &mut VarFile) -> Result<()> {
-        assert!(!self.size.is_zero());
-        //
-        let value = &self.value;
-        #[cfg(feature = "siamese_debug")]
-        let value_len = ValueLength::new(value.len().try_into().unwrap());
-        #[cfg(not(feature = "siamese_debug"))]
-        let value_len = ValueLength::new(value.len() as u32);
-        //
-        file.seek_from_start(self.offset)?;
-        file.write_piece_size(self.size)?;
-        file.write_value_len(value_len)?;
-        file.write_all_small(value)?;
-        file.write_zero_to_offset(self.offset + self.size)?;
-        //
-        Ok(())
+    (encorded_piece_len, piece_len)
+}
+
+/// writes the piece of the stored value. `value_len` has the flag of the encoding.
+fn dat_write_piece_one(
+    file: &mut VarFile,
+    offset: ValuePieceOffset,
+    size: ValuePieceSize,
+    data: &[u8],
+    value_len: ValueLength,
+) -> Result<()> {
+    assert!(!size.is_zero());
+    //
+    file.seek_from_start(offset)?;
+    file.write_piece_size(size)?;
+    file.write_value_len(value_len)?;
+    file.write_all_small(data)?;
+    file.write_zero_to_offset(offset + size)?;
+    file.write_piece_checksum(offset, size)?;
+    //
+    Ok(())
+}
+
+/// decodes the stored value that has the flag of the encoding.
+fn decode_value(codec: &Option<Arc<dyn ValueCodec>>, data: &[u8]) -> Result<Vec<u8>> {
+    match codec {
+        Some(codec) => codec.decode(data),
+        None => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "the value is encoded, but the value codec is not given",
+        )),
     }
 }
 
@@ -361,18 +636,71 @@ impl VarFileValueCache {
     }
 
     #[inline]
-    fn add_value_piece(&mut self, value: &[u8]) -> Result<ValuePiece> {
-        self.write_piece(ValuePiece::with_value(value), true)
+    fn add_value_piece(&mut self, value: &[u8], key: &[u8]) -> Result<ValuePiece> {
+        self.write_piece(ValuePiece::with_value(value), key, true)
+    }
+
+    /// the stored value and its length with the flag of the encoding.
+    /// the value is kept raw, if the encoded one is not smaller.
+    /// and then it is encrypted with the key, if the file has the cipher.
+    fn encode_value<'a>(
+        &self,
+        value: &'a [u8],
+        key: &[u8],
+    ) -> Result<(Cow<'a, [u8]>, ValueLength)> {
+        let mut data = Cow::Borrowed(value);
+        let mut flag = 0;
+        if let (true, Some(codec)) = (self.1, &self.2) {
+            let encoded = codec.encode(value);
+            if encoded.len() < value.len() {
+                data = Cow::Owned(encoded);
+                flag = VALUE_LEN_ENCODED;
+            }
+        }
+        if let Some(cipher) = &self.3 {
+            data = Cow::Owned(cipher.encrypt(&data, key, flag != 0)?);
+        }
+        let len_max = if self.1 {
+            VALUE_LEN_ENCODED as usize - 1
+        } else {
+            DATA_LEN_MAX
+        };
+        if data.len() > len_max {
+            return Err(DbError::ValueTooLarge {
+                len: value.len(),
+                max: len_max,
+            }
+            .into());
+        }
+        let value_len = ValueLength::new(data.len() as u32 | flag);
+        Ok((data, value_len))
+    }
+
+    /// the length of the stored value, and the flag of the encoding.
+    #[inline]
+    fn split_value_len(&self, value_len: ValueLength) -> (usize, bool) {
+        let len = value_len.as_value();
+        if self.1 && len & VALUE_LEN_ENCODED != 0 {
+            ((len & !VALUE_LEN_ENCODED) as usize, true)
+        } else {
+            (len as usize, false)
+        }
     }
 
-    fn write_piece(&mut self, mut piece: ValuePiece, is_new: bool) -> Result<ValuePiece> {
+    fn write_piece(
+        &mut self,
+        mut piece: ValuePiece,
+        key: &[u8],
+        is_new: bool,
+    ) -> Result<ValuePiece> {
         debug_assert!(is_new || !piece.offset.is_zero());
         //
-        let (encorded_piece_len, piece_len, _value_len) = piece.encoded_piece_size();
-        let new_piece_size = self
-            .0
-            .piece_mgr
-            .roundup(ValuePieceSize::new(encorded_piece_len + piece_len));
+        let (data, value_len) = self.encode_value(&piece.value, key)?;
+        let (encorded_piece_len, piece_len) =
+            encoded_piece_size(&data, value_len, self.0.encoding().piece);
+        let new_piece_size = self.0.piece_mgr.roundup(ValuePieceSize::new(
+            encorded_piece_len + piece_len + self.0.checksum_len(),
+        ));
         //
         if !is_new {
             let old_piece_size = {
@@ -382,9 +710,8 @@ impl VarFileValueCache {
             debug_assert!(old_piece_size.is_valid_value());
             if new_piece_size <= old_piece_size {
                 // over writes.
-                self.0.seek_from_start(piece.offset)?;
+                dat_write_piece_one(&mut self.0, piece.offset, old_piece_size, &data, value_len)?;
                 piece.size = old_piece_size;
-                piece.dat_write_piece_one(&mut self.0)?;
                 return Ok(piece);
             } else {
                 // delete old and add new
@@ -393,7 +720,7 @@ impl VarFileValueCache {
             }
         }
         // add new.
-        {
+        let new_piece_offset = {
             let free_piece_offset = self.0.pop_free_piece_list(new_piece_size)?;
             let new_piece_offset = if !free_piece_offset.is_zero() {
                 self.0.seek_from_start(free_piece_offset)?;
@@ -401,10 +728,14 @@ impl VarFileValueCache {
             } else {
                 self.0.seek_to_end()?
             };
-            piece.offset = new_piece_offset;
-            piece.size = new_piece_size;
-            debug_assert!(piece.size.is_valid_value());
-            match piece.dat_write_piece_one(&mut self.0) {
+            debug_assert!(new_piece_size.is_valid_value());
+            match dat_write_piece_one(
+                &mut self.0,
+                new_piece_offset,
+                new_piece_size,
+                &data,
+                value_len,
+            ) {
                 Ok(()) => (),
                 Err(err) => {
                     // recover on error
@@ -412,22 +743,26 @@ impl VarFileValueCache {
                     return Err(err);
                 }
             }
-            piece.offset = new_piece_offset;
-        }
+            new_piece_offset
+        };
+        piece.offset = new_piece_offset;
+        piece.size = new_piece_size;
         //
         Ok(piece)
     }
 
-    fn read_piece(&mut self, offset: ValuePieceOffset) -> Result<ValuePiece> {
+    fn read_piece(&mut self, offset: ValuePieceOffset, key: &[u8]) -> Result<ValuePiece> {
         debug_assert!(!offset.is_zero());
         //
         self.0.seek_from_start(offset)?;
         let piece_size = self.0.read_piece_size()?;
+        if self.0.has_checksum() {
+            self.0.check_piece_checksum(offset, piece_size)?;
+            self.0.seek_skip_to_piece_value(offset)?;
+        }
         debug_assert!(piece_size.is_valid_value());
         //
-        let val_len = self.0.read_value_len()?;
-        let maybe_slice = self.0.read_exact_maybeslice(val_len.into())?;
-        let value = maybe_slice.to_vec();
+        let value = self.read_value_at_here(key)?;
         //
         let piece = ValuePiece::with(offset, piece_size, value);
         //
@@ -450,21 +785,41 @@ impl VarFileValueCache {
         //
         self.0.seek_skip_to_piece_value(offset)?;
         let val_len = self.0.read_value_len()?;
+        let (len, _is_encoded) = self.split_value_len(val_len);
         //
-        Ok(val_len)
+        Ok(ValueLength::new(len as u32))
     }
 
     #[inline]
-    fn read_piece_only_value(&mut self, offset: ValuePieceOffset) -> Result<Vec<u8>> {
+    fn read_piece_only_value(&mut self, offset: ValuePieceOffset, key: &[u8]) -> Result<Vec<u8>> {
         debug_assert!(!offset.is_zero());
         //
+        if self.0.has_checksum() {
+            self.0.seek_from_start(offset)?;
+            let piece_size = self.0.read_piece_size()?;
+            self.0.check_piece_checksum(offset, piece_size)?;
+        }
         self.0.seek_skip_to_piece_value(offset)?;
         //
+        self.read_value_at_here(key)
+    }
+
+    /// reads the value length and the value at the current position,
+    /// and decrypts and decodes the value if it is encrypted and encoded.
+    #[inline]
+    fn read_value_at_here(&mut self, key: &[u8]) -> Result<Vec<u8>> {
         let val_len = self.0.read_value_len()?;
-        let maybe_slice = self.0.read_exact_maybeslice(val_len.into())?;
-        let value = maybe_slice.into_vec();
-        //
-        Ok(value)
+        let (len, is_encoded) = self.split_value_len(val_len);
+        let maybe_slice = self.0.read_exact_maybeslice(len)?;
+        let data = match &self.3 {
+            Some(cipher) => cipher.decrypt(&maybe_slice, key, is_encoded)?,
+            None => maybe_slice.into_vec(),
+        };
+        if is_encoded {
+            decode_value(&self.2, &data)
+        } else {
+            Ok(data)
+        }
     }
 }
 
@@ -475,11 +830,18 @@ used piece:
 | offset | bytes | name        | comment                           |
 +--------+-------+-------------+-----------------------------------+
 | 0      | 1..5  | piece size  | size in bytes of this piece: u32  |
-| --     | 1..5  | val len     | a byte length of value            |
-| --     | --    | val data    | raw value data                    |
+| --     | 1..5  | val len     | a byte length of value data       |
+| --     | --    | val data    | raw, encoded or encrypted data    |
 | --     | --    | reserve     | reserved free space               |
+| --     | 4     | checksum    | CRC-32C of the above, if format 1 |
 +--------+-------+-------------+-----------------------------------+
 ```
+
+- val len: if the format has the value codec, the top bit of u32 is the flag
+  of the encoded value data.
+- val data: if the format has the encryption, it is the 24 bytes random nonce
+  and the sealed value of XChaCha20-Poly1305. the associated data is 1 byte
+  of the flag of the encoded value data, 0 or 1, and the key bytes.
 */
 /*
 ```text
@@ -1,21 +1,36 @@
+use super::super::cell::{Rc, RefCell};
+use super::checksum_error;
+use super::crc::crc32c;
 use super::piece::PieceMgr;
 use super::semtype::*;
+use super::wal::{PendingPages, SetLen};
 use rabuf::{BufFile, FileSetLen, FileSync, MaybeSlice, SmallRead, SmallWrite};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
 #[cfg(feature = "siamese_debug")]
-#[cfg(not(feature = "vf_u64u64"))]
 use std::convert::TryInto;
 
-#[cfg(feature = "vf_vu64")]
+use super::super::IntEncoding;
+use super::enc::{Encoding, NodeOffsetEncoding};
 use vu64::io::{ReadVu64, WriteVu64};
 
+/// the max chunk size of the buffer.
+const BUF_CHUNK_SIZE_MAX: u64 = 128 * 1024;
+
 /// Variable length integer access for a random access file.
 #[derive(Debug)]
 pub struct VarFile {
     buf_file: BufFile,
     pub(crate) piece_mgr: PieceMgr,
+    pending: Option<Box<PendingPages>>,
+    /// the snapshots of the backups that are copying this file
+    snapshots: Vec<SnapshotRef>,
+    /// the path of the file, it is some if the pieces have the checksum.
+    checksum_path: Option<PathBuf>,
+    encoding: Encoding,
 }
 
 impl VarFile {
@@ -25,6 +40,10 @@ impl VarFile {
         Ok(Self {
             buf_file: BufFile::new(name, file)?,
             piece_mgr,
+            pending: None,
+            snapshots: Vec::new(),
+            checksum_path: None,
+            encoding: Encoding::build(),
         })
     }
     /// Creates a new VarFile with the specified number of chunks.
@@ -42,6 +61,10 @@ impl VarFile {
             buf_file: BufFile::with_capacity(name, file, chunk_size, max_num_chunks)?,
             piece_mgr,
             //piece_mgr: PieceMgr::new(free_list_offset, size_ary),
+            pending: None,
+            snapshots: Vec::new(),
+            checksum_path: None,
+            encoding: Encoding::build(),
         })
     }
     /// Creates a new VarFile with the specified number of chunks.
@@ -59,6 +82,10 @@ impl VarFile {
             buf_file: BufFile::with_per_mille(name, file, chunk_size, per_mille)?,
             piece_mgr,
             //piece_mgr: PieceMgr::new(free_list_offset, size_ary),
+            pending: None,
+            snapshots: Vec::new(),
+            checksum_path: None,
+            encoding: Encoding::build(),
         })
     }
     //
@@ -84,6 +111,9 @@ impl VarFile {
     //
     #[inline]
     pub fn prepare<T>(&mut self, offset: Offset<T>) -> Result<()> {
+        if self.pending.is_some() {
+            return Ok(());
+        }
         self.buf_file.prepare(offset.into())
     }
     //
@@ -96,16 +126,6 @@ impl VarFile {
         self.prepare(offset)?;
         Ok(pos)
     }
-    #[cfg(any(feature = "vf_vu64", feature = "vf_u64u64"))]
-    #[inline]
-    pub fn seek_skip_length<T: PartialEq + Copy>(
-        &mut self,
-        length: Length<T>,
-    ) -> Result<Offset<T>> {
-        let val: u32 = length.into();
-        self.seek(SeekFrom::Current(val as i64))
-            .map(Offset::<T>::new)
-    }
     #[inline]
     pub fn _seek_skip_size<T: PartialEq + Copy>(&mut self, size: Size<T>) -> Result<Offset<T>> {
         let val: u32 = size.into();
@@ -123,7 +143,22 @@ impl VarFile {
     //
     #[inline]
     pub fn set_file_length<T>(&mut self, file_length: Offset<T>) -> Result<()> {
-        self.buf_file.set_len(file_length.into())
+        if let Some(pp) = &mut self.pending {
+            pp.set_len(file_length.into());
+            return Ok(());
+        }
+        let new_len: u64 = file_length.into();
+        let old_len = self.buf_file.seek(SeekFrom::End(0))?;
+        if new_len < old_len {
+            self.save_snapshot_pages(new_len, old_len)?;
+            // the buffer keeps the chunk over the new end, so its tail is cleared.
+            // or it appears again when the file is extended.
+            let chunk_end = (new_len / BUF_CHUNK_SIZE_MAX + 1) * BUF_CHUNK_SIZE_MAX;
+            let tail_end = old_len.min(chunk_end);
+            self.buf_file.seek(SeekFrom::Start(new_len))?;
+            self.buf_file.write_zero((tail_end - new_len) as u32)?;
+        }
+        FileSetLen::set_len(&mut self.buf_file, new_len)
     }
     #[inline]
     pub fn read_fill_buffer(&mut self) -> Result<()> {
@@ -132,19 +167,19 @@ impl VarFile {
     //
     #[inline]
     pub fn _write_all_small(&mut self, buf: &[u8]) -> Result<()> {
-        self.buf_file.write_all_small(buf)
+        SmallWrite::write_all_small(self, buf)
     }
     //
     #[inline]
     pub fn _write_zero<T>(&mut self, size: Size<T>) -> Result<()> {
-        self.buf_file.write_zero(size.into())
+        SmallWrite::write_zero(self, size.into())
     }
     #[inline]
     pub fn write_zero_to_offset<T: PartialOrd>(&mut self, offset: Offset<T>) -> Result<()> {
         let start_offset = self.seek_position()?;
         if offset > start_offset {
             let size = offset - start_offset;
-            self.buf_file.write_zero(size.into())
+            SmallWrite::write_zero(self, size.into())
         } else {
             Ok(())
         }
@@ -197,9 +232,239 @@ impl VarFile {
     }
 }
 
+// for checksum
+impl VarFile {
+    /// the pieces of this file have the checksum at the end.
+    pub fn set_checksum(&mut self, path: &Path) {
+        self.checksum_path = Some(path.to_path_buf());
+    }
+    #[inline]
+    pub fn has_checksum(&self) -> bool {
+        self.checksum_path.is_some()
+    }
+    /// the bytes of the checksum in the piece.
+    #[inline]
+    pub fn checksum_len(&self) -> u32 {
+        if self.has_checksum() {
+            4
+        } else {
+            0
+        }
+    }
+    fn piece_checksum<T: PartialEq + Copy>(
+        &mut self,
+        offset: Offset<T>,
+        size: Size<T>,
+    ) -> Result<u32> {
+        let mut buf = vec![0u8; (size.as_value() - 4) as usize];
+        self.seek_from_start(offset)?;
+        self.read_exact(&mut buf)?;
+        Ok(crc32c(&buf))
+    }
+    /// writes the checksum of the piece to the last 4 bytes of it.
+    pub fn write_piece_checksum<T: PartialEq + Copy>(
+        &mut self,
+        offset: Offset<T>,
+        size: Size<T>,
+    ) -> Result<()> {
+        if !self.has_checksum() {
+            return Ok(());
+        }
+        let crc = self.piece_checksum(offset, size)?;
+        self.write_u32_le(crc)
+    }
+    /// checks the checksum of the piece.
+    pub fn check_piece_checksum<T: PartialEq + Copy>(
+        &mut self,
+        offset: Offset<T>,
+        size: Size<T>,
+    ) -> Result<()> {
+        let path = match &self.checksum_path {
+            Some(path) => path.clone(),
+            None => return Ok(()),
+        };
+        if size.as_value() <= 4 {
+            return Err(checksum_error(&path, offset.as_value()));
+        }
+        let crc = match self.piece_checksum(offset, size) {
+            Ok(crc) => crc,
+            Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => {
+                return Err(checksum_error(&path, offset.as_value()));
+            }
+            Err(err) => return Err(err),
+        };
+        if crc != self.read_u32_le()? {
+            return Err(checksum_error(&path, offset.as_value()));
+        }
+        Ok(())
+    }
+}
+
+// for crash recovery
+impl VarFile {
+    /// reads the dirty flag in the header.
+    pub fn read_dirty_flag<T: PartialEq + Copy>(&mut self, offset: Offset<T>) -> Result<bool> {
+        self.seek_from_start(offset)?;
+        Ok(self.read_u64_le()? != 0)
+    }
+    /// writes the dirty flag in the header.
+    /// the set flag is flushed at once, to be on the file before the other writing.
+    pub fn write_dirty_flag<T: PartialEq + Copy>(
+        &mut self,
+        offset: Offset<T>,
+        flag: bool,
+    ) -> Result<()> {
+        self.seek_from_start(offset)?;
+        self.write_u64_le(u64::from(flag))?;
+        if flag {
+            self.flush()?;
+        }
+        Ok(())
+    }
+}
+
+// for write batch
+impl VarFile {
+    /// starts to keep the writing in the pending pages, without touching the file.
+    pub fn begin_pending(&mut self) -> Result<()> {
+        debug_assert!(self.pending.is_none());
+        let pos = self.buf_file.stream_position()?;
+        let end = self.buf_file.seek(SeekFrom::End(0))?;
+        let _ = self.buf_file.seek(SeekFrom::Start(pos))?;
+        self.pending = Some(Box::new(PendingPages::new(pos, end)));
+        Ok(())
+    }
+    /// takes the pending pages, and then the writing goes to the file again.
+    pub fn take_pending(&mut self) -> Option<PendingPages> {
+        self.pending.take().map(|pp| *pp)
+    }
+    /// writes the pending pages to the file.
+    pub fn apply_pending(&mut self, pages: &PendingPages) -> Result<()> {
+        debug_assert!(self.pending.is_none());
+        let file_end = self.buf_file.seek(SeekFrom::End(0))?;
+        // the truncation clears the tail of the buffer, see `set_file_length()`.
+        pages.apply_to(self, file_end)
+    }
+}
+
+// for backup
+impl VarFile {
+    /// starts the snapshot of the file for the backup. after this, the old contents
+    /// of the pages are kept in the snapshot before they are changed.
+    /// the file must be synced before this.
+    pub fn begin_snapshot(&mut self) -> Result<SnapshotRef> {
+        debug_assert!(self.pending.is_none());
+        let pos = self.buf_file.stream_position()?;
+        let len = self.buf_file.seek(SeekFrom::End(0))?;
+        let _ = self.buf_file.seek(SeekFrom::Start(pos))?;
+        let snapshot = Rc::new(RefCell::new(FileSnapshot::new(len)));
+        self.snapshots.push(snapshot.clone());
+        Ok(snapshot)
+    }
+    /// the snapshots that are not finished.
+    pub fn snapshots(&self) -> Vec<SnapshotRef> {
+        self.snapshots.clone()
+    }
+    /// this file takes the snapshots of the other one on the same file.
+    pub fn set_snapshots(&mut self, snapshots: Vec<SnapshotRef>) {
+        self.snapshots = snapshots;
+    }
+    #[inline]
+    fn before_write(&mut self, len: usize) -> Result<()> {
+        if self.snapshots.is_empty() {
+            return Ok(());
+        }
+        let pos = self.buf_file.stream_position()?;
+        self.save_snapshot_pages(pos, pos + len as u64)
+    }
+    /// keeps the old contents of the pages from `start` to `end` in the snapshots.
+    fn save_snapshot_pages(&mut self, start: u64, end: u64) -> Result<()> {
+        self.snapshots.retain(|a| !RefCell::borrow(a).done);
+        if self.snapshots.is_empty() {
+            return Ok(());
+        }
+        let pos = self.buf_file.stream_position()?;
+        for snapshot in self.snapshots.clone() {
+            let mut locked = RefCell::borrow_mut(&snapshot);
+            let end = end.min(locked.len);
+            let mut page_offset = start.max(locked.copied);
+            page_offset -= page_offset % SNAPSHOT_PAGE_SIZE;
+            while page_offset < end {
+                if !locked.pages.contains_key(&page_offset) {
+                    let page_len = SNAPSHOT_PAGE_SIZE.min(locked.len - page_offset);
+                    let mut page = vec![0u8; page_len as usize];
+                    let _ = self.buf_file.seek(SeekFrom::Start(page_offset))?;
+                    self.buf_file.read_exact(&mut page)?;
+                    let _ = locked.pages.insert(page_offset, page);
+                }
+                page_offset += SNAPSHOT_PAGE_SIZE;
+            }
+        }
+        let _ = self.buf_file.seek(SeekFrom::Start(pos))?;
+        Ok(())
+    }
+}
+
+/// the page size of the snapshot. the chunk of the copy is a multiple of it.
+pub(crate) const SNAPSHOT_PAGE_SIZE: u64 = 4 * 1024;
+
+pub(crate) type SnapshotRef = Rc<RefCell<FileSnapshot>>;
+
+/// the file at the point in time of the backup. the file on the disk is read
+/// by the backup, and the pages that are changed after the point are read from here.
+#[derive(Debug)]
+pub(crate) struct FileSnapshot {
+    /// the file length at the point in time
+    len: u64,
+    /// the pages under this are copied already, they are not kept any more.
+    copied: u64,
+    /// the old contents of the changed pages
+    pages: BTreeMap<u64, Vec<u8>>,
+    /// the copy is finished, and the writing does not keep the pages.
+    done: bool,
+}
+
+impl FileSnapshot {
+    fn new(len: u64) -> Self {
+        Self {
+            len,
+            copied: 0,
+            pages: BTreeMap::new(),
+            done: false,
+        }
+    }
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+    /// the copy of the file goes over `end`. returns the old contents of the pages
+    /// that are changed under it.
+    pub fn take_pages(&mut self, end: u64) -> BTreeMap<u64, Vec<u8>> {
+        debug_assert!(end % SNAPSHOT_PAGE_SIZE == 0 || end == self.len);
+        let rest = self.pages.split_off(&end);
+        self.copied = end;
+        std::mem::replace(&mut self.pages, rest)
+    }
+    /// finishes the copy of the file.
+    pub fn finish(&mut self) {
+        self.pages.clear();
+        self.done = true;
+    }
+}
+
+impl SetLen for VarFile {
+    #[inline]
+    fn set_len(&mut self, size: u64) -> Result<()> {
+        self.set_file_length(Offset::<u64>::new(size))
+    }
+}
+
 impl Read for VarFile {
     #[inline]
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if let Some(pp) = &mut self.pending {
+            return pp.read(&mut self.buf_file, buf);
+        }
         self.buf_file.read(buf)
     }
 }
@@ -207,6 +472,10 @@ impl Read for VarFile {
 impl Write for VarFile {
     #[inline]
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if let Some(pp) = &mut self.pending {
+            return pp.write(&mut self.buf_file, buf);
+        }
+        self.before_write(buf.len())?;
         self.buf_file.write(buf)
     }
     #[inline]
@@ -218,6 +487,9 @@ impl Write for VarFile {
 impl Seek for VarFile {
     #[inline]
     fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        if let Some(pp) = &mut self.pending {
+            return Ok(pp.seek(pos));
+        }
         self.buf_file.seek(pos)
     }
 }
@@ -225,30 +497,63 @@ impl Seek for VarFile {
 impl rabuf::SmallRead for VarFile {
     #[inline]
     fn read_u8(&mut self) -> Result<u8> {
+        if self.pending.is_some() {
+            let mut buf = [0u8; 1];
+            self.read_exact(&mut buf)?;
+            return Ok(buf[0]);
+        }
         self.buf_file.read_u8()
     }
     #[inline]
     fn read_u16_le(&mut self) -> Result<u16> {
+        if self.pending.is_some() {
+            let mut buf = [0u8; 2];
+            self.read_exact(&mut buf)?;
+            return Ok(u16::from_le_bytes(buf));
+        }
         self.buf_file.read_u16_le()
     }
     #[inline]
     fn read_u32_le(&mut self) -> Result<u32> {
+        if self.pending.is_some() {
+            let mut buf = [0u8; 4];
+            self.read_exact(&mut buf)?;
+            return Ok(u32::from_le_bytes(buf));
+        }
         self.buf_file.read_u32_le()
     }
     #[inline]
     fn read_u64_le(&mut self) -> Result<u64> {
+        if self.pending.is_some() {
+            let mut buf = [0u8; 8];
+            self.read_exact(&mut buf)?;
+            return Ok(u64::from_le_bytes(buf));
+        }
         self.buf_file.read_u64_le()
     }
     #[inline]
     fn read_max_8_bytes(&mut self, size: usize) -> Result<u64> {
+        if self.pending.is_some() {
+            let mut buf = [0u8; 8];
+            self.read_exact(&mut buf[..size])?;
+            return Ok(u64::from_le_bytes(buf));
+        }
         self.buf_file.read_max_8_bytes(size)
     }
     #[inline]
     fn read_exact_small(&mut self, buf: &mut [u8]) -> Result<()> {
+        if self.pending.is_some() {
+            return self.read_exact(buf);
+        }
         self.buf_file.read_exact_small(buf)
     }
     #[inline]
-    fn read_exact_maybeslice(&mut self, size: usize) -> Result<MaybeSlice> {
+    fn read_exact_maybeslice(&mut self, size: usize) -> Result<MaybeSlice<'_>> {
+        if self.pending.is_some() {
+            let mut buf = vec![0u8; size];
+            self.read_exact(&mut buf)?;
+            return Ok(MaybeSlice::Buffer(buf));
+        }
         self.buf_file.read_exact_maybeslice(size)
     }
 }
@@ -256,387 +561,87 @@ impl rabuf::SmallRead for VarFile {
 impl rabuf::SmallWrite for VarFile {
     #[inline]
     fn write_u8(&mut self, val: u8) -> Result<()> {
+        if self.pending.is_some() {
+            return self.write_all(&val.to_le_bytes());
+        }
+        self.before_write(1)?;
         self.buf_file.write_u8(val)
     }
     #[inline]
     fn write_u16_le(&mut self, val: u16) -> Result<()> {
+        if self.pending.is_some() {
+            return self.write_all(&val.to_le_bytes());
+        }
+        self.before_write(2)?;
         self.buf_file.write_u16_le(val)
     }
     #[inline]
     fn write_u32_le(&mut self, val: u32) -> Result<()> {
+        if self.pending.is_some() {
+            return self.write_all(&val.to_le_bytes());
+        }
+        self.before_write(4)?;
         self.buf_file.write_u32_le(val)
     }
     #[inline]
     fn write_u64_le(&mut self, val: u64) -> Result<()> {
+        if self.pending.is_some() {
+            return self.write_all(&val.to_le_bytes());
+        }
+        self.before_write(8)?;
         self.buf_file.write_u64_le(val)
     }
     #[inline]
     fn write_u64_le_slice(&mut self, val_slice: &[u64]) -> Result<()> {
+        if self.pending.is_some() {
+            for &val in val_slice {
+                self.write_all(&val.to_le_bytes())?;
+            }
+            return Ok(());
+        }
+        self.before_write(8 * val_slice.len())?;
         self.buf_file.write_u64_le_slice(val_slice)
     }
     #[inline]
     fn write_u64_le_slice2(&mut self, val_slice1: &[u64], val_slice2: &[u64]) -> Result<()> {
+        if self.pending.is_some() {
+            self.write_u64_le_slice(val_slice1)?;
+            return self.write_u64_le_slice(val_slice2);
+        }
+        self.before_write(8 * (val_slice1.len() + val_slice2.len()))?;
         self.buf_file.write_u64_le_slice2(val_slice1, val_slice2)
     }
     #[inline]
     fn write_all_small(&mut self, buf: &[u8]) -> Result<()> {
+        if self.pending.is_some() {
+            return self.write_all(buf);
+        }
+        self.before_write(buf.len())?;
         self.buf_file.write_all_small(buf)
     }
     #[inline]
     fn write_zero(&mut self, size: u32) -> Result<()> {
+        if self.pending.is_some() {
+            return self.write_all(&vec![0u8; size as usize]);
+        }
+        self.before_write(size as usize)?;
         self.buf_file.write_zero(size)
     }
 }
 
-#[cfg(feature = "vf_node_u32")]
-impl VarFile {
-    #[inline]
-    pub fn read_piece_offset_u32<T>(&mut self) -> Result<PieceOffset<T>> {
-        self.read_u32_le().map(|o| PieceOffset::<T>::new(o as u64))
-    }
-    #[inline]
-    pub fn write_piece_offset_u32<T: Copy>(&mut self, piece_offset: PieceOffset<T>) -> Result<()> {
-        debug_assert!(piece_offset.as_value() <= u32::MAX as u64);
-        #[cfg(feature = "siamese_debug")]
-        let val = piece_offset
-            .try_into()
-            .unwrap_or_else(|err| panic!("piece_offset: {}: {}", piece_offset.as_value(), err));
-        #[cfg(not(feature = "siamese_debug"))]
-        let val = piece_offset.as_value() as u32;
-        //
-        self.write_u32_le(val)
-    }
-    #[inline]
-    pub fn read_node_offset_u32(&mut self) -> Result<NodePieceOffset> {
-        self.read_u32_le().map(|n| NodePieceOffset::new(n as u64))
-    }
-    #[inline]
-    pub fn write_node_offset_u32(&mut self, node_offset: NodePieceOffset) -> Result<()> {
-        debug_assert!(node_offset.as_value() <= u32::MAX as u64);
-        #[cfg(feature = "siamese_debug")]
-        let val = node_offset
-            .try_into()
-            .unwrap_or_else(|err| panic!("node_offset: {}: {}", node_offset.as_value(), err));
-        #[cfg(not(feature = "siamese_debug"))]
-        let val = node_offset.as_value() as u32;
-        //
-        self.write_u32_le(val)
-    }
-}
-
-#[cfg(feature = "vf_node_u64")]
-impl VarFile {
-    #[inline]
-    pub fn read_piece_offset_u64<T>(&mut self) -> Result<PieceOffset<T>> {
-        self.read_u64_le().map(PieceOffset::new)
-    }
-    #[cfg(not(feature = "idx_straight"))]
-    #[inline]
-    pub fn write_piece_offset_u64<T: Copy>(&mut self, piece_offset: PieceOffset<T>) -> Result<()> {
-        self.write_u64_le(piece_offset.into())
-    }
-    #[cfg(feature = "idx_straight")]
-    #[inline]
-    pub fn write_piece_offset_u64_slice<T: Copy>(
-        &mut self,
-        piece_offset_slice: &[PieceOffset<T>],
-    ) -> Result<()> {
-        let u64_slice =
-            unsafe { std::mem::transmute::<&[PieceOffset<T>], &[u64]>(piece_offset_slice) };
-        self.write_u64_le_slice(u64_slice)
-    }
-    #[inline]
-    pub fn read_node_offset_u64(&mut self) -> Result<NodePieceOffset> {
-        self.read_u64_le().map(NodePieceOffset::new)
-    }
-    #[cfg(not(feature = "idx_straight"))]
-    #[inline]
-    pub fn write_node_offset_u64(&mut self, node_offset: NodePieceOffset) -> Result<()> {
-        self.write_u64_le(node_offset.into())
-    }
-    #[cfg(feature = "idx_straight")]
-    #[inline]
-    pub fn _write_node_offset_u64_slice(
-        &mut self,
-        node_offset_slice: &[NodePieceOffset],
-    ) -> Result<()> {
-        let u64_slice =
-            unsafe { std::mem::transmute::<&[NodePieceOffset], &[u64]>(node_offset_slice) };
-        self.write_u64_le_slice(u64_slice)
-    }
-    #[cfg(feature = "idx_straight")]
-    #[inline]
-    pub fn write_piece_offset_and_node_offset_u64_slice<T: Copy>(
-        &mut self,
-        piece_offset_slice: &[PieceOffset<T>],
-        node_offset_slice: &[NodePieceOffset],
-    ) -> Result<()> {
-        let u64_slice1 =
-            unsafe { std::mem::transmute::<&[PieceOffset<T>], &[u64]>(piece_offset_slice) };
-        let u64_slice2 =
-            unsafe { std::mem::transmute::<&[NodePieceOffset], &[u64]>(node_offset_slice) };
-        self.write_u64_le_slice2(u64_slice1, u64_slice2)
-    }
-}
-
-#[cfg(feature = "vf_u32u32")]
-#[cfg(any(feature = "htx", feature = "idx_straight"))]
-impl VarFile {
-    #[inline]
-    pub fn read_value_piece_offset<T>(&mut self) -> Result<PieceOffset<T>> {
-        self.read_u32_le().map(|o| PieceOffset::<T>::new(o as u64))
-    }
-    #[inline]
-    pub fn write_value_piece_offset<T: Copy>(
-        &mut self,
-        piece_offset: PieceOffset<T>,
-    ) -> Result<()> {
-        debug_assert!(piece_offset.as_value() <= u32::MAX as u64);
-        #[cfg(feature = "siamese_debug")]
-        let val = piece_offset
-            .try_into()
-            .unwrap_or_else(|err| panic!("piece_offset: {}: {}", piece_offset.as_value(), err));
-        #[cfg(not(feature = "siamese_debug"))]
-        let val = piece_offset.as_value() as u32;
-        //
-        self.write_u32_le(val)
-    }
-}
-
-#[cfg(any(feature = "vf_u64u64", feature = "vf_vu64"))]
-#[cfg(any(feature = "htx", feature = "idx_straight"))]
-impl VarFile {
-    #[inline]
-    pub fn read_value_piece_offset<T>(&mut self) -> Result<PieceOffset<T>> {
-        self.read_u64_le().map(PieceOffset::new)
-    }
-    #[inline]
-    pub fn write_value_piece_offset<T>(&mut self, piece_offset: PieceOffset<T>) -> Result<()> {
-        self.write_u64_le(piece_offset.as_value())
-    }
-}
-
-#[cfg(feature = "vf_u32u32")]
-impl VarFile {
-    #[inline]
-    pub fn read_free_piece_offset<T>(&mut self) -> Result<Offset<T>> {
-        self.read_u32_le().map(|o| Offset::<T>::new(o as u64))
-    }
-    #[inline]
-    pub fn write_free_piece_offset<T: Copy>(&mut self, offset: Offset<T>) -> Result<()> {
-        debug_assert!(offset.as_value() <= u32::MAX as u64);
-        #[cfg(feature = "siamese_debug")]
-        let val = offset
-            .try_into()
-            .unwrap_or_else(|err| panic!("piece_offset: {}: {}", offset.as_value(), err));
-        #[cfg(not(feature = "siamese_debug"))]
-        let val = offset.as_value() as u32;
-        //
-        self.write_u32_le(val)
-    }
-    //
-    #[cfg(not(any(feature = "htx", feature = "idx_straight")))]
-    #[inline]
-    pub fn read_piece_offset<T>(&mut self) -> Result<PieceOffset<T>> {
-        self.read_u32_le().map(|o| PieceOffset::<T>::new(o as u64))
-    }
-    #[cfg(not(any(feature = "htx", feature = "idx_straight")))]
-    #[inline]
-    pub fn write_piece_offset<T: Copy>(&mut self, piece_offset: PieceOffset<T>) -> Result<()> {
-        debug_assert!(piece_offset.as_value() <= u32::MAX as u64);
-        #[cfg(feature = "siamese_debug")]
-        let val = piece_offset
-            .try_into()
-            .unwrap_or_else(|err| panic!("piece_offset: {}: {}", piece_offset.as_value(), err));
-        #[cfg(not(feature = "siamese_debug"))]
-        let val = piece_offset.as_value() as u32;
-        //
-        self.write_u32_le(val)
-    }
-    #[inline]
-    pub fn read_piece_size<T>(&mut self) -> Result<PieceSize<T>> {
-        self.read_u32_le().map(PieceSize::<T>::new)
-    }
-    #[inline]
-    pub fn write_piece_size<T>(&mut self, piece_size: PieceSize<T>) -> Result<()> {
-        self.write_u32_le(piece_size.into())
-    }
-    //
-    #[inline]
-    pub fn read_key_len(&mut self) -> Result<KeyLength> {
-        self.read_u32_le().map(KeyLength::new)
-    }
-    #[inline]
-    pub fn write_key_len(&mut self, key_len: KeyLength) -> Result<()> {
-        self.write_u32_le(key_len.into())
-    }
-    #[inline]
-    pub fn read_value_len(&mut self) -> Result<ValueLength> {
-        self.read_u32_le().map(ValueLength::new)
-    }
-    #[inline]
-    pub fn write_value_len(&mut self, value_len: ValueLength) -> Result<()> {
-        self.write_u32_le(value_len.into())
-    }
-    //
-    #[inline]
-    pub fn _read_node_offset(&mut self) -> Result<NodePieceOffset> {
-        self.read_u32_le().map(|n| NodePieceOffset::new(n as u64))
-    }
-    #[inline]
-    pub fn _write_node_offset(&mut self, node_offset: NodePieceOffset) -> Result<()> {
-        debug_assert!(node_offset.as_value() <= u32::MAX as u64);
-        #[cfg(feature = "siamese_debug")]
-        let val = node_offset
-            .try_into()
-            .unwrap_or_else(|err| panic!("node_offset: {}: {}", node_offset.as_value(), err));
-        #[cfg(not(feature = "siamese_debug"))]
-        let val = node_offset.as_value() as u32;
-        //
-        self.write_u32_le(val)
-    }
-    #[inline]
-    pub fn read_node_size(&mut self) -> Result<NodePieceSize> {
-        self.read_u16_le().map(|n| NodePieceSize::new(n as u32))
-    }
-    #[inline]
-    pub fn write_node_size(&mut self, node_size: NodePieceSize) -> Result<()> {
-        debug_assert!(node_size.as_value() <= u16::MAX as u32);
-        self.write_u16_le(node_size.as_value() as u16)
-    }
-    #[inline]
-    pub fn read_keys_count(&mut self) -> Result<KeysCount> {
-        self.read_u16_le().map(KeysCount::new)
-    }
-    #[inline]
-    pub fn write_keys_count(&mut self, keys_len: KeysCount) -> Result<()> {
-        self.write_u16_le(keys_len.into())
-    }
-}
-
-#[cfg(feature = "vf_u64u64")]
-impl VarFile {
-    #[inline]
-    pub fn read_free_piece_offset<T>(&mut self) -> Result<Offset<T>> {
-        self.read_u64_le().map(Offset::<T>::new)
-    }
-    #[inline]
-    pub fn write_free_piece_offset<T>(&mut self, offset: Offset<T>) -> Result<()> {
-        self.write_u64_le(offset.into())
-    }
-    //
-    #[cfg(any(
-        not(any(feature = "htx", feature = "idx_straight")),
-        not(any(feature = "vf_node_u32", feature = "vf_node_u64"))
-    ))]
-    #[inline]
-    pub fn read_piece_offset<T>(&mut self) -> Result<PieceOffset<T>> {
-        self.read_u64_le().map(PieceOffset::<T>::new)
-    }
-    #[cfg(any(
-        not(any(feature = "htx", feature = "idx_straight")),
-        not(any(feature = "vf_node_u32", feature = "vf_node_u64"))
-    ))]
-    #[inline]
-    pub fn write_piece_offset<T>(&mut self, piece_offset: PieceOffset<T>) -> Result<()> {
-        self.write_u64_le(piece_offset.into())
-    }
-    #[inline]
-    pub fn read_piece_size<T>(&mut self) -> Result<PieceSize<T>> {
-        self.read_u32_le().map(PieceSize::<T>::new)
-    }
-    #[inline]
-    pub fn write_piece_size<T>(&mut self, piece_size: PieceSize<T>) -> Result<()> {
-        self.write_u32_le(piece_size.into())
-    }
-    //
-    #[inline]
-    pub fn read_key_len(&mut self) -> Result<KeyLength> {
-        self.read_u32_le().map(KeyLength::new)
-    }
-    #[inline]
-    pub fn write_key_len(&mut self, key_len: KeyLength) -> Result<()> {
-        self.write_u32_le(key_len.into())
-    }
-    #[inline]
-    pub fn read_value_len(&mut self) -> Result<ValueLength> {
-        self.read_u32_le().map(ValueLength::new)
-    }
-    #[inline]
-    pub fn write_value_len(&mut self, value_len: ValueLength) -> Result<()> {
-        self.write_u32_le(value_len.into())
-    }
-    //
-    #[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
-    #[inline]
-    pub fn read_node_offset(&mut self) -> Result<NodePieceOffset> {
-        self.read_u64_le().map(NodePieceOffset::new)
-    }
-    #[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
-    #[inline]
-    pub fn write_node_offset(&mut self, node_offset: NodePieceOffset) -> Result<()> {
-        self.write_u64_le(node_offset.into())
-    }
-    #[inline]
-    pub fn read_node_size(&mut self) -> Result<NodePieceSize> {
-        self.read_u16_le().map(|n| NodePieceSize::new(n as u32))
-    }
-    #[inline]
-    pub fn write_node_size(&mut self, node_size: NodePieceSize) -> Result<()> {
-        debug_assert!(node_size.as_value() <= u16::MAX as u32);
-        self.write_u16_le(node_size.as_value() as u16)
-    }
-    #[inline]
-    pub fn read_keys_count(&mut self) -> Result<KeysCount> {
-        self.read_u16_le().map(KeysCount::new)
-    }
-    #[inline]
-    pub fn write_keys_count(&mut self, keys_len: KeysCount) -> Result<()> {
-        self.write_u16_le(keys_len.into())
-    }
-}
-
-#[cfg(feature = "vf_vu64")]
 impl ReadVu64 for VarFile {
     #[inline]
     fn read_one_byte(&mut self) -> Result<u8> {
-        self.buf_file.read_u8()
+        self.read_u8()
     }
     #[inline]
     fn read_exact_max8byte(&mut self, buf: &mut [u8]) -> Result<()> {
         debug_assert!(buf.len() <= 8, "buf.len(): {} <= 8", buf.len());
-        self.buf_file.read_exact_small(buf)
+        self.read_exact_small(buf)
     }
     /// reads `vu64` bytes and decods it to `u64`
     fn read_and_decode_vu64(&mut self) -> Result<u64> {
-        /*
-        let mut buf = [0u8; vu64::MAX_BYTES-1];
-        let byte_1st = self.buf_file.read_u8()?;
-        let len = vu64::decoded_len(byte_1st);
-        if len > 1 {
-            self.buf_file.read_exact_small(&mut buf[..len as usize - 1])?;
-        }
-        match vu64::decode_with_first_and_follow(len, byte_1st, &buf[..len as usize - 1]) {
-            Ok(i) => Ok(i),
-            Err(err) => Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("{}", err),
-            )),
-        }
-        */
-        /*
-        let byte_1st = self.buf_file.read_u8()?;
-        let len = vu64::decoded_len(byte_1st);
-        let maybe_slice = self.buf_file.read_exact_maybeslice(len as usize - 1)?;
-        match vu64::decode_with_first_and_follow(len, byte_1st, &maybe_slice) {
-            Ok(i) => Ok(i),
-            Err(err) => Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                format!("{}", err),
-            )),
-        }
-        */
-        let byte_1st = self.buf_file.read_u8()?;
+        let byte_1st = self.read_u8()?;
         if byte_1st < 128 {
             Ok(byte_1st as u64)
         } else {
@@ -644,10 +649,10 @@ impl ReadVu64 for VarFile {
             let follow_len = len as usize - 1;
             let max_8_bytes = match follow_len {
                 0 => 0,
-                1 => self.buf_file.read_u8()? as u64,
-                2 => self.buf_file.read_u16_le()? as u64,
-                //4 => self.buf_file.read_u32_le()? as u64,
-                _ => self.buf_file.read_max_8_bytes(follow_len)?,
+                1 => self.read_u8()? as u64,
+                2 => self.read_u16_le()? as u64,
+                //4 => self.read_u32_le()? as u64,
+                _ => self.read_max_8_bytes(follow_len)?,
             };
             match vu64::decode_with_first_and_follow_le(len, byte_1st, max_8_bytes) {
                 Ok(i) => Ok(i),
@@ -657,15 +662,11 @@ impl ReadVu64 for VarFile {
                 )),
             }
         }
-        /*
-         */
     }
 }
 
-#[cfg(feature = "vf_vu64")]
 impl WriteVu64 for VarFile {}
 
-#[cfg(feature = "vf_vu64")]
 impl VarFile {
     #[inline]
     pub fn read_vu64_u16(&mut self) -> Result<u16> {
@@ -707,171 +708,311 @@ impl VarFile {
     }
 }
 
-#[cfg(feature = "vf_vu64")]
+// for the encoding of the integers
 impl VarFile {
+    /// the encoding of the integers in this file.
+    #[inline]
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+    #[inline]
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
+    }
+    //
+    #[inline]
+    fn read_offset_u32(&mut self) -> Result<u64> {
+        self.read_u32_le().map(|o| o as u64)
+    }
+    #[inline]
+    fn write_offset_u32(&mut self, offset: u64) -> Result<()> {
+        debug_assert!(offset <= u32::MAX as u64);
+        #[cfg(feature = "siamese_debug")]
+        let val = offset
+            .try_into()
+            .unwrap_or_else(|err| panic!("offset: {}: {}", offset, err));
+        #[cfg(not(feature = "siamese_debug"))]
+        let val = offset as u32;
+        //
+        self.write_u32_le(val)
+    }
+    /// the offset of the `vu64` is divided by 8.
+    #[inline]
+    fn read_offset_vu64(&mut self) -> Result<u64> {
+        self._read_vu64_u64().map(|v| v * 8)
+    }
+    #[inline]
+    fn write_offset_vu64(&mut self, offset: u64) -> Result<()> {
+        debug_assert!(offset % 8 == 0);
+        self._write_vu64_u64(offset / 8)
+    }
+    //
+    #[inline]
+    fn read_offset_of(&mut self, enc: IntEncoding) -> Result<u64> {
+        match enc {
+            IntEncoding::U32U32 => self.read_offset_u32(),
+            IntEncoding::U64U64 => self.read_u64_le(),
+            IntEncoding::Vu64 => self.read_offset_vu64(),
+        }
+    }
+    #[inline]
+    fn write_offset_of(&mut self, enc: IntEncoding, offset: u64) -> Result<()> {
+        match enc {
+            IntEncoding::U32U32 => self.write_offset_u32(offset),
+            IntEncoding::U64U64 => self.write_u64_le(offset),
+            IntEncoding::Vu64 => self.write_offset_vu64(offset),
+        }
+    }
+    #[inline]
+    fn read_node_offset_of(&mut self) -> Result<u64> {
+        match self.encoding.node {
+            NodeOffsetEncoding::Piece => self.read_offset_of(self.encoding.piece),
+            NodeOffsetEncoding::U32 => self.read_offset_u32(),
+            NodeOffsetEncoding::U64 => self.read_u64_le(),
+        }
+    }
+    #[inline]
+    fn write_node_offset_of(&mut self, offset: u64) -> Result<()> {
+        match self.encoding.node {
+            NodeOffsetEncoding::Piece => self.write_offset_of(self.encoding.piece, offset),
+            NodeOffsetEncoding::U32 => self.write_offset_u32(offset),
+            NodeOffsetEncoding::U64 => self.write_u64_le(offset),
+        }
+    }
+    /// the encoding of the fixed width integer of the piece.
+    #[inline]
+    fn fixed_encoding(&self) -> IntEncoding {
+        match self.encoding.piece {
+            IntEncoding::U32U32 => IntEncoding::U32U32,
+            _ => IntEncoding::U64U64,
+        }
+    }
+    //
     #[inline]
     pub fn read_free_piece_offset<T>(&mut self) -> Result<Offset<T>> {
-        self.read_u64_le().map(Offset::<T>::new)
+        let enc = self.fixed_encoding();
+        self.read_offset_of(enc).map(Offset::<T>::new)
     }
     #[inline]
     pub fn write_free_piece_offset<T>(&mut self, offset: Offset<T>) -> Result<()> {
-        self.write_u64_le(offset.into())
+        let enc = self.fixed_encoding();
+        self.write_offset_of(enc, offset.into())
     }
-    //
-    #[cfg(any(
-        not(any(feature = "htx", feature = "idx_straight")),
-        not(any(feature = "vf_node_u32", feature = "vf_node_u64"))
-    ))]
+    /// the value offset in the key piece.
     #[inline]
-    pub fn read_piece_offset<T>(&mut self) -> Result<PieceOffset<T>> {
-        self._read_vu64_u64().map(|v| PieceOffset::<T>::new(v * 8))
+    pub fn read_value_piece_offset<T>(&mut self) -> Result<PieceOffset<T>> {
+        let enc = if self.encoding.value_offset_fixed {
+            self.fixed_encoding()
+        } else {
+            self.encoding.piece
+        };
+        self.read_offset_of(enc).map(PieceOffset::<T>::new)
     }
-    #[cfg(any(
-        not(any(feature = "htx", feature = "idx_straight")),
-        not(any(feature = "vf_node_u32", feature = "vf_node_u64"))
-    ))]
     #[inline]
-    pub fn write_piece_offset<T>(&mut self, piece_offset: PieceOffset<T>) -> Result<()> {
-        let v: u64 = piece_offset.into();
-
-        debug_assert!(v % 8 == 0);
-        self._write_vu64_u64(v / 8)
+    pub fn write_value_piece_offset<T>(&mut self, piece_offset: PieceOffset<T>) -> Result<()> {
+        let enc = if self.encoding.value_offset_fixed {
+            self.fixed_encoding()
+        } else {
+            self.encoding.piece
+        };
+        self.write_offset_of(enc, piece_offset.into())
+    }
+    /// the key offset in the node.
+    #[inline]
+    pub fn read_node_piece_offset<T>(&mut self) -> Result<PieceOffset<T>> {
+        self.read_node_offset_of().map(PieceOffset::<T>::new)
     }
-    /*
-    #[cfg(not(any(feature = "htx", feature = "idx_straight")))]
     #[inline]
-    pub fn read_piece_offset<T>(&mut self) -> Result<PieceOffset<T>> {
-        self._read_vu64_u64().map(PieceOffset::<T>::new)
+    pub fn write_node_piece_offset<T>(&mut self, piece_offset: PieceOffset<T>) -> Result<()> {
+        self.write_node_offset_of(piece_offset.into())
     }
-    #[cfg(not(any(feature = "htx", feature = "idx_straight")))]
+    /// the down offset in the node.
     #[inline]
-    pub fn write_piece_offset<T>(&mut self, piece_offset: PieceOffset<T>) -> Result<()> {
-        let v: u64 = piece_offset.into();
-        self._write_vu64_u64(v)
+    pub fn read_node_offset(&mut self) -> Result<NodePieceOffset> {
+        self.read_node_offset_of().map(NodePieceOffset::new)
     }
-    */
+    #[inline]
+    pub fn write_node_offset(&mut self, node_offset: NodePieceOffset) -> Result<()> {
+        self.write_node_offset_of(node_offset.into())
+    }
+    //
     #[inline]
     pub fn read_piece_size<T>(&mut self) -> Result<PieceSize<T>> {
-        self.read_vu64_u32().map(|v| PieceSize::<T>::new(v * 8))
+        match self.encoding.piece {
+            IntEncoding::Vu64 => self.read_vu64_u32().map(|v| PieceSize::<T>::new(v * 8)),
+            _ => self.read_u32_le().map(PieceSize::<T>::new),
+        }
     }
     #[inline]
     pub fn write_piece_size<T>(&mut self, piece_size: PieceSize<T>) -> Result<()> {
         let v: u32 = piece_size.into();
-        debug_assert!(v % 8 == 0);
-        self.write_vu64_u32(v / 8)
+        match self.encoding.piece {
+            IntEncoding::Vu64 => {
+                debug_assert!(v % 8 == 0);
+                self.write_vu64_u32(v / 8)
+            }
+            _ => self.write_u32_le(v),
+        }
+    }
+    #[inline]
+    fn read_len_u32(&mut self) -> Result<u32> {
+        match self.encoding.piece {
+            IntEncoding::Vu64 => self.read_vu64_u32(),
+            _ => self.read_u32_le(),
+        }
+    }
+    #[inline]
+    fn write_len_u32(&mut self, len: u32) -> Result<()> {
+        match self.encoding.piece {
+            IntEncoding::Vu64 => self.write_vu64_u32(len),
+            _ => self.write_u32_le(len),
+        }
     }
-    //
     #[inline]
     pub fn read_key_len(&mut self) -> Result<KeyLength> {
-        self.read_vu64_u32().map(KeyLength::new)
+        self.read_len_u32().map(KeyLength::new)
     }
     #[inline]
     pub fn write_key_len(&mut self, key_len: KeyLength) -> Result<()> {
-        self.write_vu64_u32(key_len.into())
+        self.write_len_u32(key_len.into())
     }
     #[inline]
     pub fn read_value_len(&mut self) -> Result<ValueLength> {
-        self.read_vu64_u32().map(ValueLength::new)
+        self.read_len_u32().map(ValueLength::new)
     }
     #[inline]
     pub fn write_value_len(&mut self, value_len: ValueLength) -> Result<()> {
-        self.write_vu64_u32(value_len.into())
+        self.write_len_u32(value_len.into())
     }
     //
-    #[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
-    #[inline]
-    pub fn read_node_offset(&mut self) -> Result<NodePieceOffset> {
-        self._read_vu64_u64().map(|a| NodePieceOffset::new(a * 8))
-    }
-    #[cfg(not(any(feature = "vf_node_u32", feature = "vf_node_u64")))]
-    #[inline]
-    pub fn write_node_offset(&mut self, node_offset: NodePieceOffset) -> Result<()> {
-        let v: u64 = node_offset.into();
-        debug_assert!(v % 8 == 0);
-        self._write_vu64_u64(v / 8)
-    }
     #[inline]
     pub fn read_node_size(&mut self) -> Result<NodePieceSize> {
-        self.read_vu64_u32().map(|v| NodePieceSize::new(v * 8))
+        match self.encoding.piece {
+            IntEncoding::Vu64 => self.read_vu64_u32().map(|v| NodePieceSize::new(v * 8)),
+            _ => self.read_u16_le().map(|n| NodePieceSize::new(n as u32)),
+        }
     }
     #[inline]
     pub fn write_node_size(&mut self, node_size: NodePieceSize) -> Result<()> {
-        debug_assert!(!node_size.is_zero());
         let v: u32 = node_size.into();
-        debug_assert!(v % 8 == 0);
-        self.write_vu64_u32(v / 8)
+        match self.encoding.piece {
+            IntEncoding::Vu64 => {
+                debug_assert!(!node_size.is_zero());
+                debug_assert!(v % 8 == 0);
+                self.write_vu64_u32(v / 8)
+            }
+            _ => {
+                debug_assert!(v <= u16::MAX as u32);
+                self.write_u16_le(v as u16)
+            }
+        }
+    }
+    /// the encoded length of the node size.
+    #[inline]
+    pub fn node_size_encoded_len(&self, node_size: u32) -> u32 {
+        match self.encoding.piece {
+            IntEncoding::Vu64 => {
+                let encoded_len = if node_size < 128 { 1 } else { 2 };
+                //let encoded_len = vu64::encoded_len(node_size as u64);
+                debug_assert!(encoded_len == vu64::encoded_len(node_size as u64));
+                encoded_len.into()
+            }
+            _ => 2,
+        }
     }
     #[inline]
     pub fn read_keys_count(&mut self) -> Result<KeysCount> {
-        self.read_vu64_u16().map(KeysCount::new)
+        match self.encoding.piece {
+            IntEncoding::Vu64 => self.read_vu64_u16().map(KeysCount::new),
+            _ => self.read_u16_le().map(KeysCount::new),
+        }
     }
     #[inline]
     pub fn write_keys_count(&mut self, keys_count: KeysCount) -> Result<()> {
-        self.write_vu64_u16(keys_count.into())
+        match self.encoding.piece {
+            IntEncoding::Vu64 => self.write_vu64_u16(keys_count.into()),
+            _ => self.write_u16_le(keys_count.into()),
+        }
+    }
+    //
+    #[inline]
+    pub fn seek_skip_length<T: PartialEq + Copy>(
+        &mut self,
+        length: Length<T>,
+    ) -> Result<Offset<T>> {
+        let val: u32 = length.into();
+        self.seek(SeekFrom::Current(val as i64))
+            .map(Offset::<T>::new)
     }
-}
-
-#[cfg(feature = "vf_vu64")]
-impl VarFile {
     #[inline]
     pub fn seek_skip_to_piece_key<T: Copy + PartialEq>(
         &mut self,
         offset: PieceOffset<T>,
     ) -> Result<PieceOffset<T>> {
-        self.seek_from_start(offset)?;
-        let byte_1st = self.buf_file.read_u8()?;
-        let piece_size_len = vu64::decoded_len(byte_1st);
-        if piece_size_len > 1 {
-            self.seek_skip_length(KeyLength::new((piece_size_len - 1).into()))?;
-        }
-        //
-        self.seek_position()
+        self.seek_skip_piece_size(offset)
     }
     #[inline]
     pub fn seek_skip_to_piece_value<T: Copy + PartialEq>(
         &mut self,
         offset: PieceOffset<T>,
     ) -> Result<PieceOffset<T>> {
-        self.seek_from_start(offset)?;
-        let byte_1st = self.buf_file.read_u8()?;
-        let piece_size_len = vu64::decoded_len(byte_1st);
-        if piece_size_len > 1 {
-            self.seek_skip_length(KeyLength::new((piece_size_len - 1).into()))?;
+        self.seek_skip_piece_size(offset)
+    }
+    #[inline]
+    fn seek_skip_piece_size<T: Copy + PartialEq>(
+        &mut self,
+        offset: PieceOffset<T>,
+    ) -> Result<PieceOffset<T>> {
+        match self.encoding.piece {
+            IntEncoding::Vu64 => {
+                self.seek_from_start(offset)?;
+                let byte_1st = self.read_u8()?;
+                let piece_size_len = vu64::decoded_len(byte_1st);
+                if piece_size_len > 1 {
+                    self.seek_skip_length(KeyLength::new((piece_size_len - 1).into()))?;
+                }
+                //
+                self.seek_position()
+            }
+            _ => {
+                self.seek_from_start(offset + PieceSize::<T>::new(4))?;
+                self.seek_position()
+            }
         }
-        //
-        self.seek_position()
     }
 }
 
-#[cfg(any(feature = "vf_u32u32", feature = "vf_u64u64"))]
+#[cfg(feature = "idx_straight")]
 impl VarFile {
     #[inline]
-    pub fn seek_skip_to_piece_key<T: Copy + PartialEq>(
+    pub fn write_piece_offset_u64_slice<T: Copy>(
         &mut self,
-        offset: PieceOffset<T>,
-    ) -> Result<PieceOffset<T>> {
-        /*
-        self.seek_from_start(offset)?;
-        self.seek_skip_length(KeyLength::new(4))?;
-        //
-        self.seek_position()
-        */
-        self.seek_from_start(offset + PieceSize::<T>::new(4))?;
-        self.seek_position()
+        piece_offset_slice: &[PieceOffset<T>],
+    ) -> Result<()> {
+        let u64_slice =
+            unsafe { std::mem::transmute::<&[PieceOffset<T>], &[u64]>(piece_offset_slice) };
+        self.write_u64_le_slice(u64_slice)
     }
     #[inline]
-    pub fn seek_skip_to_piece_value<T: Copy + PartialEq>(
+    pub fn _write_node_offset_u64_slice(
         &mut self,
-        offset: PieceOffset<T>,
-    ) -> Result<PieceOffset<T>> {
-        /*
-        self.seek_from_start(offset)?;
-        self.seek_skip_length(KeyLength::new(4))?;
-        //
-        self.seek_position()
-        */
-        self.seek_from_start(offset + PieceSize::<T>::new(4))?;
-        self.seek_position()
+        node_offset_slice: &[NodePieceOffset],
+    ) -> Result<()> {
+        let u64_slice =
+            unsafe { std::mem::transmute::<&[NodePieceOffset], &[u64]>(node_offset_slice) };
+        self.write_u64_le_slice(u64_slice)
+    }
+    #[inline]
+    pub fn write_piece_offset_and_node_offset_u64_slice<T: Copy>(
+        &mut self,
+        piece_offset_slice: &[PieceOffset<T>],
+        node_offset_slice: &[NodePieceOffset],
+    ) -> Result<()> {
+        let u64_slice1 =
+            unsafe { std::mem::transmute::<&[PieceOffset<T>], &[u64]>(piece_offset_slice) };
+        let u64_slice2 =
+            unsafe { std::mem::transmute::<&[NodePieceOffset], &[u64]>(node_offset_slice) };
+        self.write_u64_le_slice2(u64_slice1, u64_slice2)
     }
 }
 
@@ -890,24 +1031,24 @@ mod debug {
                 #[cfg(not(feature = "buf_stats"))]
                 {
                     #[cfg(not(feature = "buf_print_hits"))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 176);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 240);
                     #[cfg(feature = "buf_print_hits")]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 200);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 264);
                 }
                 #[cfg(feature = "buf_stats")]
-                assert_eq!(std::mem::size_of::<VarFile>(), 184);
+                assert_eq!(std::mem::size_of::<VarFile>(), 248);
             }
             #[cfg(feature = "buf_hash_turbo")]
             {
                 #[cfg(not(feature = "buf_stats"))]
                 {
                     #[cfg(not(feature = "buf_print_hits"))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 184);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 248);
                     #[cfg(feature = "buf_print_hits")]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 208);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 272);
                 }
                 #[cfg(feature = "buf_stats")]
-                assert_eq!(std::mem::size_of::<VarFile>(), 184);
+                assert_eq!(std::mem::size_of::<VarFile>(), 248);
             }
         }
         #[cfg(target_pointer_width = "32")]
@@ -919,38 +1060,38 @@ mod debug {
                     #[cfg(not(any(target_arch = "arm", target_arch = "mips")))]
                     {
                         #[cfg(not(feature = "buf_print_hits"))]
-                        assert_eq!(std::mem::size_of::<VarFile>(), 108);
+                        assert_eq!(std::mem::size_of::<VarFile>(), 136);
                         #[cfg(feature = "buf_print_hits")]
-                        assert_eq!(std::mem::size_of::<VarFile>(), 132);
+                        assert_eq!(std::mem::size_of::<VarFile>(), 160);
                     }
                     #[cfg(any(target_arch = "arm", target_arch = "mips"))]
                     {
                         #[cfg(not(feature = "buf_print_hits"))]
-                        assert_eq!(std::mem::size_of::<VarFile>(), 120);
+                        assert_eq!(std::mem::size_of::<VarFile>(), 148);
                         #[cfg(feature = "buf_print_hits")]
-                        assert_eq!(std::mem::size_of::<VarFile>(), 144);
+                        assert_eq!(std::mem::size_of::<VarFile>(), 172);
                     }
                 }
                 #[cfg(all(feature = "buf_stats", feature = "buf_lru"))]
                 {
                     #[cfg(not(any(target_arch = "arm", target_arch = "mips")))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 88);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 116);
                     #[cfg(any(target_arch = "arm", target_arch = "mips"))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 96);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 124);
                 }
                 #[cfg(all(feature = "buf_stats", not(feature = "buf_lru")))]
                 {
                     #[cfg(not(any(target_arch = "arm", target_arch = "mips")))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 116);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 144);
                     #[cfg(any(target_arch = "arm", target_arch = "mips"))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 128);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 156);
                 }
                 #[cfg(all(not(feature = "buf_stats"), feature = "buf_lru"))]
                 {
                     #[cfg(not(any(target_arch = "arm", target_arch = "mips")))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 80);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 108);
                     #[cfg(any(target_arch = "arm", target_arch = "mips"))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 88);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 116);
                 }
             }
             #[cfg(feature = "buf_hash_turbo")]
@@ -960,38 +1101,38 @@ mod debug {
                     #[cfg(not(any(target_arch = "arm", target_arch = "mips")))]
                     {
                         #[cfg(not(feature = "buf_print_hits"))]
-                        assert_eq!(std::mem::size_of::<VarFile>(), 112);
+                        assert_eq!(std::mem::size_of::<VarFile>(), 140);
                         #[cfg(feature = "buf_print_hits")]
-                        assert_eq!(std::mem::size_of::<VarFile>(), 164);
+                        assert_eq!(std::mem::size_of::<VarFile>(), 192);
                     }
                     #[cfg(any(target_arch = "arm", target_arch = "mips"))]
                     {
                         #[cfg(not(feature = "buf_print_hits"))]
-                        assert_eq!(std::mem::size_of::<VarFile>(), 120);
+                        assert_eq!(std::mem::size_of::<VarFile>(), 148);
                         #[cfg(feature = "buf_print_hits")]
-                        assert_eq!(std::mem::size_of::<VarFile>(), 176);
+                        assert_eq!(std::mem::size_of::<VarFile>(), 204);
                     }
                 }
                 #[cfg(all(feature = "buf_stats", feature = "buf_lru"))]
                 {
                     #[cfg(not(any(target_arch = "arm", target_arch = "mips")))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 88);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 116);
                     #[cfg(any(target_arch = "arm", target_arch = "mips"))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 96);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 124);
                 }
                 #[cfg(all(feature = "buf_stats", not(feature = "buf_lru")))]
                 {
                     #[cfg(not(any(target_arch = "arm", target_arch = "mips")))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 116);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 144);
                     #[cfg(any(target_arch = "arm", target_arch = "mips"))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 128);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 156);
                 }
                 #[cfg(all(not(feature = "buf_stats"), feature = "buf_lru"))]
                 {
                     #[cfg(not(any(target_arch = "arm", target_arch = "mips")))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 80);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 108);
                     #[cfg(any(target_arch = "arm", target_arch = "mips"))]
-                    assert_eq!(std::mem::size_of::<VarFile>(), 88);
+                    assert_eq!(std::mem::size_of::<VarFile>(), 116);
                 }
             }
         }
@@ -0,0 +1,449 @@
+use super::super::cell::{Rc, RefCell};
+use super::crc::crc32c;
+use rabuf::BufFile;
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const WAL_FILE_NAME: &str = "siamesedb.wal";
+const WAL_HEADER_SIGNATURE: [u8; 8] = *b"siamwal\0";
+const WAL_COMMIT_SIGNATURE: [u8; 8] = *b"walcmt\0\0";
+
+/// the lock of the write-ahead log of a database. all db maps share the log,
+/// so the writing, the applying and the removing of it are serialized by this.
+/// it is taken before the lock of the db map.
+pub type WalLock = Rc<RefCell<()>>;
+
+/// the size of the pending page.
+const PAGE_SIZE: u64 = 512;
+
+/// the max bytes of the pending pages of a file. the pending pages are kept
+/// in memory, and the write-ahead log is read into memory on the recovery,
+/// so the writing of a batch is limited to this per file.
+const PENDING_BYTES_MAX: u64 = 256 * 1024 * 1024;
+
+/// the pending pages of a file while a write batch is in progress.
+///
+/// the writing is kept in memory without touching the file,
+/// and the reading sees the pending pages over the file.
+#[derive(Debug, Default)]
+pub struct PendingPages {
+    /// the current position
+    pos: u64,
+    /// the file length
+    end: u64,
+    /// the file length that the bytes of the file are valid under it
+    base_end: u64,
+    /// the page offset and the page bytes
+    pages: BTreeMap<u64, Vec<u8>>,
+}
+
+impl PendingPages {
+    pub fn new(pos: u64, end: u64) -> Self {
+        Self {
+            pos,
+            end,
+            base_end: end,
+            pages: BTreeMap::new(),
+        }
+    }
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty() && self.base_end == self.end
+    }
+    pub fn seek(&mut self, pos: SeekFrom) -> u64 {
+        let new_pos = match pos {
+            SeekFrom::Start(x) => x,
+            SeekFrom::End(x) => (self.end as i64 + x) as u64,
+            SeekFrom::Current(x) => (self.pos as i64 + x) as u64,
+        };
+        if new_pos > self.end {
+            // makes a sparse file.
+            self.end = new_pos;
+        }
+        self.pos = new_pos;
+        new_pos
+    }
+    pub fn set_len(&mut self, size: u64) {
+        if size < self.base_end {
+            self.base_end = size;
+        }
+        let page_offset = size - size % PAGE_SIZE;
+        let _ = self.pages.split_off(&(page_offset + PAGE_SIZE));
+        if let Some(page) = self.pages.get_mut(&page_offset) {
+            for b in page.iter_mut().skip((size - page_offset) as usize) {
+                *b = 0;
+            }
+        }
+        self.end = size;
+        if self.pos > self.end {
+            self.pos = self.end;
+        }
+    }
+    pub fn read(&mut self, file: &mut BufFile, buf: &mut [u8]) -> Result<usize> {
+        let mut done = 0;
+        while done < buf.len() {
+            let page_offset = self.pos - self.pos % PAGE_SIZE;
+            let st = (self.pos - page_offset) as usize;
+            let len = (PAGE_SIZE as usize - st).min(buf.len() - done);
+            let dst = &mut buf[done..(done + len)];
+            match self.pages.get(&page_offset) {
+                Some(page) => dst.copy_from_slice(&page[st..(st + len)]),
+                None => self.read_base(file, self.pos, dst)?,
+            }
+            self.pos += len as u64;
+            done += len;
+        }
+        Ok(done)
+    }
+    pub fn write(&mut self, file: &mut BufFile, buf: &[u8]) -> Result<usize> {
+        let mut done = 0;
+        while done < buf.len() {
+            let page_offset = self.pos - self.pos % PAGE_SIZE;
+            let st = (self.pos - page_offset) as usize;
+            let len = (PAGE_SIZE as usize - st).min(buf.len() - done);
+            if !self.pages.contains_key(&page_offset) {
+                if (self.pages.len() as u64 + 1) * PAGE_SIZE > PENDING_BYTES_MAX {
+                    return Err(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "the batch is too large: over {} bytes of a file",
+                            PENDING_BYTES_MAX
+                        ),
+                    ));
+                }
+                let mut page = vec![0u8; PAGE_SIZE as usize];
+                self.read_base(file, page_offset, &mut page)?;
+                let _ = self.pages.insert(page_offset, page);
+            }
+            let page = self.pages.get_mut(&page_offset).unwrap();
+            page[st..(st + len)].copy_from_slice(&buf[done..(done + len)]);
+            self.pos += len as u64;
+            done += len;
+        }
+        if self.end < self.pos {
+            self.end = self.pos;
+        }
+        Ok(done)
+    }
+    /// reads the bytes of the file under the base end, the rest is zero.
+    fn read_base(&self, file: &mut BufFile, offset: u64, buf: &mut [u8]) -> Result<()> {
+        let len = if offset < self.base_end {
+            ((self.base_end - offset) as usize).min(buf.len())
+        } else {
+            0
+        };
+        if len > 0 {
+            let _ = file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(&mut buf[..len])?;
+        }
+        for b in buf[len..].iter_mut() {
+            *b = 0;
+        }
+        Ok(())
+    }
+    /// writes the pending pages to the file.
+    pub fn apply_to<F: Write + Seek + SetLen>(&self, file: &mut F, file_end: u64) -> Result<()> {
+        if self.base_end < file_end {
+            file.set_len(self.base_end)?;
+        }
+        for (&offset, page) in self.pages.iter() {
+            if offset >= self.end {
+                break;
+            }
+            let len = ((self.end - offset) as usize).min(page.len());
+            let _ = file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&page[..len])?;
+        }
+        file.set_len(self.end)
+    }
+}
+
+/// truncates or extends the file.
+pub trait SetLen {
+    fn set_len(&mut self, size: u64) -> Result<()>;
+}
+
+impl SetLen for BufFile {
+    #[inline]
+    fn set_len(&mut self, size: u64) -> Result<()> {
+        rabuf::FileSetLen::set_len(self, size)
+    }
+}
+
+impl SetLen for File {
+    #[inline]
+    fn set_len(&mut self, size: u64) -> Result<()> {
+        File::set_len(self, size)
+    }
+}
+
+/// the pending pages of a file in the write-ahead log.
+#[derive(Debug)]
+pub struct WalRecord {
+    /// the file name in the database directory
+    pub file_name: String,
+    pub pages: PendingPages,
+}
+
+impl WalRecord {
+    pub fn new(file_name: String, pages: PendingPages) -> Self {
+        Self { file_name, pages }
+    }
+}
+
+fn wal_file_path(path: &Path) -> PathBuf {
+    let mut pb = path.to_path_buf();
+    pb.push(WAL_FILE_NAME);
+    pb
+}
+
+/// writes the records to the write-ahead log, and syncs it.
+/// the records are committed when this returns `Ok`.
+pub fn write_wal_file(path: &Path, records: &[WalRecord]) -> Result<()> {
+    // the committed log that is not applied yet must be replayed first.
+    if read_wal_file(path)?.is_some() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "the write-ahead log is not applied yet, reopen the database: {}",
+                path.display()
+            ),
+        ));
+    }
+    let mut buf: Vec<u8> = Vec::new();
+    buf.extend_from_slice(&WAL_HEADER_SIGNATURE);
+    buf.extend_from_slice(&(records.len() as u32).to_le_bytes());
+    for rec in records {
+        let pages = &rec.pages;
+        let name = rec.file_name.as_bytes();
+        buf.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        buf.extend_from_slice(name);
+        buf.extend_from_slice(&pages.base_end.to_le_bytes());
+        buf.extend_from_slice(&pages.end.to_le_bytes());
+        let it = pages.pages.iter().take_while(|(&off, _)| off < pages.end);
+        buf.extend_from_slice(&(it.clone().count() as u64).to_le_bytes());
+        for (&offset, page) in it {
+            let len = ((pages.end - offset) as usize).min(page.len());
+            buf.extend_from_slice(&offset.to_le_bytes());
+            buf.extend_from_slice(&(len as u32).to_le_bytes());
+            buf.extend_from_slice(&page[..len]);
+        }
+    }
+    let sum = crc32c(&buf);
+    buf.extend_from_slice(&sum.to_le_bytes());
+    buf.extend_from_slice(&WAL_COMMIT_SIGNATURE);
+    //
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(wal_file_path(path))?;
+    file.write_all(&buf)?;
+    file.sync_all()?;
+    sync_dir(path)
+}
+
+/// removes the write-ahead log.
+pub fn remove_wal_file(path: &Path) -> Result<()> {
+    match std::fs::remove_file(wal_file_path(path)) {
+        Ok(()) => sync_dir(path),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// copies the write-ahead log into the backup directory, if it is found.
+/// the log that is not committed yet is discarded on opening the backup.
+pub fn copy_wal_file(path: &Path, dst: &Path) -> Result<u64> {
+    let dst_path = wal_file_path(dst);
+    match std::fs::copy(wal_file_path(path), &dst_path) {
+        Ok(n) => {
+            File::open(&dst_path)?.sync_all()?;
+            Ok(n)
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(0),
+        Err(err) => Err(err),
+    }
+}
+
+/// reads the committed records in the write-ahead log.
+/// if it is not found or is not committed, returns `None`.
+pub fn read_wal_file(path: &Path) -> Result<Option<Vec<WalRecord>>> {
+    let mut buf = Vec::new();
+    match File::open(wal_file_path(path)) {
+        Ok(mut file) => {
+            let _ = file.read_to_end(&mut buf)?;
+        }
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    }
+    Ok(parse_wal(&buf))
+}
+
+/// replays the committed write-ahead log to the files, and removes it.
+/// the uncommitted log is discarded, because the files are not touched yet.
+pub fn recover(path: &Path) -> Result<()> {
+    if let Some(records) = read_wal_file(path)? {
+        for rec in records {
+            let mut pb = path.to_path_buf();
+            pb.push(&rec.file_name);
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&pb)?;
+            let file_end = file.metadata()?.len();
+            rec.pages.apply_to(&mut file, file_end)?;
+            file.sync_all()?;
+        }
+    }
+    remove_wal_file(path)
+}
+
+/// checks that the write-ahead log does not need to be replayed.
+pub fn check_no_committed_wal(path: &Path) -> Result<()> {
+    if read_wal_file(path)?.is_some() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "the write-ahead log needs to be replayed, open it as writable: {}",
+                path.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+fn parse_wal(buf: &[u8]) -> Option<Vec<WalRecord>> {
+    let body_len = buf.len().checked_sub(12)?;
+    if buf[body_len + 4..] != WAL_COMMIT_SIGNATURE[..] {
+        return None;
+    }
+    let sum = u32::from_le_bytes(buf[body_len..body_len + 4].try_into().ok()?);
+    let buf = &buf[..body_len];
+    if sum != crc32c(buf) || buf.get(..8)? != &WAL_HEADER_SIGNATURE[..] {
+        return None;
+    }
+    let mut rd = WalReader { buf, pos: 8 };
+    let count = u32::from_le_bytes(rd.bytes(4)?.try_into().ok()?);
+    let mut records = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let name_len = u16::from_le_bytes(rd.bytes(2)?.try_into().ok()?);
+        let file_name = String::from_utf8(rd.bytes(name_len as usize)?.to_vec()).ok()?;
+        let base_end = rd.u64()?;
+        let end = rd.u64()?;
+        let page_count = rd.u64()?;
+        let mut pages = PendingPages::new(0, end);
+        pages.base_end = base_end;
+        for _ in 0..page_count {
+            let offset = rd.u64()?;
+            let len = u32::from_le_bytes(rd.bytes(4)?.try_into().ok()?);
+            let _ = pages.pages.insert(offset, rd.bytes(len as usize)?.to_vec());
+        }
+        records.push(WalRecord::new(file_name, pages));
+    }
+    Some(records)
+}
+
+struct WalReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> WalReader<'a> {
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let r = self.buf.get(self.pos..(self.pos + len))?;
+        self.pos += len;
+        Some(r)
+    }
+    fn u64(&mut self) -> Option<u64> {
+        self.bytes(8)?.try_into().ok().map(u64::from_le_bytes)
+    }
+}
+
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<()> {
+    File::open(path)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+//--
+#[cfg(test)]
+mod debug {
+    use super::super::super::super::{open_file, DbXxx, DbXxxBase};
+    use super::{wal_file_path, write_wal_file};
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 300;
+    //
+    #[test]
+    fn test_recover_committed() {
+        let db_name = "target/tmp/test_wal-r.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let path = Path::new(db_name);
+        {
+            let db = open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            db_map.put_string("key0", "value0").unwrap();
+            db_map.sync_all().unwrap();
+            // the crash after the log is committed, before it is applied
+            db_map.lock().begin_batch().unwrap();
+            for i in 1..LOOP_MAX {
+                db_map
+                    .put_string(&format!("key{}", i), &format!("value{}", i))
+                    .unwrap();
+            }
+            let _ = db_map.delete("key0").unwrap();
+            let records = db_map.take_batch_records().unwrap();
+            write_wal_file(path, &records).unwrap();
+            db_map.lock().rollback_batch().unwrap();
+            assert_eq!(db_map.get_string("key1").unwrap(), None);
+        }
+        assert!(wal_file_path(path).exists());
+        // the log is replayed on the open
+        let db = open_file(db_name).unwrap();
+        assert!(!wal_file_path(path).exists());
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        assert_eq!(db_map.get_string("key0").unwrap(), None);
+        for i in 1..LOOP_MAX {
+            assert_eq!(
+                db_map.get_string(&format!("key{}", i)).unwrap(),
+                Some(format!("value{}", i))
+            );
+        }
+        assert!(db.verify().unwrap().is_ok());
+    }
+    #[test]
+    fn test_broken_checksum() {
+        let db_name = "target/tmp/test_wal-c.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let path = Path::new(db_name);
+        {
+            let db = open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            db_map.put_string(&1, "value1").unwrap();
+            db_map.sync_all().unwrap();
+            db_map.lock().begin_batch().unwrap();
+            db_map.put_string(&2, "value2").unwrap();
+            let records = db_map.take_batch_records().unwrap();
+            write_wal_file(path, &records).unwrap();
+            db_map.lock().rollback_batch().unwrap();
+        }
+        // the log that is broken is not replayed
+        let mut buf = std::fs::read(wal_file_path(path)).unwrap();
+        buf[20] ^= 0xFF;
+        std::fs::write(wal_file_path(path), &buf).unwrap();
+        let db = open_file(db_name).unwrap();
+        assert!(!wal_file_path(path).exists());
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+        assert_eq!(db_map.get_string(&2).unwrap(), None);
+    }
+}
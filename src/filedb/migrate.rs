@@ -0,0 +1,208 @@
+use super::super::{DbMapKeyType, DbXxxBase, DbXxxObjectSafe, Result};
+use super::{DbMapKeyKind, FileDb, FileDbMap, FileDbParams, MigrateStats};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+/// migrates the database of the path into the format of the params.
+///
+/// every db map is streamed through the fallible iterator into a fresh database
+/// beside the path, and the record counts are checked. then the fresh database
+/// is swapped with the old one under the lock of the old one, and the old one
+/// is removed.
+pub(crate) fn migrate(
+    path: &Path,
+    src_params: FileDbParams,
+    dst_params: FileDbParams,
+) -> Result<MigrateStats> {
+    let path = path.components().as_path();
+    let tmp_path = sibling_path(path, ".migrate");
+    let old_path = sibling_path(path, ".migrate-old");
+    // the swap of the interrupted migration is finished by the opening.
+    let src_db = FileDb::open(path)?;
+    if old_path.exists() {
+        return Err(Error::new(
+            ErrorKind::AlreadyExists,
+            format!(
+                "the old database of the interrupted migration exists: {}",
+                old_path.display()
+            ),
+        )
+        .into());
+    }
+    // the fresh database of the interrupted migration is removed.
+    if tmp_path.exists() {
+        std::fs::remove_dir_all(&tmp_path)?;
+    }
+    let r = copy_db(&src_db, &tmp_path, src_params, dst_params);
+    let stats = match r {
+        Ok(stats) => stats,
+        Err(err) => {
+            let _ = std::fs::remove_dir_all(&tmp_path);
+            return Err(err);
+        }
+    };
+    swap_db(src_db, path, &tmp_path, &old_path)?;
+    Ok(stats)
+}
+
+/// swaps the fresh database of the tmp path with the old one of the path.
+/// the old one is kept locked until the swap is done, so the other process
+/// can not open the path between the renames.
+fn swap_db(src_db: FileDb, path: &Path, tmp_path: &Path, old_path: &Path) -> Result<()> {
+    if exchange_dir(tmp_path, path)? {
+        // the old database is at the tmp path.
+        drop(src_db);
+        std::fs::remove_dir_all(tmp_path)?;
+        return Ok(());
+    }
+    // the locked directory can not be renamed on some platforms.
+    let src_db = if cfg!(unix) { Some(src_db) } else { None };
+    std::fs::rename(path, old_path)?;
+    if let Err(err) = std::fs::rename(tmp_path, path) {
+        std::fs::rename(old_path, path)?;
+        return Err(err.into());
+    }
+    drop(src_db);
+    std::fs::remove_dir_all(old_path)?;
+    Ok(())
+}
+
+/// exchanges the directories atomically, and returns false if it is not supported.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn exchange_dir(path1: &Path, path2: &Path) -> std::io::Result<bool> {
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_int, c_uint};
+    use std::os::unix::ffi::OsStrExt;
+    //
+    const AT_FDCWD: c_int = -100;
+    const RENAME_EXCHANGE: c_uint = 2;
+    const EINVAL: i32 = 22;
+    const ENOSYS: i32 = 38;
+    const EOPNOTSUPP: i32 = 95;
+    extern "C" {
+        fn renameat2(
+            olddirfd: c_int,
+            oldpath: *const c_char,
+            newdirfd: c_int,
+            newpath: *const c_char,
+            flags: c_uint,
+        ) -> c_int;
+    }
+    //
+    let path1 = CString::new(path1.as_os_str().as_bytes())?;
+    let path2 = CString::new(path2.as_os_str().as_bytes())?;
+    let r = unsafe {
+        renameat2(
+            AT_FDCWD,
+            path1.as_ptr(),
+            AT_FDCWD,
+            path2.as_ptr(),
+            RENAME_EXCHANGE,
+        )
+    };
+    if r == 0 {
+        return Ok(true);
+    }
+    let err = Error::last_os_error();
+    match err.raw_os_error() {
+        Some(EINVAL) | Some(ENOSYS) | Some(EOPNOTSUPP) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+fn exchange_dir(_path1: &Path, _path2: &Path) -> std::io::Result<bool> {
+    Ok(false)
+}
+
+/// finishes the swap of the migration that is interrupted between the renames.
+/// the path is missing and the old database is beside it. if the fresh database
+/// is beside it too, it is complete, because it is synced before the swap.
+/// if the migration is still running, the old database is locked.
+pub(crate) fn recover(path: &Path) -> Result<()> {
+    let path = path.components().as_path();
+    let old_path = sibling_path(path, ".migrate-old");
+    if path.exists() || !old_path.is_dir() {
+        return Ok(());
+    }
+    let old_db = FileDb::open(&old_path)?;
+    let tmp_path = sibling_path(path, ".migrate");
+    if !tmp_path.is_dir() {
+        std::fs::rename(&old_path, path)?;
+        return Ok(());
+    }
+    std::fs::rename(&tmp_path, path)?;
+    drop(old_db);
+    std::fs::remove_dir_all(&old_path)?;
+    Ok(())
+}
+
+fn copy_db(
+    src_db: &FileDb,
+    tmp_path: &Path,
+    src_params: FileDbParams,
+    dst_params: FileDbParams,
+) -> Result<MigrateStats> {
+    let dst_db = FileDb::open(tmp_path)?;
+    let mut stats = MigrateStats::default();
+    for (name, kind) in src_db.list_maps()? {
+        let count = match kind {
+            DbMapKeyKind::DbString => copy_map(
+                &src_db.db_map_string_with_params(&name, src_params.clone())?,
+                &mut dst_db.db_map_string_with_params(&name, dst_params.clone())?,
+                &name,
+            )?,
+            DbMapKeyKind::DbInt => copy_map(
+                &src_db.db_map_int_with_params(&name, src_params.clone())?,
+                &mut dst_db.db_map_int_with_params(&name, dst_params.clone())?,
+                &name,
+            )?,
+            DbMapKeyKind::DbBytes => copy_map(
+                &src_db.db_map_bytes_with_params(&name, src_params.clone())?,
+                &mut dst_db.db_map_bytes_with_params(&name, dst_params.clone())?,
+                &name,
+            )?,
+        };
+        stats.map_count += 1;
+        stats.record_count += count;
+    }
+    dst_db.sync_all()?;
+    Ok(stats)
+}
+
+/// copies the records of the db map, and returns the count of them.
+fn copy_map<KT: DbMapKeyType>(
+    src: &FileDbMap<KT>,
+    dst: &mut FileDbMap<KT>,
+    name: &str,
+) -> Result<u64> {
+    let mut count = 0;
+    for r in src.try_iter() {
+        let (key, value) = r?;
+        dst.put_kt(&key, &value)?;
+        count += 1;
+    }
+    dst.sync_all()?;
+    let mut dst_count = 0;
+    for r in dst.try_iter() {
+        let _ = r?;
+        dst_count += 1;
+    }
+    if dst_count != count {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "the record count does not match: {}: {} != {}",
+                name, dst_count, count
+            ),
+        )
+        .into());
+    }
+    Ok(count)
+}
+
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.as_os_str().to_os_string();
+    s.push(suffix);
+    PathBuf::from(s)
+}
@@ -1,17 +1,28 @@
-use std::cell::RefCell;
-use std::io::Result;
+use super::{DbMapKeyType, Error, Result};
 use std::path::{Path, PathBuf};
-use std::rc::Rc;
 
+mod batch;
+mod cell;
+mod codec;
 mod dbmap;
+mod export;
 mod inner;
+mod migrate;
+mod tx;
 
+pub use batch::WriteBatch;
+use cell::{Rc, RefCell};
+#[cfg(feature = "lz4")]
+pub use codec::Lz4Codec;
+pub use codec::{EncryptionKey, ValueCodec};
 pub use dbmap::{
     DbBytes, DbInt, DbString, FileDbMap, FileDbMapDbBytes, FileDbMapDbInt, FileDbMapDbString,
 };
-pub use inner::dbxxx::{DbXxxIntoIter, DbXxxIter, DbXxxIterMut, FileDbXxxInner};
+pub use export::{BytesEncoding, ExportFormat};
+pub use inner::dbxxx::{DbXxxIntoIter, DbXxxIter, DbXxxIterMut, DbXxxTryIter, FileDbXxxInner};
 use inner::semtype::*;
 use inner::FileDbInner;
+pub use tx::FileDbTransaction;
 
 /// Parameters of buffer.
 #[derive(Debug, Clone)]
@@ -24,6 +35,31 @@ pub enum FileBufSizeParam {
     Auto,
 }
 
+/// Encoding of the integers in the files of the db map.
+///
+/// it is written in the file headers, and the files are read with it,
+/// whatever the features of the build are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum IntEncoding {
+    /// `u32` offsets and lengths. the files are limited to 4 GiB.
+    U32U32,
+    /// `u64` offsets and `u32` lengths.
+    U64U64,
+    /// the variable length integers of `vu64`. the files are small.
+    Vu64,
+}
+
+/// Lock mode of the database directory.
+///
+/// This is an advisory lock between processes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileDbLockMode {
+    /// Exclusive lock for a writer.
+    Exclusive,
+    /// Shared lock for readers. the database is opened as read only.
+    Shared,
+}
+
 /// Parameters of filedb.
 ///
 /// chunk_size is MUST power of 2.
@@ -37,6 +73,37 @@ pub struct FileDbParams {
     pub idx_buf_size: FileBufSizeParam,
     /// buffer size of htx file buffer. Default is full buffer size.
     pub htx_buf_size: FileBufSizeParam,
+    /// initial slots of the htx hash table. Default is 4096.
+    /// the hash table is doubled automatically as the db map grows.
+    pub htx_init_size: u64,
+    /// use the htx hash table for the key lookup. Default is true.
+    /// if it is false, the htx file is not created, and the existing one is removed
+    /// on the writable opening. the key is found in the index tree only.
+    /// it works only with the feature `htx`.
+    pub use_htx: bool,
+    /// write the CRC-32C checksum at the end of the key, value and node pieces,
+    /// and verify it on reading the value and the node. Default is false.
+    /// it is applied on creating the db map, the existing one keeps its format.
+    pub checksum: bool,
+    /// the max slots of the node of the index tree. Default is 0, that is
+    /// the default of the build, 64 with the default features.
+    /// the wide node is for a large db map, the small node is for a small db map.
+    /// it is 0 or an even number in 4..=512, and it is applied on creating the db map.
+    pub node_slots: u16,
+    /// the codec of the values, such as `Lz4Codec` with the feature `lz4`.
+    /// Default is none. the values that are written before it is set stay raw,
+    /// and the db map can not be opened with the other codec after it.
+    pub value_codec: Option<std::sync::Arc<dyn ValueCodec>>,
+    /// the key of the encryption of the values. Default is none.
+    /// it is applied on creating the db map, and the encrypted db map can not be
+    /// opened without the same key. the keys of the db map are not encrypted,
+    /// because of the order of the index tree.
+    /// it works only with the feature `encryption`.
+    pub encryption_key: Option<EncryptionKey>,
+    /// the encoding of the integers in the files. Default is none, that is
+    /// the default of the build, `U64U64` with the default features.
+    /// it is applied on creating the files, the existing ones keep their encoding.
+    pub int_encoding: Option<IntEncoding>,
 }
 
 impl std::default::Default for FileDbParams {
@@ -46,8 +113,47 @@ impl std::default::Default for FileDbParams {
             key_buf_size: FileBufSizeParam::PerMille(1000),
             idx_buf_size: FileBufSizeParam::PerMille(1000),
             htx_buf_size: FileBufSizeParam::PerMille(1000),
+            htx_init_size: 4 * 1024,
+            use_htx: true,
+            checksum: false,
+            node_slots: 0,
+            value_codec: None,
+            encryption_key: None,
+            int_encoding: None,
+        }
+    }
+}
+
+/// Key type of the db map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DbMapKeyKind {
+    /// the key is `DbString`, the db map is `FileDbMapDbString`.
+    DbString,
+    /// the key is `DbInt`, the db map is `FileDbMapDbInt`.
+    DbInt,
+    /// the key is `DbBytes`, the db map is `FileDbMapDbBytes`.
+    DbBytes,
+}
+
+impl DbMapKeyKind {
+    /// signature in header of database file.
+    pub fn signature(&self) -> [u8; 8] {
+        match self {
+            DbMapKeyKind::DbString => DbString::signature(),
+            DbMapKeyKind::DbInt => DbInt::signature(),
+            DbMapKeyKind::DbBytes => DbBytes::signature(),
         }
     }
+    /// get the key kind from the signature in header of database file.
+    pub fn from_signature(sig: &[u8; 8]) -> Option<Self> {
+        [
+            DbMapKeyKind::DbString,
+            DbMapKeyKind::DbInt,
+            DbMapKeyKind::DbBytes,
+        ]
+        .into_iter()
+        .find(|kind| kind.signature() == *sig)
+    }
 }
 
 /// Checks the file db map for debug.
@@ -174,6 +280,174 @@ impl std::fmt::Display for KeysCountStats {
     }
 }
 
+/// the result of the compaction of a db map.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactStats {
+    /// count of the free pieces and the free nodes before the compaction
+    pub free_count: u64,
+    /// bytes reclaimed from the key file
+    pub key_bytes: u64,
+    /// bytes reclaimed from the value file
+    pub value_bytes: u64,
+    /// bytes reclaimed from the index file
+    pub node_bytes: u64,
+}
+
+impl CompactStats {
+    /// total bytes reclaimed
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.key_bytes + self.value_bytes + self.node_bytes
+    }
+}
+
+impl std::fmt::Display for CompactStats {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_fmt(format_args!(
+            "free: {}, key: {}, value: {}, node: {}",
+            self.free_count, self.key_bytes, self.value_bytes, self.node_bytes
+        ))
+    }
+}
+
+/// the kind of the problem that is found by the verification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VerifyProblemKind {
+    /// the db map can not be opened, or the data can not be read.
+    Unreadable,
+    /// the index tree is not balanced, or the keys are not in order.
+    BrokenTree,
+    /// the node referenced by the other node is not found.
+    MissingNode,
+    /// the key piece referenced by a node is not found.
+    MissingKeyPiece,
+    /// the value offset of a key piece does not point at a value piece.
+    BadValueOffset,
+    /// the free piece list has a cycle.
+    FreeListCycle,
+    /// the free piece is not on the piece boundary, or is of the other size.
+    BadFreePiece,
+    /// the free piece is still used.
+    LiveFreePiece,
+    /// the htx entry does not point at a key of the db map.
+    BadHtxEntry,
+    /// the last writing was not synced, the db map is recovered on the opening.
+    NotSynced,
+}
+
+/// the problem that is found by the verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyProblem {
+    /// the name of the db map.
+    pub map: String,
+    /// the path of the file.
+    pub file: PathBuf,
+    /// the offset in the file.
+    pub offset: u64,
+    /// the kind of the problem.
+    pub kind: VerifyProblemKind,
+    /// the detail of the problem.
+    pub message: String,
+}
+
+impl std::fmt::Display for VerifyProblem {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_fmt(format_args!(
+            "{}: {}: offset {}: {:?}: {}",
+            self.map,
+            self.file.display(),
+            self.offset,
+            self.kind,
+            self.message
+        ))
+    }
+}
+
+/// the report of `FileDb::verify()`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// the names of the verified db maps.
+    pub maps: Vec<String>,
+    /// the problems that are found.
+    pub problems: Vec<VerifyProblem>,
+}
+
+impl VerifyReport {
+    /// no problem is found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl std::fmt::Display for VerifyReport {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_fmt(format_args!(
+            "maps: {}, problems: {}",
+            self.maps.len(),
+            self.problems.len()
+        ))?;
+        for problem in self.problems.iter() {
+            formatter.write_fmt(format_args!("\n{}", problem))?;
+        }
+        Ok(())
+    }
+}
+
+/// the result of the repair of a db map.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RepairStats {
+    /// count of the keys that are recovered
+    pub key_count: u64,
+    /// count of the key pieces that are not recovered, the value piece is not
+    /// found or the key is duplicated
+    pub lost_count: u64,
+}
+
+impl std::fmt::Display for RepairStats {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_fmt(format_args!(
+            "key: {}, lost: {}",
+            self.key_count, self.lost_count
+        ))
+    }
+}
+
+/// the result of the migration of a database.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MigrateStats {
+    /// count of the db maps that are migrated
+    pub map_count: u64,
+    /// count of the records that are migrated
+    pub record_count: u64,
+}
+
+impl std::fmt::Display for MigrateStats {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_fmt(format_args!(
+            "map: {}, record: {}",
+            self.map_count, self.record_count
+        ))
+    }
+}
+
+/// the result of the backup of a database.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BackupStats {
+    /// count of the db maps that are copied
+    pub map_count: u64,
+    /// bytes of the files that are copied
+    pub byte_count: u64,
+}
+
+impl std::fmt::Display for BackupStats {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_fmt(format_args!(
+            "map: {}, bytes: {}",
+            self.map_count, self.byte_count
+        ))
+    }
+}
+
 /// key or value length statistics.
 #[derive(Debug, Default)]
 pub struct LengthStats<T: Default>(Vec<(Length<T>, u64)>);
@@ -213,12 +487,36 @@ impl<T: Default + Copy> std::fmt::Display for LengthStats<T> {
 }
 
 /// File Database.
+///
+/// With the feature `sync`, this is `Send` and `Sync`, and the clones can be
+/// used from several threads. The lookups of the db maps share the read lock,
+/// and the creating, the dropping and the renaming take the write lock.
 #[derive(Debug, Clone)]
 pub struct FileDb(Rc<RefCell<FileDbInner>>);
 
 impl FileDb {
+    /// open the database with the exclusive lock.
+    /// if the database is already locked, the error kind is `WouldBlock`.
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        Ok(Self(Rc::new(RefCell::new(FileDbInner::open(path)?))))
+        Self::open_with_lock_mode(path, FileDbLockMode::Exclusive)
+    }
+    /// open the database with the lock mode.
+    /// if the database is already locked, the error kind is `WouldBlock`.
+    /// with `FileDbLockMode::Shared`, it is same as `open_read_only()`.
+    pub fn open_with_lock_mode<P: AsRef<Path>>(path: P, mode: FileDbLockMode) -> Result<Self> {
+        Ok(Self(Rc::new(RefCell::new(FileDbInner::open(path, mode)?))))
+    }
+    /// open the existing database as read only, with the shared lock.
+    /// if the database or the db map files are not found, the error kind is `NotFound`.
+    /// the writing to the db map returns the error of `PermissionDenied`.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Ok(Self(Rc::new(RefCell::new(FileDbInner::open_read_only(
+            path,
+        )?))))
+    }
+    #[inline]
+    pub fn is_read_only(&self) -> bool {
+        RefCell::borrow(&self.0).is_read_only()
     }
     pub fn db_map_string(&self, name: &str) -> Result<FileDbMapDbString> {
         self.db_map_string_with_params(name, FileDbParams::default())
@@ -231,7 +529,13 @@ impl FileDb {
         if let Some(m) = RefCell::borrow(&self.0).db_map_string(name) {
             return Ok(m);
         }
-        RefCell::borrow_mut(&self.0).create_db_map(name, params)?;
+        {
+            // the other thread may create it in the meantime
+            let mut locked = RefCell::borrow_mut(&self.0);
+            if locked.db_map_string(name).is_none() {
+                locked.create_db_map(name, params)?;
+            }
+        }
         match RefCell::borrow(&self.0).db_map_string(name) {
             Some(m) => Ok(m),
             None => panic!("Cannot create db_maps: {}", name),
@@ -248,7 +552,13 @@ impl FileDb {
         if let Some(m) = RefCell::borrow(&self.0).db_map_bytes(name) {
             return Ok(m);
         }
-        RefCell::borrow_mut(&self.0).create_db_map_bytes(name, params)?;
+        {
+            // the other thread may create it in the meantime
+            let mut locked = RefCell::borrow_mut(&self.0);
+            if locked.db_map_bytes(name).is_none() {
+                locked.create_db_map_bytes(name, params)?;
+            }
+        }
         match RefCell::borrow(&self.0).db_map_bytes(name) {
             Some(m) => Ok(m),
             None => panic!("Cannot create db_maps: {}", name),
@@ -265,20 +575,176 @@ impl FileDb {
         if let Some(m) = RefCell::borrow(&self.0).db_map_int(name) {
             return Ok(m);
         }
-        RefCell::borrow_mut(&self.0).create_db_map_dbint(name, params)?;
+        {
+            // the other thread may create it in the meantime
+            let mut locked = RefCell::borrow_mut(&self.0);
+            if locked.db_map_int(name).is_none() {
+                locked.create_db_map_dbint(name, params)?;
+            }
+        }
         match RefCell::borrow(&self.0).db_map_int(name) {
             Some(m) => Ok(m),
             None => panic!("Cannot create db_maps: {}", name),
         }
     }
+    /// list of the db map names and the key kinds, sorted by the name.
+    /// this includes the db maps that exist on the disk but are not opened yet.
+    pub fn list_maps(&self) -> Result<Vec<(String, DbMapKeyKind)>> {
+        Ok(RefCell::borrow(&self.0).list_maps()?)
+    }
+    /// remove the db map and delete its files.
+    /// if the handles of the db map are still held, the error kind is `WouldBlock`.
+    pub fn drop_map(&self, name: &str) -> Result<()> {
+        Ok(RefCell::borrow_mut(&self.0).drop_map(name)?)
+    }
+    /// rename the db map and its files. the files that are already renamed are
+    /// renamed back on the error.
+    /// if the handles of the db map are still held, the error kind is `WouldBlock`.
+    pub fn rename_map(&self, old_name: &str, new_name: &str) -> Result<()> {
+        Ok(RefCell::borrow_mut(&self.0).rename_map(old_name, new_name)?)
+    }
+    /// run the closure in a transaction over several db maps.
+    /// the writing to the db maps that are got from `tx` is committed atomically
+    /// when the closure returns `Ok`, and is rolled back when it returns `Err`.
+    /// the other handles of the db maps do not see the writing until the commit,
+    /// and their writing is rejected while the transaction runs.
+    pub fn transaction<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut FileDbTransaction) -> Result<T>,
+    {
+        let mut tx = FileDbTransaction::new(self);
+        match f(&mut tx) {
+            Ok(r) => {
+                tx.commit()?;
+                Ok(r)
+            }
+            Err(err) => {
+                tx.rollback()?;
+                Err(err)
+            }
+        }
+    }
+    /// verify the all db maps found on the disk, and returns the report of
+    /// the problems. the db map that is not opened yet is opened as read only
+    /// here, and the files are not modified. the db map that was not synced is
+    /// not recovered, it is reported as `VerifyProblemKind::NotSynced`.
+    /// the encrypted db map must be opened with its encryption key before this.
+    pub fn verify(&self) -> Result<VerifyReport> {
+        let mut report = VerifyReport::default();
+        for (name, kind) in self.list_maps()? {
+            let r = RefCell::borrow(&self.0).verify_map(&name, kind);
+            match r.map_err(Error::from) {
+                Ok(problems) => report.problems.extend(problems),
+                Err(err) => {
+                    let (file, offset) = match &err {
+                        Error::Corruption { file, offset, .. } => (file.clone(), *offset),
+                        _ => (self.path().join(format!("{}.key", name)), 0),
+                    };
+                    report.problems.push(VerifyProblem {
+                        map: name.clone(),
+                        file,
+                        offset,
+                        kind: VerifyProblemKind::Unreadable,
+                        message: err.to_string(),
+                    });
+                }
+            }
+            report.maps.push(name);
+        }
+        Ok(report)
+    }
+    /// rebuild the idx file and the htx file of the db map from its key file,
+    /// when they are lost or broken. the data is in the key file and the value
+    /// file, because each key piece has the offset of its value piece.
+    /// the handles of the db map must be dropped before this, or the error kind
+    /// is `WouldBlock`. the old idx file and htx file are replaced only after
+    /// the whole key file is read.
+    pub fn repair_map(&self, name: &str) -> Result<RepairStats> {
+        self.repair_map_with_params(name, FileDbParams::default())
+    }
+    /// rebuild the db map like `repair_map()`. the encrypted db map needs
+    /// the encryption key of the params, and the new idx file has the node slots
+    /// of the params. the other params are not used.
+    pub fn repair_map_with_params(&self, name: &str, params: FileDbParams) -> Result<RepairStats> {
+        Ok(RefCell::borrow_mut(&self.0).repair_map(name, params)?)
+    }
+    /// write a consistent copy of the database into the directory of the path,
+    /// that can be opened by `open_file()`. the directory must be empty or not exist.
+    ///
+    /// the buffers and the node caches of the opened db maps are flushed, and
+    /// the db maps are copied at the same point in time. the db maps and the commits
+    /// of the batches and the transactions are locked only while the flushing,
+    /// and the calls on the db maps do not wait for the copy. the old contents
+    /// of the pages that are changed while the copy are kept in memory, until
+    /// the copy goes over them. the creating, the dropping and the renaming
+    /// of the db maps wait until the whole copy is finished.
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<BackupStats> {
+        Ok(RefCell::borrow(&self.0).backup_to(path.as_ref())?)
+    }
+    /// export the records of the db map to the writer in the format, in the key order.
+    /// returns the count of the records. the encrypted db map must be opened with
+    /// its encryption key before this.
+    pub fn export_map<W: std::io::Write>(
+        &self,
+        name: &str,
+        writer: W,
+        format: ExportFormat,
+    ) -> Result<u64> {
+        export::export_map(self, name, writer, format)
+    }
+    /// import the records from the reader in the format into the db map, that is
+    /// exported by `export_map()`. returns the count of the records.
+    /// the db map of the kind is created if it does not exist, and it is synced.
+    ///
+    /// the records are read and put by the write batches of 4 MiB of the keys
+    /// and the values, so the memory and the changes of a batch are bounded,
+    /// and the input of any size can be imported. each batch is put
+    /// all-or-nothing, but the whole import is not. if a line is invalid, the
+    /// error of the line number is returned, and the records of the batches
+    /// before the line are already put. the db map is created after the first
+    /// batch is read, so the invalid line in the first 4 MiB puts nothing.
+    pub fn import_map<R: std::io::BufRead>(
+        &self,
+        name: &str,
+        kind: DbMapKeyKind,
+        reader: R,
+        format: ExportFormat,
+    ) -> Result<u64> {
+        export::import_map(self, name, kind, reader, format)
+    }
+    /// migrate the database of the path into the format of the params, such as
+    /// the integer encoding, the node slots and the checksum. the files of the old
+    /// format version are migrated into the current one too.
+    ///
+    /// every db map is copied into a fresh database beside the path, and the
+    /// record counts are checked, and then the fresh database is swapped with
+    /// the old one by renaming the directories, while the old one is locked.
+    /// the database must not be opened while this runs. if the swap is
+    /// interrupted, it is finished by the next opening of the path.
+    pub fn migrate<P: AsRef<Path>>(path: P, params: FileDbParams) -> Result<MigrateStats> {
+        Self::migrate_with_params(path, FileDbParams::default(), params)
+    }
+    /// migrate the database like `migrate()`. the old db maps are opened with
+    /// `src_params`, such as the encryption key or the value codec of them.
+    pub fn migrate_with_params<P: AsRef<Path>>(
+        path: P,
+        src_params: FileDbParams,
+        params: FileDbParams,
+    ) -> Result<MigrateStats> {
+        migrate::migrate(path.as_ref(), src_params, params)
+    }
+    #[inline]
+    pub(crate) fn wal_lock(&self) -> inner::wal::WalLock {
+        RefCell::borrow(&self.0).wal_lock()
+    }
     pub fn path(&self) -> PathBuf {
         RefCell::borrow(&self.0).path().to_path_buf()
     }
     pub fn sync_all(&self) -> Result<()> {
-        RefCell::borrow_mut(&self.0).sync_all()
+        Ok(RefCell::borrow_mut(&self.0).sync_all()?)
     }
     pub fn sync_data(&self) -> Result<()> {
-        RefCell::borrow_mut(&self.0).sync_data()
+        Ok(RefCell::borrow_mut(&self.0).sync_data()?)
     }
 }
 
@@ -298,7 +764,7 @@ mod debug {
             assert_eq!(std::mem::size_of::<FileDbMapDbString>(), 8);
             assert_eq!(std::mem::size_of::<FileDbMapDbInt>(), 8);
             //
-            assert_eq!(std::mem::size_of::<FileDbInner>(), 96);
+            assert_eq!(std::mem::size_of::<FileDbInner>(), 112);
             //
             assert_eq!(std::mem::size_of::<KeyPieceSizeStats>(), 24);
             assert_eq!(std::mem::size_of::<ValueRecordSizeStats>(), 24);
@@ -310,7 +776,7 @@ mod debug {
             assert_eq!(std::mem::size_of::<FileDbMapDbString>(), 4);
             assert_eq!(std::mem::size_of::<FileDbMapDbInt>(), 4);
             //
-            assert_eq!(std::mem::size_of::<FileDbInner>(), 48);
+            assert_eq!(std::mem::size_of::<FileDbInner>(), 56);
             //
             assert_eq!(std::mem::size_of::<KeyPieceSizeStats>(), 12);
             assert_eq!(std::mem::size_of::<ValueRecordSizeStats>(), 12);
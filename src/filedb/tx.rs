@@ -0,0 +1,240 @@
+use super::super::{DbMapKeyType, Result};
+use super::cell::RefCell;
+use super::inner::wal::{self, WalRecord};
+use super::{FileDb, FileDbMap, FileDbMapDbBytes, FileDbMapDbInt, FileDbMapDbString};
+use std::any::Any;
+use std::io::{Error, ErrorKind};
+
+/// A transaction over several db maps in a file database.
+///
+/// This is handed out by `FileDb::transaction()`. The db maps got from this
+/// are the transactional views, the puts and the deletes on them are kept
+/// in memory until the commit, and then they are written to the write-ahead
+/// log at once. The log is replayed at the next open if the commit is interrupted.
+///
+/// The views are isolated from the other handles of the db maps. The other
+/// handles read the committed data, and their writing is rejected with
+/// the error kind of `WouldBlock` until the end of the transaction.
+/// The views are valid only in the transaction, the writing through them
+/// is rejected after it.
+pub struct FileDbTransaction<'a> {
+    db: &'a FileDb,
+    maps: Vec<(String, Box<dyn TxMap>)>,
+}
+
+impl<'a> FileDbTransaction<'a> {
+    pub(crate) fn new(db: &'a FileDb) -> Self {
+        Self {
+            db,
+            maps: Vec::new(),
+        }
+    }
+    /// gets the transactional view of the string key db map.
+    pub fn db_map_string(&mut self, name: &str) -> Result<FileDbMapDbString> {
+        let db_map = self.db.db_map_string(name)?;
+        self.view(name, db_map)
+    }
+    /// gets the transactional view of the integer key db map.
+    pub fn db_map_int(&mut self, name: &str) -> Result<FileDbMapDbInt> {
+        let db_map = self.db.db_map_int(name)?;
+        self.view(name, db_map)
+    }
+    /// gets the transactional view of the bytes key db map.
+    pub fn db_map_bytes(&mut self, name: &str) -> Result<FileDbMapDbBytes> {
+        let db_map = self.db.db_map_bytes(name)?;
+        self.view(name, db_map)
+    }
+    fn view<KT: DbMapKeyType + 'static>(
+        &mut self,
+        name: &str,
+        db_map: FileDbMap<KT>,
+    ) -> Result<FileDbMap<KT>> {
+        if let Some((_, m)) = self.maps.iter().find(|(a, _)| a == name) {
+            return match m.as_any().downcast_ref::<TxView<KT>>() {
+                Some(tx_view) => Ok(tx_view.view.clone()),
+                None => Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "the db map is in the transaction as the other key type: {}",
+                        name
+                    ),
+                )
+                .into()),
+            };
+        }
+        let view = db_map.begin_tx()?;
+        self.maps.push((
+            name.to_string(),
+            Box::new(TxView {
+                db_map,
+                view: view.clone(),
+            }),
+        ));
+        Ok(view)
+    }
+    /// commits the writing in all of the db maps atomically.
+    ///
+    /// if the applying of the committed log fails, the db maps stay locked,
+    /// and the log is replayed on the next open.
+    pub(crate) fn commit(mut self) -> Result<()> {
+        let maps = std::mem::take(&mut self.maps);
+        let mut records = Vec::new();
+        for (_, m) in maps.iter() {
+            match m.take_batch_records() {
+                Ok(mut recs) => records.append(&mut recs),
+                Err(err) => {
+                    rollback_all(&maps)?;
+                    return Err(err);
+                }
+            }
+        }
+        if records.is_empty() {
+            return rollback_all(&maps);
+        }
+        let wal_lock = self.db.wal_lock();
+        let _wal_locked = RefCell::borrow_mut(&wal_lock);
+        let path = self.db.path();
+        if let Err(err) = wal::write_wal_file(&path, &records) {
+            rollback_all(&maps)?;
+            return Err(err.into());
+        }
+        for (_, m) in maps.iter() {
+            m.apply_batch_records(&records)?;
+        }
+        for (_, m) in maps.iter() {
+            m.commit();
+        }
+        wal::remove_wal_file(&path)?;
+        for (_, m) in maps.iter() {
+            m.after_commit()?;
+        }
+        Ok(())
+    }
+    /// discards the writing in all of the db maps.
+    pub(crate) fn rollback(mut self) -> Result<()> {
+        let maps = std::mem::take(&mut self.maps);
+        rollback_all(&maps)
+    }
+}
+
+impl<'a> std::fmt::Debug for FileDbTransaction<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = self.maps.iter().map(|(a, _)| a.as_str()).collect();
+        f.debug_struct("FileDbTransaction")
+            .field("db", &self.db.path())
+            .field("maps", &names)
+            .finish()
+    }
+}
+
+impl<'a> Drop for FileDbTransaction<'a> {
+    /// the transaction that is not committed is rolled back.
+    fn drop(&mut self) {
+        let _ = rollback_all(&self.maps);
+    }
+}
+
+fn rollback_all(maps: &[(String, Box<dyn TxMap>)]) -> Result<()> {
+    let mut r = Ok(());
+    for (_, m) in maps.iter() {
+        if let Err(err) = m.rollback() {
+            r = Err(err);
+        }
+    }
+    r
+}
+
+/// the db map and its view in the transaction.
+struct TxView<KT: DbMapKeyType> {
+    db_map: FileDbMap<KT>,
+    view: FileDbMap<KT>,
+}
+
+/// the db map in the transaction, the key type is erased.
+trait TxMap {
+    fn as_any(&self) -> &dyn Any;
+    fn take_batch_records(&self) -> Result<Vec<WalRecord>>;
+    fn apply_batch_records(&self, records: &[WalRecord]) -> Result<()>;
+    fn commit(&self);
+    fn after_commit(&self) -> Result<()>;
+    fn rollback(&self) -> Result<()>;
+}
+
+impl<KT: DbMapKeyType + 'static> TxMap for TxView<KT> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+    fn take_batch_records(&self) -> Result<Vec<WalRecord>> {
+        self.view.take_batch_records()
+    }
+    fn apply_batch_records(&self, records: &[WalRecord]) -> Result<()> {
+        self.db_map.copy_snapshots_to(&self.view);
+        self.view.apply_batch_records(records)
+    }
+    fn commit(&self) {
+        self.db_map.commit_tx(&self.view)
+    }
+    fn after_commit(&self) -> Result<()> {
+        self.db_map.after_commit()
+    }
+    fn rollback(&self) -> Result<()> {
+        self.db_map.rollback_tx(&self.view)
+    }
+}
+
+//--
+#[cfg(test)]
+mod debug {
+    use super::super::super::{open_file, DbMap, DbXxx};
+    use super::wal;
+    use super::FileDbTransaction;
+    //
+    const LOOP_MAX: u64 = 100;
+    //
+    #[test]
+    fn test_replay_committed() {
+        let db_name = "target/tmp/test_tx-p.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = open_file(db_name).unwrap();
+            db.db_map_int("records")
+                .unwrap()
+                .put_string(&0, "record0")
+                .unwrap();
+            let mut tx = FileDbTransaction::new(&db);
+            let mut index = tx.db_map_string("index").unwrap();
+            let mut records = tx.db_map_int("records").unwrap();
+            for i in 1..LOOP_MAX {
+                records.put_string(&i, &format!("record{}", i)).unwrap();
+                index
+                    .put(&format!("name{:03}", i), &i.to_le_bytes())
+                    .unwrap();
+            }
+            let _ = records.delete(&0).unwrap();
+            // the crash after the log is committed, before it is applied
+            let mut recs = Vec::new();
+            for (_, m) in tx.maps.iter() {
+                recs.append(&mut m.take_batch_records().unwrap());
+            }
+            wal::write_wal_file(&db.path(), &recs).unwrap();
+            drop(tx);
+            let mut records = db.db_map_int("records").unwrap();
+            assert_eq!(records.get_string(&0).unwrap(), Some("record0".to_string()));
+            assert_eq!(records.get_string(&1).unwrap(), None);
+            db.sync_all().unwrap();
+        }
+        // the log is replayed on the open
+        let db = open_file(db_name).unwrap();
+        let index = db.db_map_string("index").unwrap();
+        let mut records = db.db_map_int("records").unwrap();
+        assert_eq!(index.iter().count() as u64, LOOP_MAX - 1);
+        assert_eq!(records.get_string(&0).unwrap(), None);
+        for i in 1..LOOP_MAX {
+            assert_eq!(
+                records.get_string(&i).unwrap(),
+                Some(format!("record{}", i))
+            );
+        }
+        assert!(db.verify().unwrap().is_ok());
+    }
+}
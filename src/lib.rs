@@ -24,12 +24,12 @@ The simple local key-value store.
 # Todo
 
 - [ ] more performance
-- [ ] DB lock as support for multi-process-safe
+- [x] DB lock as support for multi-process-safe
 
 # Low priority todo
 
-- [ ] transaction support that handles multiple key-space at a time.
-- [ ] thread-safe support
+- [x] transaction support that handles multiple key-space at a time.
+- [x] thread-safe support, with the feature `sync`
 - [ ] non db lock multi-process-safe support
 
 # Examples
@@ -112,14 +112,17 @@ fn main() -> std::io::Result<()> {
 ```
 */
 use std::hash::Hash;
-use std::io::Result;
+use std::ops::RangeBounds;
 use std::path::Path;
 
 pub mod filedb;
 pub mod memdb;
 
+mod error;
+pub use error::{Error, Result};
+
 pub use filedb::{DbBytes, DbInt, DbString};
-pub use filedb::{DbXxxIter, DbXxxIterMut};
+pub use filedb::{DbXxxIter, DbXxxIterMut, DbXxxTryIter};
 
 /// Open the memory db. This data is not stored in file.
 pub fn open_memory<'a>() -> memdb::MemoryDb<'a> {
@@ -131,6 +134,11 @@ pub fn open_file<P: AsRef<Path>>(path: P) -> Result<filedb::FileDb> {
     filedb::FileDb::open(path)
 }
 
+/// Open the existing file db as read only.
+pub fn open_file_read_only<P: AsRef<Path>>(path: P) -> Result<filedb::FileDb> {
+    filedb::FileDb::open_read_only(path)
+}
+
 /// base interface for generic key-value map store interface. this is not include `KT`
 pub trait DbXxxBase {
     /// read and fill buffer.
@@ -196,7 +204,7 @@ pub trait DbXxx<KT: DbMapKeyType>: DbXxxObjectSafe<KT> {
             let result_value = self.get(ik.1)?;
             result.push((ik.0, result_value));
         }
-        result.sort_by(|a, b| a.0.cmp(&(b.0)));
+        result.sort_by_key(|a| a.0);
         let ret: Vec<Option<Vec<u8>>> = result.iter().map(|a| a.1.clone()).collect();
         Ok(ret)
     }
@@ -303,7 +311,7 @@ pub trait DbXxx<KT: DbMapKeyType>: DbXxxObjectSafe<KT> {
             let result_value = self.delete(ik.1)?;
             result.push((ik.0, result_value));
         }
-        result.sort_by(|a, b| a.0.cmp(&(b.0)));
+        result.sort_by_key(|a| a.0);
         let ret: Vec<Option<Vec<u8>>> = result.iter().map(|a| a.1.clone()).collect();
         Ok(ret)
     }
@@ -329,6 +337,8 @@ pub trait DbXxx<KT: DbMapKeyType>: DbXxxObjectSafe<KT> {
 pub trait DbMap<KT: DbMapKeyType>: DbXxx<KT> {
     fn iter(&self) -> DbXxxIter<KT>;
     fn iter_mut(&mut self) -> DbXxxIterMut<KT>;
+    /// gets an iterator over a sub-range of key-value pairs, in key order.
+    fn range<R: RangeBounds<KT>>(&self, range: R) -> DbXxxIter<KT>;
 }
 
 /// key-value map store interface. the key type is `String`.
@@ -1,10 +1,9 @@
 use super::{
     DbBytes, DbInt, DbMapDbBytes, DbMapDbInt, DbMapDbString, DbString, DbXxx, DbXxxBase,
-    DbXxxObjectSafe,
+    DbXxxObjectSafe, Result,
 };
 use std::cell::RefCell;
 use std::collections::BTreeMap;
-use std::io::Result;
 use std::rc::{Rc, Weak};
 
 #[derive(Debug, Clone)]
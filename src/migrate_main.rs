@@ -0,0 +1,44 @@
+use siamesedb::filedb::{FileDb, FileDbParams, IntEncoding};
+
+fn main() -> std::io::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let params = if args.len() < 2 {
+        None
+    } else {
+        parse_params(&args[1..args.len() - 1])
+    };
+    let params = match params {
+        Some(params) => params,
+        None => {
+            eprintln!(
+                "[usage] {} [-e {{u32u32|u64u64|vu64}}] [-n node_slots] [-c] path",
+                args[0]
+            );
+            std::process::exit(2);
+        }
+    };
+    let stats = FileDb::migrate(&args[args.len() - 1], params)?;
+    println!("{}", stats);
+    Ok(())
+}
+
+fn parse_params(args: &[String]) -> Option<FileDbParams> {
+    let mut params = FileDbParams::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-e" => {
+                params.int_encoding = Some(match iter.next()?.as_str() {
+                    "u32u32" => IntEncoding::U32U32,
+                    "u64u64" => IntEncoding::U64U64,
+                    "vu64" => IntEncoding::Vu64,
+                    _ => return None,
+                });
+            }
+            "-n" => params.node_slots = iter.next()?.parse().ok()?,
+            "-c" => params.checksum = true,
+            _ => return None,
+        }
+    }
+    Some(params)
+}
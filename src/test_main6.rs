@@ -22,7 +22,7 @@ fn main() -> Result<(), std::io::Error> {
     Ok(())
 }
 
-fn open_db_map(db_name: &str) -> Result<FileDbMapDbBytes, std::io::Error> {
+fn open_db_map(db_name: &str) -> siamesedb::Result<FileDbMapDbBytes> {
     let db = siamesedb::open_file(db_name).unwrap();
     db.db_map_bytes_with_params(
         "some_map1",
@@ -31,6 +31,13 @@ fn open_db_map(db_name: &str) -> Result<FileDbMapDbBytes, std::io::Error> {
             idx_buf_size: FileBufSizeParam::PerMille(1000),
             htx_buf_size: FileBufSizeParam::PerMille(1000),
             val_buf_size: FileBufSizeParam::PerMille(1000),
+            htx_init_size: 4 * 1024,
+            use_htx: true,
+            checksum: false,
+            node_slots: 0,
+            value_codec: None,
+            encryption_key: None,
+            int_encoding: None,
             /*
             key_buf_size: FileBufSizeParam::PerMille(100),
             idx_buf_size: FileBufSizeParam::PerMille(300),
@@ -55,12 +62,12 @@ fn conv_to_kv_string(ki: i64, _vi: i64) -> (DbBytes, String) {
     (k.into(), v)
 }
 
-fn _test_create(db_name: &str) -> Result<(), std::io::Error> {
+fn _test_create(db_name: &str) -> siamesedb::Result<()> {
     let _ = std::fs::remove_dir_all(db_name);
     _test_write(db_name)
 }
 
-fn _test_write(db_name: &str) -> Result<(), std::io::Error> {
+fn _test_write(db_name: &str) -> siamesedb::Result<()> {
     let mut db_map = open_db_map(db_name)?;
     db_map.read_fill_buffer()?;
     //
@@ -99,12 +106,12 @@ fn _test_write(db_name: &str) -> Result<(), std::io::Error> {
 fn _test_write_one(
     db_map: &mut FileDbMapDbBytes,
     key_vec: &[(DbBytes, String)],
-) -> Result<(), std::io::Error> {
+) -> siamesedb::Result<()> {
     let keys: Vec<(&DbBytes, &[u8])> = key_vec.iter().map(|(a, b)| (a, b.as_bytes())).collect();
     db_map.bulk_put(&keys)
 }
 
-fn _test_read(db_name: &str) -> Result<(), std::io::Error> {
+fn _test_read(db_name: &str) -> siamesedb::Result<()> {
     let mut db_map = open_db_map(db_name)?;
     db_map.read_fill_buffer()?;
     //
@@ -148,7 +155,7 @@ fn _test_read_one(
     db_map: &mut FileDbMapDbBytes,
     key_vec: &[DbBytes],
     value_vec: &[String],
-) -> Result<(), std::io::Error> {
+) -> siamesedb::Result<()> {
     let keys: Vec<&DbBytes> = key_vec.iter().collect();
     let result = db_map.bulk_get_string(&keys)?;
     //
@@ -165,7 +172,7 @@ fn _test_read_one(
     Ok(())
 }
 
-fn _test_delete(db_name: &str) -> Result<(), std::io::Error> {
+fn _test_delete(db_name: &str) -> siamesedb::Result<()> {
     let mut db_map = open_db_map(db_name)?;
     db_map.read_fill_buffer()?;
     //
@@ -209,7 +216,7 @@ fn _test_delete_one(
     db_map: &mut FileDbMapDbBytes,
     key_vec: &[DbBytes],
     value_vec: &[String],
-) -> Result<(), std::io::Error> {
+) -> siamesedb::Result<()> {
     let keys: Vec<&DbBytes> = key_vec.iter().collect();
     let result = db_map.bulk_delete_string(&keys)?;
     //
@@ -0,0 +1,123 @@
+mod test_backup {
+    use siamesedb::filedb::CheckFileDbMap;
+    use siamesedb::DbXxx;
+    //
+    const LOOP_MAX: u64 = 1000;
+    //
+    fn remove_dbs(db_name: &str, backup_name: &str) {
+        let _ = std::fs::remove_dir_all(db_name);
+        let _ = std::fs::remove_dir_all(backup_name);
+    }
+    //
+    #[test]
+    fn test_backup() {
+        let db_name = "target/tmp/test_backup-b.siamesedb";
+        let backup_name = "target/tmp/test_backup-b.backup.siamesedb";
+        remove_dbs(db_name, backup_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        for i in 0..LOOP_MAX {
+            db_map
+                .put_string(&format!("key{}", i), &format!("value{}", i))
+                .unwrap();
+        }
+        let mut db_map_int = db.db_map_int("some_u64_1").unwrap();
+        for i in 0..LOOP_MAX {
+            db_map_int.put_string(&i, &format!("value{}", i)).unwrap();
+        }
+        let _ = db.db_map_bytes("empty_bytes_1").unwrap();
+        // the buffers and the node caches are not synced yet
+        let stats = db.backup_to(backup_name).unwrap();
+        assert_eq!(stats.map_count, 3);
+        assert!(stats.byte_count > 0);
+        // the writing after the backup is not in the backup
+        db_map.put_string("key0", "new value").unwrap();
+        db_map_int.delete(&1).unwrap();
+        //
+        let backup = siamesedb::open_file(backup_name).unwrap();
+        assert_eq!(backup.list_maps().unwrap().len(), 3);
+        let mut db_map = backup.db_map_string("some_string_1").unwrap();
+        assert!(db_map.is_balanced().unwrap());
+        for i in 0..LOOP_MAX {
+            assert_eq!(
+                db_map.get_string(&format!("key{}", i)).unwrap(),
+                Some(format!("value{}", i))
+            );
+        }
+        let mut db_map_int = backup.db_map_int("some_u64_1").unwrap();
+        assert!(db_map_int.is_mst_valid().unwrap());
+        assert_eq!(
+            db_map_int.get_string(&1).unwrap(),
+            Some("value1".to_string())
+        );
+        assert!(backup.verify().unwrap().is_ok());
+    }
+    #[test]
+    fn test_read_only() {
+        let db_name = "target/tmp/test_backup-r.siamesedb";
+        let backup_name = "target/tmp/test_backup-r.backup.siamesedb";
+        remove_dbs(db_name, backup_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            db_map.put_string(&1, "value1").unwrap();
+            db.sync_all().unwrap();
+        }
+        let db = siamesedb::open_file_read_only(db_name).unwrap();
+        let _ = db.db_map_int("some_u64_1").unwrap();
+        let stats = db.backup_to(backup_name).unwrap();
+        assert_eq!(stats.map_count, 1);
+        let backup = siamesedb::open_file(backup_name).unwrap();
+        let mut db_map = backup.db_map_int("some_u64_1").unwrap();
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+    }
+    #[test]
+    fn test_not_empty() {
+        let db_name = "target/tmp/test_backup-n.siamesedb";
+        let backup_name = "target/tmp/test_backup-n.backup.siamesedb";
+        remove_dbs(db_name, backup_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let _ = db.db_map_int("some_u64_1").unwrap();
+        std::fs::create_dir_all(backup_name).unwrap();
+        std::fs::write(format!("{}/some_file", backup_name), b"data").unwrap();
+        let err = db.backup_to(backup_name).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        // the database itself is not a backup directory
+        let err = db.backup_to(db_name).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    }
+    #[cfg(feature = "sync")]
+    #[test]
+    fn test_with_transaction() {
+        let db_name = "target/tmp/test_backup-t.siamesedb";
+        let backup_name = "target/tmp/test_backup-t.backup.siamesedb";
+        remove_dbs(db_name, backup_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let _ = db.db_map_int("some_u64_1").unwrap();
+        let _ = db.db_map_int("some_u64_2").unwrap();
+        // the same key is put into the both db maps in a transaction
+        let handle = {
+            let db = db.clone();
+            std::thread::spawn(move || {
+                for i in 0..LOOP_MAX / 4 {
+                    db.transaction(|tx| {
+                        tx.db_map_int("some_u64_1")?.put_string(&i, "value")?;
+                        tx.db_map_int("some_u64_2")?.put_string(&i, "value")?;
+                        Ok(())
+                    })
+                    .unwrap();
+                }
+            })
+        };
+        for n in 0..4 {
+            let name = format!("{}-{}", backup_name, n);
+            let _ = std::fs::remove_dir_all(&name);
+            let _ = db.backup_to(&name).unwrap();
+            let backup = siamesedb::open_file(&name).unwrap();
+            let count_1 = backup.db_map_int("some_u64_1").unwrap().try_iter().count();
+            let count_2 = backup.db_map_int("some_u64_2").unwrap().try_iter().count();
+            assert_eq!(count_1, count_2);
+        }
+        handle.join().unwrap();
+    }
+}
@@ -0,0 +1,111 @@
+mod test_batch {
+    use siamesedb::filedb::{CheckFileDbMap, WriteBatch};
+    use siamesedb::{DbMap, DbXxx};
+    use std::io::ErrorKind;
+    //
+    const LOOP_MAX: u64 = 1000;
+    //
+    #[test]
+    fn test_write_batch() {
+        let db_name = "target/tmp/test_batch-w.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            db_map.put_string("key0", "value0").unwrap();
+            //
+            let mut batch = WriteBatch::new();
+            batch.put_string("key1", "value1");
+            batch.put_string("key2", "value2");
+            batch.put_string("key3", "value3");
+            batch.delete("key0");
+            batch.delete("key2");
+            assert_eq!(batch.len(), 5);
+            db_map.write_batch(batch).unwrap();
+            //
+            assert_eq!(db_map.get_string("key0").unwrap(), None);
+            assert_eq!(
+                db_map.get_string("key1").unwrap(),
+                Some("value1".to_string())
+            );
+            assert_eq!(db_map.get_string("key2").unwrap(), None);
+            assert_eq!(db_map.iter().count(), 2);
+            assert!(!std::path::Path::new(db_name).join("siamesedb.wal").exists());
+        }
+        // the batch is durable without sync
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        assert_eq!(
+            db_map.get_string("key3").unwrap(),
+            Some("value3".to_string())
+        );
+        assert_eq!(db_map.iter().count(), 2);
+    }
+    #[test]
+    fn test_write_batch_large() {
+        let db_name = "target/tmp/test_batch-l.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            let mut batch = WriteBatch::new();
+            for i in 0..LOOP_MAX {
+                batch.put_string(&i, &format!("value{}", i));
+            }
+            for i in (0..LOOP_MAX).step_by(3) {
+                batch.delete(&i);
+            }
+            db_map.write_batch(batch).unwrap();
+            assert!(db_map.is_balanced().unwrap());
+            assert!(db_map.is_mst_valid().unwrap());
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        assert!(db_map.is_balanced().unwrap());
+        assert!(db_map.is_mst_valid().unwrap());
+        for i in 0..LOOP_MAX {
+            let expected = if i % 3 == 0 {
+                None
+            } else {
+                Some(format!("value{}", i))
+            };
+            assert_eq!(db_map.get_string(&i).unwrap(), expected);
+        }
+    }
+    #[test]
+    fn test_uncommitted_wal() {
+        let db_name = "target/tmp/test_batch-u.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+            db_map.put_string(b"key1", "value1").unwrap();
+        }
+        // the log that was interrupted while writing
+        let wal_path = std::path::Path::new(db_name).join("siamesedb.wal");
+        std::fs::write(&wal_path, b"siamwal\0garbage").unwrap();
+        let db = siamesedb::open_file(db_name).unwrap();
+        assert!(!wal_path.exists());
+        let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+        assert_eq!(
+            db_map.get_string(b"key1").unwrap(),
+            Some("value1".to_string())
+        );
+    }
+    #[test]
+    fn test_write_batch_read_only() {
+        let db_name = "target/tmp/test_batch-r.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let _db_map = db.db_map_string("some_string_1").unwrap();
+        }
+        let db = siamesedb::open_file_read_only(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put_string("key1", "value1");
+        let err = db_map.write_batch(batch).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert_eq!(db_map.get_string("key1").unwrap(), None);
+    }
+}
@@ -0,0 +1,166 @@
+mod test_checksum {
+    use siamesedb::filedb::{CheckFileDbMap, FileDbParams};
+    use siamesedb::{DbXxx, DbXxxBase, Error};
+    use std::convert::TryInto;
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 500;
+    //
+    fn checksum_params() -> FileDbParams {
+        FileDbParams {
+            checksum: true,
+            ..Default::default()
+        }
+    }
+    fn flip_byte(path: &Path, pos: usize) {
+        let mut buf = std::fs::read(path).unwrap();
+        buf[pos] ^= 0x01;
+        std::fs::write(path, &buf).unwrap();
+    }
+    //
+    #[test]
+    fn test_checksum() {
+        let db_name = "target/tmp/test_checksum-c.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_int_with_params("some_u64_1", checksum_params())
+                .unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i)).unwrap();
+            }
+            for i in (0..LOOP_MAX).step_by(3) {
+                db_map.delete(&i).unwrap();
+            }
+            for i in (1..LOOP_MAX).step_by(3) {
+                db_map.put_string(&i, &"v".repeat(i as usize)).unwrap();
+            }
+            db_map.compact().unwrap();
+            db_map.sync_all().unwrap();
+        }
+        // the format is kept on the opening without the params
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        assert!(db_map.is_balanced().unwrap());
+        assert!(db_map.is_mst_valid().unwrap());
+        for i in 0..LOOP_MAX {
+            let expected = match i % 3 {
+                0 => None,
+                1 => Some("v".repeat(i as usize)),
+                _ => Some(format!("value{}", i)),
+            };
+            assert_eq!(db_map.get_string(&i).unwrap(), expected);
+        }
+    }
+    #[test]
+    fn test_broken_value() {
+        let db_name = "target/tmp/test_checksum-v.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_int_with_params("some_u64_1", checksum_params())
+                .unwrap();
+            db_map.put_string(&1, "value1").unwrap();
+            db_map.put_string(&2, "the broken value").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let val_path = Path::new(db_name).join("some_u64_1.val");
+        let buf = std::fs::read(&val_path).unwrap();
+        let pos = buf
+            .windows(6)
+            .position(|w| w == b"broken")
+            .expect("the value is in the file");
+        flip_byte(&val_path, pos);
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+        match db_map.get_string(&2).unwrap_err() {
+            Error::Corruption {
+                file,
+                offset,
+                reason,
+            } => {
+                assert_eq!(file, val_path);
+                assert!(offset >= 192 && offset < pos as u64, "{}", offset);
+                assert_eq!(reason, "checksum mismatch");
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+    #[test]
+    fn test_broken_node() {
+        let db_name = "target/tmp/test_checksum-n.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        // the key is found in the index tree only
+        let params = FileDbParams {
+            use_htx: false,
+            ..checksum_params()
+        };
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_string_with_params("some_string_1", params.clone())
+                .unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        // the offset of the top node is in the header
+        let idx_path = Path::new(db_name).join("some_string_1.idx");
+        let buf = std::fs::read(&idx_path).unwrap();
+        let top_node_offset = u64::from_le_bytes(buf[16..24].try_into().unwrap());
+        flip_byte(&idx_path, top_node_offset as usize + 8);
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let result = db
+            .db_map_string_with_params("some_string_1", params)
+            .and_then(|mut db_map| db_map.get_string("key1"));
+        match result.unwrap_err() {
+            Error::Corruption { file, offset, .. } => {
+                assert_eq!(file, idx_path);
+                assert_eq!(offset, top_node_offset);
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+    #[test]
+    fn test_broken_key() {
+        let db_name = "target/tmp/test_checksum-k.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_string_with_params("some_string_1", checksum_params())
+                .unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.put_string("the broken key", "value2").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let key_path = Path::new(db_name).join("some_string_1.key");
+        let buf = std::fs::read(&key_path).unwrap();
+        let pos = buf
+            .windows(6)
+            .position(|w| w == b"broken")
+            .expect("the key is in the file");
+        flip_byte(&key_path, pos);
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        match db_map.get_string("the broken key").unwrap_err() {
+            Error::Corruption {
+                file,
+                offset,
+                reason,
+            } => {
+                assert_eq!(file, key_path);
+                assert!(offset < pos as u64, "{}", offset);
+                assert_eq!(reason, "checksum mismatch");
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+        // the broken key is not listed as the other key
+        assert!(db_map.try_iter().any(|a| a.is_err()));
+    }
+}
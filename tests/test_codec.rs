@@ -0,0 +1,186 @@
+mod test_codec {
+    use siamesedb::filedb::{FileDbParams, ValueCodec};
+    use siamesedb::{DbXxx, DbXxxBase, Error};
+    use std::path::Path;
+    use std::sync::Arc;
+    //
+    const LOOP_MAX: u64 = 500;
+    //
+    /// the run length encoding, for the test.
+    #[derive(Debug)]
+    struct RleCodec(u64);
+    impl ValueCodec for RleCodec {
+        fn id(&self) -> u64 {
+            self.0
+        }
+        fn encode(&self, value: &[u8]) -> Vec<u8> {
+            let mut data = Vec::new();
+            for &b in value {
+                match data.len() {
+                    n if n >= 2 && data[n - 1] == b && data[n - 2] < 255 => data[n - 2] += 1,
+                    _ => data.extend_from_slice(&[1, b]),
+                }
+            }
+            data
+        }
+        fn decode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+            if data.len() % 2 != 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "odd length",
+                ));
+            }
+            let mut value = Vec::new();
+            for a in data.chunks(2) {
+                value.extend(std::iter::repeat(a[1]).take(a[0] as usize));
+            }
+            Ok(value)
+        }
+    }
+    fn codec_params(id: u64) -> FileDbParams {
+        FileDbParams {
+            value_codec: Some(Arc::new(RleCodec(id))),
+            ..Default::default()
+        }
+    }
+    fn long_value(i: u64) -> String {
+        format!("{}{}", "a".repeat(100 + i as usize % 100), i)
+    }
+    //
+    #[test]
+    fn test_codec() {
+        let db_name = "target/tmp/test_codec-c.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_int_with_params("some_u64_1", codec_params(100))
+                .unwrap();
+            let mut db_map_raw = db.db_map_int("some_u64_2").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &long_value(i)).unwrap();
+                db_map_raw.put_string(&i, &long_value(i)).unwrap();
+            }
+            // the short value is kept raw
+            db_map.put_string(&LOOP_MAX, "ab").unwrap();
+            db.sync_all().unwrap();
+        }
+        let val_len = |name: &str| {
+            std::fs::metadata(Path::new(db_name).join(name))
+                .unwrap()
+                .len()
+        };
+        assert!(val_len("some_u64_1.val") * 3 < val_len("some_u64_2.val"));
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db
+            .db_map_int_with_params("some_u64_1", codec_params(100))
+            .unwrap();
+        for i in 0..LOOP_MAX {
+            assert_eq!(db_map.get_string(&i).unwrap(), Some(long_value(i)));
+        }
+        assert_eq!(
+            db_map.get_string(&LOOP_MAX).unwrap(),
+            Some("ab".to_string())
+        );
+        let (_key, value) = db_map.try_iter().next().unwrap().unwrap();
+        assert_eq!(value, long_value(0).into_bytes());
+    }
+    #[test]
+    fn test_mixed() {
+        let db_name = "target/tmp/test_codec-m.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        // the raw values before the codec is set
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map
+                    .put_string(&format!("key{}", i), &long_value(i))
+                    .unwrap();
+            }
+            db_map.sync_all().unwrap();
+        }
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_string_with_params("some_string_1", codec_params(100))
+                .unwrap();
+            for i in (0..LOOP_MAX).step_by(2) {
+                db_map
+                    .put_string(&format!("key{}", i), &long_value(i + 1))
+                    .unwrap();
+            }
+            db_map.sync_all().unwrap();
+        }
+        let expected = |i: u64| {
+            if i % 2 == 0 {
+                long_value(i + 1)
+            } else {
+                long_value(i)
+            }
+        };
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_string_with_params("some_string_1", codec_params(100))
+                .unwrap();
+            for i in 0..LOOP_MAX {
+                assert_eq!(
+                    db_map.get_string(&format!("key{}", i)).unwrap(),
+                    Some(expected(i))
+                );
+            }
+            assert!(db.verify().unwrap().is_ok());
+        }
+        // without the codec, the raw values are readable only
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            assert_eq!(db_map.get_string("key1").unwrap(), Some(expected(1)));
+            match db_map.get_string("key0").unwrap_err() {
+                Error::Io(err) => assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput),
+                err => panic!("unexpected error: {:?}", err),
+            }
+        }
+        // the other codec can not be used
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            match db
+                .db_map_string_with_params("some_string_1", codec_params(101))
+                .unwrap_err()
+            {
+                Error::Corruption { offset, .. } => assert_eq!(offset, 160),
+                err => panic!("unexpected error: {:?}", err),
+            }
+        }
+    }
+    #[cfg(feature = "lz4")]
+    #[test]
+    fn test_lz4() {
+        use siamesedb::filedb::Lz4Codec;
+        let db_name = "target/tmp/test_codec-l.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let params = FileDbParams {
+                value_codec: Some(Arc::new(Lz4Codec)),
+                ..Default::default()
+            };
+            let mut db_map = db.db_map_bytes_with_params("some_bytes_1", params).unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i.to_be_bytes(), &long_value(i)).unwrap();
+            }
+            db_map.sync_all().unwrap();
+        }
+        // the built-in codec is used without the params
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+        for i in 0..LOOP_MAX {
+            assert_eq!(
+                db_map.get_string(&i.to_be_bytes()).unwrap(),
+                Some(long_value(i))
+            );
+        }
+    }
+}
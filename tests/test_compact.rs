@@ -0,0 +1,190 @@
+mod test_compact {
+    use siamesedb::filedb::CheckFileDbMap;
+    use siamesedb::{DbMap, DbXxx, DbXxxBase};
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 500;
+    //
+    fn file_len(path: &Path) -> u64 {
+        std::fs::metadata(path).unwrap().len()
+    }
+    //
+    #[test]
+    fn test_compact() {
+        let db_name = "target/tmp/test_compact-c.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let path = Path::new(db_name);
+        let file_lens = |path: &Path| -> Vec<u64> {
+            ["key", "val", "idx"]
+                .iter()
+                .map(|ext| file_len(&path.join(format!("some_u64_1.{}", ext))))
+                .collect()
+        };
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i)).unwrap();
+            }
+            for i in (0..LOOP_MAX).filter(|i| i % 3 != 0) {
+                db_map.delete(&i).unwrap();
+            }
+            db_map.sync_all().unwrap();
+            let lens = file_lens(path);
+            //
+            let stats = db_map.compact().unwrap();
+            assert!(stats.free_count > 0);
+            assert!(stats.value_bytes > 0);
+            let new_lens = file_lens(path);
+            assert_eq!(lens[0] - new_lens[0], stats.key_bytes);
+            assert_eq!(lens[1] - new_lens[1], stats.value_bytes);
+            assert_eq!(lens[2] - new_lens[2], stats.node_bytes);
+            assert_eq!(
+                stats.reclaimed_bytes(),
+                stats.key_bytes + stats.value_bytes + stats.node_bytes
+            );
+            //
+            assert!(db_map.is_balanced().unwrap());
+            assert!(db_map.is_mst_valid().unwrap());
+            let no_free = |vec: Vec<(u32, u64)>| vec.iter().all(|&(_, cnt)| cnt == 0);
+            assert!(no_free(db_map.count_of_free_key_piece().unwrap()));
+            assert!(no_free(db_map.count_of_free_value_piece().unwrap()));
+            assert!(no_free(db_map.count_of_free_node().unwrap()));
+            for i in 0..LOOP_MAX {
+                let expected = if i % 3 == 0 {
+                    Some(format!("value{}", i))
+                } else {
+                    None
+                };
+                assert_eq!(db_map.get_string(&i).unwrap(), expected);
+            }
+            assert_eq!(db_map.iter().count(), ((LOOP_MAX + 2) / 3) as usize);
+            // nothing to reclaim
+            let stats = db_map.compact().unwrap();
+            assert_eq!(stats.reclaimed_bytes(), 0);
+            // the db map still works
+            db_map.put_string(&1, "value1").unwrap();
+            db_map.delete(&3).unwrap();
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        assert!(db_map.is_mst_valid().unwrap());
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+        assert_eq!(db_map.get_string(&3).unwrap(), None);
+        assert_eq!(db_map.get_string(&6).unwrap(), Some("value6".to_string()));
+    }
+    #[test]
+    fn test_compact_string() {
+        let db_name = "target/tmp/test_compact-s.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        for i in 0..LOOP_MAX {
+            let key = format!("key{}", i);
+            db_map.put_string(&key, &"v".repeat(i as usize)).unwrap();
+        }
+        for i in (0..LOOP_MAX).step_by(2) {
+            db_map.delete(&format!("key{}", i)).unwrap();
+        }
+        let stats = db_map.compact().unwrap();
+        assert!(stats.key_bytes > 0 && stats.value_bytes > 0);
+        assert!(db_map.is_mst_valid().unwrap());
+        for i in 0..LOOP_MAX {
+            let expected = if i % 2 == 0 {
+                None
+            } else {
+                Some("v".repeat(i as usize))
+            };
+            assert_eq!(db_map.get_string(&format!("key{}", i)).unwrap(), expected);
+        }
+    }
+    #[test]
+    fn test_interrupted_compact() {
+        let db_name = "target/tmp/test_compact-i.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let path = Path::new(db_name);
+        let file_path = |name: &str, ext: &str| path.join(format!("{}.{}", name, ext));
+        let exts = ["htx", "idx", "val", "key"];
+        let old_dir = path.join("old");
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i)).unwrap();
+            }
+            for i in (0..LOOP_MAX).filter(|i| i % 3 != 0) {
+                db_map.delete(&i).unwrap();
+            }
+            db_map.sync_all().unwrap();
+            std::fs::create_dir(&old_dir).unwrap();
+            for ext in exts {
+                let _ = std::fs::copy(
+                    file_path("some_u64_1", ext),
+                    old_dir.join(format!("some_u64_1.{}", ext)),
+                )
+                .unwrap();
+            }
+            let _ = db_map.compact().unwrap();
+            for ext in exts {
+                assert!(!file_path("some_u64_1..compact", ext).exists());
+            }
+            assert!(!file_path("some_u64_1..compact", "done").exists());
+        }
+        let new_len = file_len(&file_path("some_u64_1", "val"));
+        // the crash after the compacted files are complete, before the renaming
+        for ext in exts {
+            std::fs::rename(
+                file_path("some_u64_1", ext),
+                file_path("some_u64_1..compact", ext),
+            )
+            .unwrap();
+            let _ = std::fs::copy(
+                old_dir.join(format!("some_u64_1.{}", ext)),
+                file_path("some_u64_1", ext),
+            )
+            .unwrap();
+        }
+        std::fs::write(file_path("some_u64_1..compact", "done"), b"").unwrap();
+        {
+            // the read only can not finish it
+            let db = siamesedb::open_file_read_only(db_name).unwrap();
+            assert!(db.db_map_int("some_u64_1").is_err());
+        }
+        {
+            // the compaction is finished on the open
+            let db = siamesedb::open_file(db_name).unwrap();
+            let names: Vec<String> = db.list_maps().unwrap().into_iter().map(|a| a.0).collect();
+            assert_eq!(names, vec!["some_u64_1".to_string()]);
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            assert_eq!(file_len(&file_path("some_u64_1", "val")), new_len);
+            assert!(!file_path("some_u64_1..compact", "done").exists());
+            assert!(db_map.is_mst_valid().unwrap());
+            for i in 0..LOOP_MAX {
+                let expected = if i % 3 == 0 {
+                    Some(format!("value{}", i))
+                } else {
+                    None
+                };
+                assert_eq!(db_map.get_string(&i).unwrap(), expected);
+            }
+        }
+        // the crash before the compacted files are complete
+        for ext in exts {
+            let _ = std::fs::copy(
+                old_dir.join(format!("some_u64_1.{}", ext)),
+                file_path("some_u64_1..compact", ext),
+            )
+            .unwrap();
+        }
+        {
+            // the files of the interrupted compaction are removed on the open
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            for ext in exts {
+                assert!(!file_path("some_u64_1..compact", ext).exists());
+            }
+            assert_eq!(file_len(&file_path("some_u64_1", "val")), new_len);
+            assert_eq!(db_map.get_string(&3).unwrap(), Some("value3".to_string()));
+        }
+    }
+}
@@ -0,0 +1,183 @@
+mod test_encryption {
+    use siamesedb::filedb::{EncryptionKey, FileDbParams};
+    use siamesedb::Error;
+    #[cfg(feature = "encryption")]
+    use siamesedb::{DbXxx, DbXxxBase};
+    use std::path::Path;
+    //
+    #[cfg(feature = "encryption")]
+    const LOOP_MAX: u64 = 300;
+    //
+    fn key_params(b: u8) -> FileDbParams {
+        FileDbParams {
+            encryption_key: Some(EncryptionKey::new([b; 32])),
+            ..Default::default()
+        }
+    }
+    #[cfg(feature = "encryption")]
+    fn assert_input_error(err: Error) {
+        match err {
+            Error::Io(err) => assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput),
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+    //
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encryption() {
+        let db_name = "target/tmp/test_encryption-e.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_string_with_params("some_string_1", key_params(1))
+                .unwrap();
+            for i in 0..LOOP_MAX {
+                db_map
+                    .put_string(&format!("key{}", i), &format!("secret-value{}", i))
+                    .unwrap();
+            }
+            db_map.put_string("empty", "").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        // the values are not in the clear, the keys are.
+        let buf = std::fs::read(Path::new(db_name).join("some_string_1.val")).unwrap();
+        assert!(!buf.windows(12).any(|w| w == b"secret-value"));
+        let buf = std::fs::read(Path::new(db_name).join("some_string_1.key")).unwrap();
+        assert!(buf.windows(6).any(|w| w == b"key123"));
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        assert_input_error(db.db_map_string("some_string_1").unwrap_err());
+        assert_input_error(
+            db.db_map_string_with_params("some_string_1", key_params(2))
+                .unwrap_err(),
+        );
+        let mut db_map = db
+            .db_map_string_with_params("some_string_1", key_params(1))
+            .unwrap();
+        for i in 0..LOOP_MAX {
+            assert_eq!(
+                db_map.get_string(&format!("key{}", i)).unwrap(),
+                Some(format!("secret-value{}", i))
+            );
+        }
+        assert_eq!(db_map.get_string("empty").unwrap(), Some("".to_string()));
+        assert!(db.verify().unwrap().is_ok());
+        // the idx file is rebuilt with the key
+        drop(db_map);
+        std::fs::remove_file(Path::new(db_name).join("some_string_1.idx")).unwrap();
+        let stats = db
+            .repair_map_with_params("some_string_1", key_params(1))
+            .unwrap();
+        assert_eq!(stats.key_count, LOOP_MAX + 1);
+        let mut db_map = db
+            .db_map_string_with_params("some_string_1", key_params(1))
+            .unwrap();
+        assert_eq!(
+            db_map.get_string("key0").unwrap(),
+            Some("secret-value0".to_string())
+        );
+    }
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_swapped_values() {
+        let db_name = "target/tmp/test_encryption-s.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let path = Path::new(db_name).join("some_string_1.val");
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_string_with_params("some_string_1", key_params(1))
+                .unwrap();
+            db_map.put_string("key1", "secret-value1").unwrap();
+            db_map.put_string("key2", "secret-value2").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        // the pieces of the same size are swapped
+        let mut buf = std::fs::read(&path).unwrap();
+        let header_len = 192;
+        let piece_len = (buf.len() - header_len) / 2;
+        let (piece1, piece2) = buf[header_len..].split_at_mut(piece_len);
+        piece1.swap_with_slice(piece2);
+        std::fs::write(&path, buf).unwrap();
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db
+            .db_map_string_with_params("some_string_1", key_params(1))
+            .unwrap();
+        for key in ["key1", "key2"] {
+            match db_map.get_string(key).unwrap_err() {
+                Error::Io(err) => assert_eq!(err.kind(), std::io::ErrorKind::InvalidData),
+                err => panic!("unexpected error: {:?}", err),
+            }
+        }
+    }
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_plain_map() {
+        let db_name = "target/tmp/test_encryption-p.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            db_map.put_string(&1, "value1").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        assert_input_error(
+            db.db_map_int_with_params("some_u64_1", key_params(1))
+                .unwrap_err(),
+        );
+    }
+    #[cfg(all(feature = "encryption", feature = "lz4"))]
+    #[test]
+    fn test_lz4() {
+        use siamesedb::filedb::Lz4Codec;
+        use std::sync::Arc;
+        let db_name = "target/tmp/test_encryption-l.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let params = || FileDbParams {
+            value_codec: Some(Arc::new(Lz4Codec)),
+            ..key_params(1)
+        };
+        let value = |i: u64| format!("{}{}", "a".repeat(1000), i);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_bytes_with_params("some_bytes_1", params())
+                .unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i.to_be_bytes(), &value(i)).unwrap();
+            }
+            db_map.sync_all().unwrap();
+        }
+        // the value is compressed before the encryption
+        let len = std::fs::metadata(Path::new(db_name).join("some_bytes_1.val"))
+            .unwrap()
+            .len();
+        assert!(len < LOOP_MAX * 1000 / 3);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db
+            .db_map_bytes_with_params("some_bytes_1", params())
+            .unwrap();
+        for i in 0..LOOP_MAX {
+            assert_eq!(db_map.get_string(&i.to_be_bytes()).unwrap(), Some(value(i)));
+        }
+    }
+    #[cfg(not(feature = "encryption"))]
+    #[test]
+    fn test_unsupported() {
+        let db_name = "target/tmp/test_encryption-u.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        match db
+            .db_map_string_with_params("some_string_1", key_params(1))
+            .unwrap_err()
+        {
+            Error::Io(err) => assert_eq!(err.kind(), std::io::ErrorKind::Unsupported),
+            err => panic!("unexpected error: {:?}", err),
+        }
+        // no file is created
+        assert!(!Path::new(db_name).join("some_string_1.key").exists());
+    }
+}
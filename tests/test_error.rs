@@ -0,0 +1,111 @@
+mod test_error {
+    use siamesedb::{DbMap, DbXxx, Error};
+    use std::io::ErrorKind;
+    use std::path::Path;
+    //
+    #[cfg(unix)]
+    #[test]
+    fn test_locked() {
+        let db_name = "target/tmp/test_error-l.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let _db = siamesedb::open_file(db_name).unwrap();
+        let err = siamesedb::open_file(db_name).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert!(matches!(err, Error::Locked(_)));
+    }
+    #[test]
+    fn test_read_only() {
+        let db_name = "target/tmp/test_error-r.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let _db_map = db.db_map_string("some_string_1").unwrap();
+            db.sync_all().unwrap();
+        }
+        let db = siamesedb::open_file_read_only(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        let err = db_map.put_string("key1", "value1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        assert!(matches!(err, Error::ReadOnly(_)));
+    }
+    #[test]
+    fn test_key_type_mismatch() {
+        let db_name = "target/tmp/test_error-k.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let _db_map = db.db_map_string("some_string_1").unwrap();
+            db.sync_all().unwrap();
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        match db.db_map_int("some_string_1").unwrap_err() {
+            Error::KeyTypeMismatch {
+                file,
+                expected,
+                found,
+            } => {
+                assert!(file.ends_with("some_string_1.key"));
+                assert_eq!(expected, "\"uint64\"");
+                assert_eq!(found, "\"string\"");
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+    #[test]
+    fn test_corruption() {
+        let db_name = "target/tmp/test_error-c.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let _db_map = db.db_map_int("some_u64_1").unwrap();
+            db.sync_all().unwrap();
+        }
+        let idx_path = Path::new(db_name).join("some_u64_1.idx");
+        let mut buf = std::fs::read(&idx_path).unwrap();
+        buf[..8].copy_from_slice(&[0u8; 8]);
+        std::fs::write(&idx_path, &buf).unwrap();
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        match db.db_map_int("some_u64_1").unwrap_err() {
+            Error::Corruption { file, offset, .. } => {
+                assert_eq!(file, idx_path);
+                assert_eq!(offset, 0);
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+    #[test]
+    fn test_io_error_conversion() {
+        let err = Error::ValueTooLarge { len: 10, max: 5 };
+        // the variant is kept through `std::io::Error`
+        let io_err: std::io::Error = err.into();
+        assert_eq!(io_err.kind(), ErrorKind::InvalidInput);
+        let err: Error = io_err.into();
+        assert!(matches!(err, Error::ValueTooLarge { len: 10, max: 5 }));
+        //
+        let err: Error = std::io::Error::new(ErrorKind::Other, "other").into();
+        assert!(matches!(err, Error::Io(_)));
+        assert_eq!(err.to_string(), "other");
+        // `?` in the function of `std::io::Result`
+        fn open(db_name: &str) -> std::io::Result<()> {
+            let _db = siamesedb::open_file_read_only(db_name)?;
+            Ok(())
+        }
+        let err = open("target/tmp/test_error-not-found.siamesedb").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+    }
+    #[test]
+    fn test_try_iter() {
+        let db_name = "target/tmp/test_error-i.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        for i in 0..100u64 {
+            db_map.put_string(&i, &format!("value{}", i)).unwrap();
+        }
+        let vec: Vec<_> = db_map.try_iter().collect::<Result<_, _>>().unwrap();
+        assert_eq!(vec, db_map.iter().collect::<Vec<_>>());
+        let last = db_map.try_iter().next_back().unwrap().unwrap();
+        assert_eq!(last, (99.into(), b"value99".to_vec()));
+    }
+}
@@ -0,0 +1,291 @@
+mod test_export {
+    use siamesedb::filedb::{BytesEncoding, DbMapKeyKind, ExportFormat};
+    use siamesedb::{DbMap, DbXxx};
+    //
+    const LOOP_MAX: u64 = 300;
+    //
+    fn tricky_value(i: u64) -> String {
+        format!("value{}, \"quoted\"\r\n\ttab \u{3042} \u{1f600} \u{1}", i)
+    }
+    fn export_string(db: &siamesedb::filedb::FileDb, name: &str, format: ExportFormat) -> String {
+        let mut buf = Vec::new();
+        let _ = db.export_map(name, &mut buf, format).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+    //
+    #[test]
+    fn test_string_map() {
+        let db_name = "target/tmp/test_export-s.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        for i in 0..LOOP_MAX {
+            db_map
+                .put_string(&format!("key,{}", i), &tricky_value(i))
+                .unwrap();
+        }
+        for (n, format) in [
+            ExportFormat::JsonLines(BytesEncoding::Utf8),
+            ExportFormat::Csv(BytesEncoding::Utf8),
+            ExportFormat::Csv(BytesEncoding::Base64),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let text = export_string(&db, "some_string_1", format);
+            let copy_name = format!("copy_{}", n);
+            let count = db
+                .import_map(&copy_name, DbMapKeyKind::DbString, text.as_bytes(), format)
+                .unwrap();
+            assert_eq!(count, LOOP_MAX);
+            let mut copy_map = db.db_map_string(&copy_name).unwrap();
+            let vec: Vec<_> = copy_map.iter().collect();
+            assert_eq!(vec, db_map.iter().collect::<Vec<_>>());
+            assert_eq!(copy_map.get_string("key,1").unwrap(), Some(tricky_value(1)));
+        }
+        let text = export_string(
+            &db,
+            "some_string_1",
+            ExportFormat::JsonLines(BytesEncoding::Utf8),
+        );
+        assert_eq!(
+            text.lines().next().unwrap(),
+            "{\"key\":\"key,0\",\"value\":\"value0, \\\"quoted\\\"\\r\\n\\ttab \u{3042} \u{1f600} \\u0001\"}"
+        );
+        let text = export_string(&db, "some_string_1", ExportFormat::Csv(BytesEncoding::Utf8));
+        assert!(text.starts_with(
+            "key,value\r\n\"key,0\",\"value0, \"\"quoted\"\"\r\n\ttab \u{3042} \u{1f600} \u{1}\"\r\n"
+        ));
+    }
+    #[test]
+    fn test_int_map() {
+        let db_name = "target/tmp/test_export-i.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        for i in 0..LOOP_MAX {
+            db_map
+                .put_string(&(i * 1000), &format!("value{}", i))
+                .unwrap();
+        }
+        // the keys are the numbers
+        let text = export_string(
+            &db,
+            "some_u64_1",
+            ExportFormat::JsonLines(BytesEncoding::Utf8),
+        );
+        assert_eq!(
+            text.lines().nth(1).unwrap(),
+            "{\"key\":1000,\"value\":\"value1\"}"
+        );
+        let text = export_string(&db, "some_u64_1", ExportFormat::Csv(BytesEncoding::Utf8));
+        assert_eq!(text.lines().nth(2).unwrap(), "1000,value1");
+        //
+        let mut copy_map = db.db_map_int("copy").unwrap();
+        let count = db
+            .import_map(
+                "copy",
+                DbMapKeyKind::DbInt,
+                text.as_bytes(),
+                ExportFormat::Csv(BytesEncoding::Utf8),
+            )
+            .unwrap();
+        assert_eq!(count, LOOP_MAX);
+        assert_eq!(
+            copy_map.get_string(&299000).unwrap(),
+            Some("value299".to_string())
+        );
+    }
+    #[test]
+    fn test_bytes_map() {
+        let db_name = "target/tmp/test_export-b.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+        for i in 0..LOOP_MAX {
+            let key = [0xFF, (i >> 8) as u8, i as u8];
+            db_map.put(&key, &[0x80; 5][..(i % 6) as usize]).unwrap();
+        }
+        db_map.put(&[0x01][..], b"foob").unwrap();
+        // the bytes that are not utf-8
+        let mut buf = Vec::new();
+        let err = db
+            .export_map(
+                "some_bytes_1",
+                &mut buf,
+                ExportFormat::JsonLines(BytesEncoding::Utf8),
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        //
+        let text = export_string(
+            &db,
+            "some_bytes_1",
+            ExportFormat::JsonLines(BytesEncoding::Hex),
+        );
+        assert_eq!(
+            text.lines().next().unwrap(),
+            "{\"key\":\"01\",\"value\":\"666f6f62\"}"
+        );
+        let text = export_string(
+            &db,
+            "some_bytes_1",
+            ExportFormat::Csv(BytesEncoding::Base64),
+        );
+        let lines: Vec<&str> = text.lines().take(6).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "key,value",
+                "AQ==,Zm9vYg==",
+                "/wAA,",
+                "/wAB,gA==",
+                "/wAC,gIA=",
+                "/wAD,gICA"
+            ]
+        );
+        for (n, enc) in [BytesEncoding::Hex, BytesEncoding::Base64]
+            .into_iter()
+            .enumerate()
+        {
+            for format in [ExportFormat::JsonLines(enc), ExportFormat::Csv(enc)] {
+                let text = export_string(&db, "some_bytes_1", format);
+                let copy_name = format!("copy_{}_{:?}", n, format).replace(['(', ')'], "_");
+                let copy_map = db.db_map_bytes(&copy_name).unwrap();
+                let count = db
+                    .import_map(&copy_name, DbMapKeyKind::DbBytes, text.as_bytes(), format)
+                    .unwrap();
+                assert_eq!(count, LOOP_MAX + 1);
+                let vec: Vec<_> = copy_map.iter().collect();
+                assert_eq!(vec, db_map.iter().collect::<Vec<_>>());
+            }
+        }
+    }
+    #[test]
+    fn test_large_import() {
+        let db_name = "target/tmp/test_export-l.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let json = ExportFormat::JsonLines(BytesEncoding::Utf8);
+        // over the 4 MiB of a write batch
+        let count = 100;
+        let value = "v".repeat(64 * 1024);
+        let mut text = String::new();
+        for i in 0..count {
+            text.push_str(&format!("{{\"key\":{},\"value\":\"{}\"}}\n", i, value));
+        }
+        let n = db
+            .import_map("some_u64_1", DbMapKeyKind::DbInt, text.as_bytes(), json)
+            .unwrap();
+        assert_eq!(n, count);
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        assert_eq!(db_map.try_iter().count() as u64, count);
+        assert_eq!(
+            db_map.get_string(&(count - 1)).unwrap(),
+            Some(value.clone())
+        );
+        // the batches before the invalid line are put
+        text.push_str("{\"key\":\"x\",\"value\":\"a\"}\n");
+        let err = db
+            .import_map("some_u64_2", DbMapKeyKind::DbInt, text.as_bytes(), json)
+            .unwrap_err();
+        assert!(err.to_string().contains(&format!("line {}:", count + 1)));
+        let db_map = db.db_map_int("some_u64_2").unwrap();
+        let n = db_map.try_iter().count() as u64;
+        assert!(n > 0 && n < count, "{}", n);
+        assert!(db.verify().unwrap().is_ok());
+    }
+    #[test]
+    fn test_invalid() {
+        let db_name = "target/tmp/test_export-e.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        db_map.put_string(&1, "value1").unwrap();
+        let json = ExportFormat::JsonLines(BytesEncoding::Utf8);
+        let csv = ExportFormat::Csv(BytesEncoding::Utf8);
+        for (name, kind, text, format, line_num) in [
+            (
+                "some_u64_1",
+                DbMapKeyKind::DbInt,
+                "{\"key\":1,\"value\":\"a\"}\n{\"key\":\"2\",\"value\":\"b\"}\n",
+                json,
+                2,
+            ),
+            (
+                "some_u64_1",
+                DbMapKeyKind::DbInt,
+                "{\"key\":1,\"value\":\"a\"\n",
+                json,
+                1,
+            ),
+            ("some_u64_1", DbMapKeyKind::DbInt, "{\"key\":1}\n", json, 1),
+            (
+                "some_u64_1",
+                DbMapKeyKind::DbInt,
+                "key,value\r\n1,a\r\nx,b\r\n",
+                csv,
+                3,
+            ),
+            (
+                "some_u64_1",
+                DbMapKeyKind::DbInt,
+                "key,value\r\n1,\"a\r\n",
+                csv,
+                2,
+            ),
+            ("some_u64_1", DbMapKeyKind::DbInt, "1,a\r\n", csv, 1),
+            (
+                "some_bytes_1",
+                DbMapKeyKind::DbBytes,
+                "{\"key\":\"zz\",\"value\":\"00\"}\n",
+                ExportFormat::JsonLines(BytesEncoding::Hex),
+                1,
+            ),
+            (
+                "some_bytes_1",
+                DbMapKeyKind::DbBytes,
+                "{\"key\":\"00\",\"value\":\"+f\"}\n",
+                ExportFormat::JsonLines(BytesEncoding::Hex),
+                1,
+            ),
+            (
+                "some_bytes_1",
+                DbMapKeyKind::DbBytes,
+                "key,value\r\n00,00\r\n-1,00\r\n",
+                ExportFormat::Csv(BytesEncoding::Hex),
+                3,
+            ),
+            (
+                "some_bytes_1",
+                DbMapKeyKind::DbBytes,
+                "key,value\r\nAA==,A===\r\n",
+                ExportFormat::Csv(BytesEncoding::Base64),
+                2,
+            ),
+        ] {
+            let err = db
+                .import_map(name, kind, text.as_bytes(), format)
+                .unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{}", text);
+            assert!(
+                err.to_string().contains(&format!("line {}:", line_num)),
+                "{}: {}",
+                text,
+                err
+            );
+        }
+        // nothing is put by the invalid input, and the db map is not created
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+        let names: Vec<_> = db.list_maps().unwrap().into_iter().map(|a| a.0).collect();
+        assert_eq!(names, vec!["some_u64_1".to_string()]);
+        // the db map of the other kind
+        let err = db
+            .import_map("some_u64_1", DbMapKeyKind::DbBytes, "".as_bytes(), json)
+            .unwrap_err();
+        match err {
+            siamesedb::Error::KeyTypeMismatch { .. } => (),
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+mod test_format {
+    use siamesedb::filedb::{CheckFileDbMap, FileDbParams, IntEncoding};
+    use siamesedb::{DbXxx, DbXxxBase, Error};
+    use std::convert::TryInto;
+    use std::io::ErrorKind;
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 1000;
+    //
+    // the offsets of the version and the encoding in the headers
+    const VERSION_OFFSETS: [(&str, usize); 4] =
+        [("key", 160), ("val", 184), ("idx", 112), ("htx", 48)];
+    //
+    fn encoding_params(int_encoding: IntEncoding) -> FileDbParams {
+        FileDbParams {
+            int_encoding: Some(int_encoding),
+            ..Default::default()
+        }
+    }
+    fn map_path(db_name: &str, map_name: &str, ext: &str) -> std::path::PathBuf {
+        Path::new(db_name).join(format!("{}.{}", map_name, ext))
+    }
+    fn header_u32(db_name: &str, map_name: &str, ext: &str, offset: usize) -> u32 {
+        let buf = std::fs::read(map_path(db_name, map_name, ext)).unwrap();
+        u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+    }
+    fn write_header_u32(db_name: &str, map_name: &str, ext: &str, offset: usize, val: u32) {
+        let path = map_path(db_name, map_name, ext);
+        let mut buf = std::fs::read(&path).unwrap();
+        buf[offset..offset + 4].copy_from_slice(&val.to_le_bytes());
+        std::fs::write(&path, buf).unwrap();
+    }
+    fn make_map(db_name: &str, map_name: &str, params: FileDbParams) {
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int_with_params(map_name, params).unwrap();
+        for i in 0..LOOP_MAX {
+            db_map.put_string(&i, &format!("value{}", i)).unwrap();
+        }
+        for i in (0..LOOP_MAX).step_by(3) {
+            db_map.delete(&i).unwrap();
+        }
+        db_map.sync_all().unwrap();
+    }
+    //
+    #[test]
+    fn test_version() {
+        let db_name = "target/tmp/test_format-v.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        make_map(db_name, "some_u64_1", FileDbParams::default());
+        for (ext, offset) in VERSION_OFFSETS {
+            if ext == "htx" && !map_path(db_name, "some_u64_1", ext).exists() {
+                continue;
+            }
+            assert_eq!(header_u32(db_name, "some_u64_1", ext, offset), 1, "{}", ext);
+            assert_ne!(
+                header_u32(db_name, "some_u64_1", ext, offset + 4),
+                0,
+                "{}",
+                ext
+            );
+        }
+    }
+    #[test]
+    fn test_int_encoding() {
+        let db_name = "target/tmp/test_format-e.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let encodings = [
+            ("u32u32", IntEncoding::U32U32, 1),
+            ("u64u64", IntEncoding::U64U64, 2),
+            ("vu64", IntEncoding::Vu64, 3),
+        ];
+        for (name, int_encoding, _) in encodings {
+            make_map(db_name, name, encoding_params(int_encoding));
+        }
+        for (name, _, id) in encodings {
+            assert_eq!(header_u32(db_name, name, "key", 164) & 0x0F, id, "{}", name);
+            assert_eq!(header_u32(db_name, name, "val", 188) & 0x0F, id, "{}", name);
+            assert_eq!(header_u32(db_name, name, "idx", 116) & 0x0F, id, "{}", name);
+        }
+        // the encoding in the headers is used, not the params
+        let db = siamesedb::open_file(db_name).unwrap();
+        for (name, _, _) in encodings {
+            let mut db_map = db.db_map_int(name).unwrap();
+            assert!(db_map.is_balanced().unwrap(), "{}", name);
+            assert!(db_map.is_mst_valid().unwrap(), "{}", name);
+            for i in 0..LOOP_MAX {
+                let expected = if i % 3 == 0 {
+                    None
+                } else {
+                    Some(format!("value{}", i))
+                };
+                assert_eq!(db_map.get_string(&i).unwrap(), expected, "{}", name);
+            }
+            db_map.put_string(&0, "value0").unwrap();
+            assert_eq!(db_map.get_string(&0).unwrap(), Some("value0".to_string()));
+        }
+        assert!(db.verify().unwrap().is_ok());
+    }
+    #[test]
+    fn test_legacy() {
+        let db_name = "target/tmp/test_format-l.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        make_map(db_name, "some_u64_1", FileDbParams::default());
+        // the files before the version have the encoding of the build
+        for (ext, offset) in VERSION_OFFSETS {
+            if map_path(db_name, "some_u64_1", ext).exists() {
+                write_header_u32(db_name, "some_u64_1", ext, offset, 0);
+                write_header_u32(db_name, "some_u64_1", ext, offset + 4, 0);
+            }
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+        assert_eq!(db_map.get_string(&3).unwrap(), None);
+        assert!(db_map.is_balanced().unwrap());
+    }
+    #[test]
+    fn test_newer_version() {
+        let db_name = "target/tmp/test_format-n.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        make_map(db_name, "some_u64_1", FileDbParams::default());
+        write_header_u32(db_name, "some_u64_1", "key", 160, 2);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let err = db.db_map_int("some_u64_1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Unsupported);
+        assert!(err.to_string().contains("some_u64_1.key"), "{}", err);
+    }
+    #[test]
+    fn test_unknown_encoding() {
+        let db_name = "target/tmp/test_format-u.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        make_map(db_name, "some_u64_1", FileDbParams::default());
+        write_header_u32(db_name, "some_u64_1", "val", 188, 0x0F);
+        let db = siamesedb::open_file(db_name).unwrap();
+        match db.db_map_int("some_u64_1").unwrap_err() {
+            Error::Corruption { file, offset, .. } => {
+                assert!(file.ends_with("some_u64_1.val"), "{:?}", file);
+                assert_eq!(offset, 188);
+            }
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+}
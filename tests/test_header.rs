@@ -0,0 +1,94 @@
+mod test_header {
+    use siamesedb::{DbXxx, DbXxxBase};
+    use std::io::ErrorKind;
+    use std::path::Path;
+    //
+    #[test]
+    fn test_mismatched_type() {
+        let db_name = "target/tmp/test_header-m.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        let err = db.db_map_int("some_string_1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let msg = err.to_string();
+        assert!(msg.contains("some_string_1.key"), "{}", msg);
+        assert!(
+            msg.contains("expected \"uint64\", found \"string\""),
+            "{}",
+            msg
+        );
+        let err = db.db_map_bytes("some_string_1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        // the db map is still good
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        assert_eq!(
+            db_map.get_string("key1").unwrap(),
+            Some("value1".to_string())
+        );
+    }
+    #[test]
+    fn test_mismatched_type_opened() {
+        let db_name = "target/tmp/test_header-o.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        db_map.put_string(&1, "value1").unwrap();
+        // the header is not yet written to the file
+        let err = db.db_map_string("some_u64_1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("found \"uint64\""), "{}", err);
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+    }
+    #[test]
+    fn test_truncated_file() {
+        let db_name = "target/tmp/test_header-t.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let idx_path = Path::new(db_name).join("some_string_1.idx");
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&idx_path)
+            .unwrap();
+        file.set_len(20).unwrap();
+        drop(file);
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let err = db.db_map_string("some_string_1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let msg = err.to_string();
+        assert!(msg.contains("some_string_1.idx"), "{}", msg);
+        assert!(msg.contains("found 20 bytes"), "{}", msg);
+    }
+    #[test]
+    fn test_broken_signature() {
+        let db_name = "target/tmp/test_header-b.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let _db_map = db.db_map_bytes("some_bytes_1").unwrap();
+            db.sync_all().unwrap();
+        }
+        let val_path = Path::new(db_name).join("some_bytes_1.val");
+        let mut buf = std::fs::read(&val_path).unwrap();
+        buf[..8].copy_from_slice(b"broken\0\0");
+        std::fs::write(&val_path, &buf).unwrap();
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let err = db.db_map_bytes("some_bytes_1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let msg = err.to_string();
+        assert!(msg.starts_with("invalid header signature1"), "{}", msg);
+        assert!(msg.contains("found \"broken\""), "{}", msg);
+    }
+}
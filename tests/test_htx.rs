@@ -0,0 +1,194 @@
+#[cfg(feature = "htx")]
+mod test_htx {
+    use siamesedb::filedb::{CheckFileDbMap, FileDbParams, WriteBatch};
+    use siamesedb::{DbXxx, DbXxxBase};
+    use std::convert::TryInto;
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 2000;
+    //
+    #[test]
+    fn test_small_map() {
+        let db_name = "target/tmp/test_htx-s.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        for i in 0..10 {
+            db_map.put_string(&format!("key{}", i), "value").unwrap();
+        }
+        db_map.sync_all().unwrap();
+        let (ht_size, count) = db_map.ht_size_and_count().unwrap();
+        assert_eq!(ht_size, FileDbParams::default().htx_init_size);
+        assert!(count > 0 && count <= 10);
+        let htx_path = Path::new(db_name).join("some_string_1.htx");
+        let len = std::fs::metadata(htx_path).unwrap().len();
+        assert_eq!(len, 128 + 8 * ht_size);
+    }
+    #[test]
+    fn test_grow() {
+        let db_name = "target/tmp/test_htx-g.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let params = FileDbParams {
+            htx_init_size: 16,
+            ..Default::default()
+        };
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int_with_params("some_u64_1", params).unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i)).unwrap();
+            }
+            let (ht_size, count) = db_map.ht_size_and_count().unwrap();
+            assert!(ht_size > 16 && ht_size % 16 == 0, "{} {}", ht_size, count);
+            assert!(count * 1000 < ht_size * 500, "{} {}", count, ht_size);
+            // the count is the same as the filling rate
+            let (filled, _per_mill) = db_map.htx_filling_rate_per_mill().unwrap();
+            assert_eq!(filled, count);
+            for i in (0..LOOP_MAX).step_by(2) {
+                db_map.delete(&i).unwrap();
+            }
+            let (_ht_size, count) = db_map.ht_size_and_count().unwrap();
+            let (filled, _per_mill) = db_map.htx_filling_rate_per_mill().unwrap();
+            assert_eq!(filled, count);
+            db_map.sync_all().unwrap();
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        let (ht_size, _count) = db_map.ht_size_and_count().unwrap();
+        assert!(ht_size > 16);
+        assert!(db_map.is_mst_valid().unwrap());
+        for i in 0..LOOP_MAX {
+            let expected = if i % 2 == 0 {
+                None
+            } else {
+                Some(format!("value{}", i))
+            };
+            assert_eq!(db_map.get_string(&i).unwrap(), expected);
+        }
+    }
+    #[test]
+    fn test_grow_in_batch() {
+        let db_name = "target/tmp/test_htx-b.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let params = FileDbParams {
+            htx_init_size: 16,
+            ..Default::default()
+        };
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db
+            .db_map_int_with_params("some_u64_1", params.clone())
+            .unwrap();
+        // the batch crosses the threshold many times
+        let mut batch = WriteBatch::new();
+        for i in 0..LOOP_MAX {
+            batch.put_string(&i, &format!("value{}", i));
+        }
+        db_map.write_batch(batch).unwrap();
+        let (ht_size, count) = db_map.ht_size_and_count().unwrap();
+        assert!(ht_size >= LOOP_MAX * 2, "{} {}", ht_size, count);
+        assert!(count * 1000 < ht_size * 500, "{} {}", count, ht_size);
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+        // and the transaction
+        let _ = db.db_map_int_with_params("some_u64_2", params).unwrap();
+        db.transaction(|tx| {
+            let mut db_map = tx.db_map_int("some_u64_2")?;
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i))?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        let mut db_map = db.db_map_int("some_u64_2").unwrap();
+        let (ht_size, count) = db_map.ht_size_and_count().unwrap();
+        assert!(ht_size >= LOOP_MAX * 2, "{} {}", ht_size, count);
+        assert!(db_map.is_mst_valid().unwrap());
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+        assert!(db.verify().unwrap().is_ok());
+    }
+    #[test]
+    fn test_use_htx_off() {
+        let db_name = "target/tmp/test_htx-o.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let htx_path = Path::new(db_name).join("some_string_1.htx");
+        let params = FileDbParams {
+            use_htx: false,
+            ..Default::default()
+        };
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db
+                .db_map_string_with_params("some_string_1", params.clone())
+                .unwrap();
+            for i in 0..LOOP_MAX {
+                db_map
+                    .put_string(&format!("key{}", i), &format!("value{}", i))
+                    .unwrap();
+            }
+            db_map.delete("key1").unwrap();
+            assert_eq!(db_map.get_string("key1").unwrap(), None);
+            assert_eq!(db_map.get_string("key2").unwrap(), Some("value2".into()));
+            assert_eq!(db_map.ht_size_and_count().unwrap(), (0, 0));
+            db_map.sync_all().unwrap();
+        }
+        assert!(!htx_path.exists());
+        // the htx is turned on, it is built from the index tree
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            assert!(htx_path.exists());
+            let (_ht_size, count) = db_map.ht_size_and_count().unwrap();
+            assert!(count > 0);
+            assert_eq!(db_map.get_string("key1").unwrap(), None);
+            assert_eq!(db_map.get_string("key3").unwrap(), Some("value3".into()));
+        }
+        // the htx is turned off again, the htx file is removed
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db
+            .db_map_string_with_params("some_string_1", params)
+            .unwrap();
+        assert!(!htx_path.exists());
+        db_map.put_string("key3", "value3-new").unwrap();
+        assert_eq!(
+            db_map.get_string("key3").unwrap(),
+            Some("value3-new".into())
+        );
+        assert!(db_map.is_mst_valid().unwrap());
+    }
+    #[test]
+    fn test_legacy_count() {
+        let db_name = "target/tmp/test_htx-l.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let htx_path = Path::new(db_name).join("some_u64_1.htx");
+        let header_u64 = |offset: usize| {
+            let buf = std::fs::read(&htx_path).unwrap();
+            u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+        };
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i)).unwrap();
+            }
+            db_map.sync_all().unwrap();
+            let (_ht_size, count) = db_map.ht_size_and_count().unwrap();
+            // the count in memory is written by the sync
+            assert_eq!(header_u64(24), count);
+        }
+        // the file before the version has the count of zero
+        let mut buf = std::fs::read(&htx_path).unwrap();
+        buf[24..32].copy_from_slice(&[0; 8]);
+        buf[48..56].copy_from_slice(&[0; 8]);
+        std::fs::write(&htx_path, buf).unwrap();
+        let count = {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let db_map = db.db_map_int("some_u64_1").unwrap();
+            let (_ht_size, count) = db_map.ht_size_and_count().unwrap();
+            let (filled, _per_mill) = db_map.htx_filling_rate_per_mill().unwrap();
+            assert!(count > 0);
+            assert_eq!(filled, count);
+            count
+        };
+        // the count is written on closing
+        assert_eq!(header_u64(24), count);
+    }
+}
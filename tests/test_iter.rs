@@ -1,5 +1,7 @@
 mod test_iter {
-    use siamesedb::{DbBytes, DbInt, DbMap, DbString};
+    use siamesedb::filedb::{FileDbMapDbBytes, FileDbMapDbString};
+    use siamesedb::{DbBytes, DbInt, DbMap, DbString, DbXxx};
+    use std::ops::Bound;
     //
     fn basic_test_map_string<T: DbMap<DbString>>(db_map: &mut T) {
         // insert
@@ -334,6 +336,296 @@ mod test_iter {
         //
         //db_map.sync_data().unwrap();
     }
+    fn range_test_map_string<T: DbMap<DbString>>(db_map: &mut T) {
+        #[rustfmt::skip]
+        const LOOP_MAX: i32 = if cfg!(miri) { 10 } else { 1000 };
+        // insert
+        for i in 0..LOOP_MAX {
+            let key = format!("key{:04}", i);
+            let value = format!("value{}", i);
+            db_map.put_string(&key, &value).unwrap();
+        }
+        // range: included .. excluded
+        let start: DbString = "key0003".into();
+        let end: DbString = "key0007".into();
+        let keys: Vec<DbString> = db_map.range(start..end).map(|(k, _)| k).collect();
+        let expected: Vec<DbString> = (3..7).map(|i| format!("key{:04}", i).into()).collect();
+        assert_eq!(keys, expected);
+        // range: included ..= included
+        let start: DbString = "key0003".into();
+        let end: DbString = "key0007".into();
+        let mut iter = db_map.range(start..=end);
+        for i in 3..=7 {
+            let key = format!("key{:04}", i);
+            let value = format!("value{}", i);
+            assert_eq!(iter.next(), Some((key.into(), value.as_bytes().to_vec())));
+        }
+        assert_eq!(iter.next(), None);
+        // range: excluded .. unbounded
+        let start: DbString = "key0990".into();
+        let keys: Vec<DbString> = db_map
+            .range((Bound::Excluded(start), Bound::Unbounded))
+            .map(|(k, _)| k)
+            .collect();
+        let expected: Vec<DbString> = (991..LOOP_MAX)
+            .map(|i| format!("key{:04}", i).into())
+            .collect();
+        assert_eq!(keys, expected);
+        // range: bounds between keys
+        let start: DbString = "key0100a".into();
+        let end: DbString = "key0104a".into();
+        let keys: Vec<DbString> = db_map.range(start..end).map(|(k, _)| k).collect();
+        let expected: Vec<DbString> = (101..=104)
+            .filter(|&i| i < LOOP_MAX)
+            .map(|i| format!("key{:04}", i).into())
+            .collect();
+        assert_eq!(keys, expected);
+        // range: unbounded
+        assert_eq!(db_map.range(..).count(), LOOP_MAX as usize);
+        // range: empty
+        let start: DbString = "key0500".into();
+        let end: DbString = "key0500".into();
+        assert_eq!(db_map.range(start..end).next(), None);
+        let start: DbString = "zzz".into();
+        assert_eq!(db_map.range(start..).next(), None);
+    }
+    fn range_test_map_dbint<T: DbMap<DbInt>>(db_map: &mut T) {
+        #[rustfmt::skip]
+        const LOOP_MAX: u64 = if cfg!(miri) { 10 } else { 1000 };
+        // insert
+        for i in 0..LOOP_MAX {
+            let key = 10000u64 + i * 10;
+            let value = format!("value{}", i);
+            db_map.put_string(&key, &value).unwrap();
+        }
+        // range: included .. excluded
+        let start: DbInt = 10030.into();
+        let end: DbInt = 10070.into();
+        let keys: Vec<DbInt> = db_map.range(start..end).map(|(k, _)| k).collect();
+        let expected: Vec<DbInt> = (3..7).map(|i| (10000u64 + i * 10).into()).collect();
+        assert_eq!(keys, expected);
+        // range: unbounded ..= included
+        let end: DbInt = 10025.into();
+        let mut iter = db_map.range(..=end);
+        assert_eq!(iter.next(), Some((10000.into(), b"value0".to_vec())));
+        assert_eq!(iter.next(), Some((10010.into(), b"value1".to_vec())));
+        assert_eq!(iter.next(), Some((10020.into(), b"value2".to_vec())));
+        assert_eq!(iter.next(), None);
+        // range: excluded ..= included
+        let start: DbInt = 15000.into();
+        let end: DbInt = 15050.into();
+        let keys: Vec<DbInt> = db_map
+            .range((Bound::Excluded(start), Bound::Included(end)))
+            .map(|(k, _)| k)
+            .collect();
+        let expected: Vec<DbInt> = (501..=505)
+            .filter(|&i| i < LOOP_MAX)
+            .map(|i| (10000u64 + i * 10).into())
+            .collect();
+        assert_eq!(keys, expected);
+    }
+    fn range_test_map_bytes<T: DbMap<DbBytes>>(db_map: &mut T) {
+        #[rustfmt::skip]
+        const LOOP_MAX: i32 = if cfg!(miri) { 10 } else { 1000 };
+        // insert
+        for i in 0..LOOP_MAX {
+            let key = format!("key{:04}", i);
+            let value = format!("value{}", i);
+            db_map.put_string(&key, &value).unwrap();
+        }
+        // range: included ..
+        let start: DbBytes = b"key0995".into();
+        let keys: Vec<DbBytes> = db_map.range(start..).map(|(k, _)| k).collect();
+        let expected: Vec<DbBytes> = (995..LOOP_MAX)
+            .map(|i| format!("key{:04}", i).into())
+            .collect();
+        assert_eq!(keys, expected);
+        // range: .. excluded
+        let end: DbBytes = b"key0003".into();
+        let keys: Vec<DbBytes> = db_map.range(..end).map(|(k, _)| k).collect();
+        let expected: Vec<DbBytes> = (0..3).map(|i| format!("key{:04}", i).into()).collect();
+        assert_eq!(keys, expected);
+    }
+    fn rev_test_map_string<T: DbMap<DbString>>(db_map: &mut T) {
+        #[rustfmt::skip]
+        const LOOP_MAX: i32 = if cfg!(miri) { 10 } else { 1000 };
+        // insert
+        for i in 0..LOOP_MAX {
+            let key = format!("key{:04}", i);
+            let value = format!("value{}", i);
+            db_map.put_string(&key, &value).unwrap();
+        }
+        // reverse iterator
+        let mut iter = db_map.iter_mut().rev();
+        for i in (0..LOOP_MAX).rev() {
+            let key = format!("key{:04}", i);
+            let value = format!("value{}", i);
+            assert_eq!(iter.next(), Some((key.into(), value.as_bytes().to_vec())));
+        }
+        assert_eq!(iter.next(), None);
+        // the newest 3 entries
+        let keys: Vec<DbString> = db_map.iter().rev().take(3).map(|(k, _)| k).collect();
+        let expected: Vec<DbString> = (0..LOOP_MAX)
+            .rev()
+            .take(3)
+            .map(|i| format!("key{:04}", i).into())
+            .collect();
+        assert_eq!(keys, expected);
+        // meet in the middle
+        let mut iter = db_map.iter();
+        let mut count = 0;
+        while let Some((k, _)) = iter.next() {
+            count += 1;
+            if let Some((k_back, _)) = iter.next_back() {
+                count += 1;
+                assert!(k < k_back);
+            }
+        }
+        assert_eq!(count, LOOP_MAX);
+        assert_eq!(iter.next_back(), None);
+        // range
+        let start: DbString = "key0003".into();
+        let end: DbString = "key0007".into();
+        let keys: Vec<DbString> = db_map.range(start..end).rev().map(|(k, _)| k).collect();
+        let expected: Vec<DbString> = (3..7)
+            .rev()
+            .map(|i| format!("key{:04}", i).into())
+            .collect();
+        assert_eq!(keys, expected);
+        let start: DbString = "key0003a".into();
+        let end: DbString = "key0007".into();
+        let keys: Vec<DbString> = db_map.range(start..=end).rev().map(|(k, _)| k).collect();
+        let expected: Vec<DbString> = (4..=7)
+            .rev()
+            .map(|i| format!("key{:04}", i).into())
+            .collect();
+        assert_eq!(keys, expected);
+        let start: DbString = "key0003".into();
+        let end: DbString = "key0007".into();
+        let mut iter = db_map.range((Bound::Excluded(start), Bound::Excluded(end)));
+        assert_eq!(
+            iter.next_back(),
+            Some(("key0006".into(), b"value6".to_vec()))
+        );
+        assert_eq!(iter.next(), Some(("key0004".into(), b"value4".to_vec())));
+        assert_eq!(
+            iter.next_back(),
+            Some(("key0005".into(), b"value5".to_vec()))
+        );
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+        // forward to the end, then reverse
+        let mut iter = db_map.iter();
+        for _ in iter.by_ref() {}
+        assert_eq!(iter.next_back(), None);
+    }
+    fn rev_test_map_dbint<T: DbMap<DbInt>>(db_map: &mut T) {
+        #[rustfmt::skip]
+        const LOOP_MAX: u64 = if cfg!(miri) { 10 } else { 1000 };
+        // insert
+        for i in 0..LOOP_MAX {
+            let key = 10000u64 + i * 10;
+            let value = format!("value{}", i);
+            db_map.put_string(&key, &value).unwrap();
+        }
+        // reverse iterator
+        for (i, (k, v)) in (0..LOOP_MAX).rev().zip(db_map.iter().rev()) {
+            let key = 10000u64 + i * 10;
+            let value = format!("value{}", i);
+            assert_eq!(k, key.into());
+            assert_eq!(v, value.as_bytes().to_vec());
+        }
+        assert_eq!(db_map.iter().rev().count(), LOOP_MAX as usize);
+        // range
+        let end: DbInt = 10025.into();
+        let mut iter = db_map.range(..end).rev();
+        assert_eq!(iter.next(), Some((10020.into(), b"value2".to_vec())));
+        assert_eq!(iter.next(), Some((10010.into(), b"value1".to_vec())));
+        assert_eq!(iter.next(), Some((10000.into(), b"value0".to_vec())));
+        assert_eq!(iter.next(), None);
+    }
+    fn rev_test_map_bytes<T: DbMap<DbBytes>>(db_map: &mut T) {
+        #[rustfmt::skip]
+        const LOOP_MAX: i32 = if cfg!(miri) { 10 } else { 1000 };
+        // insert
+        for i in 0..LOOP_MAX {
+            let key = format!("key{:04}", i);
+            let value = format!("value{}", i);
+            db_map.put_string(&key, &value).unwrap();
+        }
+        // reverse iterator
+        let keys: Vec<DbBytes> = db_map.iter_mut().rev().map(|(k, _)| k).collect();
+        let expected: Vec<DbBytes> = (0..LOOP_MAX)
+            .rev()
+            .map(|i| format!("key{:04}", i).into())
+            .collect();
+        assert_eq!(keys, expected);
+    }
+    fn prefix_test_map_string(db_map: &mut FileDbMapDbString) {
+        #[rustfmt::skip]
+        const LOOP_MAX: i32 = if cfg!(miri) { 10 } else { 300 };
+        // insert
+        for i in 0..LOOP_MAX {
+            let key = format!("user/{}/settings", i);
+            let value = format!("value{}", i);
+            db_map.put_string(&key, &value).unwrap();
+            let key = format!("user/{}/profile", i);
+            db_map.put_string(&key, &value).unwrap();
+        }
+        db_map.put_string("user", "value").unwrap();
+        db_map.put_string("user0", "value").unwrap();
+        db_map.put_string("users/1", "value").unwrap();
+        // prefix
+        let keys: Vec<DbString> = db_map.prefix_iter("user/1/").map(|(k, _)| k).collect();
+        let expected: Vec<DbString> = vec!["user/1/profile".into(), "user/1/settings".into()];
+        assert_eq!(keys, expected);
+        let mut iter = db_map.prefix_iter("user/2/s");
+        assert_eq!(
+            iter.next(),
+            Some(("user/2/settings".into(), b"value2".to_vec()))
+        );
+        assert_eq!(iter.next(), None);
+        // all keys under the path
+        assert_eq!(db_map.prefix_iter("user/").count(), 2 * LOOP_MAX as usize);
+        assert_eq!(
+            db_map.prefix_iter("user").count(),
+            2 * LOOP_MAX as usize + 3
+        );
+        // reverse
+        let keys: Vec<DbString> = db_map
+            .prefix_iter("user/1/")
+            .rev()
+            .map(|(k, _)| k)
+            .collect();
+        let expected: Vec<DbString> = vec!["user/1/settings".into(), "user/1/profile".into()];
+        assert_eq!(keys, expected);
+        // nothing
+        assert_eq!(db_map.prefix_iter("user/1/x").next(), None);
+        assert_eq!(db_map.prefix_iter("zzz").next(), None);
+        // empty prefix
+        assert_eq!(db_map.prefix_iter("").count(), 2 * LOOP_MAX as usize + 3);
+    }
+    fn prefix_test_map_bytes(db_map: &mut FileDbMapDbBytes) {
+        // insert
+        db_map.put_string(b"\x01\xfe", "value1").unwrap();
+        db_map.put_string(b"\x01\xff", "value2").unwrap();
+        db_map.put_string(b"\x01\xff\x00", "value3").unwrap();
+        db_map.put_string(b"\x01\xff\xff", "value4").unwrap();
+        db_map.put_string(b"\x02", "value5").unwrap();
+        db_map.put_string(b"\xff\xff", "value6").unwrap();
+        // prefix
+        let keys: Vec<DbBytes> = db_map.prefix_iter(b"\x01\xff").map(|(k, _)| k).collect();
+        let expected: Vec<DbBytes> = vec![
+            b"\x01\xff".into(),
+            b"\x01\xff\x00".into(),
+            b"\x01\xff\xff".into(),
+        ];
+        assert_eq!(keys, expected);
+        let keys: Vec<DbBytes> = db_map.prefix_iter(b"\xff").map(|(k, _)| k).collect();
+        let expected: Vec<DbBytes> = vec![b"\xff\xff".into()];
+        assert_eq!(keys, expected);
+        assert_eq!(db_map.prefix_iter(b"\x01").count(), 4);
+    }
     //
     #[test]
     fn test_file_map_string() {
@@ -362,4 +654,75 @@ mod test_iter {
         basic_test_map_bytes(&mut db_map);
         medium_test_map_bytes(&mut db_map);
     }
+    //
+    #[test]
+    fn test_file_map_string_range() {
+        let db_name = "target/tmp/test_iter-range-s.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        range_test_map_string(&mut db_map);
+    }
+    #[test]
+    fn test_file_map_dbint_range() {
+        let db_name = "target/tmp/test_iter-range-u.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        range_test_map_dbint(&mut db_map);
+    }
+    #[test]
+    fn test_file_map_bytes_range() {
+        let db_name = "target/tmp/test_iter-range-b.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+        range_test_map_bytes(&mut db_map);
+    }
+    //
+    #[test]
+    fn test_file_map_string_rev() {
+        let db_name = "target/tmp/test_iter-rev-s.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        rev_test_map_string(&mut db_map);
+    }
+    #[test]
+    fn test_file_map_dbint_rev() {
+        let db_name = "target/tmp/test_iter-rev-u.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        rev_test_map_dbint(&mut db_map);
+    }
+    #[test]
+    fn test_file_map_bytes_rev() {
+        let db_name = "target/tmp/test_iter-rev-b.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+        rev_test_map_bytes(&mut db_map);
+        // into iter
+        let last = db_map.iter().next_back();
+        assert!(last.is_some());
+        assert_eq!(db_map.into_iter().next_back(), last);
+    }
+    //
+    #[test]
+    fn test_file_map_string_prefix() {
+        let db_name = "target/tmp/test_iter-prefix-s.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        prefix_test_map_string(&mut db_map);
+    }
+    #[test]
+    fn test_file_map_bytes_prefix() {
+        let db_name = "target/tmp/test_iter-prefix-b.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+        prefix_test_map_bytes(&mut db_map);
+    }
 }
@@ -0,0 +1,49 @@
+#[cfg(unix)]
+mod test_lock {
+    use siamesedb::filedb::{FileDb, FileDbLockMode};
+    use siamesedb::DbXxx;
+    use std::io::ErrorKind;
+    //
+    #[test]
+    fn test_exclusive() {
+        let db_name = "target/tmp/test_lock-e.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let _db = siamesedb::open_file(db_name).unwrap();
+            let err = siamesedb::open_file(db_name).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::WouldBlock);
+            let err = FileDb::open_with_lock_mode(db_name, FileDbLockMode::Shared).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        }
+        // the lock is released
+        let _db = siamesedb::open_file(db_name).unwrap();
+    }
+    #[test]
+    fn test_shared() {
+        let db_name = "target/tmp/test_lock-s.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        // the shared lock does not create the database
+        let err = FileDb::open_with_lock_mode(db_name, FileDbLockMode::Shared).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            db_map.put_string(&1, "value1").unwrap();
+            db.sync_all().unwrap();
+        }
+        {
+            let db1 = FileDb::open_with_lock_mode(db_name, FileDbLockMode::Shared).unwrap();
+            let db2 = FileDb::open_with_lock_mode(db_name, FileDbLockMode::Shared).unwrap();
+            let err = siamesedb::open_file(db_name).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::WouldBlock);
+            // the shared lock is for the readers
+            assert!(db1.is_read_only());
+            let mut db_map = db2.db_map_int("some_u64_1").unwrap();
+            assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+            let err = db_map.put_string(&2, "value2").unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        }
+        // the lock is released
+        let _db = siamesedb::open_file(db_name).unwrap();
+    }
+}
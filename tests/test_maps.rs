@@ -0,0 +1,159 @@
+mod test_maps {
+    use siamesedb::filedb::DbMapKeyKind;
+    use siamesedb::{DbXxx, DbXxxBase};
+    //
+    #[test]
+    fn test_list_maps() {
+        let db_name = "target/tmp/test_maps-l.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            assert_eq!(db.list_maps().unwrap(), vec![]);
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            db_map.put_string(&1, "value1").unwrap();
+            // not yet synced
+            assert_eq!(
+                db.list_maps().unwrap(),
+                vec![
+                    ("some_string_1".to_string(), DbMapKeyKind::DbString),
+                    ("some_u64_1".to_string(), DbMapKeyKind::DbInt),
+                ]
+            );
+            let _db_map = db.db_map_bytes("some_bytes_1").unwrap();
+            db.sync_all().unwrap();
+        }
+        // not yet opened
+        let db = siamesedb::open_file(db_name).unwrap();
+        assert_eq!(
+            db.list_maps().unwrap(),
+            vec![
+                ("some_bytes_1".to_string(), DbMapKeyKind::DbBytes),
+                ("some_string_1".to_string(), DbMapKeyKind::DbString),
+                ("some_u64_1".to_string(), DbMapKeyKind::DbInt),
+            ]
+        );
+    }
+    #[test]
+    fn test_drop_map() {
+        let db_name = "target/tmp/test_maps-d.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.sync_all().unwrap();
+            let mut db_map = db.db_map_string("some_string_2").unwrap();
+            db_map.put_string("key2", "value2").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        // drop the map that is not opened
+        db.drop_map("some_string_1").unwrap();
+        for ext in ["key", "val", "idx", "htx"] {
+            let path = format!("{}/some_string_1.{}", db_name, ext);
+            assert!(!std::path::Path::new(&path).exists(), "{}", path);
+        }
+        // drop the opened map
+        let db_map = db.db_map_string("some_string_2").unwrap();
+        let err = db.drop_map("some_string_2").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+        drop(db_map);
+        db.drop_map("some_string_2").unwrap();
+        assert_eq!(db.list_maps().unwrap(), vec![]);
+        // not found
+        let err = db.drop_map("some_string_2").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+        // re-create with the same name
+        let mut db_map = db.db_map_string("some_string_2").unwrap();
+        assert_eq!(db_map.get_string("key2").unwrap(), None);
+    }
+    #[test]
+    fn test_rename_map() {
+        let db_name = "target/tmp/test_maps-r.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            db_map.put_string(&1, "value1").unwrap();
+            db_map.sync_all().unwrap();
+            let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+            db_map.put_string(b"key1", "value1").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            // rename the map that is not opened
+            db.rename_map("some_u64_1", "some_u64_2").unwrap();
+            // rename the opened map
+            let db_map = db.db_map_bytes("some_bytes_1").unwrap();
+            let err = db.rename_map("some_bytes_1", "some_bytes_2").unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+            drop(db_map);
+            db.rename_map("some_bytes_1", "some_bytes_2").unwrap();
+            let mut db_map = db.db_map_bytes("some_bytes_2").unwrap();
+            db_map.put_string(b"key2", "value2").unwrap();
+            db_map.sync_all().unwrap();
+            // errors
+            let err = db.rename_map("some_u64_1", "some_u64_3").unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+            let err = db.rename_map("some_u64_2", "some_bytes_2").unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        assert_eq!(
+            db.list_maps().unwrap(),
+            vec![
+                ("some_bytes_2".to_string(), DbMapKeyKind::DbBytes),
+                ("some_u64_2".to_string(), DbMapKeyKind::DbInt),
+            ]
+        );
+        let mut db_map = db.db_map_int("some_u64_2").unwrap();
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+        let mut db_map = db.db_map_bytes("some_bytes_2").unwrap();
+        assert_eq!(
+            db_map.get_string(b"key1").unwrap(),
+            Some("value1".to_string())
+        );
+        assert_eq!(
+            db_map.get_string(b"key2").unwrap(),
+            Some("value2".to_string())
+        );
+    }
+    #[test]
+    fn test_rename_rollback() {
+        let db_name = "target/tmp/test_maps-b.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        db_map.put_string(&1, "value1").unwrap();
+        db_map.sync_all().unwrap();
+        drop(db_map);
+        // the key file is renamed the last, and it fails
+        std::fs::create_dir_all(format!("{}/some_u64_2.key/dir", db_name)).unwrap();
+        let _ = db.rename_map("some_u64_1", "some_u64_2").unwrap_err();
+        for ext in ["key", "val", "idx", "htx"] {
+            let path = format!("{}/some_u64_1.{}", db_name, ext);
+            assert!(std::path::Path::new(&path).is_file(), "{}", path);
+        }
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+    }
+    #[test]
+    fn test_invalid_name() {
+        let db_name = "target/tmp/test_maps-n.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let _ = db.db_map_string("some_string_1").unwrap();
+        for name in ["", "../x", "a/b", "a\\b", ".."] {
+            let err = db.db_map_string(name).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", name);
+            let err = db.drop_map(name).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", name);
+            let err = db.rename_map("some_string_1", name).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", name);
+        }
+        assert!(!std::path::Path::new("target/tmp/x.key").exists());
+    }
+}
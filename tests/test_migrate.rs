@@ -0,0 +1,153 @@
+mod test_migrate {
+    use siamesedb::filedb::{CheckFileDbMap, FileDb, FileDbParams, IntEncoding};
+    use siamesedb::{DbXxx, Error};
+    use std::convert::TryInto;
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 1000;
+    //
+    fn header_u32(db_name: &str, file_name: &str, offset: usize) -> u32 {
+        let buf = std::fs::read(Path::new(db_name).join(file_name)).unwrap();
+        u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+    }
+    fn make_db(db_name: &str) {
+        let _ = std::fs::remove_dir_all(db_name);
+        let _ = std::fs::remove_dir_all(format!("{}.migrate", db_name));
+        let _ = std::fs::remove_dir_all(format!("{}.migrate-old", db_name));
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        for i in 0..LOOP_MAX {
+            db_map
+                .put_string(&format!("key{}", i), &format!("value{}", i))
+                .unwrap();
+        }
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        for i in 0..LOOP_MAX {
+            db_map.put_string(&i, &format!("value{}", i)).unwrap();
+        }
+        for i in (0..LOOP_MAX).step_by(3) {
+            db_map.delete(&i).unwrap();
+        }
+        let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+        db_map.put_string(b"key1", "value1").unwrap();
+        let _ = db.db_map_bytes("empty_bytes_1").unwrap();
+        db.sync_all().unwrap();
+    }
+    fn check_db(db_name: &str) {
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        assert!(db_map.is_balanced().unwrap());
+        for i in 0..LOOP_MAX {
+            assert_eq!(
+                db_map.get_string(&format!("key{}", i)).unwrap(),
+                Some(format!("value{}", i))
+            );
+        }
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        assert!(db_map.is_mst_valid().unwrap());
+        for i in 0..LOOP_MAX {
+            let expected = if i % 3 == 0 {
+                None
+            } else {
+                Some(format!("value{}", i))
+            };
+            assert_eq!(db_map.get_string(&i).unwrap(), expected);
+        }
+        let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+        assert_eq!(
+            db_map.get_string(b"key1").unwrap(),
+            Some("value1".to_string())
+        );
+        assert_eq!(db.list_maps().unwrap().len(), 4);
+        assert!(db.verify().unwrap().is_ok());
+    }
+    //
+    #[test]
+    fn test_migrate() {
+        let db_name = "target/tmp/test_migrate-m.siamesedb";
+        make_db(db_name);
+        let params = FileDbParams {
+            int_encoding: Some(IntEncoding::Vu64),
+            node_slots: 8,
+            checksum: true,
+            ..Default::default()
+        };
+        let stats = FileDb::migrate(db_name, params).unwrap();
+        assert_eq!(stats.map_count, 4);
+        assert_eq!(stats.record_count, LOOP_MAX + LOOP_MAX * 2 / 3 + 1);
+        // the files have the format of the params
+        assert_eq!(header_u32(db_name, "some_string_1.key", 164) & 0x0F, 3);
+        assert_eq!(header_u32(db_name, "some_u64_1.idx", 104), 8);
+        check_db(db_name);
+        assert!(!Path::new(&format!("{}.migrate", db_name)).exists());
+        assert!(!Path::new(&format!("{}.migrate-old", db_name)).exists());
+    }
+    #[test]
+    fn test_legacy() {
+        let db_name = "target/tmp/test_migrate-l.siamesedb";
+        make_db(db_name);
+        // the files before the version
+        for (ext, offset) in [("key", 160), ("val", 184), ("idx", 112)] {
+            let path = Path::new(db_name).join(format!("some_string_1.{}", ext));
+            let mut buf = std::fs::read(&path).unwrap();
+            buf[offset..offset + 8].copy_from_slice(&[0; 8]);
+            std::fs::write(&path, buf).unwrap();
+        }
+        let _ = FileDb::migrate(db_name, FileDbParams::default()).unwrap();
+        assert_eq!(header_u32(db_name, "some_string_1.key", 160), 1);
+        assert_eq!(header_u32(db_name, "some_string_1.val", 184), 1);
+        check_db(db_name);
+    }
+    #[test]
+    fn test_locked() {
+        let db_name = "target/tmp/test_migrate-k.siamesedb";
+        make_db(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        match FileDb::migrate(db_name, FileDbParams::default()).unwrap_err() {
+            Error::Locked(_) => (),
+            err => panic!("unexpected error: {:?}", err),
+        }
+        drop(db);
+        check_db(db_name);
+    }
+    #[test]
+    fn test_interrupted() {
+        let db_name = "target/tmp/test_migrate-i.siamesedb";
+        make_db(db_name);
+        let old_name = format!("{}.migrate-old", db_name);
+        std::fs::create_dir_all(&old_name).unwrap();
+        let err = FileDb::migrate(db_name, FileDbParams::default()).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+        std::fs::remove_dir_all(&old_name).unwrap();
+        // the fresh database of the interrupted migration is replaced
+        std::fs::create_dir_all(format!("{}.migrate", db_name)).unwrap();
+        let stats = FileDb::migrate(db_name, FileDbParams::default()).unwrap();
+        assert_eq!(stats.map_count, 4);
+        check_db(db_name);
+    }
+    #[test]
+    fn test_interrupted_swap() {
+        let db_name = "target/tmp/test_migrate-s.siamesedb";
+        let tmp_name = format!("{}.migrate", db_name);
+        let old_name = format!("{}.migrate-old", db_name);
+        make_db(db_name);
+        make_db(&tmp_name);
+        std::fs::rename(db_name, &old_name).unwrap();
+        // the running migration holds the lock of the old database
+        let old_db = siamesedb::open_file(&old_name).unwrap();
+        match siamesedb::open_file(db_name).unwrap_err() {
+            Error::Locked(_) => (),
+            err => panic!("unexpected error: {:?}", err),
+        }
+        assert!(!Path::new(db_name).exists());
+        drop(old_db);
+        // the swap is finished by the opening
+        check_db(db_name);
+        assert!(!Path::new(&tmp_name).exists());
+        assert!(!Path::new(&old_name).exists());
+        // without the fresh database, the old one is put back
+        std::fs::rename(db_name, &old_name).unwrap();
+        check_db(db_name);
+        assert!(!Path::new(&old_name).exists());
+    }
+}
@@ -0,0 +1,91 @@
+mod test_node_slots {
+    use siamesedb::filedb::{CheckFileDbMap, FileDbParams};
+    use siamesedb::{DbXxx, DbXxxBase, Error};
+    use std::convert::TryInto;
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 2000;
+    //
+    fn slots_params(node_slots: u16) -> FileDbParams {
+        FileDbParams {
+            node_slots,
+            ..Default::default()
+        }
+    }
+    fn header_node_slots(db_name: &str, map_name: &str) -> u64 {
+        let buf = std::fs::read(Path::new(db_name).join(format!("{}.idx", map_name))).unwrap();
+        u64::from_le_bytes(buf[104..112].try_into().unwrap())
+    }
+    //
+    #[test]
+    fn test_node_slots() {
+        let db_name = "target/tmp/test_node_slots-s.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            for (name, node_slots) in [("small", 4), ("wide", 256)] {
+                let mut db_map = db
+                    .db_map_int_with_params(name, slots_params(node_slots))
+                    .unwrap();
+                for i in 0..LOOP_MAX {
+                    db_map.put_string(&i, &format!("value{}", i)).unwrap();
+                }
+                for i in (0..LOOP_MAX).step_by(3) {
+                    db_map.delete(&i).unwrap();
+                }
+                db_map.sync_all().unwrap();
+            }
+        }
+        assert_eq!(header_node_slots(db_name, "small"), 4);
+        assert_eq!(header_node_slots(db_name, "wide"), 256);
+        // the node slots in the header are used, not the params
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut depths = Vec::new();
+        for name in ["small", "wide"] {
+            let mut db_map = db.db_map_int(name).unwrap();
+            assert!(db_map.is_balanced().unwrap());
+            assert!(db_map.is_mst_valid().unwrap());
+            assert!(db_map.is_dense().unwrap());
+            for i in 0..LOOP_MAX {
+                let expected = if i % 3 == 0 {
+                    None
+                } else {
+                    Some(format!("value{}", i))
+                };
+                assert_eq!(db_map.get_string(&i).unwrap(), expected);
+            }
+            db_map.put_string(&0, "value0").unwrap();
+            depths.push(db_map.depth_of_node_tree().unwrap());
+        }
+        assert!(depths[0] > depths[1], "{:?}", depths);
+        assert!(db.verify().unwrap().is_ok());
+    }
+    #[test]
+    fn test_default() {
+        let db_name = "target/tmp/test_node_slots-d.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        db_map.put_string("key1", "value1").unwrap();
+        db_map.sync_all().unwrap();
+        let node_slots = header_node_slots(db_name, "some_string_1");
+        assert!(node_slots >= 4 && node_slots % 2 == 0, "{}", node_slots);
+    }
+    #[test]
+    fn test_invalid() {
+        let db_name = "target/tmp/test_node_slots-i.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        for node_slots in [2, 5, 1024] {
+            match db
+                .db_map_string_with_params("some_string_1", slots_params(node_slots))
+                .unwrap_err()
+            {
+                Error::Io(err) => assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput),
+                err => panic!("unexpected error: {:?}", err),
+            }
+        }
+        // no file is created
+        assert!(!Path::new(db_name).join("some_string_1.key").exists());
+    }
+}
@@ -0,0 +1,97 @@
+mod test_read_only {
+    use siamesedb::{DbMap, DbXxx, DbXxxBase};
+    use std::io::ErrorKind;
+    //
+    #[test]
+    fn test_not_found() {
+        let db_name = "target/tmp/test_read_only-n.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let err = siamesedb::open_file_read_only(db_name).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(!std::path::Path::new(db_name).exists());
+        //
+        let _ = siamesedb::open_file(db_name).unwrap();
+        let db = siamesedb::open_file_read_only(db_name).unwrap();
+        let err = db.db_map_string("some_string_1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::NotFound);
+        assert!(db.list_maps().unwrap().is_empty());
+    }
+    #[test]
+    fn test_read_and_write() {
+        let db_name = "target/tmp/test_read_only-r.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.put_string("key2", "value2").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let db = siamesedb::open_file_read_only(db_name).unwrap();
+        assert!(db.is_read_only());
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        assert!(db_map.is_read_only());
+        assert_eq!(
+            db_map.get_string("key1").unwrap(),
+            Some("value1".to_string())
+        );
+        assert_eq!(db_map.iter().count(), 2);
+        //
+        let err = db_map.put_string("key3", "value3").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        let err = db_map.delete("key1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        let err = db.drop_map("some_string_1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PermissionDenied);
+        db_map.sync_all().unwrap();
+        assert_eq!(db_map.get_string("key3").unwrap(), None);
+        // the shared lock
+        let _db2 = siamesedb::open_file_read_only(db_name).unwrap();
+        #[cfg(unix)]
+        {
+            let err = siamesedb::open_file(db_name).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        }
+    }
+    #[cfg(unix)]
+    #[test]
+    fn test_unwritable() {
+        use std::os::unix::fs::PermissionsExt;
+        let db_name = "target/tmp/test_read_only-w.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let set_mode = |path: &str, mode: u32| {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+        };
+        // the lock file that is read only
+        let lock_name = format!("{}/siamesedb.lock", db_name);
+        set_mode(&lock_name, 0o444);
+        set_mode(db_name, 0o555);
+        {
+            let db = siamesedb::open_file_read_only(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            assert_eq!(
+                db_map.get_string("key1").unwrap(),
+                Some("value1".to_string())
+            );
+        }
+        // the lock file that is not found
+        set_mode(db_name, 0o755);
+        std::fs::remove_file(&lock_name).unwrap();
+        set_mode(db_name, 0o555);
+        {
+            let db = siamesedb::open_file_read_only(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            assert_eq!(
+                db_map.get_string("key1").unwrap(),
+                Some("value1".to_string())
+            );
+        }
+        set_mode(db_name, 0o755);
+    }
+}
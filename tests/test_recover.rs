@@ -0,0 +1,113 @@
+mod test_recover {
+    use siamesedb::filedb::CheckFileDbMap;
+    use siamesedb::{DbXxx, DbXxxBase};
+    use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 200;
+    //
+    fn read_u64_at(path: &Path, offset: u64) -> u64 {
+        let mut file = std::fs::File::open(path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        let mut buf = [0u8; 8];
+        file.read_exact(&mut buf).unwrap();
+        u64::from_le_bytes(buf)
+    }
+    fn write_at(path: &Path, offset: u64, buf: &[u8]) {
+        let mut file = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(buf).unwrap();
+    }
+    //
+    #[test]
+    fn test_dirty_flag() {
+        let db_name = "target/tmp/test_recover-f.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let key_path = Path::new(db_name).join("some_string_1.key");
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        // it is set on the first writing
+        db_map.put_string("key1", "value1").unwrap();
+        assert_ne!(read_u64_at(&key_path, 24), 0);
+        // it is cleared by the sync
+        db_map.sync_all().unwrap();
+        assert_eq!(read_u64_at(&key_path, 24), 0);
+    }
+    #[test]
+    fn test_rebuild_free_list() {
+        let db_name = "target/tmp/test_recover-r.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let counts = {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i)).unwrap();
+            }
+            for i in (0..LOOP_MAX).step_by(2) {
+                db_map.delete(&i).unwrap();
+            }
+            db_map.sync_all().unwrap();
+            (
+                db_map.count_of_free_key_piece().unwrap(),
+                db_map.count_of_free_value_piece().unwrap(),
+                db_map.count_of_free_node().unwrap(),
+            )
+        };
+        assert!(counts.0.iter().any(|&(_, cnt)| cnt > 0));
+        // the free lists are lost, and the dirty flag is left
+        let path = Path::new(db_name);
+        for ext in ["key", "val"] {
+            let file_path = path.join(format!("some_u64_1.{}", ext));
+            write_at(&file_path, 24, &1u64.to_le_bytes());
+            write_at(&file_path, 32, &[0u8; 128]);
+        }
+        let idx_path = path.join("some_u64_1.idx");
+        write_at(&idx_path, 24, &[0u8; 64]);
+        write_at(&idx_path, 88, &1u64.to_le_bytes());
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        assert_eq!(read_u64_at(&path.join("some_u64_1.key"), 24), 0);
+        assert!(db_map.is_balanced().unwrap());
+        assert!(db_map.is_mst_valid().unwrap());
+        assert_eq!(db_map.count_of_free_key_piece().unwrap(), counts.0);
+        assert_eq!(db_map.count_of_free_value_piece().unwrap(), counts.1);
+        // the leaked nodes are free too
+        let free_nodes = db_map.count_of_free_node().unwrap();
+        for (a, b) in free_nodes.iter().zip(counts.2.iter()) {
+            assert!(a.0 == b.0 && a.1 >= b.1);
+        }
+        for i in 0..LOOP_MAX {
+            let expected = if i % 2 == 0 {
+                None
+            } else {
+                Some(format!("value{}", i))
+            };
+            assert_eq!(db_map.get_string(&i).unwrap(), expected);
+        }
+    }
+    #[test]
+    fn test_read_only() {
+        let db_name = "target/tmp/test_recover-o.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let key_path = Path::new(db_name).join("some_string_1.key");
+        write_at(&key_path, 24, &1u64.to_le_bytes());
+        {
+            let db = siamesedb::open_file_read_only(db_name).unwrap();
+            let err = db.db_map_string("some_string_1").unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        assert_eq!(
+            db_map.get_string("key1").unwrap(),
+            Some("value1".to_string())
+        );
+    }
+}
@@ -0,0 +1,199 @@
+mod test_repair {
+    use siamesedb::filedb::{CheckFileDbMap, FileDbParams};
+    use siamesedb::{DbXxx, DbXxxBase};
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 1000;
+    //
+    fn expected_string(i: u64) -> Option<String> {
+        match i % 3 {
+            0 => None,
+            1 => Some("v".repeat(i as usize % 100)),
+            _ => Some(format!("value{}", i)),
+        }
+    }
+    //
+    #[test]
+    fn test_repair_lost_idx() {
+        let db_name = "target/tmp/test_repair-l.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map
+                    .put_string(&format!("key{}", i), &format!("value{}", i))
+                    .unwrap();
+            }
+            for i in (0..LOOP_MAX).step_by(3) {
+                db_map.delete(&format!("key{}", i)).unwrap();
+            }
+            for i in (1..LOOP_MAX).step_by(3) {
+                db_map
+                    .put_string(&format!("key{}", i), &"v".repeat(i as usize % 100))
+                    .unwrap();
+            }
+            db_map.sync_all().unwrap();
+        }
+        std::fs::remove_file(Path::new(db_name).join("some_string_1.idx")).unwrap();
+        let _ = std::fs::remove_file(Path::new(db_name).join("some_string_1.htx"));
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let stats = db.repair_map("some_string_1").unwrap();
+        assert_eq!(stats.key_count, LOOP_MAX - LOOP_MAX / 3 - 1);
+        assert_eq!(stats.lost_count, 0);
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        assert!(db_map.is_balanced().unwrap());
+        assert!(db_map.is_mst_valid().unwrap());
+        for i in 0..LOOP_MAX {
+            assert_eq!(
+                db_map.get_string(&format!("key{}", i)).unwrap(),
+                expected_string(i)
+            );
+        }
+        assert!(db.verify().unwrap().is_ok());
+        // it works as usual after the repair
+        db_map.put_string("key0", "value0").unwrap();
+        assert_eq!(
+            db_map.get_string("key0").unwrap(),
+            Some("value0".to_string())
+        );
+    }
+    #[test]
+    fn test_repair_broken_idx() {
+        let db_name = "target/tmp/test_repair-b.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i)).unwrap();
+            }
+            db_map.sync_all().unwrap();
+        }
+        let idx_path = Path::new(db_name).join("some_u64_1.idx");
+        let mut buf = std::fs::read(&idx_path).unwrap();
+        for b in buf.iter_mut() {
+            *b = 0xFF;
+        }
+        std::fs::write(&idx_path, &buf).unwrap();
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        assert!(db.db_map_int("some_u64_1").is_err());
+        let stats = db.repair_map("some_u64_1").unwrap();
+        assert_eq!(stats.key_count, LOOP_MAX);
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        for i in 0..LOOP_MAX {
+            assert_eq!(db_map.get_string(&i).unwrap(), Some(format!("value{}", i)));
+        }
+    }
+    #[test]
+    fn test_repair_checksum() {
+        let db_name = "target/tmp/test_repair-c.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let params = FileDbParams {
+                checksum: true,
+                ..Default::default()
+            };
+            let mut db_map = db.db_map_bytes_with_params("some_bytes_1", params).unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put(&i.to_be_bytes(), b"value").unwrap();
+            }
+            db_map.sync_all().unwrap();
+        }
+        std::fs::remove_file(Path::new(db_name).join("some_bytes_1.idx")).unwrap();
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let stats = db.repair_map("some_bytes_1").unwrap();
+        assert_eq!(stats.key_count, LOOP_MAX);
+        assert!(db.verify().unwrap().is_ok());
+        // the new idx file has the checksum too
+        let buf = std::fs::read(Path::new(db_name).join("some_bytes_1.idx")).unwrap();
+        assert_eq!(buf[96], 1);
+    }
+    #[test]
+    fn test_repair_in_use() {
+        let db_name = "target/tmp/test_repair-u.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut db_map = db.db_map_int("some_u64_1").unwrap();
+        db_map.put_string(&1, "value1").unwrap();
+        let err = db.repair_map("some_u64_1").unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+        assert_eq!(db_map.get_string(&1).unwrap(), Some("value1".to_string()));
+        drop(db_map);
+        let stats = db.repair_map("some_u64_1").unwrap();
+        assert_eq!(stats.key_count, 1);
+    }
+    #[test]
+    fn test_repair_broken_key() {
+        let db_name = "target/tmp/test_repair-k.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let params = FileDbParams {
+                checksum: true,
+                ..Default::default()
+            };
+            let mut db_map = db
+                .db_map_string_with_params("some_string_1", params)
+                .unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.put_string("the-broken-key", "value2").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let key_path = Path::new(db_name).join("some_string_1.key");
+        let mut buf = std::fs::read(&key_path).unwrap();
+        let pos = buf
+            .windows(14)
+            .position(|w| w == b"the-broken-key")
+            .expect("the key is in the file");
+        buf[pos] ^= 0x01;
+        std::fs::write(&key_path, &buf).unwrap();
+        let idx_path = Path::new(db_name).join("some_string_1.idx");
+        let idx_buf = std::fs::read(&idx_path).unwrap();
+        // the key file can not be read, the idx file is kept
+        let db = siamesedb::open_file(db_name).unwrap();
+        assert!(db.repair_map("some_string_1").is_err());
+        assert_eq!(std::fs::read(&idx_path).unwrap(), idx_buf);
+        let names: Vec<_> = std::fs::read_dir(db_name)
+            .unwrap()
+            .map(|a| a.unwrap().file_name().into_string().unwrap())
+            .collect();
+        assert!(names.iter().all(|a| !a.contains("repair")), "{:?}", names);
+        let mut db_map = db.db_map_string("some_string_1").unwrap();
+        assert_eq!(
+            db_map.get_string("key1").unwrap(),
+            Some("value1".to_string())
+        );
+    }
+    #[test]
+    fn test_repair_invalid_name() {
+        let db_name = "target/tmp/test_repair-n.siamesedb";
+        let outer_name = "target/tmp/test_repair-n.outer";
+        let _ = std::fs::remove_dir_all(db_name);
+        let _ = std::fs::remove_dir_all(outer_name);
+        // the db map beside the database directory
+        {
+            let db = siamesedb::open_file(outer_name).unwrap();
+            let mut db_map = db.db_map_string("x").unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.sync_all().unwrap();
+        }
+        let outer_map = format!(
+            "../{}/x",
+            Path::new(outer_name).file_name().unwrap().to_str().unwrap()
+        );
+        let idx_path = Path::new(outer_name).join("x.idx");
+        let idx_buf = std::fs::read(&idx_path).unwrap();
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        for name in ["", "../x", "a/b", "a\\b", "..", outer_map.as_str()] {
+            let err = db.repair_map(name).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput, "{}", name);
+        }
+        assert_eq!(std::fs::read(&idx_path).unwrap(), idx_buf);
+    }
+}
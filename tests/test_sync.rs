@@ -0,0 +1,132 @@
+#[cfg(feature = "sync")]
+mod test_sync {
+    use siamesedb::filedb::{
+        CheckFileDbMap, FileDb, FileDbMapDbInt, FileDbMapDbString, WriteBatch,
+    };
+    use siamesedb::{DbMap, DbXxx};
+    use std::thread;
+    //
+    const LOOP_MAX: u64 = 500;
+    const THREAD_MAX: u64 = 4;
+    //
+    fn assert_send_sync<T: Send + Sync>() {}
+    //
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<FileDb>();
+        assert_send_sync::<FileDbMapDbString>();
+        assert_send_sync::<FileDbMapDbInt>();
+    }
+    #[test]
+    fn test_threads() {
+        let db_name = "target/tmp/test_sync-t.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        {
+            let mut db_map = db.db_map_int("shared").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i)).unwrap();
+            }
+        }
+        let handles: Vec<_> = (0..THREAD_MAX)
+            .map(|n| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    // the writing to the own db map
+                    let mut own = db.db_map_string(&format!("own{}", n)).unwrap();
+                    // the reading from the shared db map
+                    let mut shared = db.db_map_int("shared").unwrap();
+                    for i in 0..LOOP_MAX {
+                        own.put_string(&format!("key{}", i), &format!("{}-{}", n, i))
+                            .unwrap();
+                        assert_eq!(shared.get_string(&i).unwrap(), Some(format!("value{}", i)));
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        //
+        for n in 0..THREAD_MAX {
+            let mut own = db.db_map_string(&format!("own{}", n)).unwrap();
+            assert!(own.is_mst_valid().unwrap());
+            assert_eq!(own.iter().count(), LOOP_MAX as usize);
+            assert_eq!(own.get_string("key7").unwrap(), Some(format!("{}-7", n)));
+        }
+    }
+    #[test]
+    fn test_shared_reading() {
+        let db_name = "target/tmp/test_sync-r.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        {
+            let mut db_map = db.db_map_int("shared").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i)).unwrap();
+            }
+        }
+        // the threads read the same db map at the same time
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(2));
+        let handles: Vec<_> = (0..2)
+            .map(|n| {
+                let db = db.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    let mut shared = db.db_map_int("shared").unwrap();
+                    barrier.wait();
+                    for j in 0..LOOP_MAX {
+                        let i = if n == 0 { j } else { LOOP_MAX - 1 - j };
+                        assert_eq!(shared.get_string(&i).unwrap(), Some(format!("value{}", i)));
+                    }
+                    let values: Vec<Vec<u8>> = shared.iter().map(|(_k, v)| v).collect();
+                    assert_eq!(values.len(), LOOP_MAX as usize);
+                    let rev_values: Vec<Vec<u8>> = shared.iter().rev().map(|(_k, v)| v).collect();
+                    assert!(values.iter().eq(rev_values.iter().rev()));
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+    #[test]
+    fn test_write_ahead_log() {
+        let db_name = "target/tmp/test_sync-w.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        // the batches and the transactions share the write-ahead log
+        let handles: Vec<_> = (0..THREAD_MAX)
+            .map(|n| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    let name = format!("own{}", n);
+                    let mut own = db.db_map_int(&name).unwrap();
+                    for i in 0..LOOP_MAX / 10 {
+                        if n % 2 == 0 {
+                            let mut batch = WriteBatch::new();
+                            batch.put_string(&i, &format!("{}-{}", n, i));
+                            own.write_batch(batch).unwrap();
+                        } else {
+                            db.transaction(|tx| {
+                                tx.db_map_int(&name)?
+                                    .put_string(&i, &format!("{}-{}", n, i))
+                            })
+                            .unwrap();
+                        }
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(!std::path::Path::new(db_name).join("siamesedb.wal").exists());
+        for n in 0..THREAD_MAX {
+            let mut own = db.db_map_int(&format!("own{}", n)).unwrap();
+            assert!(own.is_mst_valid().unwrap());
+            assert_eq!(own.iter().count(), (LOOP_MAX / 10) as usize);
+            assert_eq!(own.get_string(&7).unwrap(), Some(format!("{}-7", n)));
+        }
+    }
+}
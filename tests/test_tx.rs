@@ -0,0 +1,147 @@
+mod test_tx {
+    use siamesedb::filedb::{CheckFileDbMap, WriteBatch};
+    use siamesedb::{DbMap, DbXxx};
+    use std::io::{Error, ErrorKind};
+    //
+    #[test]
+    fn test_commit() {
+        let db_name = "target/tmp/test_tx-c.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let count = db
+                .transaction(|tx| {
+                    let mut index = tx.db_map_string("index")?;
+                    let mut records = tx.db_map_int("records")?;
+                    for i in 0..100u64 {
+                        records.put_string(&i, &format!("record{}", i))?;
+                        index.put(&format!("name{:03}", i), &i.to_le_bytes())?;
+                    }
+                    // the writing is seen in the transaction
+                    assert_eq!(records.get_string(&5)?, Some("record5".to_string()));
+                    Ok(index.iter().count())
+                })
+                .unwrap();
+            assert_eq!(count, 100);
+            let mut records = db.db_map_int("records").unwrap();
+            assert_eq!(
+                records.get_string(&99).unwrap(),
+                Some("record99".to_string())
+            );
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        let index = db.db_map_string("index").unwrap();
+        let mut records = db.db_map_int("records").unwrap();
+        assert!(index.is_mst_valid().unwrap());
+        assert!(records.is_mst_valid().unwrap());
+        for (k, v) in index.iter() {
+            let mut buf = [0u8; 8];
+            buf.copy_from_slice(&v);
+            let i = u64::from_le_bytes(buf);
+            assert_eq!(k, format!("name{:03}", i).as_str().into());
+            assert_eq!(
+                records.get_string(&i).unwrap(),
+                Some(format!("record{}", i))
+            );
+        }
+    }
+    #[test]
+    fn test_rollback() {
+        let db_name = "target/tmp/test_tx-r.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        {
+            let mut index = db.db_map_string("index").unwrap();
+            index.put_string("name000", "0").unwrap();
+            let mut records = db.db_map_int("records").unwrap();
+            records.put_string(&0, "record0").unwrap();
+        }
+        let err = db
+            .transaction(|tx| {
+                let mut index = tx.db_map_string("index")?;
+                let mut records = tx.db_map_int("records")?;
+                for i in 1..100u64 {
+                    records.put_string(&i, &format!("record{}", i))?;
+                    index.put_string(&format!("name{:03}", i), &i.to_string())?;
+                }
+                records.delete(&0)?;
+                let mut batch = WriteBatch::new();
+                batch.delete("name000");
+                index.write_batch(batch)?;
+                Err::<(), _>(Error::new(ErrorKind::Other, "abort").into())
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "abort");
+        //
+        let mut index = db.db_map_string("index").unwrap();
+        let mut records = db.db_map_int("records").unwrap();
+        assert!(index.is_mst_valid().unwrap());
+        assert!(records.is_mst_valid().unwrap());
+        assert_eq!(index.iter().count(), 1);
+        assert_eq!(records.iter().count(), 1);
+        assert_eq!(index.get_string("name000").unwrap(), Some("0".to_string()));
+        assert_eq!(records.get_string(&0).unwrap(), Some("record0".to_string()));
+        // the db maps are writable after the rollback
+        records.put_string(&1, "record1").unwrap();
+        assert_eq!(records.iter().count(), 2);
+    }
+    #[test]
+    fn test_isolation() {
+        let db_name = "target/tmp/test_tx-i.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut records = db.db_map_int("records").unwrap();
+        records.put_string(&0, "record0").unwrap();
+        let mut stale = None;
+        db.transaction(|tx| {
+            let mut view = tx.db_map_int("records")?;
+            view.put_string(&1, "record1")?;
+            view.delete(&0)?;
+            // the other handle reads the committed data
+            assert_eq!(records.get_string(&0)?, Some("record0".to_string()));
+            assert_eq!(records.get_string(&1)?, None);
+            assert_eq!(records.iter().count(), 1);
+            // the writing of the other handle is rejected
+            let err = records.put_string(&2, "record2").unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::WouldBlock);
+            let err = records.delete(&0).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::WouldBlock);
+            // the same view
+            assert_eq!(
+                tx.db_map_int("records")?.get_string(&1)?,
+                Some("record1".to_string())
+            );
+            stale = Some(view);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(records.get_string(&0).unwrap(), None);
+        assert_eq!(records.get_string(&1).unwrap(), Some("record1".to_string()));
+        records.put_string(&2, "record2").unwrap();
+        assert!(records.is_mst_valid().unwrap());
+        // the view is not valid after the transaction
+        let err = stale.unwrap().put_string(&3, "record3").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        assert_eq!(records.iter().count(), 2);
+    }
+    #[test]
+    fn test_locked_after_rollback() {
+        let db_name = "target/tmp/test_tx-l.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let db = siamesedb::open_file(db_name).unwrap();
+        let mut records = db.db_map_int("records").unwrap();
+        let mut stale = None;
+        let _ = db
+            .transaction(|tx| {
+                let mut view = tx.db_map_int("records")?;
+                view.put_string(&1, "record1")?;
+                stale = Some(view);
+                Err::<(), _>(Error::new(ErrorKind::Other, "abort").into())
+            })
+            .unwrap_err();
+        let err = stale.unwrap().put_string(&1, "record1").unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::WouldBlock);
+        records.put_string(&2, "record2").unwrap();
+        assert_eq!(records.iter().count(), 1);
+    }
+}
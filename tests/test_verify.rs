@@ -0,0 +1,183 @@
+mod test_verify {
+    use siamesedb::filedb::{FileDbParams, VerifyProblemKind};
+    use siamesedb::DbXxx;
+    use std::convert::TryInto;
+    use std::path::Path;
+    //
+    const LOOP_MAX: u64 = 300;
+    //
+    fn write_u64_le(path: &Path, pos: usize, val: u64) {
+        let mut buf = std::fs::read(path).unwrap();
+        buf[pos..pos + 8].copy_from_slice(&val.to_le_bytes());
+        std::fs::write(path, &buf).unwrap();
+    }
+    fn read_u64_le(path: &Path, pos: usize) -> u64 {
+        let buf = std::fs::read(path).unwrap();
+        u64::from_le_bytes(buf[pos..pos + 8].try_into().unwrap())
+    }
+    //
+    #[test]
+    fn test_verify_ok() {
+        let db_name = "target/tmp/test_verify-o.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("some_string_1").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&format!("key{}", i), "value").unwrap();
+            }
+            for i in (0..LOOP_MAX).step_by(3) {
+                db_map.delete(&format!("key{}", i)).unwrap();
+            }
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&i, &format!("value{}", i)).unwrap();
+            }
+            let mut db_map = db.db_map_bytes("some_bytes_1").unwrap();
+            db_map.put(b"key1", b"value1").unwrap();
+            db.sync_all().unwrap();
+        }
+        let db = siamesedb::open_file(db_name).unwrap();
+        let report = db.verify().unwrap();
+        assert_eq!(report.maps.len(), 3);
+        assert!(report.is_ok(), "{}", report);
+    }
+    #[test]
+    fn test_verify_broken() {
+        let db_name = "target/tmp/test_verify-b.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_string("bad_value").unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.put_string("the-broken-key", "value2").unwrap();
+            let mut db_map = db.db_map_string("bad_free").unwrap();
+            for i in 0..LOOP_MAX {
+                db_map.put_string(&format!("key{}", i), "value").unwrap();
+            }
+            for i in 0..LOOP_MAX {
+                db_map.delete(&format!("key{}", i)).unwrap();
+            }
+            let params = FileDbParams {
+                use_htx: true,
+                ..Default::default()
+            };
+            let mut db_map = db.db_map_string_with_params("bad_htx", params).unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db.sync_all().unwrap();
+        }
+        // the value offset follows the key data
+        let key_path = Path::new(db_name).join("bad_value.key");
+        let buf = std::fs::read(&key_path).unwrap();
+        let pos = buf
+            .windows(14)
+            .position(|w| w == b"the-broken-key")
+            .expect("the key is in the file");
+        write_u64_le(&key_path, pos + 14, 3);
+        // the next of the free piece is itself: piece size u32, key len u32
+        let key_path = Path::new(db_name).join("bad_free.key");
+        let head = (0..16)
+            .map(|i| read_u64_le(&key_path, 32 + 8 * i))
+            .find(|&offset| offset != 0)
+            .expect("the free piece is in the file");
+        write_u64_le(&key_path, head as usize + 8, head);
+        // the empty slot of the htx
+        #[cfg(feature = "htx")]
+        {
+            let htx_path = Path::new(db_name).join("bad_htx.htx");
+            let buf = std::fs::read(&htx_path).unwrap();
+            let slot = (128..buf.len())
+                .step_by(8)
+                .find(|&pos| buf[pos..pos + 8] == [0u8; 8])
+                .expect("the empty slot is in the file");
+            write_u64_le(&htx_path, slot, 3);
+        }
+        //
+        let db = siamesedb::open_file(db_name).unwrap();
+        let report = db.verify().unwrap();
+        assert_eq!(report.maps.len(), 3);
+        assert!(!report.is_ok());
+        let kinds = |map: &str| {
+            report
+                .problems
+                .iter()
+                .filter(|a| a.map == map)
+                .map(|a| a.kind)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds("bad_value"), vec![VerifyProblemKind::BadValueOffset]);
+        assert_eq!(kinds("bad_free"), vec![VerifyProblemKind::FreeListCycle]);
+        #[cfg(feature = "htx")]
+        assert_eq!(kinds("bad_htx"), vec![VerifyProblemKind::BadHtxEntry]);
+    }
+    #[test]
+    fn test_verify_not_synced() {
+        let db_name = "target/tmp/test_verify-n.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        let read_files = || {
+            let mut vec: Vec<_> = std::fs::read_dir(db_name)
+                .unwrap()
+                .map(|a| a.unwrap().path())
+                .filter(|a| a.extension().unwrap() != "lock")
+                .map(|a| (a.clone(), std::fs::read(a).unwrap()))
+                .collect();
+            vec.sort();
+            vec
+        };
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let mut db_map = db.db_map_int("some_u64_1").unwrap();
+            db_map.put_string(&1, "value1").unwrap();
+            db.sync_all().unwrap();
+            // the writing that is not synced
+            db_map.put_string(&2, "value2").unwrap();
+        }
+        let files = read_files();
+        let db = siamesedb::open_file(db_name).unwrap();
+        let report = db.verify().unwrap();
+        let kinds: Vec<_> = report.problems.iter().map(|a| a.kind).collect();
+        assert!(kinds.contains(&VerifyProblemKind::NotSynced), "{}", report);
+        // the db map is not recovered by the verification
+        assert!(files == read_files());
+        let _ = db.db_map_int("some_u64_1").unwrap();
+        assert!(db.verify().unwrap().is_ok());
+    }
+    #[test]
+    fn test_verify_broken_key() {
+        let db_name = "target/tmp/test_verify-k.siamesedb";
+        let _ = std::fs::remove_dir_all(db_name);
+        {
+            let db = siamesedb::open_file(db_name).unwrap();
+            let params = FileDbParams {
+                checksum: true,
+                ..Default::default()
+            };
+            let mut db_map = db
+                .db_map_string_with_params("some_string_1", params)
+                .unwrap();
+            db_map.put_string("key1", "value1").unwrap();
+            db_map.put_string("the-broken-key", "value2").unwrap();
+            db.sync_all().unwrap();
+        }
+        let key_path = Path::new(db_name).join("some_string_1.key");
+        let mut buf = std::fs::read(&key_path).unwrap();
+        let pos = buf
+            .windows(14)
+            .position(|w| w == b"the-broken-key")
+            .expect("the key is in the file");
+        buf[pos] ^= 0x01;
+        std::fs::write(&key_path, &buf).unwrap();
+        // the broken key piece is reported, the verification goes on
+        let db = siamesedb::open_file(db_name).unwrap();
+        let report = db.verify().unwrap();
+        assert!(!report.is_ok());
+        assert!(report
+            .problems
+            .iter()
+            .all(|a| a.kind == VerifyProblemKind::Unreadable));
+        assert!(report
+            .problems
+            .iter()
+            .any(|a| a.file == key_path && a.offset < pos as u64));
+    }
+}